@@ -0,0 +1,13 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use transadif::fuzz::AdifDocument;
+use transadif::{AdifEncoding, ConversionOptions};
+
+fuzz_target!(|document: AdifDocument| {
+    let data = document.to_bytes();
+    for output_encoding in [AdifEncoding::Utf8, AdifEncoding::Iso88591, AdifEncoding::Ascii] {
+        let options = ConversionOptions { output_encoding, ..Default::default() };
+        let _ = transadif::convert(&data, &options);
+    }
+});
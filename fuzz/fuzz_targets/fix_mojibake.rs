@@ -0,0 +1,9 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use transadif::mojibake::fix_mojibake;
+
+fuzz_target!(|input: (&str, u8)| {
+    let (text, threshold) = input;
+    let _ = fix_mojibake(text, f64::from(threshold) / 255.0);
+});
@@ -0,0 +1,16 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use transadif::adif::{AdifFile, StreamingParser};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = AdifFile::parse(data);
+    let _ = AdifFile::parse_recovering(data.to_vec());
+    if let Ok(parser) = StreamingParser::new(data) {
+        for record in parser {
+            if record.is_err() {
+                break;
+            }
+        }
+    }
+});
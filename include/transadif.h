@@ -0,0 +1,44 @@
+/* C API of TransADIF, built with `cargo build --profile ffi --features ffi` */
+#ifndef TRANSADIF_H
+#define TRANSADIF_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Do not correct invalid characters or field counts */
+#define TRANSADIF_STRICT 1u
+/* Transliterate to characters without diacritics */
+#define TRANSADIF_ASCII 2u
+/* Delete what the output encoding cannot represent instead of writing '?' */
+#define TRANSADIF_DELETE 4u
+/* Skip fields that cannot be parsed instead of failing */
+#define TRANSADIF_SKIP_MALFORMED 8u
+
+/*
+ * Converts the ADIF file in input. input_encoding NULL takes the encoding from
+ * the header or detects it; output_encoding NULL writes UTF-8. flags combines
+ * the TRANSADIF_* constants.
+ *
+ * Returns 0 and stores the converted file in *output and *output_len, to be
+ * released with transadif_free, or -1 with the reason in transadif_last_error.
+ * An internal error that would have crashed the conversion returns -2.
+ */
+int transadif_convert(const uint8_t *input, size_t input_len,
+                      const char *input_encoding, const char *output_encoding,
+                      uint32_t flags, uint8_t **output, size_t *output_len);
+
+/* Releases output returned by transadif_convert. */
+void transadif_free(uint8_t *data, size_t len);
+
+/* Why the last transadif_convert call on this thread failed, or NULL. */
+const char *transadif_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
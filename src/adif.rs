@@ -1,560 +1,1881 @@
-use thiserror::Error;
-
-#[derive(Error, Debug)]
-pub enum AdifError {
-    #[error("Invalid field format: {0}")]
-    InvalidField(String),
-    #[error("Encoding error: {0}")]
-    EncodingError(String),
-    #[error("Parse error: {0}")]
-    ParseError(String),
-}
-
-#[derive(Debug, Clone)]
-pub enum FieldCountMode {
-    Bytes,
-    Characters,
-}
-
-#[derive(Debug, Clone)]
-pub struct Field {
-    pub name: String,
-    pub length: usize,
-    pub field_type: Option<String>,
-    pub data: String,
-    pub excess_data: String,
-    pub original_bytes: Vec<u8>,
-}
-
-#[derive(Debug, Clone)]
-pub struct Record {
-    pub fields: Vec<Field>,
-    pub excess_data: String,
-}
-
-#[derive(Debug, Clone)]
-pub struct AdifFile {
-    pub preamble: String,
-    pub header_fields: Vec<Field>,
-    pub header_excess_data: String,
-    pub records: Vec<Record>,
-    pub encoding: Option<String>,
-}
-
-impl AdifFile {
-    pub fn new() -> Self {
-        Self {
-            preamble: String::new(),
-            header_fields: Vec::new(),
-            header_excess_data: String::new(),
-            records: Vec::new(),
-            encoding: None,
-        }
-    }
-
-    pub fn parse(data: &[u8]) -> Result<Self, AdifError> {
-        let mut parser = AdifParser::new(data);
-        parser.parse()
-    }
-}
-
-struct AdifParser<'a> {
-    data: &'a [u8],
-    position: usize,
-}
-
-impl<'a> AdifParser<'a> {
-    fn new(data: &'a [u8]) -> Self {
-        Self { data, position: 0 }
-    }
-
-    fn parse(&mut self) -> Result<AdifFile, AdifError> {
-        let mut adif = AdifFile::new();
-
-        // Check if file starts with '<' (no header)
-        if self.peek_byte() == Some(b'<') {
-            // No header, start parsing records
-            adif.records = self.parse_records()?;
-        } else {
-            // Parse header
-            adif.preamble = self.parse_preamble()?;
-            adif.header_fields = self.parse_header_fields()?;
-            adif.header_excess_data = self.parse_excess_until_record()?;
-            adif.records = self.parse_records()?;
-        }
-
-        // Extract encoding from header fields
-        for field in &adif.header_fields {
-            if field.name.to_lowercase() == "encoding" {
-                adif.encoding = Some(field.data.clone());
-                break;
-            }
-        }
-
-        Ok(adif)
-    }
-
-    fn parse_preamble(&mut self) -> Result<String, AdifError> {
-        let start = self.position;
-
-        // Find the start of the first field or <eoh>
-        while self.position < self.data.len() {
-            if self.peek_byte() == Some(b'<') {
-                // Check if this is <eoh>
-                if self.is_at_eoh() {
-                    break;
-                }
-                // Check if this looks like a field
-                if self.is_at_field() {
-                    break;
-                }
-            }
-            self.position += 1;
-        }
-
-        let preamble_bytes = &self.data[start..self.position];
-        Ok(String::from_utf8_lossy(preamble_bytes).to_string())
-    }
-
-    fn parse_header_fields(&mut self) -> Result<Vec<Field>, AdifError> {
-        let mut fields = Vec::new();
-
-        while self.position < self.data.len() {
-            if self.is_at_eoh() {
-                // Skip <eoh>
-                self.skip_eoh();
-                break;
-            }
-
-            if self.is_at_field() {
-                fields.push(self.parse_field()?);
-            } else {
-                self.position += 1;
-            }
-        }
-
-        Ok(fields)
-    }
-
-    fn parse_records(&mut self) -> Result<Vec<Record>, AdifError> {
-        let mut records = Vec::new();
-
-        while self.position < self.data.len() {
-            if self.is_at_field() {
-                let record = self.parse_record()?;
-                records.push(record);
-            } else {
-                self.position += 1;
-            }
-        }
-
-        Ok(records)
-    }
-
-    fn parse_record(&mut self) -> Result<Record, AdifError> {
-        let mut fields = Vec::new();
-
-        while self.position < self.data.len() {
-            if self.is_at_eor() {
-                // Skip <eor>
-                self.skip_eor();
-                break;
-            }
-
-            if self.is_at_field() {
-                fields.push(self.parse_field()?);
-            } else {
-                self.position += 1;
-            }
-        }
-
-        let excess_data = self.parse_excess_until_record()?;
-
-        Ok(Record {
-            fields,
-            excess_data,
-        })
-    }
-
-    fn parse_field(&mut self) -> Result<Field, AdifError> {
-        self.parse_field_with_count_mode(None)
-    }
-
-    fn parse_field_with_count_mode(&mut self, count_mode: Option<FieldCountMode>) -> Result<Field, AdifError> {
-        if self.peek_byte() != Some(b'<') {
-            return Err(AdifError::InvalidField("Field must start with '<'".to_string()));
-        }
-
-        self.position += 1; // Skip '<'
-
-        // Parse field name
-        let name_start = self.position;
-        while self.position < self.data.len() && self.peek_byte() != Some(b':') {
-            self.position += 1;
-        }
-
-        if self.position >= self.data.len() {
-            return Err(AdifError::InvalidField("Unexpected end of field".to_string()));
-        }
-
-        let name = String::from_utf8_lossy(&self.data[name_start..self.position]).to_string();
-        self.position += 1; // Skip ':'
-
-        // Parse length
-        let length_start = self.position;
-        while self.position < self.data.len() && self.peek_byte().unwrap().is_ascii_digit() {
-            self.position += 1;
-        }
-
-        if self.position == length_start {
-            return Err(AdifError::InvalidField("Missing field length".to_string()));
-        }
-
-        let length_str = String::from_utf8_lossy(&self.data[length_start..self.position]);
-        let declared_length: usize = length_str.parse()
-            .map_err(|_| AdifError::InvalidField("Invalid field length".to_string()))?;
-
-        // Check for optional type
-        let mut field_type = None;
-        if self.peek_byte() == Some(b':') {
-            self.position += 1; // Skip ':'
-            let type_start = self.position;
-            while self.position < self.data.len() && self.peek_byte() != Some(b'>') {
-                self.position += 1;
-            }
-            field_type = Some(String::from_utf8_lossy(&self.data[type_start..self.position]).to_string());
-        }
-
-        if self.peek_byte() != Some(b'>') {
-            return Err(AdifError::InvalidField("Field must end with '>'".to_string()));
-        }
-
-        self.position += 1; // Skip '>'
-
-        // Try to parse data with the declared length first
-        let (final_length, data_bytes, excess_data) =
-            self.parse_field_data_with_count_handling(declared_length, count_mode)?;
-
-        let data = String::from_utf8_lossy(data_bytes).to_string();
-
-        Ok(Field {
-            name,
-            length: final_length,
-            field_type,
-            data,
-            excess_data,
-            original_bytes: data_bytes.to_vec(),
-        })
-    }
-
-    fn parse_field_data_with_count_handling(
-        &mut self,
-        declared_length: usize,
-        count_mode: Option<FieldCountMode>
-    ) -> Result<(usize, &[u8], String), AdifError> {
-        let data_start = self.position;
-
-        // First attempt with declared length as bytes
-        let data_end = std::cmp::min(self.position + declared_length, self.data.len());
-        let data_bytes = &self.data[data_start..data_end];
-        self.position = data_end;
-
-        // Parse excess data to check if reinterpretation is needed
-        let excess_start = self.position;
-        while self.position < self.data.len() {
-            if self.is_at_field() || self.is_at_eor() || self.is_at_eoh() {
-                break;
-            }
-            self.position += 1;
-        }
-
-        let excess_data = String::from_utf8_lossy(&self.data[excess_start..self.position]).to_string();
-
-        // Check if we need to reinterpret the field count
-        if self.should_reinterpret_field_count(data_bytes, &excess_data, count_mode) {
-            // Try character-based counting
-            if let Some((char_end, char_byte_count)) = self.calculate_character_based_field(data_start, declared_length) {
-                // Reset position for character-based parsing
-                self.position = char_end;
-
-                // Parse new excess data
-                let new_excess_start = self.position;
-                while self.position < self.data.len() {
-                    if self.is_at_field() || self.is_at_eor() || self.is_at_eoh() {
-                        break;
-                    }
-                    self.position += 1;
-                }
-
-                let new_excess_data = String::from_utf8_lossy(&self.data[new_excess_start..self.position]).to_string();
-
-                // If the new interpretation produces cleaner excess data, use it
-                if self.is_excess_data_cleaner(&new_excess_data, &excess_data) {
-                    let char_data_bytes = &self.data[data_start..char_end];
-                    return Ok((declared_length, char_data_bytes, new_excess_data));
-                }
-            }
-
-            // Revert to original interpretation
-            self.position = excess_start + excess_data.as_bytes().len();
-        }
-
-        Ok((declared_length, data_bytes, excess_data))
-    }
-
-    fn calculate_character_based_field(&self, start_pos: usize, n: usize) -> Option<(usize, usize)> {
-        let mut pos = start_pos;
-        let mut char_count = 0;
-
-        while pos < self.data.len() && char_count < n {
-            // Try to decode the next character
-            let remaining = &self.data[pos..];
-            if let Some(ch) = std::str::from_utf8(remaining).ok()?.chars().next() {
-                pos += ch.len_utf8();
-                char_count += 1;
-            } else {
-                // Not valid UTF-8, treat as single byte
-                pos += 1;
-                char_count += 1;
-            }
-        }
-
-        if char_count == n {
-            Some((pos, pos - start_pos))
-        } else {
-            None
-        }
-    }
-
-    fn should_reinterpret_field_count(
-        &self,
-        data_bytes: &[u8],
-        excess_data: &str,
-        _count_mode: Option<FieldCountMode>
-    ) -> bool {
-        // Only reinterpret if excess data contains non-whitespace
-        if excess_data.trim().is_empty() {
-            return false;
-        }
-
-        // Check if data contains UTF-8 sequences
-        self.has_utf8_sequences_in_bytes(data_bytes)
-    }
-
-    fn has_utf8_sequences_in_bytes(&self, data: &[u8]) -> bool {
-        let mut i = 0;
-        while i < data.len() {
-            if data[i] > 127 {
-                // Check for valid UTF-8 sequence
-                let mut count = 0;
-                if data[i] & 0b11100000 == 0b11000000 {
-                    count = 1;
-                } else if data[i] & 0b11110000 == 0b11100000 {
-                    count = 2;
-                } else if data[i] & 0b11111000 == 0b11110000 {
-                    count = 3;
-                }
-
-                if count > 0 && i + count < data.len() {
-                    let mut valid = true;
-                    for j in 1..=count {
-                        if data[i + j] & 0b11000000 != 0b10000000 {
-                            valid = false;
-                            break;
-                        }
-                    }
-                    if valid {
-                        return true;
-                    }
-                }
-            }
-            i += 1;
-        }
-        false
-    }
-
-    fn try_reinterpret_field_count(&self, declared_length: usize, data_bytes: &[u8]) -> Option<usize> {
-        // If we have UTF-8 sequences and non-whitespace excess data,
-        // the declared length is likely in bytes but should be in characters
-        if self.has_utf8_sequences_in_bytes(data_bytes) {
-            if let Ok(utf8_str) = std::str::from_utf8(data_bytes) {
-                let char_count = utf8_str.chars().count();
-                // If the character count is different from declared length,
-                // we might need to read more data to get the full character count
-                if char_count < declared_length {
-                    // We need more bytes to reach the character count
-                    return Some(declared_length); // Keep trying with character-based counting
-                }
-            }
-        }
-
-        // Try interpreting as bytes instead of characters
-        if data_bytes.len() != declared_length {
-            return Some(data_bytes.len());
-        }
-
-        None
-    }
-
-    fn is_excess_data_cleaner(&self, new_excess: &str, old_excess: &str) -> bool {
-        let new_non_whitespace = new_excess.chars().filter(|c| !c.is_whitespace()).count();
-        let old_non_whitespace = old_excess.chars().filter(|c| !c.is_whitespace()).count();
-
-        new_non_whitespace < old_non_whitespace
-    }
-
-    fn parse_excess_until_record(&mut self) -> Result<String, AdifError> {
-        let start = self.position;
-
-        while self.position < self.data.len() {
-            if self.is_at_field() {
-                break;
-            }
-            self.position += 1;
-        }
-
-        Ok(String::from_utf8_lossy(&self.data[start..self.position]).to_string())
-    }
-
-    fn peek_byte(&self) -> Option<u8> {
-        if self.position < self.data.len() {
-            Some(self.data[self.position])
-        } else {
-            None
-        }
-    }
-
-    fn is_at_eoh(&self) -> bool {
-        self.check_tag(b"eoh")
-    }
-
-    fn is_at_eor(&self) -> bool {
-        self.check_tag(b"eor")
-    }
-
-    fn is_at_field(&self) -> bool {
-        if self.peek_byte() != Some(b'<') {
-            return false;
-        }
-
-        // Look ahead to see if this looks like a field
-        let mut pos = self.position + 1;
-
-        // Skip field name (alphanumeric + underscore)
-        while pos < self.data.len() {
-            let byte = self.data[pos];
-            if byte == b':' {
-                break;
-            }
-            if !byte.is_ascii_alphanumeric() && byte != b'_' {
-                return false;
-            }
-            pos += 1;
-        }
-
-        if pos >= self.data.len() || self.data[pos] != b':' {
-            return false;
-        }
-
-        pos += 1;
-
-        // Check for length (digits)
-        let length_start = pos;
-        while pos < self.data.len() && self.data[pos].is_ascii_digit() {
-            pos += 1;
-        }
-
-        if pos == length_start {
-            return false;
-        }
-
-        // Optional type
-        if pos < self.data.len() && self.data[pos] == b':' {
-            pos += 1;
-            while pos < self.data.len() && self.data[pos] != b'>' {
-                let byte = self.data[pos];
-                if !byte.is_ascii_alphanumeric() && byte != b'_' {
-                    return false;
-                }
-                pos += 1;
-            }
-        }
-
-        pos < self.data.len() && self.data[pos] == b'>'
-    }
-
-    fn check_tag(&self, tag: &[u8]) -> bool {
-        if self.position + tag.len() + 2 > self.data.len() {
-            return false;
-        }
-
-        if self.data[self.position] != b'<' {
-            return false;
-        }
-
-        let tag_slice = &self.data[self.position + 1..self.position + 1 + tag.len()];
-        let tag_match = tag_slice.eq_ignore_ascii_case(tag);
-
-        if !tag_match {
-            return false;
-        }
-
-        self.data[self.position + 1 + tag.len()] == b'>'
-    }
-
-    fn skip_eoh(&mut self) {
-        self.skip_tag(b"eoh");
-    }
-
-    fn skip_eor(&mut self) {
-        self.skip_tag(b"eor");
-    }
-
-    fn skip_tag(&mut self, tag: &[u8]) {
-        if self.check_tag(tag) {
-            self.position += tag.len() + 2; // '<' + tag + '>'
-        }
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_parse_simple_field() {
-        let data = b"<call:5>K1MIX";
-        let mut parser = AdifParser::new(data);
-        let field = parser.parse_field().unwrap();
-
-        assert_eq!(field.name, "call");
-        assert_eq!(field.length, 5);
-        assert_eq!(field.data, "K1MIX");
-        assert!(field.field_type.is_none());
-    }
-
-    #[test]
-    fn test_parse_field_with_type() {
-        let data = b"<freq:5:N>7.200";
-        let mut parser = AdifParser::new(data);
-        let field = parser.parse_field().unwrap();
-
-        assert_eq!(field.name, "freq");
-        assert_eq!(field.length, 5);
-        assert_eq!(field.data, "7.200");
-        assert_eq!(field.field_type, Some("N".to_string()));
-    }
-
-    #[test]
-    fn test_parse_simple_record() {
-        let data = b"<call:5>K1MIX<band:3>40m<eor>";
-        let mut parser = AdifParser::new(data);
-        let record = parser.parse_record().unwrap();
-
-        assert_eq!(record.fields.len(), 2);
-        assert_eq!(record.fields[0].name, "call");
-        assert_eq!(record.fields[0].data, "K1MIX");
-        assert_eq!(record.fields[1].name, "band");
-        assert_eq!(record.fields[1].data, "40m");
-    }
+use crate::encoding::{detect_utf16, AdifEncoding, ByteOrderMark, Detection};
+use encoding_rs::Decoder;
+use std::fmt;
+use std::io::{self, Read};
+use std::ops::{Deref, Range};
+use std::sync::Arc;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum AdifError {
+    #[error("Invalid field format: {0}")]
+    InvalidField(ParseIssue),
+    #[error("Encoding error: {0}")]
+    EncodingError(ParseIssue),
+    #[error("Parse error: {0}")]
+    ParseError(ParseIssue),
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// Where in the input parsing failed, and why
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseIssue {
+    /// Byte offset in the input
+    pub offset: usize,
+    /// Index of the record in the file, starting at 0; `None` in the header
+    pub record: Option<usize>,
+    /// Name of the field, once its tag got that far
+    pub field: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for ParseIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.record {
+            Some(record) => write!(f, "QSO {}", record + 1)?,
+            None => write!(f, "Header")?,
+        }
+        if let Some(field) = &self.field {
+            write!(f, ": {}", field.to_uppercase())?;
+        }
+        write!(f, " at byte {}: {}", self.offset, self.message)
+    }
+}
+
+/// How the parser read a field whose declared length did not fit its value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LengthStrategy {
+    /// The length counted characters rather than bytes
+    Characters,
+    /// The value ran up to the next tag, whatever its length said
+    NextTag,
+}
+
+impl LengthStrategy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Characters => "characters",
+            Self::NextTag => "next-tag",
+        }
+    }
+}
+
+/// A field the parser read with another length than the declared one
+#[derive(Debug, Clone, PartialEq)]
+pub struct LengthCorrection {
+    /// Index of the record in the file, starting at 0; `None` in the header
+    pub record: Option<usize>,
+    pub field: String,
+    /// Byte offset of the field's `<` in the input
+    pub offset: usize,
+    pub declared: usize,
+    /// Bytes of input the value took
+    pub used: usize,
+    pub strategy: LengthStrategy,
+}
+
+impl fmt::Display for LengthCorrection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.record {
+            Some(record) => write!(f, "QSO {}", record + 1)?,
+            None => write!(f, "Header")?,
+        }
+        let how = match self.strategy {
+            LengthStrategy::Characters => "counting characters",
+            LengthStrategy::NextTag => "up to the next tag",
+        };
+        write!(f, ": {} at byte {}: declared {}, read {} bytes {}", self.field.to_uppercase(), self.offset, self.declared, self.used, how)
+    }
+}
+
+/// How many bytes `StreamingParser` reads from its source at a time
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+/// ADIF version declared in headers TransADIF writes
+pub const ADIF_VERSION: &str = "3.1.4";
+
+/// Header field declaring what the lengths of a file's fields count
+pub const COUNT_MODE_FIELD: &str = "APP_TRANSADIF_COUNT_MODE";
+
+/// What field lengths count. Loggers differ on UTF-8 values: some count
+/// bytes, as the ADIF specification does, others characters.
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum FieldCountMode {
+    /// Bytes of the encoded value
+    Bytes,
+    /// Unicode characters
+    #[value(name = "chars")]
+    Characters,
+    /// Characters for UTF-8 and bytes otherwise; when reading, whichever
+    /// makes the value end right before the next tag
+    Auto,
+}
+
+impl FieldCountMode {
+    /// The value `COUNT_MODE_FIELD` declares the mode with
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Bytes => "bytes",
+            Self::Characters => "chars",
+            Self::Auto => "auto",
+        }
+    }
+
+    /// The mode a `COUNT_MODE_FIELD` value declares.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "bytes" => Some(Self::Bytes),
+            "chars" | "characters" => Some(Self::Characters),
+            "auto" => Some(Self::Auto),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Field {
+    pub name: String,
+    pub length: usize,
+    pub field_type: Option<String>,
+    /// The value as text, once decoded
+    pub data: String,
+    /// Text between this field and the next tag, read as UTF-8. It is
+    /// normally whitespace and carries no ADIF data, so bytes that are not
+    /// UTF-8 are replaced rather than decoded like values.
+    pub excess_data: String,
+    /// The value's bytes in the input, which may share the input's buffer
+    pub original_bytes: InputBytes,
+    /// Whether `data` holds the value. The parser only decodes ASCII values,
+    /// which read the same in every encoding; `EncodingProcessor::decode_file`
+    /// decodes the others once for all later steps.
+    pub decoded: bool,
+    /// Byte offset of the field's `<` in the input
+    pub offset: usize,
+}
+
+impl Field {
+    /// Creates a field from a Unicode value, as if it had been read from UTF-8 input.
+    pub fn new(name: &str, value: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            length: value.chars().count(),
+            field_type: None,
+            data: value.to_string(),
+            excess_data: String::new(),
+            original_bytes: value.as_bytes().into(),
+            offset: 0,
+            decoded: true,
+        }
+    }
+}
+
+/// Fields are equal when they hold the same ADIF, wherever and from whatever
+/// bytes they were read.
+impl PartialEq for Field {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.length == other.length
+            && self.field_type == other.field_type
+            && self.data == other.data
+            && self.excess_data == other.excess_data
+    }
+}
+
+/// A field's bytes as they were in the input. Fields parsed from a whole file
+/// share its buffer and each hold the range of their value, instead of a copy.
+/// This covers the raw bytes only: the decoded value and excess data are
+/// still copied into each field.
+#[derive(Clone)]
+pub struct InputBytes(Bytes);
+
+#[derive(Clone)]
+enum Bytes {
+    Owned(Vec<u8>),
+    Shared(Arc<Vec<u8>>, Range<usize>),
+}
+
+impl InputBytes {
+    fn shared(buffer: &Arc<Vec<u8>>, range: Range<usize>) -> Self {
+        Self(Bytes::Shared(Arc::clone(buffer), range))
+    }
+}
+
+impl Deref for InputBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &self.0 {
+            Bytes::Owned(bytes) => bytes,
+            Bytes::Shared(buffer, range) => &buffer[range.clone()],
+        }
+    }
+}
+
+impl From<Vec<u8>> for InputBytes {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(Bytes::Owned(bytes))
+    }
+}
+
+impl From<&[u8]> for InputBytes {
+    fn from(bytes: &[u8]) -> Self {
+        Self(Bytes::Owned(bytes.to_vec()))
+    }
+}
+
+impl PartialEq for InputBytes {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl PartialEq<&[u8]> for InputBytes {
+    fn eq(&self, other: &&[u8]) -> bool {
+        **self == **other
+    }
+}
+
+impl fmt::Debug for InputBytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub fields: Vec<Field>,
+    pub excess_data: String,
+    /// Byte offset of the record's first field in the input
+    pub offset: usize,
+    /// The `<eor>` tag as spelled in the input; empty for records not read from ADI
+    pub end_tag: String,
+}
+
+/// Fields that identify a QSO independently of later edits to other fields
+const FINGERPRINT_FIELDS: [&str; 5] = ["call", "qso_date", "time_on", "band", "mode"];
+
+impl Record {
+    /// Returns the data of the first field with the given (case-insensitive) name.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|field| field.name.eq_ignore_ascii_case(name))
+            .map(|field| field.data.as_str())
+    }
+
+    /// Replaces the data of an existing field or appends a new one, keeping the
+    /// separator used after the record's last field.
+    pub fn set(&mut self, name: &str, value: &str) {
+        let mut field = Field::new(name, value);
+
+        if let Some(existing) = self.fields.iter_mut().find(|f| f.name.eq_ignore_ascii_case(name)) {
+            field.name = existing.name.clone();
+            field.excess_data = std::mem::take(&mut existing.excess_data);
+            *existing = field;
+        } else {
+            if let Some(last) = self.fields.last() {
+                field.excess_data = last.excess_data.clone();
+            }
+            self.fields.push(field);
+        }
+    }
+
+    /// Stable content hash of the QSO's key fields (call, date, time, band,
+    /// mode), canonicalized so cosmetic differences such as case, whitespace
+    /// or seconds in TIME_ON do not change it. Returned as 16 hex digits.
+    pub fn fingerprint(&self) -> String {
+        let mut canonical = String::new();
+
+        for name in FINGERPRINT_FIELDS {
+            let value = self.get(name).unwrap_or("").trim().to_uppercase();
+            let value = if name == "time_on" { value.chars().take(4).collect() } else { value };
+            canonical.push_str(name);
+            canonical.push('=');
+            canonical.push_str(&value);
+            canonical.push('|');
+        }
+
+        // FNV-1a, which unlike std's hashers is guaranteed stable across releases
+        let hash = canonical.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+        format!("{:016x}", hash)
+    }
+}
+
+/// Records are equal when their fields and the text around them are, like `Field`s.
+impl PartialEq for Record {
+    fn eq(&self, other: &Self) -> bool {
+        self.fields == other.fields && self.excess_data == other.excess_data && self.end_tag == other.end_tag
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AdifFile {
+    pub preamble: String,
+    /// The preamble as read, until `EncodingProcessor::decode_file` decodes
+    /// it into `preamble` with the file's encoding
+    pub preamble_bytes: Vec<u8>,
+    pub header_fields: Vec<Field>,
+    pub header_excess_data: String,
+    /// The `<eoh>` tag as spelled in the input; empty if there was none
+    pub header_end_tag: String,
+    pub records: Vec<Record>,
+    /// Encoding named by the header's ENCODING field, or implied by a UTF-8 byte order mark
+    pub encoding: Option<String>,
+    /// What field lengths count, as declared by the header's `COUNT_MODE_FIELD`
+    pub count_mode: Option<FieldCountMode>,
+    /// What encoding detection made of the file's values, set when they are
+    /// decoded without an input encoding
+    pub detected_encoding: Option<Detection>,
+    /// Problems the parser recovered from, meant to be shown to the user
+    pub warnings: Vec<String>,
+    /// Malformed fields skipped by `parse_recovering`
+    pub errors: Vec<ParseIssue>,
+    /// Fields read with another length than the declared one
+    pub length_corrections: Vec<LengthCorrection>,
+}
+
+/// Files are equal when they hold the same ADIF, like `Field`s. What the
+/// header declares is compared through the header fields, and what parsing
+/// and decoding made of the input is left out.
+impl PartialEq for AdifFile {
+    fn eq(&self, other: &Self) -> bool {
+        self.preamble == other.preamble
+            && self.preamble_bytes == other.preamble_bytes
+            && self.header_fields == other.header_fields
+            && self.header_excess_data == other.header_excess_data
+            && self.header_end_tag == other.header_end_tag
+            && self.records == other.records
+    }
+}
+
+impl Default for AdifFile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AdifFile {
+    pub fn new() -> Self {
+        Self {
+            preamble: String::new(),
+            preamble_bytes: Vec::new(),
+            header_fields: Vec::new(),
+            header_excess_data: String::new(),
+            header_end_tag: String::new(),
+            records: Vec::new(),
+            encoding: None,
+            count_mode: None,
+            detected_encoding: None,
+            warnings: Vec::new(),
+            errors: Vec::new(),
+            length_corrections: Vec::new(),
+        }
+    }
+
+    /// The header and the records at `indexes` (from 0) of `data`, the input
+    /// the file was parsed from, as their original bytes with whatever follows
+    /// each `<eor>`. Offsets in UTF-16 input refer to the converted text, so
+    /// `data` must not be UTF-16.
+    pub fn extract_records(&self, data: &[u8], indexes: &[usize]) -> Vec<u8> {
+        let start = |index: usize| self.records.get(index).map_or(data.len(), |record| record.offset);
+        let mut extract = data[..start(0)].to_vec();
+        for &index in indexes.iter().filter(|&&index| index < self.records.len()) {
+            extract.extend_from_slice(&data[start(index)..start(index + 1)]);
+        }
+        extract
+    }
+
+    /// Whether the input had a header, i.e. did not start with its first record.
+    pub fn has_header(&self) -> bool {
+        !self.header_end_tag.is_empty() || !self.header_fields.is_empty()
+    }
+
+    /// Gives a file without a header one, with `preamble` unless it already
+    /// has one and ADIF_VER, PROGRAMID and CREATED_TIMESTAMP fields. The
+    /// formatter adds ENCODING. Returns whether there was no header.
+    pub fn add_header(&mut self, preamble: &str, created_at: u64) -> bool {
+        if self.has_header() {
+            return false;
+        }
+        if self.preamble.trim().is_empty() {
+            self.preamble = preamble.to_string();
+            self.preamble_bytes.clear();
+        }
+        self.set_header("adif_ver", ADIF_VERSION);
+        self.set_header("programid", "TransADIF");
+        self.set_header("created_timestamp", &timestamp(created_at));
+        self.header_end_tag = "<eoh>".to_string();
+        self.header_excess_data = "\r\n".to_string();
+        true
+    }
+
+    /// The encoding the header names, if it is one TransADIF knows. UTF-16 is
+    /// only a hint: input `detect_utf16` recognizes is converted to UTF-8
+    /// while parsing, so a header still naming UTF-16 is wrong about the
+    /// bytes, and detection has to decide.
+    pub fn declared_encoding(&self) -> Option<AdifEncoding> {
+        self.encoding.as_ref().and_then(|encoding| encoding.parse::<AdifEncoding>().ok()).filter(|encoding| !encoding.is_utf16())
+    }
+
+    /// Parses ADIF from a copy of `data`; `parse_owned` saves the copy.
+    /// UTF-16 input is converted to UTF-8 first, so byte offsets then refer
+    /// to the converted text.
+    pub fn parse(data: &[u8]) -> Result<Self, AdifError> {
+        Self::parse_resuming(data, 0)
+    }
+
+    /// Parses `data` without copying it again: the fields' original bytes are
+    /// ranges of the buffer, which lives as long as any of them.
+    pub fn parse_owned(data: Vec<u8>) -> Result<Self, AdifError> {
+        Self::parse_with(data, 0, ParseOptions::default())
+    }
+
+    /// Like `parse_owned`, but skips fields that cannot be parsed, listing
+    /// them in `errors`, instead of failing on the first.
+    pub fn parse_recovering(data: Vec<u8>) -> Result<Self, AdifError> {
+        Self::parse_with(data, 0, ParseOptions { recover: true, ..ParseOptions::default() })
+    }
+
+    /// Like `parse_owned`, with the records starting before `offset` skipped
+    /// as in `parse_resuming`.
+    pub fn parse_with(data: Vec<u8>, offset: usize, options: ParseOptions) -> Result<Self, AdifError> {
+        Self::parse_buffer(Arc::new(data), offset, options)
+    }
+
+    /// Header fields as they should be written: without ENCODING and the
+    /// count mode, which the writers add for the output, and with repeated
+    /// fields (names compared ignoring case) reduced to the first, in its
+    /// original place.
+    pub fn output_header_fields(&self) -> Vec<&Field> {
+        let mut seen = Vec::new();
+
+        self.header_fields
+            .iter()
+            .filter(|field| {
+                let name = field.name.trim().to_lowercase();
+                if name == "encoding" || name.eq_ignore_ascii_case(COUNT_MODE_FIELD) || seen.contains(&name) {
+                    return false;
+                }
+                seen.push(name);
+                true
+            })
+            .collect()
+    }
+
+    /// Replaces the value of a header field or appends a new one, keeping the
+    /// separator used after the last header field.
+    pub fn set_header(&mut self, name: &str, value: &str) {
+        let mut field = Field::new(name, value);
+
+        if let Some(existing) = self.header_fields.iter_mut().find(|f| f.name.eq_ignore_ascii_case(name)) {
+            field.name = existing.name.clone();
+            field.excess_data = std::mem::take(&mut existing.excess_data);
+            *existing = field;
+        } else {
+            field.excess_data = self.header_fields.last().map_or("\r\n".to_string(), |last| last.excess_data.clone());
+            self.header_fields.push(field);
+        }
+    }
+
+    /// Parses the header as usual, but only the records starting at or after
+    /// `offset`. Used to continue an interrupted conversion.
+    pub fn parse_resuming(data: &[u8], offset: usize) -> Result<Self, AdifError> {
+        Self::parse_with(data.to_vec(), offset, ParseOptions::default())
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(bytes = data.len(), offset, ?options)))]
+    fn parse_buffer(data: Arc<Vec<u8>>, offset: usize, options: ParseOptions) -> Result<Self, AdifError> {
+        let Some(encoding) = detect_utf16(&data) else {
+            return AdifParser::shared(&data).with_options(options).parse(offset);
+        };
+
+        let (text, _had_errors) = encoding.to_encoding_rs().decode_with_bom_removal(&data);
+        let text = Arc::new(text.into_owned().into_bytes());
+        let mut adif = AdifParser::shared(&text).with_options(options).parse(offset)?;
+        adif.encoding = Some("UTF-8".to_string()); // Whatever the header said
+        Ok(adif)
+    }
+}
+
+/// How `AdifFile::parse_with` and `StreamingParser` treat questionable input.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ParseOptions {
+    /// Skip fields that cannot be parsed, listing them in `errors`
+    pub recover: bool,
+    /// Take declared lengths as bytes or characters only, never reading a
+    /// value up to the next tag instead
+    pub strict: bool,
+}
+
+/// An ADIF `YYYYMMDD HHMMSS` timestamp (UTC) for seconds since the Unix epoch,
+/// as written to CREATED_TIMESTAMP.
+pub fn timestamp(unix_seconds: u64) -> String {
+    let days = (unix_seconds / 86400) as i64;
+    let seconds = unix_seconds % 86400;
+
+    // Civil date from days since 1970-01-01, after Howard Hinnant's days_from_civil inverse
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!("{:04}{:02}{:02} {:02}{:02}{:02}", year, month, day, seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+/// Parser that reads records one at a time from an `io::Read` source, so huge
+/// logs never have to be held in memory as a whole.
+///
+/// The header is parsed up front and available through `header()`; records
+/// are then produced by iterating. Only the record being parsed and a read
+/// chunk are buffered. Parsing a record is retried with more input whenever
+/// the attempt ran into the end of the buffer, because the field count
+/// heuristics and excess data both need to see what follows a field.
+pub struct StreamingParser<R: Read> {
+    reader: R,
+    buffer: Vec<u8>,
+    /// Position in `buffer` of the first byte not parsed yet
+    position: usize,
+    /// Input offset of `buffer[0]`
+    buffer_offset: usize,
+    eof: bool,
+    failed: bool,
+    /// Records produced so far
+    records: usize,
+    options: ParseOptions,
+    /// Malformed fields skipped since `take_errors` was last called
+    errors: Vec<ParseIssue>,
+    /// Ambiguities resolved since `take_warnings` was last called
+    warnings: Vec<String>,
+    header: AdifFile,
+    /// Converts UTF-16 input to UTF-8 as it is read
+    decoder: Option<Decoder>,
+}
+
+impl<R: Read> StreamingParser<R> {
+    pub fn new(reader: R) -> Result<Self, AdifError> {
+        let mut parser = Self {
+            reader,
+            buffer: Vec::new(),
+            position: 0,
+            buffer_offset: 0,
+            eof: false,
+            failed: false,
+            records: 0,
+            options: ParseOptions::default(),
+            errors: Vec::new(),
+            warnings: Vec::new(),
+            header: AdifFile::new(),
+            decoder: None,
+        };
+
+        parser.fill()?;
+        if let Some(encoding) = detect_utf16(&parser.buffer) {
+            let mut decoder = encoding.to_encoding_rs().new_decoder_with_bom_removal();
+            parser.buffer = decode_to_utf8(&mut decoder, &parser.buffer, parser.eof);
+            parser.decoder = Some(decoder);
+        }
+
+        parser.header = parser.parse_complete(|p| p.parse_header())?;
+        if parser.decoder.is_some() {
+            parser.header.encoding = Some("UTF-8".to_string());
+        }
+        Ok(parser)
+    }
+
+    /// Preamble, header fields and encoding of the input; `records` is always empty.
+    pub fn header(&self) -> &AdifFile {
+        &self.header
+    }
+
+    /// Skips fields of records that cannot be parsed instead of failing,
+    /// like `AdifFile::parse_recovering`. The header is parsed as before.
+    pub fn set_recover(&mut self, recover: bool) {
+        self.options.recover = recover;
+    }
+
+    /// Takes declared lengths as they are, like `ParseOptions::strict`.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.options.strict = strict;
+    }
+
+    /// The malformed fields skipped since the last call.
+    pub fn take_errors(&mut self) -> Vec<ParseIssue> {
+        std::mem::take(&mut self.errors)
+    }
+
+    /// The ambiguities in the records parsed since the last call, like
+    /// `AdifFile::warnings`; those in the header are in `header().warnings`.
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// Runs `parse` on the buffered input, reading more and starting over
+    /// until it stops short of the end of the buffer or the input is exhausted.
+    fn parse_complete<T, P>(&mut self, parse: P) -> Result<T, AdifError>
+    where
+        P: Fn(&mut AdifParser) -> Result<T, AdifError>,
+    {
+        loop {
+            let mut parser = AdifParser::new(&self.buffer).with_options(self.options);
+            parser.position = self.position;
+            parser.input_offset = self.buffer_offset;
+            parser.record_index = self.records;
+            parser.count_mode = self.header.count_mode.unwrap_or(FieldCountMode::Auto);
+            let result = parse(&mut parser);
+
+            if self.eof || (result.is_ok() && parser.position < self.buffer.len()) {
+                self.position = parser.position;
+                for mut issue in parser.errors {
+                    issue.offset += self.buffer_offset;
+                    self.errors.push(issue);
+                }
+                self.warnings.append(&mut parser.warnings);
+                return result;
+            }
+
+            self.fill()?;
+        }
+    }
+
+    fn fill(&mut self) -> io::Result<()> {
+        // Drop what has been parsed already before growing the buffer
+        if self.position > 0 {
+            self.buffer.drain(..self.position);
+            self.buffer_offset += self.position;
+            self.position = 0;
+        }
+
+        let length = self.buffer.len();
+        self.buffer.resize(length + READ_CHUNK_SIZE, 0);
+        let read = loop {
+            match self.reader.read(&mut self.buffer[length..]) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                result => break result,
+            }
+        };
+        self.buffer.truncate(length + *read.as_ref().unwrap_or(&0));
+
+        self.eof = read? == 0;
+        if let Some(decoder) = &mut self.decoder {
+            let raw = self.buffer.split_off(length);
+            self.buffer.extend(decode_to_utf8(decoder, &raw, self.eof));
+        }
+        Ok(())
+    }
+}
+
+fn decode_to_utf8(decoder: &mut Decoder, raw: &[u8], last: bool) -> Vec<u8> {
+    let capacity = decoder.max_utf8_buffer_length(raw.len()).unwrap_or(raw.len() * 3 + 16);
+    let mut decoded = vec![0; capacity];
+    let (_result, _read, written, _had_errors) = decoder.decode_to_utf8(raw, &mut decoded, last);
+    decoded.truncate(written);
+    decoded
+}
+
+impl<R: Read> Iterator for StreamingParser<R> {
+    type Item = Result<Record, AdifError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+
+        match self.parse_complete(|p| p.parse_next_record()) {
+            Ok(Some(mut record)) => {
+                self.records += 1;
+                record.offset += self.buffer_offset;
+                for field in &mut record.fields {
+                    field.offset += self.buffer_offset;
+                }
+                Some(Ok(record))
+            }
+            Ok(None) => None,
+            Err(e) => {
+                self.failed = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+struct AdifParser<'a> {
+    data: &'a [u8],
+    /// `data` as a buffer fields can share, when it is the whole input
+    shared: Option<Arc<Vec<u8>>>,
+    position: usize,
+    /// Input offset of `data[0]`, for the byte offsets in `warnings`
+    input_offset: usize,
+    /// Ambiguities resolved while parsing records, for `AdifFile::warnings`
+    warnings: Vec<String>,
+    /// Index of the record parsed next; `None` while in the header
+    record: Option<usize>,
+    /// Index the next record gets once the header is done
+    record_index: usize,
+    /// Whether malformed fields are skipped and listed in `errors`
+    recover: bool,
+    errors: Vec<ParseIssue>,
+    /// What the lengths of record fields count
+    count_mode: FieldCountMode,
+    /// Whether wrong lengths may be read up to the next tag; off in strict
+    /// mode and for probes, which look at fields as declared
+    lookahead: bool,
+    length_corrections: Vec<LengthCorrection>,
+}
+
+impl<'a> AdifParser<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            shared: None,
+            position: 0,
+            input_offset: 0,
+            warnings: Vec::new(),
+            record: None,
+            record_index: 0,
+            recover: false,
+            errors: Vec::new(),
+            count_mode: FieldCountMode::Auto,
+            lookahead: true,
+            length_corrections: Vec::new(),
+        }
+    }
+
+    fn shared(data: &'a Arc<Vec<u8>>) -> Self {
+        Self { shared: Some(Arc::clone(data)), ..Self::new(data) }
+    }
+
+    fn with_options(self, options: ParseOptions) -> Self {
+        Self { recover: options.recover, lookahead: !options.strict, ..self }
+    }
+
+    fn parse(&mut self, records_offset: usize) -> Result<AdifFile, AdifError> {
+        let mut adif = self.parse_header()?;
+
+        self.position = self.position.max(records_offset);
+        adif.records = self.parse_records()?;
+        adif.warnings.append(&mut self.warnings);
+        adif.errors.append(&mut self.errors);
+        adif.length_corrections.append(&mut self.length_corrections);
+
+        Ok(adif)
+    }
+
+    /// Parses everything before the first record into an `AdifFile` without records.
+    fn parse_header(&mut self) -> Result<AdifFile, AdifError> {
+        let mut adif = AdifFile::new();
+
+        let byte_order_mark = ByteOrderMark::detect(&self.data[self.position..]);
+        if let Some(warning) = self.skip_leading_junk() {
+            adif.warnings.push(warning);
+        }
+
+        // Check if file starts with '<' (no header)
+        if self.peek_byte() != Some(b'<') {
+            // Parse header
+            let start = self.position;
+            adif.preamble = self.parse_preamble()?;
+            adif.preamble_bytes = self.data[start..self.position].to_vec();
+            (adif.header_fields, adif.header_end_tag) = self.parse_header_fields()?;
+            adif.header_excess_data = self.parse_excess_until_record()?;
+        }
+
+        // Extract encoding from header fields
+        for field in &adif.header_fields {
+            if field.name.to_lowercase() == "encoding" {
+                adif.encoding = Some(String::from_utf8_lossy(&field.original_bytes).into_owned());
+                break;
+            }
+        }
+
+        adif.count_mode = adif.header_fields
+            .iter()
+            .find(|field| field.name.eq_ignore_ascii_case(COUNT_MODE_FIELD))
+            .and_then(|field| FieldCountMode::from_name(&field.data));
+        if let Some(count_mode) = adif.count_mode {
+            self.count_mode = count_mode;
+        }
+
+        // Only a program writing UTF-8 starts the file with its byte order mark
+        if adif.encoding.is_none() && byte_order_mark == Some(ByteOrderMark::Utf8) {
+            adif.encoding = Some(ByteOrderMark::Utf8.encoding_name().to_string());
+        }
+
+        Ok(adif)
+    }
+
+    /// Skips byte order marks, NULs and HTML markup (left over from files
+    /// saved from web pages) in front of the preamble or first field, so they
+    /// neither end up in the preamble nor hide the header. Whitespace is only
+    /// skipped between and directly after pieces of junk. Returns a
+    /// description of what was discarded.
+    fn skip_leading_junk(&mut self) -> Option<String> {
+        let start = self.position;
+        let mut kinds: Vec<&str> = Vec::new();
+
+        loop {
+            let junk_start = self.position;
+            while self.peek_byte().is_some_and(|b| b.is_ascii_whitespace()) {
+                self.position += 1;
+            }
+
+            let kind = if self.data[self.position..].starts_with(b"\xEF\xBB\xBF") {
+                self.position += 3;
+                "byte order mark"
+            } else if self.peek_byte() == Some(0) {
+                while self.peek_byte() == Some(0) {
+                    self.position += 1;
+                }
+                "NUL bytes"
+            } else if let Some(end) = self.html_tag_end() {
+                self.position = end;
+                "HTML markup"
+            } else {
+                self.position = junk_start;
+                break;
+            };
+
+            if !kinds.contains(&kind) {
+                kinds.push(kind);
+            }
+        }
+
+        if self.position == start {
+            return None;
+        }
+
+        // Line breaks after the junk (such as after `<pre>`) belong to it
+        while self.peek_byte().is_some_and(|b| b.is_ascii_whitespace()) {
+            self.position += 1;
+        }
+
+        Some(format!(
+            "Discarded {} bytes of leading junk ({})",
+            self.position - start,
+            kinds.join(", ")
+        ))
+    }
+
+    /// Position just past an HTML tag, comment or doctype starting at the
+    /// current position. ADIF fields and `<eoh>`/`<eor>` are never treated as HTML.
+    fn html_tag_end(&self) -> Option<usize> {
+        if self.peek_byte() != Some(b'<') || self.is_at_field() || self.is_at_eoh() || self.is_at_eor() {
+            return None;
+        }
+
+        let next = *self.data.get(self.position + 1)?;
+        if !(next.is_ascii_alphabetic() || next == b'/' || next == b'!' || next == b'?') {
+            return None;
+        }
+
+        self.data[self.position..]
+            .iter()
+            .position(|&b| b == b'>')
+            .map(|end| self.position + end + 1)
+    }
+
+    fn parse_preamble(&mut self) -> Result<String, AdifError> {
+        let start = self.position;
+
+        // Find the start of the first field or <eoh>
+        while self.position < self.data.len() {
+            if self.peek_byte() == Some(b'<') {
+                // Check if this is <eoh>
+                if self.is_at_eoh() {
+                    break;
+                }
+                // Check if this looks like a field
+                if self.is_at_field() {
+                    break;
+                }
+            }
+            self.position += 1;
+        }
+
+        let preamble_bytes = &self.data[start..self.position];
+        Ok(String::from_utf8_lossy(preamble_bytes).to_string())
+    }
+
+    /// Parses the header fields and the `<eoh>` tag, returned as written.
+    fn parse_header_fields(&mut self) -> Result<(Vec<Field>, String), AdifError> {
+        let mut fields = Vec::new();
+        let mut end_tag = String::new();
+
+        while self.position < self.data.len() {
+            if self.is_at_eoh() {
+                end_tag = self.skip_eoh();
+                break;
+            }
+
+            if self.is_at_field() {
+                fields.extend(self.parse_field_or_skip()?);
+            } else {
+                self.position += 1;
+            }
+        }
+
+        Ok((fields, end_tag))
+    }
+
+    fn parse_records(&mut self) -> Result<Vec<Record>, AdifError> {
+        let mut records = Vec::new();
+
+        while self.position < self.data.len() {
+            if self.is_at_field() {
+                let record = self.parse_record()?;
+                records.push(record);
+            } else {
+                self.position += 1;
+            }
+        }
+
+        Ok(records)
+    }
+
+    /// Skips to the next record and parses it, or returns `None` at the end of the data.
+    fn parse_next_record(&mut self) -> Result<Option<Record>, AdifError> {
+        while self.position < self.data.len() && !self.is_at_field() {
+            self.position += 1;
+        }
+
+        if self.position < self.data.len() {
+            Ok(Some(self.parse_record()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn parse_record(&mut self) -> Result<Record, AdifError> {
+        let offset = self.position;
+        let mut fields = Vec::new();
+        let mut end_tag = String::new();
+        self.record = Some(self.record_index);
+
+        while self.position < self.data.len() {
+            if self.is_at_eor() {
+                end_tag = self.skip_eor();
+                break;
+            }
+
+            if self.is_at_field() {
+                fields.extend(self.parse_field_or_skip()?);
+            } else {
+                self.position += 1;
+            }
+        }
+
+        let excess_data = self.parse_excess_until_record()?;
+        #[cfg(feature = "tracing")]
+        tracing::trace!(record = self.record_index, offset, fields = fields.len(), "parsed record");
+        self.record_index += 1;
+
+        Ok(Record {
+            fields,
+            excess_data,
+            offset,
+            end_tag,
+        })
+    }
+
+    /// Parses the field at the current position. When recovering, a field
+    /// that cannot be parsed is listed in `errors` and skipped instead.
+    fn parse_field_or_skip(&mut self) -> Result<Option<Field>, AdifError> {
+        let offset = self.position;
+        match self.parse_field() {
+            Ok(field) => Ok(Some(field)),
+            Err(AdifError::InvalidField(issue)) if self.recover => {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(%issue, "skipped field");
+                self.errors.push(issue);
+                // What follows the `<` is read as stray text up to the next tag
+                self.position = offset + 1;
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn parse_field(&mut self) -> Result<Field, AdifError> {
+        self.parse_field_with_count_mode(self.count_mode)
+    }
+
+    fn invalid_field(&self, offset: usize, field: Option<&str>, message: &str) -> AdifError {
+        AdifError::InvalidField(ParseIssue {
+            offset,
+            record: self.record,
+            field: field.map(str::to_string),
+            message: message.to_string(),
+        })
+    }
+
+    fn parse_field_with_count_mode(&mut self, count_mode: FieldCountMode) -> Result<Field, AdifError> {
+        let offset = self.position;
+        if self.peek_byte() != Some(b'<') {
+            return Err(self.invalid_field(offset, None, "Field must start with '<'"));
+        }
+
+        self.position += 1; // Skip '<'
+
+        // Parse field name
+        let name_start = self.position;
+        while self.position < self.data.len() && self.peek_byte() != Some(b':') {
+            self.position += 1;
+        }
+
+        if self.position >= self.data.len() {
+            return Err(self.invalid_field(offset, None, "Unexpected end of field"));
+        }
+
+        let name = String::from_utf8_lossy(&self.data[name_start..self.position]).to_string();
+        self.position += 1; // Skip ':'
+
+        // Parse length
+        let length_start = self.position;
+        while self.position < self.data.len() && self.peek_byte().unwrap().is_ascii_digit() {
+            self.position += 1;
+        }
+
+        if self.position == length_start {
+            return Err(self.invalid_field(offset, Some(&name), "Missing field length"));
+        }
+
+        let length_str = String::from_utf8_lossy(&self.data[length_start..self.position]);
+        let declared_length: usize = length_str.parse()
+            .map_err(|_| self.invalid_field(offset, Some(&name), "Invalid field length"))?;
+
+        // Check for optional type
+        let mut field_type = None;
+        if self.peek_byte() == Some(b':') {
+            self.position += 1; // Skip ':'
+            let type_start = self.position;
+            while self.position < self.data.len() && self.peek_byte() != Some(b'>') {
+                self.position += 1;
+            }
+            field_type = Some(String::from_utf8_lossy(&self.data[type_start..self.position]).to_string());
+        }
+
+        if self.peek_byte() != Some(b'>') {
+            return Err(self.invalid_field(offset, Some(&name), "Field must end with '>'"));
+        }
+
+        self.position += 1; // Skip '>'
+
+        // Try to parse data with the declared length first
+        let (final_length, data_range, excess_data) =
+            self.parse_field_data_with_count_handling(&name, offset, declared_length, count_mode)?;
+
+        let data_bytes = &self.data[data_range.clone()];
+        // ASCII reads the same in every supported encoding; anything else
+        // waits for the input encoding
+        let decoded = data_bytes.is_ascii();
+        let data = if decoded { String::from_utf8(data_bytes.to_vec()).unwrap() } else { String::new() };
+        let original_bytes = match &self.shared {
+            Some(buffer) => InputBytes::shared(buffer, data_range),
+            None => data_bytes.into(),
+        };
+
+        Ok(Field {
+            name,
+            length: final_length,
+            field_type,
+            data,
+            excess_data,
+            original_bytes,
+            offset,
+            decoded,
+        })
+    }
+
+    fn parse_field_data_with_count_handling(
+        &mut self,
+        name: &str,
+        offset: usize,
+        declared_length: usize,
+        count_mode: FieldCountMode
+    ) -> Result<(usize, Range<usize>, String), AdifError> {
+        let data_start = self.position;
+
+        // Declared to count characters: no need to guess
+        if count_mode == FieldCountMode::Characters {
+            if let Some((char_end, _)) = self.calculate_character_based_field(data_start, declared_length) {
+                self.position = char_end;
+                while self.position < self.data.len() && !(self.is_at_field() || self.is_at_eor() || self.is_at_eoh()) {
+                    self.position += 1;
+                }
+                let excess_data = String::from_utf8_lossy(&self.data[char_end..self.position]).to_string();
+                return Ok((declared_length, data_start..char_end, excess_data));
+            }
+        }
+
+        // First attempt with declared length as bytes
+        let data_end = std::cmp::min(self.position.saturating_add(declared_length), self.data.len());
+        let data_bytes = &self.data[data_start..data_end];
+        self.position = data_end;
+
+        // Parse excess data to check if reinterpretation is needed
+        let excess_start = self.position;
+        while self.position < self.data.len() {
+            if self.is_at_field() || self.is_at_eor() || self.is_at_eoh() {
+                break;
+            }
+            self.position += 1;
+        }
+
+        let excess_end = self.position;
+        let excess_data = String::from_utf8_lossy(&self.data[excess_start..excess_end]).to_string();
+
+        // Check if we need to reinterpret the field count
+        if self.should_reinterpret_field_count(data_bytes, data_end, &excess_data, count_mode) {
+            // Try character-based counting
+            if let Some((char_end, _)) = self.calculate_character_based_field(data_start, declared_length) {
+                // Reset position for character-based parsing
+                self.position = char_end;
+
+                // Parse new excess data
+                let new_excess_start = self.position;
+                while self.position < self.data.len() {
+                    if self.is_at_field() || self.is_at_eor() || self.is_at_eoh() {
+                        break;
+                    }
+                    self.position += 1;
+                }
+
+                let new_excess_data = String::from_utf8_lossy(&self.data[new_excess_start..self.position]).to_string();
+
+                // If the new interpretation produces cleaner excess data, use it
+                if self.is_excess_data_cleaner(&new_excess_data, &excess_data) {
+                    self.length_corrected(name, offset, declared_length, data_start..char_end, LengthStrategy::Characters);
+                    return Ok((declared_length, data_start..char_end, new_excess_data));
+                }
+            }
+
+            // Revert to original interpretation
+            self.position = excess_end;
+        } else if let Some(char_end) = self
+            .tag_inside_character_count(data_bytes, data_start, excess_end, declared_length)
+            .filter(|_| count_mode == FieldCountMode::Auto)
+        {
+            // Data such as `dipole <10m up` may hold something that looks like
+            // a tag right where the length counted in bytes ends
+            let tag = self.tag_text(excess_end);
+            if self.breaks_following_tag(excess_end, char_end) {
+                self.warnings.push(format!(
+                    "{} at byte {}: kept {} as part of the value, since the declared length counts characters",
+                    name.to_uppercase(), self.input_offset + offset, tag
+                ));
+                self.position = char_end;
+                let new_excess_start = self.position;
+                while self.position < self.data.len() && !(self.is_at_field() || self.is_at_eor() || self.is_at_eoh()) {
+                    self.position += 1;
+                }
+                let new_excess_data = String::from_utf8_lossy(&self.data[new_excess_start..self.position]).to_string();
+                self.length_corrected(name, offset, declared_length, data_start..char_end, LengthStrategy::Characters);
+                return Ok((declared_length, data_start..char_end, new_excess_data));
+            }
+
+            self.warnings.push(format!(
+                "{} at byte {}: read {} as a tag, since the declared length counts bytes; counted in characters it would be part of the value",
+                name.to_uppercase(), self.input_offset + offset, tag
+            ));
+        }
+
+        if let Some(result) = self.read_to_next_tag(name, offset, declared_length, data_start..data_end, &excess_data, count_mode) {
+            return Ok(result);
+        }
+
+        Ok((declared_length, data_start..data_end, excess_data))
+    }
+
+    /// The last resort for a declared length that is simply wrong, as in
+    /// hand-edited logs: when the value declared in bytes runs into the next
+    /// tag and leaves more than whitespace after it, or stops short of text
+    /// that runs right up to the next tag (`<name:4>Robert<eor>`), the value
+    /// ends at that tag instead. Text set off from a value of the right
+    /// length, such as comments between fields, stays excess data.
+    fn read_to_next_tag(
+        &mut self,
+        name: &str,
+        offset: usize,
+        declared_length: usize,
+        declared: Range<usize>,
+        excess_data: &str,
+        count_mode: FieldCountMode,
+    ) -> Option<(usize, Range<usize>, String)> {
+        if !self.lookahead || count_mode != FieldCountMode::Auto || excess_data.trim().is_empty() {
+            return None;
+        }
+
+        let data_start = declared.start;
+        let mut probe = AdifParser { position: data_start, ..AdifParser::new(self.data) };
+        while probe.position < declared.end && !(probe.is_at_field() || probe.is_at_eor() || probe.is_at_eoh()) {
+            probe.position += 1;
+        }
+        let (tag_start, data_end, reason) = if probe.position < declared.end {
+            let tag_start = probe.position;
+            (tag_start, data_start + self.data[data_start..tag_start].trim_ascii_end().len(), "runs past it")
+        } else if self.continues_value(declared.end, self.position) {
+            (self.position, self.position, "ends before it")
+        } else {
+            return None;
+        };
+
+        let length = String::from_utf8_lossy(&self.data[data_start..data_end]).chars().count();
+        self.warnings.push(format!(
+            "{} at byte {}: read the value up to the next tag, as {} characters, since the declared length of {} {}",
+            name.to_uppercase(),
+            self.input_offset + offset,
+            length,
+            declared_length,
+            reason
+        ));
+
+        self.position = tag_start;
+        self.length_corrected(name, offset, declared_length, data_start..data_end, LengthStrategy::NextTag);
+        let excess_data = String::from_utf8_lossy(&self.data[data_end..tag_start]).to_string();
+        Some((length, data_start..data_end, excess_data))
+    }
+
+    /// Whether the excess data from `start` to `end` looks like the rest of
+    /// the value before it: text that follows the value without a break and
+    /// runs right up to the next field or `<eor>`.
+    fn continues_value(&self, start: usize, end: usize) -> bool {
+        let excess = &self.data[start..end];
+        let at_tag = AdifParser { position: end, ..AdifParser::new(self.data) };
+        match (excess.first(), excess.last()) {
+            (Some(first), Some(last)) => {
+                *first != b'<' && !first.is_ascii_whitespace() && !last.is_ascii_whitespace() && (at_tag.is_at_field() || at_tag.is_at_eor())
+            }
+            _ => false,
+        }
+    }
+
+    fn length_corrected(&mut self, name: &str, offset: usize, declared: usize, data: Range<usize>, strategy: LengthStrategy) {
+        let correction = LengthCorrection {
+            record: self.record,
+            field: name.to_string(),
+            offset,
+            declared,
+            used: data.len(),
+            strategy,
+        };
+        #[cfg(feature = "tracing")]
+        tracing::debug!(%correction, "length corrected");
+        self.length_corrections.push(correction);
+    }
+
+    /// Where a UTF-8 value would end if `declared_length` counted characters,
+    /// when reading it in bytes stopped only whitespace short of something
+    /// tag-like at `tag_start` that the character count would include, and
+    /// the character count also ends just before a tag (or the end of the data).
+    fn tag_inside_character_count(&self, data_bytes: &[u8], data_start: usize, tag_start: usize, declared_length: usize) -> Option<usize> {
+        if tag_start >= self.data.len() || !self.has_utf8_sequences_in_bytes(data_bytes) {
+            return None;
+        }
+
+        let (char_end, _) = self.calculate_character_based_field(data_start, declared_length)?;
+        if char_end <= tag_start {
+            return None;
+        }
+
+        let mut after = char_end;
+        while after < self.data.len() && self.data[after].is_ascii_whitespace() {
+            after += 1;
+        }
+        let at_tag = after == self.data.len() || {
+            let probe = AdifParser { position: after, ..AdifParser::new(self.data) };
+            probe.is_at_field() || probe.is_at_eor() || probe.is_at_eoh()
+        };
+        at_tag.then_some(char_end)
+    }
+
+    /// Whether reading the tag at `tag_start` as a field would run its data
+    /// past `char_end`, into the tag that ends the value counted in characters.
+    fn breaks_following_tag(&self, tag_start: usize, char_end: usize) -> bool {
+        let mut probe = AdifParser { position: tag_start, lookahead: false, ..AdifParser::new(self.data) };
+        if !probe.is_at_field() {
+            // <eor> or <eoh>: everything after it up to `char_end` would be stray text
+            return self.data[tag_start..char_end].iter().skip(5).any(|b| !b.is_ascii_whitespace());
+        }
+
+        match probe.parse_field() {
+            Ok(field) => field.offset + field.original_bytes.len() + self.tag_text(tag_start).len() > char_end,
+            Err(_) => true,
+        }
+    }
+
+    /// The tag starting at `start`, as written.
+    fn tag_text(&self, start: usize) -> String {
+        let end = self.data[start..].iter().position(|&b| b == b'>').map_or(self.data.len(), |end| start + end + 1);
+        String::from_utf8_lossy(&self.data[start..end]).to_string()
+    }
+
+    fn calculate_character_based_field(&self, start_pos: usize, n: usize) -> Option<(usize, usize)> {
+        let mut pos = start_pos;
+        let mut char_count = 0;
+
+        while pos < self.data.len() && char_count < n {
+            // Decode the next character on its own, so counting stays linear
+            let width = match self.data[pos] {
+                0xC0..=0xDF => 2,
+                0xE0..=0xEF => 3,
+                0xF0..=0xF7 => 4,
+                _ => 1,
+            };
+            match self.data.get(pos..pos + width).map(std::str::from_utf8) {
+                Some(Ok(_)) => pos += width,
+                // Not valid UTF-8, treat as single byte
+                _ => pos += 1,
+            }
+            char_count += 1;
+        }
+
+        if char_count == n {
+            Some((pos, pos - start_pos))
+        } else {
+            None
+        }
+    }
+
+    fn should_reinterpret_field_count(
+        &self,
+        data_bytes: &[u8],
+        data_end: usize,
+        excess_data: &str,
+        count_mode: FieldCountMode
+    ) -> bool {
+        // Only reinterpret if the lengths' meaning is unknown and excess data
+        // contains non-whitespace
+        if count_mode != FieldCountMode::Auto || excess_data.trim().is_empty() {
+            return false;
+        }
+
+        // Check if data contains UTF-8 sequences, or ends in the middle of one
+        self.has_utf8_sequences_in_bytes(data_bytes) || self.splits_utf8_sequence(data_end)
+    }
+
+    /// Whether `end` falls inside a well-formed UTF-8 sequence, where a
+    /// length counting bytes would not end.
+    fn splits_utf8_sequence(&self, end: usize) -> bool {
+        (end.saturating_sub(3)..end).any(|start| {
+            let width = match self.data[start] {
+                0xC0..=0xDF => 2,
+                0xE0..=0xEF => 3,
+                0xF0..=0xF7 => 4,
+                _ => return false,
+            };
+            start + width > end && start + width <= self.data.len() && std::str::from_utf8(&self.data[start..start + width]).is_ok()
+        })
+    }
+
+    fn has_utf8_sequences_in_bytes(&self, data: &[u8]) -> bool {
+        let mut i = 0;
+        while i < data.len() {
+            if data[i] > 127 {
+                // Check for valid UTF-8 sequence
+                let mut count = 0;
+                if data[i] & 0b11100000 == 0b11000000 {
+                    count = 1;
+                } else if data[i] & 0b11110000 == 0b11100000 {
+                    count = 2;
+                } else if data[i] & 0b11111000 == 0b11110000 {
+                    count = 3;
+                }
+
+                if count > 0 && i + count < data.len() {
+                    let mut valid = true;
+                    for j in 1..=count {
+                        if data[i + j] & 0b11000000 != 0b10000000 {
+                            valid = false;
+                            break;
+                        }
+                    }
+                    if valid {
+                        return true;
+                    }
+                }
+            }
+            i += 1;
+        }
+        false
+    }
+
+    fn is_excess_data_cleaner(&self, new_excess: &str, old_excess: &str) -> bool {
+        let new_non_whitespace = new_excess.chars().filter(|c| !c.is_whitespace()).count();
+        let old_non_whitespace = old_excess.chars().filter(|c| !c.is_whitespace()).count();
+
+        new_non_whitespace < old_non_whitespace
+    }
+
+    fn parse_excess_until_record(&mut self) -> Result<String, AdifError> {
+        let start = self.position;
+
+        while self.position < self.data.len() {
+            if self.is_at_field() {
+                break;
+            }
+            self.position += 1;
+        }
+
+        Ok(String::from_utf8_lossy(&self.data[start..self.position]).to_string())
+    }
+
+    fn peek_byte(&self) -> Option<u8> {
+        if self.position < self.data.len() {
+            Some(self.data[self.position])
+        } else {
+            None
+        }
+    }
+
+    fn is_at_eoh(&self) -> bool {
+        self.check_tag(b"eoh")
+    }
+
+    fn is_at_eor(&self) -> bool {
+        self.check_tag(b"eor")
+    }
+
+    fn is_at_field(&self) -> bool {
+        if self.peek_byte() != Some(b'<') {
+            return false;
+        }
+
+        // Look ahead to see if this looks like a field
+        let mut pos = self.position + 1;
+
+        // Skip field name (alphanumeric + underscore)
+        while pos < self.data.len() {
+            let byte = self.data[pos];
+            if byte == b':' {
+                break;
+            }
+            if !byte.is_ascii_alphanumeric() && byte != b'_' {
+                return false;
+            }
+            pos += 1;
+        }
+
+        if pos >= self.data.len() || self.data[pos] != b':' {
+            return false;
+        }
+
+        pos += 1;
+
+        // Check for length (digits)
+        let length_start = pos;
+        while pos < self.data.len() && self.data[pos].is_ascii_digit() {
+            pos += 1;
+        }
+
+        if pos == length_start {
+            return false;
+        }
+
+        // Optional type
+        if pos < self.data.len() && self.data[pos] == b':' {
+            pos += 1;
+            while pos < self.data.len() && self.data[pos] != b'>' {
+                let byte = self.data[pos];
+                if !byte.is_ascii_alphanumeric() && byte != b'_' {
+                    return false;
+                }
+                pos += 1;
+            }
+        }
+
+        pos < self.data.len() && self.data[pos] == b'>'
+    }
+
+    fn check_tag(&self, tag: &[u8]) -> bool {
+        if self.position + tag.len() + 2 > self.data.len() {
+            return false;
+        }
+
+        if self.data[self.position] != b'<' {
+            return false;
+        }
+
+        let tag_slice = &self.data[self.position + 1..self.position + 1 + tag.len()];
+        let tag_match = tag_slice.eq_ignore_ascii_case(tag);
+
+        if !tag_match {
+            return false;
+        }
+
+        self.data[self.position + 1 + tag.len()] == b'>'
+    }
+
+    fn skip_eoh(&mut self) -> String {
+        self.skip_tag(b"eoh")
+    }
+
+    fn skip_eor(&mut self) -> String {
+        self.skip_tag(b"eor")
+    }
+
+    /// Skips the tag if it is at the current position, returning it as written.
+    fn skip_tag(&mut self, tag: &[u8]) -> String {
+        if !self.check_tag(tag) {
+            return String::new();
+        }
+
+        let start = self.position;
+        self.position += tag.len() + 2; // '<' + tag + '>'
+        String::from_utf8_lossy(&self.data[start..self.position]).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timestamp() {
+        assert_eq!(timestamp(0), "19700101 000000");
+        assert_eq!(timestamp(951_782_400 + 3_723), "20000229 010203");
+        assert_eq!(timestamp(1_735_689_599), "20241231 235959");
+    }
+
+    #[test]
+    fn test_parse_simple_field() {
+        let data = b"<call:5>K1MIX";
+        let mut parser = AdifParser::new(data);
+        let field = parser.parse_field().unwrap();
+
+        assert_eq!(field.name, "call");
+        assert_eq!(field.length, 5);
+        assert_eq!(field.data, "K1MIX");
+        assert!(field.field_type.is_none());
+    }
+
+    #[test]
+    fn test_fields_share_input_buffer() {
+        let adif = AdifFile::parse_owned(b"<call:4>W1AW<name:3>Bob<eor>".to_vec()).unwrap();
+        let fields = &adif.records[0].fields;
+
+        assert_eq!(fields[0].original_bytes, b"W1AW".as_slice());
+        assert_eq!(fields[1].original_bytes, b"Bob".as_slice());
+        assert_eq!(fields[1].original_bytes.as_ptr() as usize - fields[0].original_bytes.as_ptr() as usize, 12);
+    }
+
+    #[test]
+    fn test_parse_field_with_type() {
+        let data = b"<freq:5:N>7.200";
+        let mut parser = AdifParser::new(data);
+        let field = parser.parse_field().unwrap();
+
+        assert_eq!(field.name, "freq");
+        assert_eq!(field.length, 5);
+        assert_eq!(field.data, "7.200");
+        assert_eq!(field.field_type, Some("N".to_string()));
+    }
+
+    #[test]
+    fn test_parse_simple_record() {
+        let data = b"<call:5>K1MIX<band:3>40m<eor>";
+        let mut parser = AdifParser::new(data);
+        let record = parser.parse_record().unwrap();
+
+        assert_eq!(record.fields.len(), 2);
+        assert_eq!(record.fields[0].name, "call");
+        assert_eq!(record.fields[0].data, "K1MIX");
+        assert_eq!(record.fields[1].name, "band");
+        assert_eq!(record.fields[1].data, "40m");
+    }
+
+    /// Reader handing out a few bytes per call, to exercise buffer boundaries
+    struct TrickleReader<'a> {
+        data: &'a [u8],
+        step: usize,
+    }
+
+    impl Read for TrickleReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.step.min(buf.len()).min(self.data.len());
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_streaming_parser_matches_full_parse() {
+        let data = "Log export\r\n<adif_ver:5>3.1.4 <encoding:5>UTF-8\r\n<eoh>\r\n\
+            <call:5>K1MIX <name:4>Jörg junk <eor>\r\n\
+            <CALL:4>W1AW<qth:12>Kent<band:3>20m<comment:7>Tnx 73!\r\n<EOR>\r\ntrailer"
+            .as_bytes();
+        let full = AdifFile::parse(data).unwrap();
+        assert_eq!(full.warnings.len(), 1);
+
+        for step in [1, 2, 7, 4096] {
+            let mut parser = StreamingParser::new(TrickleReader { data, step }).unwrap();
+            assert_eq!(parser.header().preamble, full.preamble);
+            assert_eq!(parser.header().header_fields.len(), 2);
+            assert_eq!(parser.header().encoding.as_deref(), Some("UTF-8"));
+
+            let records: Vec<Record> = parser.by_ref().map(|r| r.unwrap()).collect();
+            assert_eq!(records.len(), full.records.len());
+            for (streamed, parsed) in records.iter().zip(&full.records) {
+                assert_eq!(streamed.offset, parsed.offset);
+                assert_eq!(streamed.excess_data, parsed.excess_data);
+                let fields = |r: &Record| r.fields.iter().map(|f| (f.data.clone(), f.excess_data.clone(), f.offset)).collect::<Vec<_>>();
+                assert_eq!(fields(streamed), fields(parsed));
+            }
+            assert_eq!(parser.take_warnings(), full.warnings);
+        }
+    }
+
+    #[test]
+    fn test_leading_junk_is_discarded_with_warning() {
+        let adif = AdifFile::parse(b"\xEF\xBB\xBF\0\0<html><body>\r\n<pre>Exported log\r\n<adif_ver:5>3.1.4<eoh>\r\n<call:4>W1AW<eor>").unwrap();
+
+        assert_eq!(adif.preamble, "Exported log\r\n");
+        assert_eq!(adif.header_fields.len(), 1);
+        assert_eq!(adif.records.len(), 1);
+        assert_eq!(adif.warnings, vec!["Discarded 24 bytes of leading junk (byte order mark, NUL bytes, HTML markup)"]);
+
+        let adif = AdifFile::parse(b"  My log\r\n<eoh>\r\n<call:4>W1AW<eor>").unwrap();
+        assert!(adif.warnings.is_empty());
+        assert_eq!(adif.preamble, "  My log\r\n");
+    }
+
+    #[test]
+    fn test_set_header() {
+        let mut adif = AdifFile::parse(b"Log\n<ADIF_VER:5>3.0.4\n<programid:4>Test\n<eoh>\n").unwrap();
+        adif.set_header("adif_ver", "3.1.4");
+        adif.set_header("created_timestamp", "20240101 120000");
+
+        let fields: Vec<(&str, &str, &str)> = adif.header_fields.iter().map(|f| (f.name.as_str(), f.data.as_str(), f.excess_data.as_str())).collect();
+        assert_eq!(fields, vec![("ADIF_VER", "3.1.4", "\n"), ("programid", "Test", "\n"), ("created_timestamp", "20240101 120000", "\n")]);
+    }
+
+    #[test]
+    fn test_extract_records() {
+        let data = b"Log\r\n<eoh>\r\n<call:4>DL1X<eor>\r\n<call:4>DL2X<name:3>J\xfcr<eor> stray\r\n<call:4>DL3X<eor>";
+        let adif = AdifFile::parse(data).unwrap();
+
+        assert_eq!(adif.extract_records(data, &[1, 7]), b"Log\r\n<eoh>\r\n<call:4>DL2X<name:3>J\xfcr<eor> stray\r\n");
+        assert_eq!(adif.extract_records(data, &[2]), b"Log\r\n<eoh>\r\n<call:4>DL3X<eor>");
+    }
+
+    #[test]
+    fn test_add_header() {
+        let mut adif = AdifFile::parse(b"<call:4>DL1X<eor>\n").unwrap();
+        assert!(adif.add_header("Converted by TransADIF\r\n\r\n", 1_704_110_400));
+
+        let fields: Vec<(&str, &str)> = adif.header_fields.iter().map(|f| (f.name.as_str(), f.data.as_str())).collect();
+        assert_eq!(fields, vec![("adif_ver", ADIF_VERSION), ("programid", "TransADIF"), ("created_timestamp", "20240101 120000")]);
+        assert_eq!(adif.preamble, "Converted by TransADIF\r\n\r\n");
+
+        // Files with a header keep it
+        let mut adif = AdifFile::parse(b"Log\n<programid:4>Test\n<eoh>\n").unwrap();
+        assert!(!adif.add_header("Converted by TransADIF\r\n\r\n", 1_704_110_400));
+        assert_eq!(adif.header_fields.len(), 1);
+    }
+
+    #[test]
+    fn test_byte_order_marks() {
+        let adif = AdifFile::parse(b"\xEF\xBB\xBFLog\r\n<eoh>\r\n<name:4>J\xc3\xbcrg<eor>").unwrap();
+        assert_eq!(adif.preamble, "Log\r\n");
+        assert_eq!(adif.encoding.as_deref(), Some("UTF-8"));
+
+        let adif = AdifFile::parse(b"\xEF\xBB\xBFLog\r\n<encoding:10>ISO-8859-1<eoh>\r\n").unwrap();
+        assert_eq!(adif.encoding.as_deref(), Some("ISO-8859-1"));
+
+    }
+
+    #[test]
+    fn test_utf16_input() {
+        let text = "Log\r\n<encoding:6>UTF-16<eoh>\r\n<call:5>UA1AA<name:6>Сергей<eor>\r\n";
+        let le: Vec<u8> = text.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        let be: Vec<u8> = text.encode_utf16().flat_map(u16::to_be_bytes).collect();
+
+        for data in [[b"\xFF\xFE".as_slice(), &le].concat(), le, be] {
+            let adif = AdifFile::parse(&data).unwrap();
+            assert_eq!(adif.encoding.as_deref(), Some("UTF-8"));
+            assert_eq!(adif.records[0].fields[1].original_bytes, "Сергей".as_bytes());
+
+            let parser = StreamingParser::new(data.as_slice()).unwrap();
+            assert_eq!(parser.header().encoding.as_deref(), Some("UTF-8"));
+            let records: Vec<Record> = parser.map(Result::unwrap).collect();
+            assert_eq!(records[0].fields[1].original_bytes, "Сергей".as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_angle_brackets_in_data() {
+        let adif = AdifFile::parse(b"<comment:14>dipole <10m up<eor>").unwrap();
+        assert_eq!(adif.records[0].get("comment"), Some("dipole <10m up"));
+        assert!(adif.warnings.is_empty());
+
+        // Counted in bytes, the value would end before `<a:1>`, whose data
+        // would then swallow the `<` of `<eor>`
+        let adif = AdifFile::parse("<comment:12>ääääää <a:1><eor>".as_bytes()).unwrap();
+        assert_eq!(adif.records[0].fields.len(), 1);
+        assert_eq!(adif.records[0].fields[0].original_bytes, "ääääää <a:1>".as_bytes());
+        assert_eq!(adif.warnings, vec!["COMMENT at byte 0: kept <a:1> as part of the value, since the declared length counts characters"]);
+
+        // Both readings fit; the byte count wins
+        let adif = AdifFile::parse("<comment:10>äääää<a:0><eor>".as_bytes()).unwrap();
+        assert_eq!(adif.records[0].fields[0].original_bytes, "äääää".as_bytes());
+        assert_eq!(adif.records[0].get("a"), Some(""));
+        assert_eq!(adif.warnings.len(), 1);
+        assert!(adif.warnings[0].starts_with("COMMENT at byte 0: read <a:0> as a tag"));
+    }
+
+    #[test]
+    fn test_record_fingerprint_ignores_cosmetic_differences() {
+        let a = AdifFile::parse(b"<call:5>k1mix<qso_date:8>20240101<time_on:6>120000<band:3>20m<mode:3>SSB<name:3>Bob<eor>").unwrap();
+        let b = AdifFile::parse(b"<CALL:6>K1MIX <BAND:3>20M<MODE:3>ssb<QSO_DATE:8>20240101<TIME_ON:4>1200<eor>").unwrap();
+        let c = AdifFile::parse(b"<call:5>K1MIX<qso_date:8>20240101<time_on:4>1201<band:3>20m<mode:3>SSB<eor>").unwrap();
+
+        assert_eq!(a.records[0].fingerprint(), b.records[0].fingerprint());
+        assert_ne!(a.records[0].fingerprint(), c.records[0].fingerprint());
+        assert_eq!(a.records[0].fingerprint().len(), 16);
+    }
+
+    #[test]
+    fn test_record_set_replaces_or_appends() {
+        let mut adif = AdifFile::parse(b"<call:5>K1MIX\r\n<band:3>20m\r\n<eor>").unwrap();
+        let record = &mut adif.records[0];
+
+        record.set("BAND", "40m");
+        record.set("mode", "CW");
+
+        assert_eq!(record.get("band"), Some("40m"));
+        assert_eq!(record.fields[1].name, "band");
+        assert_eq!(record.fields[2].name, "mode");
+        assert_eq!(record.fields[2].excess_data, "\r\n");
+    }
+
+    #[test]
+    fn test_parse_resuming_skips_earlier_records() {
+        let data = b"Log<eoh>\r\n<call:4>K1AA<eor>\r\n<call:4>K1BB<eor>\r\n";
+        let full = AdifFile::parse(data).unwrap();
+        assert_eq!(full.records[1].offset, 29);
+
+        let resumed = AdifFile::parse_resuming(data, full.records[1].offset).unwrap();
+        assert_eq!(resumed.preamble, "Log");
+        assert_eq!(resumed.records.len(), 1);
+        assert_eq!(resumed.records[0].fields[0].data, "K1BB");
+        assert_eq!(resumed.records[0].offset, 29);
+    }
+
+    #[test]
+    fn test_parse_errors_are_positioned_and_recoverable() {
+        let data = b"Log<eoh>\r\n<call:4>K1AA<eor>\r\n<call:4>K1BB<freq:99999999999999999999>14.074<mode:3>FT8<eor>\r\n";
+
+        let error = AdifFile::parse(data).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid field format: QSO 2: FREQ at byte 41: Invalid field length");
+
+        let adif = AdifFile::parse_recovering(data.to_vec()).unwrap();
+        assert_eq!(adif.errors, vec![ParseIssue {
+            offset: 41,
+            record: Some(1),
+            field: Some("freq".to_string()),
+            message: "Invalid field length".to_string(),
+        }]);
+        let record = &adif.records[1];
+        assert_eq!(record.get("call"), Some("K1BB"));
+        assert_eq!(record.get("mode"), Some("FT8"));
+        assert_eq!(record.get("freq"), None);
+
+        let mut parser = StreamingParser::new(&data[..]).unwrap();
+        parser.set_recover(true);
+        assert_eq!(parser.by_ref().count(), 2);
+        assert_eq!(parser.take_errors(), adif.errors);
+    }
+
+    #[test]
+    fn test_declared_count_mode_is_honored() {
+        let records = "<comment:3>éa <call:1>X<eor>\n<comment:3>é a<eor>\n";
+        let read = |header: &str| {
+            let data = format!("{}<eoh>\n{}", header, records);
+            let adif = AdifFile::parse(data.as_bytes()).unwrap();
+            let streamed: Vec<Record> = StreamingParser::new(data.as_bytes()).unwrap().map(Result::unwrap).collect();
+            assert_eq!(streamed[0].fields[0].original_bytes, adif.records[0].fields[0].original_bytes);
+            let comments = adif.records.iter().map(|record| String::from_utf8(record.fields[0].original_bytes.to_vec()).unwrap()).collect::<Vec<_>>();
+            (adif.count_mode, comments, adif.warnings.len())
+        };
+        let comments = |first: &str, second: &str| vec![first.to_string(), second.to_string()];
+
+        // Guessing, 3 means bytes in the first record and characters in the second
+        assert_eq!(read("Log"), (None, comments("éa", "é a"), 0));
+        assert_eq!(read("Log<app_transadif_count_mode:5>chars"), (Some(FieldCountMode::Characters), comments("éa ", "é a"), 0));
+        // Declared lengths are taken as they are, even where they look wrong
+        assert_eq!(read("Log<app_transadif_count_mode:5>bytes"), (Some(FieldCountMode::Bytes), comments("éa", "é "), 0));
+    }
+
+    #[test]
+    fn test_wrong_lengths_read_up_to_next_tag() {
+        let data = b"<name:6>Robert <qth:12>Kent<band:3>20m<eor>\n<comment:14>dipole <10m up<eor>\n";
+        let adif = AdifFile::parse(data).unwrap();
+
+        let record = &adif.records[0];
+        assert_eq!((record.get("qth"), record.fields[1].length), (Some("Kent"), 4));
+        assert_eq!(record.get("band"), Some("20m"));
+        assert_eq!(adif.warnings, vec![
+            "QTH at byte 15: read the value up to the next tag, as 4 characters, since the declared length of 12 runs past it",
+        ]);
+        assert_eq!(adif.length_corrections, vec![LengthCorrection {
+            record: Some(0),
+            field: "qth".to_string(),
+            offset: 15,
+            declared: 12,
+            used: 4,
+            strategy: LengthStrategy::NextTag,
+        }]);
+        assert_eq!(adif.length_corrections[0].to_string(), "QSO 1: QTH at byte 15: declared 12, read 4 bytes up to the next tag");
+
+        // A correct length keeps what looks like a tag
+        assert_eq!(adif.records[1].get("comment"), Some("dipole <10m up"));
+
+        // Strict parsing takes the length as declared
+        let strict = AdifFile::parse_with(data.to_vec(), 0, ParseOptions { strict: true, ..ParseOptions::default() }).unwrap();
+        assert_eq!(strict.records[0].get("qth"), Some("Kent<band:3>"));
+        assert!(strict.length_corrections.is_empty());
+    }
+
+    #[test]
+    fn test_text_between_fields_stays_excess_data() {
+        for (data, excess) in [
+            (&b"<call:4>W1AW junk <name:3>Bob<eor>"[..], " junk "),
+            (b"<call:4>W1AW\r\n// note\r\n<name:3>Bob<eor>", "\r\n// note\r\n"),
+            (b"<call:4>W1AW<junk<name:3>Bob<eor>", "<junk"),
+            (b"<call:4>W1AW and more <name:3>Bob<eor>", " and more "),
+        ] {
+            let adif = AdifFile::parse(data).unwrap();
+            let record = &adif.records[0];
+            assert_eq!((record.get("call"), record.fields[0].length), (Some("W1AW"), 4));
+            assert_eq!(record.fields[0].excess_data, excess);
+            assert_eq!(record.get("name"), Some("Bob"));
+            assert!(adif.length_corrections.is_empty());
+        }
+
+        // Too short a length, with the rest of the value up against the next
+        // tag, is read up to that tag
+        for data in [&b"<name:4>Robert<eor>"[..], b"<name:4>Robert<call:4>W1AW<eor>"] {
+            let adif = AdifFile::parse(data).unwrap();
+            let name = &adif.records[0].fields[0];
+            assert_eq!((name.data.as_str(), name.length, name.excess_data.as_str()), ("Robert", 6, ""));
+            assert_eq!(adif.length_corrections.len(), 1);
+        }
+        let adif = AdifFile::parse(b"<name:4>Robert <eor>").unwrap();
+        assert_eq!((adif.records[0].get("name"), adif.records[0].fields[0].excess_data.as_str()), (Some("Robe"), "rt "));
+    }
+
+    #[test]
+    fn test_huge_lengths_do_not_overflow() {
+        for data in [&b"<name:18446744073709551615>"[..], b"<call:4>W1AW<name:18446744073709551614:S>Bob<eor>"] {
+            let _ = AdifFile::parse(data);
+            let _ = AdifFile::parse_recovering(data.to_vec());
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,357 @@
+use crate::adif::{AdifFile, Field, Record};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum AdxError {
+    #[error("Malformed XML at byte {0}: {1}")]
+    Malformed(usize, String),
+    #[error("Missing <ADX> root element")]
+    MissingRoot,
+}
+
+/// Reads ADX, the XML flavour of ADIF, into an `AdifFile`. This is the
+/// inverse of `AdxFormatter`: `APP` elements become `APP_<PROGRAMID>_<FIELDNAME>`
+/// fields, header `USERDEF` elements become `USERDEFn` fields and record
+/// `USERDEF` elements fields named after their `FIELDNAME`.
+pub fn parse(data: &[u8]) -> Result<AdifFile, AdxError> {
+    let data = data.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(data);
+    let text = String::from_utf8_lossy(data);
+    let root = Reader { text: &text, position: 0 }.root()?;
+    if !root.name.eq_ignore_ascii_case("ADX") {
+        return Err(AdxError::MissingRoot);
+    }
+
+    let mut adif = AdifFile::new();
+    adif.preamble = "Converted by TransADIF\r\n\r\n".to_string();
+    adif.header_excess_data = "\r\n".to_string();
+    adif.encoding = Some("UTF-8".to_string());
+
+    for section in &root.children {
+        if section.name.eq_ignore_ascii_case("HEADER") {
+            for element in &section.children {
+                let mut field = header_field(element);
+                field.excess_data = "\r\n".to_string();
+                adif.header_fields.push(field);
+            }
+        } else if section.name.eq_ignore_ascii_case("RECORDS") {
+            for record in section.children.iter().filter(|element| element.name.eq_ignore_ascii_case("RECORD")) {
+                let mut fields: Vec<Field> = record.children.iter().map(record_field).collect();
+                for field in &mut fields {
+                    field.excess_data = " ".to_string();
+                }
+                if let Some(last) = fields.last_mut() {
+                    last.excess_data.clear();
+                }
+
+                adif.records.push(Record {
+                    fields,
+                    excess_data: "\r\n".to_string(),
+                    offset: record.offset,
+                    end_tag: String::new(),
+                });
+            }
+        }
+    }
+
+    Ok(adif)
+}
+
+fn header_field(element: &Element) -> Field {
+    if !element.name.eq_ignore_ascii_case("USERDEF") {
+        return element.field(&element.name);
+    }
+
+    // USERDEFn carries the field name, then its enumeration or range
+    let mut value = element.text.clone();
+    if let Some(restriction) = element.attribute("ENUM").or_else(|| element.attribute("RANGE")) {
+        value = format!("{},{}", value, restriction);
+    }
+
+    let mut field = Field::new(&format!("USERDEF{}", element.attribute("FIELDID").unwrap_or_default()), &value);
+    field.field_type = element.attribute("TYPE").map(str::to_string);
+    field.offset = element.offset;
+    field
+}
+
+fn record_field(element: &Element) -> Field {
+    let name = if element.name.eq_ignore_ascii_case("APP") {
+        format!(
+            "APP_{}_{}",
+            element.attribute("PROGRAMID").unwrap_or_default(),
+            element.attribute("FIELDNAME").unwrap_or_default()
+        )
+    } else if element.name.eq_ignore_ascii_case("USERDEF") {
+        element.attribute("FIELDNAME").unwrap_or_default().to_string()
+    } else {
+        element.name.clone()
+    };
+
+    element.field(&name)
+}
+
+/// An XML element with its attributes, text content and child elements
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    text: String,
+    children: Vec<Element>,
+    /// Byte offset of the element's `<` in the input
+    offset: usize,
+}
+
+impl Element {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(attribute, _)| attribute.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn field(&self, name: &str) -> Field {
+        let mut field = Field::new(name, &self.text);
+        field.field_type = self.attribute("TYPE").map(str::to_string);
+        field.offset = self.offset;
+        field
+    }
+}
+
+/// Just enough of an XML parser for ADX: elements, attributes, text, CDATA,
+/// and the predefined and numeric entities. Comments, processing instructions
+/// and the doctype are skipped.
+struct Reader<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn rest(&self) -> &'a str {
+        &self.text[self.position..]
+    }
+
+    fn error(&self, message: &str) -> AdxError {
+        AdxError::Malformed(self.position, message.to_string())
+    }
+
+    fn root(&mut self) -> Result<Element, AdxError> {
+        loop {
+            self.skip_whitespace();
+            let rest = self.rest();
+
+            if rest.is_empty() {
+                return Err(AdxError::MissingRoot);
+            } else if rest.starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if rest.starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if rest.starts_with("<!") {
+                self.skip_past(">")?;
+            } else if rest.starts_with('<') {
+                return self.element();
+            } else {
+                return Err(self.error("text before the root element"));
+            }
+        }
+    }
+
+    fn skip_past(&mut self, end: &str) -> Result<&'a str, AdxError> {
+        let rest = self.rest();
+        let index = rest.find(end).ok_or_else(|| self.error(&format!("missing {}", end)))?;
+        self.position += index + end.len();
+        Ok(&rest[..index])
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.position = self.text.len() - self.rest().trim_start().len();
+    }
+
+    fn name(&mut self) -> String {
+        let length = self.rest()
+            .find(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | ':')))
+            .unwrap_or(self.rest().len());
+        let name = self.rest()[..length].to_string();
+        self.position += length;
+        name
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), AdxError> {
+        if self.rest().starts_with(expected) {
+            self.position += expected.len_utf8();
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", expected)))
+        }
+    }
+
+    fn element(&mut self) -> Result<Element, AdxError> {
+        let offset = self.position;
+        self.expect('<')?;
+        let name = self.name();
+        if name.is_empty() {
+            return Err(self.error("missing element name"));
+        }
+
+        let mut element = Element { name, attributes: Vec::new(), text: String::new(), children: Vec::new(), offset };
+
+        loop {
+            self.skip_whitespace();
+            if self.rest().starts_with("/>") {
+                self.position += 2;
+                return Ok(element);
+            }
+            if self.rest().starts_with('>') {
+                self.position += 1;
+                break;
+            }
+
+            let attribute = self.name();
+            if attribute.is_empty() {
+                return Err(self.error("expected an attribute or '>'"));
+            }
+            self.skip_whitespace();
+            self.expect('=')?;
+            self.skip_whitespace();
+            let quote = if self.rest().starts_with('\'') { "'" } else { "\"" };
+            self.expect(quote.chars().next().unwrap())?;
+            let value = self.skip_past(quote)?;
+            element.attributes.push((attribute, unescape(value)));
+        }
+
+        loop {
+            let text = self.rest()
+                .find('<')
+                .map(|index| &self.rest()[..index])
+                .ok_or_else(|| self.error(&format!("unclosed <{}>", element.name)))?;
+            element.text.push_str(&unescape(text));
+            self.position += text.len();
+
+            let rest = self.rest();
+            if rest.starts_with("</") {
+                self.position += 2;
+                let closing = self.name();
+                self.skip_whitespace();
+                self.expect('>')?;
+                if closing != element.name {
+                    return Err(self.error(&format!("<{}> closed by </{}>", element.name, closing)));
+                }
+                return Ok(element);
+            } else if rest.starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if rest.starts_with("<![CDATA[") {
+                self.position += "<![CDATA[".len();
+                element.text.push_str(self.skip_past("]]>")?);
+            } else if rest.starts_with("<?") {
+                self.skip_past("?>")?;
+            } else {
+                element.children.push(self.element()?);
+            }
+        }
+    }
+}
+
+/// Resolves the predefined XML entities and character references.
+/// Anything unrecognized is kept as written.
+fn unescape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let Some(end) = rest.find(';') else { break };
+        let entity = &rest[1..end];
+        let resolved = match entity {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(str::parse::<u32>))
+                .and_then(Result::ok)
+                .and_then(char::from_u32),
+        };
+
+        match resolved {
+            Some(c) => {
+                result.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                result.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::AdifEncoding;
+    use crate::output::{AdxFormatter, OutputError, OutputFormatter};
+
+    #[test]
+    fn test_parse_adx() {
+        let adif = parse(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\r\n\
+             <!-- exported -->\r\n\
+             <ADX>\r\n  <HEADER>\r\n    <ADIF_VER>3.1.4</ADIF_VER>\r\n\
+             <USERDEF FIELDID=\"1\" TYPE=\"N\" RANGE=\"{0:100}\">EPC</USERDEF>\r\n  </HEADER>\r\n\
+             <RECORDS>\r\n    <RECORD>\r\n      <CALL>OH2AB</CALL>\r\n      <NAME>J&#228;rvi &amp; Co</NAME>\r\n\
+             <APP PROGRAMID=\"LOG4OM\" FIELDNAME=\"QSL\" TYPE=\"S\"><![CDATA[<ok>]]></APP>\r\n\
+             <USERDEF FIELDNAME=\"EPC\">42</USERDEF>\r\n      <COMMENT/>\r\n    </RECORD>\r\n  </RECORDS>\r\n</ADX>\r\n"
+                .as_bytes(),
+        )
+        .unwrap();
+
+        let fields = |fields: &[Field]| fields.iter().map(|f| (f.name.clone(), f.data.clone())).collect::<Vec<_>>();
+        let pair = |name: &str, value: &str| (name.to_string(), value.to_string());
+
+        assert_eq!(fields(&adif.header_fields), vec![pair("ADIF_VER", "3.1.4"), pair("USERDEF1", "EPC,{0:100}")]);
+        assert_eq!(adif.header_fields[1].field_type.as_deref(), Some("N"));
+        assert_eq!(
+            fields(&adif.records[0].fields),
+            vec![pair("CALL", "OH2AB"), pair("NAME", "Järvi & Co"), pair("APP_LOG4OM_QSL", "<ok>"), pair("EPC", "42"), pair("COMMENT", "")]
+        );
+    }
+
+    #[test]
+    fn test_round_trip_through_adx_formatter() {
+        let original = AdifFile::parse("Log\r\n<adif_ver:5>3.1.4<eoh>\r\n<call:4>W1AW<name:4>Zoë<app_n1mm_id:2>17<eor>\r\n".as_bytes()).unwrap();
+        let formatter = AdxFormatter::new(OutputFormatter::new(None, AdifEncoding::Utf8, false, Some('?'), false, false));
+        let mut adx = Vec::new();
+        formatter.format_adx(&original, &mut adx).unwrap();
+
+        let parsed = parse(&adx).unwrap();
+        let record = &parsed.records[0];
+        assert_eq!(record.get("call"), Some("W1AW"));
+        assert_eq!(record.get("name"), Some("Zoë"));
+        assert_eq!(record.get("app_n1mm_id"), Some("17"));
+    }
+
+    #[test]
+    fn test_adx_names_are_escaped() {
+        let mut original = AdifFile::parse(b"<eoh><app_n1mm_id:2>17<epc:2>42<eor>").unwrap();
+        original.records[0].fields[0].name = "APP_A&\"<B_ID".to_string();
+        original.records[0].fields[1].name = "E&\"<C".to_string();
+        original.header_fields.push(Field::new("USERDEF1", "E&\"<C"));
+        let formatter = AdxFormatter::new(OutputFormatter::new(None, AdifEncoding::Utf8, false, Some('?'), false, false));
+        let mut adx = Vec::new();
+        formatter.format_adx(&original, &mut adx).unwrap();
+
+        let parsed = parse(&adx).unwrap();
+        let names: Vec<&str> = parsed.records[0].fields.iter().map(|field| field.name.as_str()).collect();
+        assert_eq!(names, vec!["APP_A&\"<B_ID", "E&\"<C"]);
+
+        // Other fields become elements, which cannot have such names
+        original.header_fields.clear();
+        let error = formatter.format_adx(&original, &mut Vec::new()).unwrap_err();
+        assert!(matches!(error, OutputError::InvalidElementName(name) if name == "E&\"<C"));
+    }
+}
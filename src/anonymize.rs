@@ -0,0 +1,100 @@
+use crate::adif::Field;
+
+/// Fields that identify or locate a person rather than describe the QSO
+pub const PERSONAL_FIELDS: &[&str] = &[
+    "name",
+    "name_intl",
+    "qth",
+    "qth_intl",
+    "address",
+    "address_intl",
+    "email",
+    "web",
+    "lat",
+    "lon",
+    "comment",
+    "comment_intl",
+    "notes",
+    "notes_intl",
+    "qslmsg",
+    "qslmsg_intl",
+    "qslmsg_rcvd",
+    "contacted_op",
+    "public_key",
+    "my_name",
+    "my_name_intl",
+    "my_street",
+    "my_street_intl",
+    "my_city",
+    "my_city_intl",
+    "my_postal_code",
+    "my_postal_code_intl",
+    "my_lat",
+    "my_lon",
+];
+
+/// What `anonymize` does with personal fields
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Anonymization {
+    /// Remove them
+    Strip,
+    /// Replace each value with a hash of it, so equal values stay equal
+    Hash,
+}
+
+/// Whether `name` is one of `PERSONAL_FIELDS`.
+pub fn is_personal(name: &str) -> bool {
+    PERSONAL_FIELDS.iter().any(|field| field.eq_ignore_ascii_case(name.trim()))
+}
+
+/// Removes the personal fields from `fields`, a record's or the header's,
+/// returning how many there were.
+pub fn strip(fields: &mut Vec<Field>) -> usize {
+    let count = fields.len();
+    fields.retain(|field| !is_personal(&field.name));
+    count - fields.len()
+}
+
+/// Hash of a personal field's value, as a field transform: `anon-` and 16 hex
+/// digits of the FNV-1a hash of `salt` and `value`. Equal values get equal
+/// hashes within a run; a random salt keeps them from being looked up in a
+/// dictionary of names and places.
+pub fn hash_field(name: &str, value: &str, salt: u64) -> Option<String> {
+    if !is_personal(name) {
+        return None;
+    }
+    let hash = salt.to_le_bytes().iter().chain(value.as_bytes()).fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    });
+    Some(format!("anon-{:016x}", hash))
+}
+
+/// A salt for `hash_field` that differs from run to run.
+pub fn random_salt() -> u64 {
+    use std::hash::{BuildHasher, Hasher};
+    std::collections::hash_map::RandomState::new().build_hasher().finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adif::AdifFile;
+
+    #[test]
+    fn test_anonymize() {
+        let adif = AdifFile::parse(
+            "<eoh><call:4>W1AW<band:3>20m<name:6>Jürgen<QTH:6>Berlin<email:9>j@example<my_name:6>Jürgen<eor>".as_bytes(),
+        )
+        .unwrap();
+
+        let mut record = adif.records[0].clone();
+        assert_eq!(strip(&mut record.fields), 4);
+        let names: Vec<&str> = record.fields.iter().map(|field| field.name.as_str()).collect();
+        assert_eq!(names, vec!["call", "band"]);
+
+        assert_eq!(hash_field("call", "W1AW", 1), None);
+        assert!(hash_field("QTH", "Berlin", 1).unwrap().starts_with("anon-"));
+        assert_eq!(hash_field("name", "Jürgen", 1), hash_field("my_name", "Jürgen", 1));
+        assert_ne!(hash_field("name", "Jürgen", 1), hash_field("name", "Jürgen", 2));
+    }
+}
@@ -0,0 +1,144 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// File that only appears at its path once it has been completely written.
+///
+/// Output goes to a temporary file next to the target, which `commit` flushes,
+/// syncs and renames into place. Dropping an uncommitted file removes the
+/// temporary file, so an interrupted run leaves either the old file or the
+/// complete new one, never a truncated log that another program imports.
+///
+/// `close` gives up the file handle without committing; the next write
+/// reopens the temporary file and appends to it.
+pub struct AtomicFile {
+    writer: Option<BufWriter<File>>,
+    temp_path: PathBuf,
+    path: PathBuf,
+}
+
+impl AtomicFile {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+        temp_name.push(format!(".{}.tmp", std::process::id()));
+        let temp_path = path.with_file_name(temp_name);
+
+        Ok(Self {
+            writer: Some(BufWriter::new(File::create(&temp_path)?)),
+            temp_path,
+            path,
+        })
+    }
+
+    /// The path the file appears at once committed.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Moves the written file into place. Later calls do nothing.
+    pub fn commit(&mut self) -> io::Result<()> {
+        if self.temp_path.as_os_str().is_empty() {
+            return Ok(());
+        }
+        let file = match self.writer.take() {
+            Some(writer) => writer.into_inner().map_err(|error| error.into_error())?,
+            None => OpenOptions::new().write(true).open(&self.temp_path)?,
+        };
+        file.sync_all()?;
+        drop(file);
+        fs::rename(&self.temp_path, &self.path)?;
+        self.temp_path.clear();
+        Ok(())
+    }
+
+    /// Flushes and closes the temporary file, which stays in place until the
+    /// file is committed or dropped.
+    pub fn close(&mut self) -> io::Result<()> {
+        if let Some(writer) = self.writer.take() {
+            writer.into_inner().map_err(|error| error.into_error())?;
+        }
+        Ok(())
+    }
+
+    fn writer(&mut self) -> io::Result<&mut BufWriter<File>> {
+        if self.temp_path.as_os_str().is_empty() {
+            return Err(io::Error::other(format!("{} was already committed", self.path.display())));
+        }
+        if self.writer.is_none() {
+            self.writer = Some(BufWriter::new(OpenOptions::new().append(true).open(&self.temp_path)?));
+        }
+        Ok(self.writer.as_mut().unwrap())
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer()?.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.writer {
+            Some(writer) => writer.flush(),
+            // A closed file has nothing buffered
+            None if !self.temp_path.as_os_str().is_empty() => Ok(()),
+            None => self.writer()?.flush(),
+        }
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        // Close the file before removing it, which Windows requires
+        drop(self.writer.take());
+        if !self.temp_path.as_os_str().is_empty() {
+            let _ = fs::remove_file(&self.temp_path);
+        }
+    }
+}
+
+/// Atomic replacement for `fs::write`.
+pub fn write<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, contents: C) -> io::Result<()> {
+    let mut file = AtomicFile::create(path)?;
+    file.write_all(contents.as_ref())?;
+    file.commit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_atomic_file() {
+        let dir = std::env::temp_dir().join(format!("transadif-atomic-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("log.adi");
+        fs::write(&path, "old").unwrap();
+
+        let mut file = AtomicFile::create(&path).unwrap();
+        file.write_all(b"<eoh>").unwrap();
+        drop(file);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        let mut file = AtomicFile::create(&path).unwrap();
+        file.write_all(b"<eoh>").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old");
+        file.commit().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "<eoh>");
+
+        let mut file = AtomicFile::create(&path).unwrap();
+        file.write_all(b"<eoh>").unwrap();
+        file.close().unwrap();
+        file.write_all(b"<eor>").unwrap();
+        file.close().unwrap();
+        file.commit().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "<eoh><eor>");
+
+        write(&path, "new").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,103 @@
+/// Bytes shown on either side of a difference
+const CONTEXT_SIZE: usize = 20;
+
+/// Bytes shown on either side of a difference in hex
+const HEX_CONTEXT_SIZE: usize = 8;
+
+#[derive(Debug)]
+pub struct ByteDifference {
+    pub position: usize,
+    /// Byte of the first file, or 0 past its end
+    pub expected: u8,
+    /// Byte of the second file, or 0 past its end
+    pub actual: u8,
+    /// The text of both files around the difference, for the first
+    /// differences only
+    pub context: Option<String>,
+}
+
+/// Compares two files byte by byte, reporting every position where they differ,
+/// with the context of the first `context_limit` differences.
+/// Past the end of the shorter file, its bytes count as 0.
+pub fn compare_bytes(expected: &[u8], actual: &[u8], context_limit: usize) -> Vec<ByteDifference> {
+    let mut differences = Vec::new();
+    let max_len = expected.len().max(actual.len());
+
+    for i in 0..max_len {
+        let expected_byte = expected.get(i).copied().unwrap_or(0);
+        let actual_byte = actual.get(i).copied().unwrap_or(0);
+
+        if expected_byte != actual_byte {
+            let context = (differences.len() < context_limit).then(|| context_string(expected, actual, i));
+            differences.push(ByteDifference {
+                position: i,
+                expected: expected_byte,
+                actual: actual_byte,
+                context,
+            });
+        }
+    }
+
+    differences
+}
+
+/// The text of both files around `position`.
+pub fn context_string(expected: &[u8], actual: &[u8], position: usize) -> String {
+    format!(
+        "Expected: {:?} | Actual: {:?}",
+        text_context(expected, position),
+        text_context(actual, position)
+    )
+}
+
+/// The text of `data` around `position`.
+pub fn text_context(data: &[u8], position: usize) -> String {
+    let start = position.saturating_sub(CONTEXT_SIZE);
+    let end = (position + CONTEXT_SIZE).min(data.len());
+
+    if start < data.len() {
+        String::from_utf8_lossy(&data[start..end]).to_string()
+    } else {
+        String::new()
+    }
+}
+
+/// The bytes of `data` around `position` in hex, with the byte at `position`
+/// in brackets, e.g. `3c 6e 3a 34 3e [4a] 6f 72 67`.
+pub fn hex_context(data: &[u8], position: usize) -> String {
+    let start = position.saturating_sub(HEX_CONTEXT_SIZE).min(data.len());
+    let end = (position + HEX_CONTEXT_SIZE + 1).min(data.len());
+
+    let mut bytes: Vec<String> = (start..end)
+        .map(|i| if i == position { format!("[{:02x}]", data[i]) } else { format!("{:02x}", data[i]) })
+        .collect();
+    if position >= data.len() {
+        bytes.push("[end]".to_string());
+    }
+    bytes.join(" ")
+}
+
+/// The byte as a character if it is printable ASCII, else '.'.
+pub fn printable(byte: u8) -> char {
+    if byte.is_ascii_graphic() { byte as char } else { '.' }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare_bytes() {
+        let differences = compare_bytes(b"<name:4>Jorg<eor>", b"<name:4>Jorg<EOR>!", 1);
+        let positions: Vec<usize> = differences.iter().map(|d| d.position).collect();
+
+        assert_eq!(positions, vec![13, 14, 15, 17]);
+        assert_eq!((differences[0].expected, differences[0].actual), (b'e', b'E'));
+        assert_eq!((differences[3].expected, differences[3].actual), (0, b'!'));
+        assert_eq!(differences[0].context.as_deref(), Some("Expected: \"<name:4>Jorg<eor>\" | Actual: \"<name:4>Jorg<EOR>!\""));
+        assert_eq!(differences[1].context, None);
+
+        assert_eq!(hex_context(b"<name:4>Jorg<eor>", 8), "3c 6e 61 6d 65 3a 34 3e [4a] 6f 72 67 3c 65 6f 72 3e");
+        assert_eq!(hex_context(b"<eor>", 5), "3c 65 6f 72 3e [end]");
+    }
+}
@@ -1,29 +1,68 @@
-use clap::Parser;
-use std::path::PathBuf;
-use transadif::test_runner::TestRunner;
-
-#[derive(Parser)]
-#[command(name = "test-runner")]
-#[command(about = "Test runner for transadif")]
-pub struct TestRunnerCli {
-    /// Path to the test cases directory
-    #[arg(default_value = "test-cases")]
-    pub test_dir: PathBuf,
-
-    /// Filter to run only specific tests
-    #[arg(short, long)]
-    pub filter: Option<String>,
-
-    /// Path to the transadif executable
-    #[arg(short, long, default_value = "target/debug/transadif")]
-    pub executable: PathBuf,
-}
-
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = TestRunnerCli::parse();
-
-    let runner = TestRunner::new(args.executable);
-    runner.run_all_tests(&args.test_dir, args.filter.as_deref())?;
-
-    Ok(())
+use clap::Parser;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::time::Duration;
+use transadif::color::ColorChoice;
+use transadif::test_runner::{CompareMode, TestRunner};
+
+#[derive(Parser)]
+#[command(name = "test-runner")]
+#[command(about = "Test runner for transadif")]
+pub struct TestRunnerCli {
+    /// Path to the test cases directory
+    #[arg(default_value = "test-cases")]
+    pub test_dir: PathBuf,
+
+    /// Filter to run only specific tests
+    #[arg(short, long)]
+    pub filter: Option<String>,
+
+    /// Path to the transadif executable
+    #[arg(short, long, default_value = "target/debug/transadif")]
+    pub executable: PathBuf,
+
+    /// How to compare actual against expected output
+    #[arg(short, long, value_enum, default_value_t = CompareMode::Bytes)]
+    pub compare: CompareMode,
+
+    /// Keep running, re-running affected tests when the executable or test files change
+    #[arg(short, long)]
+    pub watch: bool,
+
+    /// When to color the results; `auto` colors them on a terminal unless NO_COLOR is set
+    #[arg(long, value_enum, default_value_t = ColorChoice::Auto)]
+    pub color: ColorChoice,
+
+    /// Seconds a test may run before it is killed and reported as timed out
+    #[arg(short, long, value_name = "SECONDS", default_value_t = 10)]
+    pub timeout: u64,
+
+    /// How many tests to run at the same time [default: the number of CPUs]
+    #[arg(short, long)]
+    pub jobs: Option<NonZeroUsize>,
+
+    /// Also write the results as JUnit XML to this file, for CI servers
+    #[arg(long, value_name = "FILE")]
+    pub junit: Option<PathBuf>,
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = TestRunnerCli::parse();
+
+    let mut runner = TestRunner::new(args.executable);
+    runner.compare_mode = args.compare;
+    runner.palette = args.color.stdout();
+    runner.timeout = Duration::from_secs(args.timeout);
+    runner.junit_file = args.junit;
+    if let Some(jobs) = args.jobs {
+        runner.jobs = jobs;
+    }
+
+    if args.watch {
+        runner.watch(&args.test_dir, args.filter.as_deref(), Duration::from_millis(500))?;
+    } else {
+        runner.run_all_tests(&args.test_dir, args.filter.as_deref())?;
+    }
+
+    Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,114 @@
+use crate::adif::{AdifFile, Field, Record};
+use crate::encoding::AdifEncoding;
+use crate::output::{OutputError, OutputFormatter};
+use std::io::Write;
+
+/// Builds ADIF files from Rust values, for programs that generate logs.
+///
+/// Field lengths are computed when the file is written, in the output
+/// encoding, so callers never count bytes or characters themselves. Values
+/// are written as given, without the corrections applied to parsed input;
+/// characters the output encoding cannot represent become `?`.
+#[derive(Debug, Clone)]
+pub struct AdifFileBuilder {
+    adif: AdifFile,
+    output_encoding: AdifEncoding,
+}
+
+impl AdifFileBuilder {
+    /// An empty file with a short preamble and PROGRAMID, written in `output_encoding`
+    pub fn new(output_encoding: AdifEncoding) -> Self {
+        let mut adif = AdifFile::new();
+        adif.preamble = "Generated by TransADIF\r\n\r\n".to_string();
+        adif.header_excess_data = "\r\n".to_string();
+
+        Self { adif, output_encoding }.header("programid", "TransADIF")
+    }
+
+    /// Replaces the free text before the header.
+    pub fn preamble(mut self, text: &str) -> Self {
+        self.adif.preamble = text.to_string();
+        self
+    }
+
+    /// Sets a header field, replacing an earlier one of the same name.
+    /// ENCODING is always written for the output encoding.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.adif.header_fields.retain(|field| !field.name.eq_ignore_ascii_case(name));
+        let mut field = Field::new(name, value);
+        field.excess_data = "\r\n".to_string();
+        self.adif.header_fields.push(field);
+        self
+    }
+
+    /// Starts a record, added to the file by `RecordBuilder::finish`.
+    pub fn record(&mut self) -> RecordBuilder<'_> {
+        RecordBuilder {
+            records: &mut self.adif.records,
+            record: Record { fields: Vec::new(), excess_data: "\r\n".to_string(), offset: 0, end_tag: String::new() },
+        }
+    }
+
+    /// Writes the file in the output encoding.
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<(), OutputError> {
+        OutputFormatter::new(Some(AdifEncoding::Utf8), self.output_encoding.clone(), true, Some('?'), false, false)
+            .format_adif(&self.adif, writer)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, OutputError> {
+        let mut bytes = Vec::new();
+        self.write(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// The file built so far, for further processing with the rest of the crate.
+    pub fn build(self) -> AdifFile {
+        self.adif
+    }
+}
+
+/// A record being added to an `AdifFileBuilder`
+pub struct RecordBuilder<'a> {
+    records: &'a mut Vec<Record>,
+    record: Record,
+}
+
+impl RecordBuilder<'_> {
+    /// Sets a field, replacing an earlier one of the same name.
+    pub fn field(mut self, name: &str, value: &str) -> Self {
+        if let Some(last) = self.record.fields.last_mut() {
+            last.excess_data = " ".to_string();
+        }
+        self.record.set(name, value);
+        if let Some(last) = self.record.fields.last_mut() {
+            last.excess_data.clear();
+        }
+        self
+    }
+
+    pub fn finish(self) {
+        self.records.push(self.record);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder() {
+        let mut builder = AdifFileBuilder::new(AdifEncoding::Utf8).header("adif_ver", "3.1.4");
+        builder.record().field("CALL", "EA4ABC").field("BAND", "20m").field("NAME", "José").finish();
+        builder.record().field("CALL", "JA1ABC").field("QTH", "東京").field("CALL", "JA1XYZ").finish();
+
+        let text = String::from_utf8(builder.to_bytes().unwrap()).unwrap();
+        assert_eq!(text, "Generated by TransADIF\r\n\r\n<programid:9>TransADIF\r\n<adif_ver:5>3.1.4\r\n<encoding:5>UTF-8\r\n<eoh>\r\n\
+            <CALL:6>EA4ABC <BAND:3>20m <NAME:4>José<eor>\r\n\
+            <CALL:6>JA1XYZ <QTH:2>東京<eor>\r\n");
+
+        let mut latin1 = AdifFileBuilder::new(AdifEncoding::Iso88591).preamble("");
+        latin1.record().field("NAME", "José").field("QTH", "東京").finish();
+        assert!(latin1.to_bytes().unwrap().ends_with(b"<NAME:4>Jos\xe9 <QTH:2>??<eor>\r\n"));
+        assert_eq!(latin1.build().records[0].get("name"), Some("José"));
+    }
+}
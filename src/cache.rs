@@ -0,0 +1,94 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// How the values of files were decoded in earlier batch runs, keyed by a
+/// hash of the file contents, so reruns over the same archive skip detection
+/// and make the same decisions. Stored as a JSON object.
+pub struct EncodingCache {
+    path: PathBuf,
+    entries: BTreeMap<String, Decision>,
+}
+
+/// What detection decided for one file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Decision {
+    /// One encoding for every value, named as in ENCODING headers
+    Encoding(String),
+    /// The encoding of each detected value, in the order
+    /// `EncodingProcessor::field_encodings` lists them
+    PerField(Vec<String>),
+}
+
+impl EncodingCache {
+    pub const FILE_NAME: &'static str = ".transadif-cache.json";
+
+    /// Loads the cache in `dir`. A missing, unreadable or malformed cache is
+    /// an empty one.
+    pub fn load(dir: &Path) -> Self {
+        let path = dir.join(Self::FILE_NAME);
+        let entries = fs::read(&path)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .unwrap_or_default();
+
+        Self { path, entries }
+    }
+
+    pub fn get(&self, hash: &str) -> Option<&Decision> {
+        self.entries.get(hash)
+    }
+
+    pub fn insert(&mut self, hash: String, decision: Decision) {
+        self.entries.insert(hash, decision);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        crate::atomic::write(&self.path, serde_json::to_vec_pretty(&self.entries)?)
+    }
+}
+
+/// Hash of a file's contents as 16 hex digits (FNV-1a, stable across releases).
+pub fn content_hash(data: &[u8]) -> String {
+    let hash = data.iter().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{:016x}", hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_round_trip() {
+        let dir = std::env::temp_dir().join(format!("transadif-cache-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut cache = EncodingCache::load(&dir);
+        assert!(cache.is_empty());
+        let per_field = Decision::PerField(vec!["UTF-8".to_string(), "windows-1252".to_string()]);
+        cache.insert(content_hash(b"<call:4>W1AW<eor>"), Decision::Encoding("Windows-1252".to_string()));
+        cache.insert(content_hash(b"<call:4>K1AB<eor>"), per_field.clone());
+        cache.save().unwrap();
+
+        let cache = EncodingCache::load(&dir);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(&content_hash(b"<call:4>W1AW<eor>")), Some(&Decision::Encoding("Windows-1252".to_string())));
+        assert_eq!(cache.get(&content_hash(b"<call:4>K1AB<eor>")), Some(&per_field));
+        assert_eq!(cache.get(&content_hash(b"")), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,92 @@
+/// Fields holding a single callsign
+const CALLSIGN_FIELDS: [&str; 6] = ["call", "contacted_op", "eq_call", "operator", "owner_callsign", "station_callsign"];
+
+/// Longest callsign without prefixes or suffixes, such as special event calls
+const MAX_BASE_LENGTH: usize = 10;
+
+/// Longest prefix or suffix around a callsign, such as `VP2E/` or `/QRP`
+const MAX_AFFIX_LENGTH: usize = 4;
+
+pub fn is_callsign_field(name: &str) -> bool {
+    CALLSIGN_FIELDS.iter().any(|field| field.eq_ignore_ascii_case(name))
+}
+
+/// Uppercases a callsign and removes whitespace anywhere in it.
+pub fn normalize(value: &str) -> String {
+    value.chars().filter(|c| !c.is_whitespace()).map(|c| c.to_ascii_uppercase()).collect()
+}
+
+/// Field transform normalizing the callsign fields.
+pub fn normalize_field(name: &str, value: &str) -> Option<String> {
+    if !is_callsign_field(name) {
+        return None;
+    }
+
+    let normalized = normalize(value);
+    (normalized != value).then_some(normalized)
+}
+
+/// Why `value` cannot be a callsign, or `None` if it looks like one: a base
+/// call of letters and digits with a digit after a prefix of at most four
+/// characters, ending in a letter, and optionally a prefix and a suffix
+/// separated by `/`, as in `HB9/DL1ABC/P`. Case does not matter.
+pub fn check(value: &str) -> Option<String> {
+    if value.is_empty() {
+        return Some("is empty".to_string());
+    }
+    if let Some(c) = value.chars().find(|c| !c.is_ascii_alphanumeric() && *c != '/') {
+        return Some(format!("contains {:?}, which no callsign has", c));
+    }
+
+    let parts: Vec<&str> = value.split('/').collect();
+    if parts.len() > 3 || parts.iter().any(|part| part.is_empty()) {
+        return Some("has more than a prefix and a suffix around the call".to_string());
+    }
+
+    // The base call is the longest part; the others are affixes
+    let (base_index, base) = parts.iter().enumerate().max_by_key(|(index, part)| (part.len(), *index == 0)).unwrap();
+    let affix_too_long = parts
+        .iter()
+        .enumerate()
+        .any(|(index, part)| index != base_index && part.len() > MAX_AFFIX_LENGTH);
+
+    if !is_base_call(base) || affix_too_long {
+        return Some("does not look like a callsign".to_string());
+    }
+    None
+}
+
+fn is_base_call(call: &str) -> bool {
+    let bytes = call.as_bytes();
+    (3..=MAX_BASE_LENGTH).contains(&bytes.len())
+        && bytes.iter().any(u8::is_ascii_alphabetic)
+        && bytes.last().is_some_and(u8::is_ascii_alphabetic)
+        && bytes[1..bytes.len().min(5)].iter().any(u8::is_ascii_digit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        for call in ["W1AW", "EA4ABC", "3DA0RU", "9A1A", "2E0ABC", "GB100RSGB", "HB9/DL1ABC/P", "ea4abc/qrp", "VP2E/W1AW", "DL1ABC/MM"] {
+            assert_eq!(check(call), None, "{}", call);
+        }
+
+        assert_eq!(check("EA4ÃBC").as_deref(), Some("contains 'Ã', which no callsign has"));
+        assert_eq!(check("EA4 ABC").as_deref(), Some("contains ' ', which no callsign has"));
+        assert_eq!(check("HB9//DL1ABC").as_deref(), Some("has more than a prefix and a suffix around the call"));
+        for impossible in ["ABCDE", "12345", "W1", "QRZABC1", "DL1ABC/PORTABLE"] {
+            assert_eq!(check(impossible).as_deref(), Some("does not look like a callsign"), "{}", impossible);
+        }
+    }
+
+    #[test]
+    fn test_normalize_field() {
+        assert_eq!(normalize_field("CALL", " ea4 abc\t").as_deref(), Some("EA4ABC"));
+        assert_eq!(normalize_field("station_callsign", "DL1ABC/p").as_deref(), Some("DL1ABC/P"));
+        assert_eq!(normalize_field("call", "W1AW"), None);
+        assert_eq!(normalize_field("NAME", "joe"), None);
+    }
+}
@@ -1,60 +1,722 @@
-use clap::Parser;
-use std::path::PathBuf;
-
-#[derive(Parser)]
-#[command(name = "transadif")]
-#[command(about = "Command-line tool for processing ADIF files with proper encoding handling")]
-#[command(version = "0.1.0")]
-pub struct Cli {
-    /// Input ADIF file (reads from stdin if not specified)
-    pub input: Option<PathBuf>,
-
-    /// Output file (writes to stdout if not specified)
-    #[arg(short, long)]
-    pub output: Option<PathBuf>,
-
-    /// Suggested encoding for the input file
-    #[arg(short = 'i', long)]
-    pub input_encoding: Option<String>,
-
-    /// Encoding for the output file
-    #[arg(short, long, default_value = "UTF-8")]
-    pub encoding: String,
-
-    /// Transcode compatible characters
-    #[arg(short, long)]
-    pub transcode: bool,
-
-    /// Replace incompatible characters with specified character
-    #[arg(short, long, default_value = "?")]
-    pub replace: char,
-
-    /// Delete incompatible characters instead of replacing them
-    #[arg(long)]
-    pub delete: bool,
-
-    /// Transliterate to characters without diacritics (ASCII mode)
-    #[arg(short, long)]
-    pub ascii: bool,
-
-    /// Strict mode - do not correct invalid characters or field counts
-    #[arg(short, long)]
-    pub strict: bool,
-
-    /// Debug mode - print contents of specified QSOs (comma-separated)
-    #[arg(short, long)]
-    pub debug: Option<String>,
-}
-
-impl Cli {
-    pub fn parse_debug_qsos(&self) -> Vec<usize> {
-        if let Some(ref debug_str) = self.debug {
-            debug_str
-                .split(',')
-                .filter_map(|s| s.trim().parse().ok())
-                .collect()
-        } else {
-            Vec::new()
-        }
-    }
-}
\ No newline at end of file
+use crate::adif::FieldCountMode;
+use crate::anonymize::Anonymization;
+use crate::color::ColorChoice;
+use crate::compress::Compression;
+use crate::encoding::Locale;
+use crate::mojibake::MojibakeMode;
+use crate::output::{EmojiPolicy, ExcessPolicy, KeyCase, Layout, LineEndings, PreamblePolicy, QsoSelection};
+use crate::preset::Preset;
+use crate::profile::Profile;
+use crate::qslmsg::QslMessageFix;
+use crate::reconcile::QslService;
+use crate::rewrite::FieldRewrite;
+use crate::sink::SplitKey;
+use crate::sort::FieldOrder;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::num::NonZeroUsize;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[command(name = "transadif")]
+#[command(about = "Command-line tool for processing ADIF files with proper encoding handling")]
+#[command(version = "0.1.0")]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Options of the default `convert` command, for invocations without one
+    #[command(flatten)]
+    pub convert: ConvertArgs,
+
+    /// Ignore transadif.toml in the current directory and ~/.config/transadif/
+    #[arg(long, global = true)]
+    pub no_config: bool,
+
+    /// Log what the conversion does on stderr: -v for its steps, -vv also
+    /// for every changed field, -vvv also for every QSO (RUST_LOG overrides
+    /// this)
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Write the Unix epoch for every generated timestamp, so repeated runs
+    /// produce identical files (for tests)
+    #[arg(long, global = true, hide = true)]
+    pub deterministic: bool,
+}
+
+/// Options of a conversion, shared by `convert` and the commands that run one
+/// for a narrower purpose (`validate`, `stats`, `merge`, `dedupe`, `debug`)
+#[derive(Args, Clone)]
+pub struct ConvertArgs {
+    /// Input ADIF files (reads from stdin if not specified); several files are
+    /// merged into one output, each decoded with its own encoding
+    pub input: Vec<PathBuf>,
+
+    /// Output file (writes to stdout if not specified)
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// Write one file per year, band, callsign or value of a field
+    /// (`field:NAME`), named after the output file: `log.adi` -> `log-2024.adi`
+    #[arg(long, value_name = "KEY", requires = "output", conflicts_with = "resume")]
+    pub split_by: Option<SplitKey>,
+
+    /// Start a new numbered file after this many records, each with a full
+    /// header: `log-001.adi`, `log-002.adi`, ...
+    #[arg(long, value_name = "N", requires = "output", conflicts_with = "resume")]
+    pub max_records_per_file: Option<NonZeroUsize>,
+
+    /// Compress the output; gzip and zip input is decompressed without asking
+    #[arg(long, value_enum, conflicts_with_all = ["resume", "split_by", "max_records_per_file"])]
+    pub compress: Option<Compression>,
+
+    /// Suggested encoding for the input file
+    #[arg(short = 'i', long)]
+    pub input_encoding: Option<String>,
+
+    /// Print the encoding detected for the file, and for every value detected
+    /// as a different one, with the detector's confidence on stderr
+    #[arg(long, conflicts_with_all = ["stream", "watch"])]
+    pub show_detected: bool,
+
+    /// Language of the log's free text, which steers encoding detection
+    /// towards the encodings used for it
+    #[arg(long, value_enum)]
+    pub locale: Option<Locale>,
+
+    /// Input format; CSV and TSV files name their columns in the first row
+    #[arg(long, value_enum, default_value_t = InputFormat::Adi)]
+    pub from: InputFormat,
+
+    /// Rename CSV/TSV columns to ADIF fields, e.g. `date=qso_date` (comma-separated
+    /// or repeated; an empty name drops the column)
+    #[arg(long, value_delimiter = ',')]
+    pub map: Vec<String>,
+
+    /// Encoding for the output file
+    #[arg(short, long, default_value = "UTF-8")]
+    pub encoding: String,
+
+    /// What output field lengths count; bytes and chars are declared in the
+    /// header as APP_TRANSADIF_COUNT_MODE, which is honored when reading
+    #[arg(long, value_enum, default_value_t = FieldCountMode::Auto)]
+    pub count_mode: FieldCountMode,
+
+    /// Start UTF-8 output with a byte order mark, for tools that require one
+    #[arg(long)]
+    pub bom: bool,
+
+    /// Line breaks in the preamble and between fields and records; values
+    /// are written as they are
+    #[arg(long, value_enum, default_value_t = LineEndings::Preserve)]
+    pub line_endings: LineEndings,
+
+    /// Free text in front of the header: the input's, none, or a generated
+    /// line naming the tool, input and time
+    #[arg(long, value_enum, default_value_t = PreamblePolicy::Keep)]
+    pub preamble: PreamblePolicy,
+
+    /// Text after `<eoh>` and between records, such as tool banners, stray
+    /// NULs or partial records: written as it is, replaced by one line break,
+    /// or dropped
+    #[arg(long, value_enum, default_value_t = ExcessPolicy::Keep)]
+    pub excess: ExcessPolicy,
+
+    /// Whitespace between fields and records, for reading or for upload;
+    /// the input's is kept if not given
+    #[arg(long, value_enum)]
+    pub layout: Option<Layout>,
+
+    /// Set a header field on output, e.g. `programid=MyLogger`, replacing the
+    /// input's value (repeatable)
+    #[arg(long, value_name = "NAME=VALUE", value_parser = parse_header_assignment)]
+    pub set_header: Vec<(String, String)>,
+
+    /// Set CREATED_TIMESTAMP in the header to the time of the conversion.
+    /// Off by default, so the input's timestamp is kept and converting a
+    /// file twice gives identical output
+    #[arg(long)]
+    pub created_timestamp: bool,
+
+    /// Give input without a header, such as a WSJT-X log, one with a
+    /// preamble, ADIF_VER, PROGRAMID and CREATED_TIMESTAMP
+    #[arg(long)]
+    pub add_header: bool,
+
+    /// Transcode compatible characters
+    #[arg(short, long)]
+    pub transcode: bool,
+
+    /// Replace incompatible characters with specified character
+    #[arg(short, long, default_value = "?")]
+    pub replace: char,
+
+    /// Delete incompatible characters instead of replacing them
+    #[arg(long)]
+    pub delete: bool,
+
+    /// Write incompatible characters as `&0xNN;` entity references, which
+    /// transadif decodes again, instead of replacing them
+    #[arg(long, conflicts_with = "delete")]
+    pub entities: bool,
+
+    /// Transliterate to characters without diacritics (ASCII mode)
+    #[arg(short, long)]
+    pub ascii: bool,
+
+    /// File of `character=replacement` lines that override the built-in
+    /// transliterations used by --ascii
+    #[arg(long, requires = "ascii")]
+    pub translit_table: Option<PathBuf>,
+
+    /// What to do with emoji the output encoding cannot represent
+    #[arg(long, value_enum, default_value_t = EmojiPolicy::Keep)]
+    pub emoji: EmojiPolicy,
+
+    /// How eagerly to correct UTF-8 that was misread as Windows-1252,
+    /// ISO-8859-1 or MacRoman
+    #[arg(long, value_enum, default_value_t = MojibakeMode::Conservative)]
+    pub mojibake: MojibakeMode,
+
+    /// Confidence from 0 to 1 a correction needs, overriding the one implied
+    /// by --mojibake (aggressive 0.5, conservative 0.9)
+    #[arg(long, value_parser = parse_confidence)]
+    pub mojibake_threshold: Option<f64>,
+
+    /// Encoding candidates, mojibake reversals and transliterations for the
+    /// logs of a region; --translit-table entries still take precedence
+    #[arg(long, value_enum)]
+    pub preset: Option<Preset>,
+
+    /// Replace look-alike Cyrillic, Greek and fullwidth characters in callsign
+    /// and grid square fields with their ASCII counterparts
+    #[arg(long)]
+    pub fix_homoglyphs: bool,
+
+    /// Uppercase callsign fields and remove whitespace from them
+    #[arg(long)]
+    pub fix_callsigns: bool,
+
+    /// Write valid GRIDSQUARE and MY_GRIDSQUARE locators as JN58td
+    #[arg(long)]
+    pub fix_grids: bool,
+
+    /// Cut valid locators down to this many characters, or extend them
+    /// towards the center of their square; implies --fix-grids
+    #[arg(long, value_name = "CHARACTERS", value_parser = parse_grid_precision)]
+    pub grid_precision: Option<usize>,
+
+    /// Uppercase SOTA, POTA and WWFF references and pad their numbers with
+    /// zeros (`ea4/md-1` -> `EA4/MD-001`)
+    #[arg(long)]
+    pub fix_refs: bool,
+
+    /// Replace regex matches in a field's values, e.g. `call:/\/QRP$//` to strip
+    /// a suffix; `$1` in the replacement refers to a group (repeatable)
+    #[arg(long, value_name = "FIELD:/PATTERN/REPLACEMENT/")]
+    pub replace_field: Vec<FieldRewrite>,
+
+    /// Rewrite QSLMSG and QSLMSG_INTL values so they render on QSL cards
+    #[arg(long, value_enum)]
+    pub qslmsg_fix: Option<QslMessageFix>,
+
+    /// Line length for `--qslmsg-fix wrap`
+    #[arg(long, default_value_t = 40, requires = "qslmsg_fix")]
+    pub qslmsg_width: usize,
+
+    /// Add RST_SENT and RST_RCVD where missing: 59 for phone modes, 599 for
+    /// CW and digital modes
+    #[arg(long)]
+    pub fill_rst: bool,
+
+    /// Add DXCC, COUNTRY, CONT, CQZ and ITUZ where missing, looked up from
+    /// the CALL in --cty-file
+    #[arg(long, requires = "cty_file")]
+    pub enrich_dxcc: bool,
+
+    /// Country file for --enrich-dxcc: cty.dat, or cty.csv, which also
+    /// carries the DXCC entity codes
+    #[arg(long, value_name = "PATH", requires = "enrich_dxcc")]
+    pub cty_file: Option<PathBuf>,
+
+    /// Rewrite numbers written with decimal commas or thousands separators
+    /// (`14,074` -> `14.074`); with --strict, ambiguous values are an error
+    #[arg(long)]
+    pub fix_decimals: bool,
+
+    /// Add an APP_TRANSADIF_SOURCE_ENCODING_<FIELD> field naming the guessed
+    /// input encoding of each value that could not be decoded with confidence
+    #[arg(long)]
+    pub annotate_encoding: bool,
+
+    /// Keep everything that needs no conversion as it was in the input:
+    /// declared lengths of unchanged fields, the header layout and the
+    /// spelling of <eoh> and <eor>
+    #[arg(long)]
+    pub preserve: bool,
+
+    /// Only write these record fields (comma-separated or repeated)
+    #[arg(long, value_delimiter = ',', conflicts_with = "drop_fields")]
+    pub keep_fields: Vec<String>,
+
+    /// Leave these record fields out, e.g. `address,comment` (comma-separated or repeated)
+    #[arg(long, value_delimiter = ',')]
+    pub drop_fields: Vec<String>,
+
+    /// Order of the fields in each record: CALL, QSO_DATE, TIME_ON and the
+    /// other fields identifying a QSO first, as in the input, or by name
+    #[arg(long, value_enum, default_value_t = FieldOrder::Preserve)]
+    pub field_order: FieldOrder,
+
+    /// Write these record fields first, in this order, e.g.
+    /// `call,qso_date,time_on`, for import tools that expect them early
+    /// (comma-separated or repeated)
+    #[arg(long, value_delimiter = ',')]
+    pub field_priority: Vec<String>,
+
+    /// Replace NAME, QTH, ADDRESS, EMAIL, COMMENT, NOTES and the other
+    /// personal fields with hashes of their values, or strip them with
+    /// `--anonymize=strip`, and regenerate the preamble, e.g. for logs
+    /// attached to bug reports
+    #[arg(long, value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "hash")]
+    pub anonymize: Option<Anonymization>,
+
+    /// Strict mode - do not correct invalid characters or field counts
+    #[arg(short, long)]
+    pub strict: bool,
+
+    /// Skip fields whose tags cannot be parsed, such as lengths too large to
+    /// be real, reporting each on stderr, instead of stopping at the first
+    #[arg(long, conflicts_with_all = ["resume", "watch"])]
+    pub skip_malformed: bool,
+
+    /// Report fields that do not conform to the ADIF specification (unknown
+    /// fields, bad enumeration values, malformed dates and times) on stderr
+    #[arg(long)]
+    pub validate: bool,
+
+    /// Like --validate, but exit with an error instead of writing output if
+    /// any field does not conform to the ADIF specification
+    #[arg(long, conflicts_with = "stream")]
+    pub validate_strict: bool,
+
+    /// Exit with an error after writing the output if the input needed more
+    /// than a plain conversion, so scripts can detect dirty input
+    #[arg(long, value_enum, default_value_t = FailOn::Error)]
+    pub fail_on: FailOn,
+
+    /// Repair the known quirks of a program's logs before converting them
+    #[arg(long, value_enum, conflicts_with_all = ["resume", "stream"])]
+    pub profile: Option<Profile>,
+
+    /// Drop QSOs that duplicate an earlier one (see --dedupe-key and --dedupe-window)
+    #[arg(long, conflicts_with_all = ["resume", "stream"])]
+    pub dedupe: bool,
+
+    /// List QSOs that duplicate an earlier one on stderr
+    #[arg(long, conflicts_with_all = ["resume", "stream"])]
+    pub report_dupes: bool,
+
+    /// Fields that must match for two QSOs to be duplicates; QSO_DATE and
+    /// TIME_ON match when the start times are within --dedupe-window
+    #[arg(long, value_delimiter = ',', default_value = "call,band,mode,qso_date,time_on")]
+    pub dedupe_key: Vec<String>,
+
+    /// Minutes two duplicate QSOs' start times may differ by
+    #[arg(long, default_value_t = 2)]
+    pub dedupe_window: u32,
+
+    /// Order records by these fields, e.g. `qso_date,time_on`; dates, times,
+    /// numbers and bands compare by value
+    #[arg(long, value_delimiter = ',', conflicts_with_all = ["resume", "stream"])]
+    pub sort: Vec<String>,
+
+    /// Sort in descending order
+    #[arg(long, requires = "sort")]
+    pub sort_desc: bool,
+
+    /// Debug mode - print contents of specified QSOs (comma-separated), by
+    /// number or as FIELD=VALUE or FIELD~PATTERN, e.g. `call=EA4ABC`; a comma
+    /// followed by anything else is part of the value or pattern
+    #[arg(short, long)]
+    pub debug: Option<QsoSelection>,
+
+    /// With --debug, show each field's bytes as a hex dump with the
+    /// characters they decode to and invalid bytes marked
+    #[arg(long)]
+    pub debug_hex: bool,
+
+    /// When to color --debug and --report output; `auto` colors it on a
+    /// terminal unless NO_COLOR is set
+    #[arg(long, value_enum, default_value_t = ColorChoice::Auto)]
+    pub color: ColorChoice,
+
+    /// Write only the header and these QSOs, e.g. `17,42,100-110`, byte for
+    /// byte as they are in the input, as a reproducer for a bug report
+    #[arg(long, value_name = "QSOS", value_delimiter = ',', value_parser = parse_record_range, conflicts_with_all = ["stream", "watch", "resume"])]
+    pub extract_records: Vec<RangeInclusive<usize>>,
+
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Adi)]
+    pub format: OutputFormat,
+
+    /// Case of the field names used as keys in JSON output
+    #[arg(long, value_enum, default_value_t = KeyCase::Lower)]
+    pub json_keys: KeyCase,
+
+    /// Keep a progress journal next to the output file and continue from it
+    /// if a previous run was interrupted (requires --output)
+    #[arg(long, requires = "output")]
+    pub resume: bool,
+
+    /// Emit a stable per-QSO hash for external sync tools, either as an
+    /// APP_TRANSADIF_HASH field or in a `<output>.hashes` sidecar file
+    #[arg(long, value_enum)]
+    pub emit_hashes: Option<HashOutput>,
+
+    /// Instead of writing output, list every field the conversion would change
+    #[arg(long, conflicts_with_all = ["resume", "stream"])]
+    pub dry_run: bool,
+
+    /// Instead of writing output, print totals: QSOs, date range, QSOs per
+    /// band and mode, unique callsigns, DXCC entities and fields present
+    #[arg(long, conflicts_with_all = ["resume", "stream", "dry_run"])]
+    pub stats: bool,
+
+    /// With --dry-run, print a unified diff of each record's decoded input
+    /// against what would be written
+    #[arg(long, requires = "dry_run")]
+    pub show_diff: bool,
+
+    /// Print statistics on stderr: encodings detected, fields with invalid
+    /// sequences, mojibake corrections, characters replaced or deleted per
+    /// field, and every changed field
+    #[arg(long, conflicts_with = "stream")]
+    pub report: bool,
+
+    /// Write every change made to a field to this file as JSON: record index,
+    /// field, original bytes, value before and after, and the reason
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["resume", "stream"])]
+    pub log_json: Option<PathBuf>,
+
+    /// Convert record by record with bounded memory instead of loading the
+    /// whole file, for use as a pipe filter on huge logs
+    #[arg(long, conflicts_with_all = ["resume", "debug"])]
+    pub stream: bool,
+
+    /// Keep converting records as they are appended to the input file, e.g.
+    /// WSJT-X's wsjtx_log.adi during a contest, until interrupted
+    #[arg(long, requires = "input", conflicts_with_all = [
+        "resume", "stream", "debug", "profile", "dedupe", "report_dupes", "sort", "dry_run", "stats",
+        "report", "log_json", "split_by", "max_records_per_file", "compress",
+    ])]
+    pub watch: bool,
+
+    /// Stop after validation instead of writing output (`transadif validate`)
+    #[arg(skip)]
+    pub validate_only: bool,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Convert ADIF files to another encoding or format (the default command:
+    /// `transadif log.adi` is `transadif convert log.adi`)
+    Convert(ConvertArgs),
+
+    /// Report fields that do not conform to the ADIF specification without
+    /// writing output; exits with an error if there are any
+    Validate(ConvertArgs),
+
+    /// Print totals: QSOs, date range, QSOs per band and mode, unique
+    /// callsigns, DXCC entities and fields present
+    Stats(ConvertArgs),
+
+    /// Merge several ADIF files into one output, each decoded with its own encoding
+    Merge(ConvertArgs),
+
+    /// Convert, dropping QSOs that duplicate an earlier one
+    Dedupe(ConvertArgs),
+
+    /// Print the decoding details of QSOs, e.g. `transadif debug 5,10 log.adi`
+    Debug {
+        /// QSO numbers, FIELD=VALUE or FIELD~PATTERN (comma-separated)
+        qsos: QsoSelection,
+
+        #[command(flatten)]
+        args: ConvertArgs,
+    },
+
+    /// Browse records and their per-field decoding details in a terminal UI
+    View {
+        /// ADIF file to inspect
+        file: PathBuf,
+
+        /// Suggested encoding for the input file
+        #[arg(short = 'i', long)]
+        input_encoding: Option<String>,
+    },
+
+    /// Concatenate several ADIF files under a single clean header
+    Cat {
+        /// Input ADIF files, in output order
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+
+        /// Output file (writes to stdout if not specified)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Encoding for the output file
+        #[arg(short, long, default_value = "UTF-8")]
+        encoding: String,
+
+        /// Replace incompatible characters with specified character
+        #[arg(short, long, default_value = "?")]
+        replace: char,
+
+        /// Delete incompatible characters instead of replacing them
+        #[arg(long)]
+        delete: bool,
+
+        /// Transliterate to characters without diacritics (ASCII mode)
+        #[arg(short, long)]
+        ascii: bool,
+    },
+
+    /// Mark the QSOs of a log confirmed by a LoTW report, an eQSL inbox
+    /// download or a list of received cards, and list confirmations of QSOs
+    /// the log does not have
+    Reconcile {
+        /// The log to update
+        log: PathBuf,
+
+        /// The confirmations, e.g. lotwreport.adi
+        confirmations: PathBuf,
+
+        /// Output file (writes to stdout if not specified); may be the log itself
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Service the confirmations are from, deciding the QSL fields set;
+        /// detected from their PROGRAMID if not given
+        #[arg(long, value_enum)]
+        service: Option<QslService>,
+
+        /// Minutes the start times of a QSO and its confirmation may differ by
+        #[arg(long, default_value_t = 30)]
+        window: u32,
+
+        /// Encoding for the output file
+        #[arg(short, long, default_value = "UTF-8")]
+        encoding: String,
+    },
+
+    /// Convert every .adi and .adx file in a directory tree
+    Batch {
+        /// Directory to search for ADIF files
+        dir: PathBuf,
+
+        /// Directory for the converted files, which keep their relative paths
+        #[arg(long)]
+        out_dir: PathBuf,
+
+        /// Encoding for the output files (ADX files are always UTF-8)
+        #[arg(short, long, default_value = "UTF-8")]
+        encoding: String,
+
+        /// Replace incompatible characters with specified character
+        #[arg(short, long, default_value = "?")]
+        replace: char,
+
+        /// Delete incompatible characters instead of replacing them
+        #[arg(long)]
+        delete: bool,
+
+        /// Transliterate to characters without diacritics (ASCII mode)
+        #[arg(short, long)]
+        ascii: bool,
+
+        /// Number of files to convert at once [default: number of CPUs]
+        #[arg(short, long)]
+        jobs: Option<usize>,
+
+        /// Detect every file's encoding again instead of reusing the decisions
+        /// cached in the output directory's .transadif-cache.json
+        #[arg(long)]
+        no_cache: bool,
+    },
+
+    /// Show where two files differ byte by byte, in hex and as text
+    Bdiff {
+        /// First file, e.g. the original
+        a: PathBuf,
+
+        /// Second file, e.g. the converted output
+        b: PathBuf,
+
+        /// Number of differences to show
+        #[arg(short = 'n', long, default_value_t = 5)]
+        limit: usize,
+    },
+
+    /// Compare the QSOs of two ADIF files, e.g. a log and its conversion:
+    /// those only in one of them and the fields that differ. Exits with
+    /// status 1 if they differ
+    Diff {
+        /// First file, e.g. the original
+        a: PathBuf,
+
+        /// Second file, e.g. the converted output
+        b: PathBuf,
+
+        /// Fields identifying a QSO in both files (comma-separated)
+        #[arg(long, value_delimiter = ',', default_value = "call,qso_date,time_on,band,mode")]
+        key: Vec<String>,
+    },
+
+    /// Print a completion script for bash, zsh, fish, elvish or PowerShell
+    Completions {
+        shell: clap_complete::Shell,
+    },
+
+    /// Print the man page, or write one page per command to a directory
+    Manpage {
+        /// Directory for transadif.1 and a transadif-COMMAND.1 for every subcommand
+        #[arg(long)]
+        out_dir: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum InputFormat {
+    /// Tagged ADIF (.adi)
+    Adi,
+    /// Comma-separated values with a header row
+    Csv,
+    /// Tab-separated values with a header row
+    Tsv,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    /// Tagged ADIF (.adi)
+    Adi,
+    /// XML ADIF (.adx), always UTF-8
+    Adx,
+    /// JSON object with the header and an array of records, always UTF-8
+    Json,
+}
+
+/// What makes a successful conversion exit with an error; each level
+/// includes the ones before it
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, ValueEnum)]
+pub enum FailOn {
+    /// Only errors that stop the conversion
+    Error,
+    /// Also warnings about the input and --validate violations
+    Warning,
+    /// Also any field damaged by mojibake, entity references or a wrong
+    /// length, or fixed by --profile or --fix-decimals; changes the options
+    /// ask for, such as --ascii or --replace-field, do not count
+    Correction,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum HashOutput {
+    /// Add an APP_TRANSADIF_HASH field to every record
+    App,
+    /// Write `record-number<TAB>hash` lines to a file next to the output
+    Sidecar,
+}
+
+impl ConvertArgs {
+    /// Column renames from `--map`, as `(from, to)` pairs
+    pub fn parse_field_map(&self) -> Vec<(String, String)> {
+        self.map
+            .iter()
+            .filter_map(|entry| entry.split_once('='))
+            .map(|(from, to)| (from.trim().to_string(), to.trim().to_string()))
+            .collect()
+    }
+
+    /// Whether `--keep-fields` and `--drop-fields` let a record field through
+    pub fn selects_field(&self, name: &str) -> bool {
+        let listed = |names: &[String]| names.iter().any(|listed| listed.trim().eq_ignore_ascii_case(name));
+
+        if !self.keep_fields.is_empty() {
+            listed(&self.keep_fields)
+        } else {
+            !listed(&self.drop_fields)
+        }
+    }
+
+    /// Confidence a mojibake correction needs, or `None` with `--mojibake off`
+    pub fn mojibake_confidence(&self) -> Option<f64> {
+        let threshold = self.mojibake.threshold()?;
+        Some(self.mojibake_threshold.unwrap_or(threshold))
+    }
+
+}
+
+fn parse_confidence(value: &str) -> Result<f64, String> {
+    let confidence: f64 = value.parse().map_err(|_| format!("{} is not a number", value))?;
+    if (0.0..=1.0).contains(&confidence) {
+        Ok(confidence)
+    } else {
+        Err(format!("{} is not between 0 and 1", value))
+    }
+}
+
+fn parse_header_assignment(value: &str) -> Result<(String, String), String> {
+    let (name, value) = value.split_once('=').ok_or_else(|| format!("{} is not NAME=VALUE", value))?;
+    let name = name.trim();
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(format!("{:?} is not a field name", name));
+    }
+    if name.eq_ignore_ascii_case("encoding") {
+        return Err("ENCODING always names the output encoding; use --encoding".to_string());
+    }
+    Ok((name.to_string(), value.to_string()))
+}
+
+/// A QSO number or a range of them, like `100-110`, counting from 1.
+fn parse_record_range(value: &str) -> Result<RangeInclusive<usize>, String> {
+    let number = |text: &str| match text.trim().parse::<usize>() {
+        Ok(number) if number > 0 => Ok(number),
+        _ => Err(format!("{:?} is not a QSO number", text.trim())),
+    };
+    let (start, end) = match value.split_once('-') {
+        Some((start, end)) => (number(start)?, number(end)?),
+        None => (number(value)?, number(value)?),
+    };
+    if start > end {
+        return Err(format!("{} ends before it starts", value));
+    }
+    Ok(start..=end)
+}
+
+fn parse_grid_precision(value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(precision @ (2 | 4 | 6 | 8)) => Ok(precision),
+        _ => Err(format!("{} is not 2, 4, 6 or 8", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::error::ErrorKind;
+
+    #[test]
+    fn test_validate_strict_conflicts_with_stream() {
+        // Streaming writes records before the last one is validated
+        let error = Cli::try_parse_from(["transadif", "--stream", "--validate-strict", "log.adi"]).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::ArgumentConflict);
+        let error = Cli::try_parse_from(["transadif", "convert", "--validate-strict", "--stream"]).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::ArgumentConflict);
+
+        assert!(Cli::try_parse_from(["transadif", "--stream", "--validate", "log.adi"]).is_ok());
+    }
+}
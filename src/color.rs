@@ -0,0 +1,123 @@
+use std::io::IsTerminal;
+
+/// When to color terminal output
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum)]
+pub enum ColorChoice {
+    /// When writing to a terminal, unless NO_COLOR is set
+    #[default]
+    Auto,
+    /// Also when writing to a file or a pipe
+    Always,
+    /// Plain text only
+    Never,
+}
+
+impl ColorChoice {
+    /// Styles for standard output.
+    pub fn stdout(self) -> Palette {
+        self.palette(std::io::stdout().is_terminal())
+    }
+
+    /// Styles for standard error.
+    pub fn stderr(self) -> Palette {
+        self.palette(std::io::stderr().is_terminal())
+    }
+
+    /// Styles for a stream, `terminal` telling whether it is one.
+    pub fn palette(self, terminal: bool) -> Palette {
+        let enabled = match self {
+            Self::Always => true,
+            Self::Never => false,
+            Self::Auto => terminal && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty()),
+        };
+        Palette { enabled }
+    }
+}
+
+/// ANSI styles for text, or no styling at all, which is the default
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Palette {
+    enabled: bool,
+}
+
+impl Palette {
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn red(&self, text: &str) -> String {
+        self.paint("31", text)
+    }
+
+    pub fn green(&self, text: &str) -> String {
+        self.paint("32", text)
+    }
+
+    pub fn yellow(&self, text: &str) -> String {
+        self.paint("33", text)
+    }
+
+    pub fn bold(&self, text: &str) -> String {
+        self.paint("1", text)
+    }
+
+    fn paint(&self, code: &str, text: &str) -> String {
+        if self.enabled && !text.is_empty() {
+            format!("\x1b[{}m{}\x1b[0m", code, text)
+        } else {
+            text.to_string()
+        }
+    }
+
+    /// `before` and `after` quoted like `{:?}`, with the characters between
+    /// what they have in common at the start and the end in red and green.
+    pub fn change(&self, before: &str, after: &str) -> (String, String) {
+        if !self.enabled {
+            return (format!("{:?}", before), format!("{:?}", after));
+        }
+
+        let prefix: usize = before.chars().zip(after.chars()).take_while(|(a, b)| a == b).map(|(a, _)| a.len_utf8()).sum();
+        let suffix: usize = before[prefix..]
+            .chars()
+            .rev()
+            .zip(after[prefix..].chars().rev())
+            .take_while(|(a, b)| a == b)
+            .map(|(a, _)| a.len_utf8())
+            .sum();
+        let quote = |text: &str, paint: fn(&Self, &str) -> String| {
+            let end = text.len() - suffix;
+            format!("\"{}{}{}\"", escape(&text[..prefix]), paint(self, &escape(&text[prefix..end])), escape(&text[end..]))
+        };
+        (quote(before, Self::red), quote(after, Self::green))
+    }
+}
+
+/// `text` escaped as it would be between the quotes of `{:?}`.
+fn escape(text: &str) -> String {
+    let quoted = format!("{:?}", text);
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+/// `lines` as label and value pairs, with the values lined up after the
+/// longest label.
+pub fn align<L: AsRef<str>>(lines: &[(L, String)]) -> Vec<String> {
+    let width = lines.iter().map(|(label, _)| label.as_ref().chars().count()).max().unwrap_or(0);
+    lines.iter().map(|(label, value)| format!("{:<width$} {}", format!("{}:", label.as_ref()), value, width = width + 1)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_change() {
+        assert_eq!(Palette::default().change("Jã¼rgen", "Jürgen"), ("\"Jã¼rgen\"".to_string(), "\"Jürgen\"".to_string()));
+        assert!(!ColorChoice::Never.palette(true).enabled());
+        assert!(!ColorChoice::Auto.palette(false).enabled());
+
+        let palette = ColorChoice::Always.palette(false);
+        let (before, after) = palette.change("Jã¼rgen\n", "Jürgen\n");
+        assert_eq!(before, "\"J\x1b[31mã¼\x1b[0mrgen\\n\"");
+        assert_eq!(after, "\"J\x1b[32mü\x1b[0mrgen\\n\"");
+    }
+}
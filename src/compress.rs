@@ -0,0 +1,172 @@
+use flate2::read::MultiGzDecoder;
+use std::io::{self, BufRead, BufReader, Cursor, Read};
+use zip::ZipArchive;
+
+const GZIP_MAGIC: &[u8] = b"\x1f\x8b";
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+/// Extensions of the zip archive members that can hold the log
+const ADIF_EXTENSIONS: [&str; 2] = ["adi", "adif"];
+
+/// Compression for output files
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Compression {
+    /// gzip, as in `log.adi.gz`
+    Gz,
+}
+
+/// Decompresses gzip data and extracts the first ADIF file from a zip
+/// archive, recognizing both by their first bytes. Anything else is returned
+/// as it is.
+pub fn decompress(data: Vec<u8>) -> io::Result<Vec<u8>> {
+    if data.starts_with(GZIP_MAGIC) {
+        let mut decompressed = Vec::new();
+        MultiGzDecoder::new(data.as_slice()).read_to_end(&mut decompressed)?;
+        Ok(decompressed)
+    } else if data.starts_with(ZIP_MAGIC) {
+        extract_zip_member(&data)
+    } else {
+        Ok(data)
+    }
+}
+
+/// Wraps `reader` to decompress gzip input as it is read. Zip archives are
+/// read completely first, since their directory comes last.
+pub fn reader<R: Read + 'static>(reader: R) -> io::Result<Box<dyn Read>> {
+    let mut reader = BufReader::new(reader);
+    let start = reader.fill_buf()?;
+    if start.starts_with(GZIP_MAGIC) {
+        Ok(Box::new(MultiGzDecoder::new(reader)))
+    } else if start.starts_with(ZIP_MAGIC) {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        Ok(Box::new(Cursor::new(extract_zip_member(&data)?)))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("zip archive: {}", message))
+}
+
+/// The contents of the first `.adi` or `.adif` member of a zip archive.
+fn extract_zip_member(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut archive = ZipArchive::new(Cursor::new(data)).map_err(|e| invalid(&e.to_string()))?;
+    for index in 0..archive.len() {
+        let mut member = archive.by_index(index).map_err(|e| invalid(&e.to_string()))?;
+        // Skip the resource forks macOS adds as __MACOSX/._log.adi
+        let name = member.name().to_string();
+        let is_log = member.is_file()
+            && !name.starts_with("__MACOSX/")
+            && name.rsplit_once('.').is_some_and(|(_, extension)| ADIF_EXTENSIONS.iter().any(|adif| adif.eq_ignore_ascii_case(extension)));
+        if !is_log {
+            continue;
+        }
+
+        let mut contents = Vec::new();
+        member.read_to_end(&mut contents).map_err(|e| invalid(&format!("{}: {}", name, e)))?;
+        return Ok(contents);
+    }
+
+    Err(invalid("no .adi or .adif file"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::{DeflateEncoder, GzEncoder};
+    use flate2::Crc;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+    use zip::{CompressionMethod, ZipWriter};
+
+    /// A zip archive with one member per `(name, method, contents)`, with
+    /// the sizes after each member's data instead of in its local header if
+    /// `descriptors` is set, as zip tools writing to a pipe do
+    fn zip(members: &[(&str, u16, &[u8])], descriptors: bool) -> Vec<u8> {
+        let mut archive = Vec::new();
+        let mut directory = Vec::new();
+        for (name, method, contents) in members {
+            let stored = match method {
+                8 => {
+                    let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+                    encoder.write_all(contents).unwrap();
+                    encoder.finish().unwrap()
+                }
+                _ => contents.to_vec(),
+            };
+            let mut crc = Crc::new();
+            crc.update(contents);
+            let mut sizes = crc.sum().to_le_bytes().to_vec();
+            sizes.extend((stored.len() as u32).to_le_bytes());
+            sizes.extend((contents.len() as u32).to_le_bytes());
+            let flags: u16 = if descriptors { 8 } else { 0 };
+
+            let offset = archive.len() as u32;
+            archive.extend(b"PK\x03\x04\x14\x00");
+            archive.extend(flags.to_le_bytes());
+            archive.extend(method.to_le_bytes());
+            archive.extend([0; 4]);
+            archive.extend(if descriptors { vec![0; 12] } else { sizes.clone() });
+            archive.extend((name.len() as u16).to_le_bytes());
+            archive.extend([0, 0]);
+            archive.extend(name.as_bytes());
+            archive.extend(&stored);
+            if descriptors {
+                archive.extend(b"PK\x07\x08");
+                archive.extend(&sizes);
+            }
+
+            directory.extend(b"PK\x01\x02\x14\x00\x14\x00");
+            directory.extend(flags.to_le_bytes());
+            directory.extend(method.to_le_bytes());
+            directory.extend([0; 4]);
+            directory.extend(&sizes);
+            directory.extend((name.len() as u16).to_le_bytes());
+            directory.extend([0; 12]);
+            directory.extend(offset.to_le_bytes());
+            directory.extend(name.as_bytes());
+        }
+        let directory_offset = archive.len() as u32;
+        archive.extend(&directory);
+        archive.extend(b"PK\x05\x06\x00\x00\x00\x00");
+        archive.extend((members.len() as u16).to_le_bytes());
+        archive.extend((members.len() as u16).to_le_bytes());
+        archive.extend((directory.len() as u32).to_le_bytes());
+        archive.extend(directory_offset.to_le_bytes());
+        archive.extend([0, 0]);
+        archive
+    }
+
+    #[test]
+    fn test_decompress() {
+        let log = b"<call:4>W1AW<eor>\n";
+
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(log).unwrap();
+        assert_eq!(decompress(encoder.finish().unwrap()).unwrap(), log);
+
+        for descriptors in [false, true] {
+            let archive = zip(&[("readme.txt", 0, b"73"), ("__MACOSX/._contest.adi", 0, b"\0\0"), ("contest.ADI", 8, log)], descriptors);
+            assert_eq!(decompress(archive).unwrap(), log);
+        }
+
+        let error = decompress(zip(&[("readme.txt", 0, b"73")], false)).unwrap_err();
+        assert_eq!(error.to_string(), "zip archive: no .adi or .adif file");
+
+        assert_eq!(decompress(log.to_vec()).unwrap(), log);
+    }
+
+    #[test]
+    fn test_zip64_archive() {
+        let log = b"<call:4>W1AW<eor>\n";
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated).large_file(true);
+        writer.start_file("log.adi", options).unwrap();
+        writer.write_all(log).unwrap();
+        let archive = writer.finish().unwrap().into_inner();
+
+        assert_eq!(decompress(archive).unwrap(), log);
+    }
+}
@@ -0,0 +1,193 @@
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Command};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("{path}: {source}")]
+    Io { path: PathBuf, source: std::io::Error },
+    #[error("{path}: {source}")]
+    Syntax { path: PathBuf, source: toml::de::Error },
+    #[error("{path}: unsupported value for {key}")]
+    UnsupportedValue { path: PathBuf, key: String },
+    #[error("{path}: transadif has no --{name} option")]
+    UnknownOption { path: PathBuf, name: String },
+}
+
+/// Default options from a `transadif.toml`, one per option the file sets.
+///
+/// Keys are long option names (`encoding = "ISO-8859-1"`), with `true` for
+/// flags and arrays for repeatable options. A `[table]` turns its entries into
+/// `NAME=VALUE` options, so `[map]` holds column renames and `[set-header]`
+/// header fields.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub path: PathBuf,
+    /// Option names and their values, `None` for flags
+    pub options: Vec<(String, Option<String>)>,
+}
+
+impl Config {
+    pub const FILE_NAME: &'static str = "transadif.toml";
+
+    /// Loads the first config file in the current directory or
+    /// `~/.config/transadif/`, if there is one.
+    pub fn find() -> Result<Option<Self>, ConfigError> {
+        let mut candidates = vec![PathBuf::from(Self::FILE_NAME)];
+        let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")));
+        if let Some(config_dir) = config_dir {
+            candidates.push(config_dir.join("transadif").join(Self::FILE_NAME));
+        }
+
+        match candidates.into_iter().find(|path| path.is_file()) {
+            Some(path) => Self::load(&path).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let text = fs::read_to_string(path).map_err(|source| ConfigError::Io { path: path.to_path_buf(), source })?;
+        Self::parse(path, &text)
+    }
+
+    pub fn parse(path: &Path, text: &str) -> Result<Self, ConfigError> {
+        let table: toml::Table = toml::from_str(text).map_err(|source| ConfigError::Syntax { path: path.to_path_buf(), source })?;
+        let unsupported = |key: &str| ConfigError::UnsupportedValue { path: path.to_path_buf(), key: key.to_string() };
+        let mut options = Vec::new();
+
+        for (key, value) in &table {
+            match value {
+                toml::Value::Table(entries) => {
+                    for (entry, value) in entries {
+                        for value in values(value) {
+                            let value = scalar(value).ok_or_else(|| unsupported(&format!("{}.{}", key, entry)))?;
+                            options.push((key.clone(), Some(format!("{}={}", entry, value))));
+                        }
+                    }
+                }
+                value => {
+                    for value in values(value) {
+                        match value {
+                            toml::Value::Boolean(true) => options.push((key.clone(), None)),
+                            toml::Value::Boolean(false) => {}
+                            value => options.push((key.clone(), Some(scalar(value).ok_or_else(|| unsupported(key))?))),
+                        }
+                    }
+                }
+            }
+        }
+
+        let options = options.into_iter().map(|(name, value)| (name.replace('_', "-"), value)).collect();
+        Ok(Self { path: path.to_path_buf(), options })
+    }
+
+    /// Command-line arguments for the options of `command` the config sets
+    /// and `matches` (the actual command line) does not, so that flags given
+    /// on the command line override the file.
+    pub fn args(&self, command: &Command, matches: &ArgMatches) -> Result<Vec<OsString>, ConfigError> {
+        let mut args = Vec::new();
+        for (name, value) in &self.options {
+            let arg = command
+                .get_arguments()
+                .find(|arg| arg.get_long() == Some(name.as_str()))
+                .ok_or_else(|| ConfigError::UnknownOption { path: self.path.clone(), name: name.clone() })?;
+            if matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine) {
+                continue;
+            }
+            args.push(match value {
+                Some(value) => OsString::from(format!("--{}={}", name, value)),
+                None => OsString::from(format!("--{}", name)),
+            });
+        }
+        Ok(args)
+    }
+}
+
+/// The values of a key: the elements of an array, or the value itself.
+fn values(value: &toml::Value) -> &[toml::Value] {
+    match value {
+        toml::Value::Array(values) => values,
+        value => std::slice::from_ref(value),
+    }
+}
+
+/// A string or number as an option value.
+fn scalar(value: &toml::Value) -> Option<String> {
+    match value {
+        toml::Value::String(value) => Some(value.clone()),
+        toml::Value::Integer(value) => Some(value.to_string()),
+        toml::Value::Float(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Cli;
+    use clap::CommandFactory;
+
+    const CONFIG: &str = r#"
+# Defaults for the club log
+encoding = "ISO-8859-1"
+replace = '_'
+ascii = true
+strict = false
+profile = "wsjtx"
+drop-fields = ["address", "comment"]
+dedupe_window = 5
+
+[map]
+date = "qso_date"
+"#;
+
+    #[test]
+    fn test_parse() {
+        let config = Config::parse(Path::new("transadif.toml"), CONFIG).unwrap();
+        let option = |name: &str, value: Option<&str>| (name.to_string(), value.map(str::to_string));
+        assert_eq!(
+            config.options,
+            vec![
+                option("encoding", Some("ISO-8859-1")),
+                option("replace", Some("_")),
+                option("ascii", None),
+                option("profile", Some("wsjtx")),
+                option("drop-fields", Some("address")),
+                option("drop-fields", Some("comment")),
+                option("dedupe-window", Some("5")),
+                option("map", Some("date=qso_date")),
+            ]
+        );
+
+        let error = Config::parse(Path::new("transadif.toml"), "encoding = \"UTF-8\"\nascii = yes\n").unwrap_err();
+        assert!(error.to_string().starts_with("transadif.toml: TOML parse error at line 2, column 9"), "{}", error);
+
+        let error = Config::parse(Path::new("transadif.toml"), "[map]\ndate = { from = \"date\" }\n").unwrap_err();
+        assert_eq!(error.to_string(), "transadif.toml: unsupported value for map.date");
+    }
+
+    #[test]
+    fn test_command_line_overrides_config() {
+        let config = Config::parse(Path::new("transadif.toml"), CONFIG).unwrap();
+        let matches = Cli::command().get_matches_from(["transadif", "-e", "UTF-8", "--drop-fields", "notes", "log.adi"]);
+        let args: Vec<String> = config
+            .args(&Cli::command(), &matches)
+            .unwrap()
+            .into_iter()
+            .map(|arg| arg.into_string().unwrap())
+            .collect();
+        assert_eq!(
+            args,
+            vec!["--replace=_", "--ascii", "--profile=wsjtx", "--dedupe-window=5", "--map=date=qso_date"]
+        );
+
+        let unknown = Config::parse(Path::new("transadif.toml"), "colour = \"always\"").unwrap();
+        let error = unknown.args(&Cli::command(), &matches).unwrap_err();
+        assert_eq!(error.to_string(), "transadif.toml: transadif has no --colour option");
+    }
+}
@@ -0,0 +1,149 @@
+use crate::adif::{AdifFile, ParseIssue, ParseOptions};
+use crate::compress;
+use crate::encoding::{AdifEncoding, Locale};
+use crate::output::OutputFormatter;
+use crate::preset::Preset;
+use crate::profile::{Profile, Repair};
+use crate::report::Report;
+use crate::Result;
+
+/// Settings for `convert`, the library counterpart of the `convert` command's
+/// main options.
+#[derive(Debug, Clone)]
+pub struct ConversionOptions {
+    /// Encoding of the input; `None` uses the header's ENCODING, else detects per field
+    pub input_encoding: Option<AdifEncoding>,
+    pub output_encoding: AdifEncoding,
+    /// Do not correct invalid characters or field counts
+    pub strict: bool,
+    /// Character for what the output encoding cannot represent; `None` deletes it
+    pub replacement_char: Option<char>,
+    /// Transliterate to characters without diacritics
+    pub ascii: bool,
+    /// Skip fields that cannot be parsed instead of failing
+    pub skip_malformed: bool,
+    /// Program whose known quirks to repair first
+    pub profile: Option<Profile>,
+    /// Region whose encodings, misreadings and transliterations to use
+    pub preset: Option<Preset>,
+    /// Language of the free text, hinting encoding detection
+    pub locale: Option<Locale>,
+}
+
+impl Default for ConversionOptions {
+    fn default() -> Self {
+        Self {
+            input_encoding: None,
+            output_encoding: AdifEncoding::Utf8,
+            strict: false,
+            replacement_char: Some('?'),
+            ascii: false,
+            skip_malformed: false,
+            profile: None,
+            preset: None,
+            locale: None,
+        }
+    }
+}
+
+/// The converted file and what the conversion ran into
+#[derive(Debug, Clone)]
+pub struct ConversionResult {
+    /// The converted ADIF
+    pub output: Vec<u8>,
+    /// Number of records written
+    pub records: usize,
+    /// Problems the parser recovered from, as `AdifFile::warnings`
+    pub warnings: Vec<String>,
+    /// Fields left out with `skip_malformed`
+    pub errors: Vec<ParseIssue>,
+    /// Changes made by the `profile`
+    pub repairs: Vec<Repair>,
+}
+
+/// Converts a whole ADIF file, which may be gzip-compressed or zipped, the
+/// way `transadif convert` does without further options.
+pub fn convert(input: &[u8], options: &ConversionOptions) -> Result<ConversionResult> {
+    let (adif, formatter, repairs) = prepare(input, options)?;
+    let mut output = Vec::new();
+    formatter.format_adif(&adif, &mut output)?;
+
+    Ok(ConversionResult { output, records: adif.records.len(), warnings: adif.warnings, errors: adif.errors, repairs })
+}
+
+/// Converts a file like `convert` and reports what the conversion changed,
+/// for programs that show it to the user before saving the output.
+pub fn convert_bytes(input: &[u8], options: ConversionOptions) -> Result<(Vec<u8>, Report)> {
+    let (adif, formatter, _repairs) = prepare(input, &options)?;
+    let report = formatter.report(&adif)?;
+    let mut output = Vec::new();
+    formatter.format_adif(&adif, &mut output)?;
+
+    Ok((output, report))
+}
+
+/// Parses and decodes `input` and applies the profile, returning the file
+/// with the formatter that writes it.
+fn prepare(input: &[u8], options: &ConversionOptions) -> Result<(AdifFile, OutputFormatter, Vec<Repair>)> {
+    let data = compress::decompress(input.to_vec())?;
+    let mut adif = AdifFile::parse_with(data, 0, ParseOptions { recover: options.skip_malformed, strict: options.strict })?;
+
+    // The parser converts UTF-16 input to UTF-8, which the header then names
+    let input_encoding = options
+        .input_encoding
+        .clone()
+        .filter(|encoding| !encoding.is_utf16())
+        .or_else(|| adif.declared_encoding());
+    let mut formatter = OutputFormatter::new(
+        input_encoding,
+        options.output_encoding.clone(),
+        options.strict,
+        options.replacement_char,
+        options.replacement_char.is_none(),
+        options.ascii,
+    );
+    if let Some(preset) = options.preset {
+        formatter.set_preset(preset);
+    }
+    formatter.set_locale(options.locale);
+    formatter.decode(&mut adif)?;
+    let repairs = options.profile.map_or_else(Vec::new, |profile| profile.repair(&mut adif));
+
+    Ok((adif, formatter, repairs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert() {
+        let options = ConversionOptions { output_encoding: AdifEncoding::Iso88591, ..ConversionOptions::default() };
+        let result = convert(b"Log<encoding:5>UTF-8<eoh>\n<call:4>DL1X<name:5>J\xc3\xbcrg<eor>\n", &options).unwrap();
+
+        assert_eq!(result.output, b"Log<encoding:10>ISO-8859-1\n<eoh>\n<call:4>DL1X<name:4>J\xfcrg<eor>\n");
+        assert_eq!(result.records, 1);
+        assert!(result.warnings.is_empty() && result.errors.is_empty());
+    }
+
+    #[test]
+    fn test_utf16_header_over_utf8_is_ignored() {
+        let result = convert(b"Log<encoding:6>UTF-16<eoh>\n<call:5>UA1AA<name:4>J\xc3\xb6rg<eor>\n", &ConversionOptions::default()).unwrap();
+        assert!(result.output.ends_with("<call:5>UA1AA<name:4>Jörg<eor>\n".as_bytes()));
+
+        // Real UTF-16 is still read as such
+        let utf16: Vec<u8> = "\u{feff}Log<encoding:6>UTF-16<eoh>\n<call:5>UA1AA<eor>\n".encode_utf16().flat_map(u16::to_le_bytes).collect();
+        assert!(convert(&utf16, &ConversionOptions::default()).unwrap().output.ends_with(b"<call:5>UA1AA<eor>\n"));
+    }
+
+    #[test]
+    fn test_convert_bytes_reports_changes() {
+        let options = ConversionOptions { profile: Some(Profile::Wsjtx), ..ConversionOptions::default() };
+        let (output, report) = convert_bytes(b"<call:4>DL1X<name:7>J\xc3\x83\xc2\xbcrg<eor>\n<call:4>DL1Y", options).unwrap();
+
+        assert!(output.ends_with(b"<call:4>DL1X<name:4>J\xc3\xbcrg<eor>\n"));
+        assert_eq!(report.mojibake_corrections, 1);
+        assert_eq!(report.changes.len(), 1);
+        assert_eq!(report.changes[0].after, "Jürg");
+    }
+}
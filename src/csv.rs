@@ -0,0 +1,199 @@
+use crate::adif::{AdifFile, Field, Record};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum CsvError {
+    #[error("Missing header row")]
+    MissingHeader,
+    #[error("Unterminated quoted value starting on line {0}")]
+    UnterminatedQuote(usize),
+}
+
+/// Converts delimited text (CSV, TSV) into an `AdifFile`, one record per row.
+///
+/// The first row names the columns. Column names go through `renames`
+/// (case-insensitive `from → to` pairs, an empty `to` drops the column) and
+/// are then turned into ADIF field names. Cells keep their raw bytes so the
+/// output formatter decodes them like any ADIF field; empty cells are left out.
+pub fn parse(data: &[u8], delimiter: u8, renames: &[(String, String)]) -> Result<AdifFile, CsvError> {
+    let data = data.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(data);
+    let mut rows = Rows { data, delimiter, position: 0, line: 1 };
+
+    let header = rows.next_row()?.ok_or(CsvError::MissingHeader)?;
+    let names: Vec<Option<String>> = header.cells.iter().map(|column| field_name(column, renames)).collect();
+
+    let mut adif = AdifFile::new();
+    adif.preamble = "Converted by TransADIF\r\n\r\n".to_string();
+    let mut programid = Field::new("programid", "TransADIF");
+    programid.excess_data = "\r\n".to_string();
+    adif.header_fields.push(programid);
+    adif.header_excess_data = "\r\n".to_string();
+
+    while let Some(row) = rows.next_row()? {
+        let offset = row.offset;
+        let mut fields: Vec<Field> = names
+            .iter()
+            .zip(row.cells)
+            .filter_map(|(name, cell)| Some((name.as_ref()?, cell)))
+            .filter(|(_, cell)| !cell.is_empty())
+            .map(|(name, cell)| {
+                let mut field = Field::new(name, &String::from_utf8_lossy(&cell));
+                field.original_bytes = cell.into();
+                field.excess_data = " ".to_string();
+                field.offset = offset; // Cells only know their row
+                field
+            })
+            .collect();
+
+        if let Some(last) = fields.last_mut() {
+            last.excess_data.clear();
+        }
+        adif.records.push(Record {
+            fields,
+            excess_data: "\r\n".to_string(),
+            offset,
+            end_tag: String::new(),
+        });
+    }
+
+    Ok(adif)
+}
+
+/// ADIF field name for a column: renamed if requested, then lowercased with
+/// anything but letters, digits and underscores turned into underscores.
+fn field_name(column: &[u8], renames: &[(String, String)]) -> Option<String> {
+    let column = String::from_utf8_lossy(column);
+    let column = column.trim();
+
+    let name = renames
+        .iter()
+        .find(|(from, _)| from.eq_ignore_ascii_case(column))
+        .map_or(column, |(_, to)| to.as_str());
+
+    if name.is_empty() {
+        return None;
+    }
+
+    Some(
+        name.chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+            .collect(),
+    )
+}
+
+struct Row {
+    offset: usize,
+    cells: Vec<Vec<u8>>,
+}
+
+struct Rows<'a> {
+    data: &'a [u8],
+    delimiter: u8,
+    position: usize,
+    line: usize,
+}
+
+impl Rows<'_> {
+    /// Reads the next non-blank row.
+    fn next_row(&mut self) -> Result<Option<Row>, CsvError> {
+        while matches!(self.data.get(self.position), Some(b'\r' | b'\n')) {
+            self.skip_line_break();
+        }
+        if self.position >= self.data.len() {
+            return Ok(None);
+        }
+
+        let offset = self.position;
+        let mut cells = Vec::new();
+        let mut cell = Vec::new();
+
+        while let Some(&byte) = self.data.get(self.position) {
+            match byte {
+                b'"' if cell.is_empty() => self.read_quoted(&mut cell)?,
+                b'\r' | b'\n' => {
+                    self.skip_line_break();
+                    break;
+                }
+                _ if byte == self.delimiter => {
+                    cells.push(std::mem::take(&mut cell));
+                    self.position += 1;
+                }
+                _ => {
+                    cell.push(byte);
+                    self.position += 1;
+                }
+            }
+        }
+
+        cells.push(cell);
+        Ok(Some(Row { offset, cells }))
+    }
+
+    fn read_quoted(&mut self, cell: &mut Vec<u8>) -> Result<(), CsvError> {
+        let start_line = self.line;
+        self.position += 1;
+
+        loop {
+            match self.data.get(self.position) {
+                None => return Err(CsvError::UnterminatedQuote(start_line)),
+                Some(b'"') if self.data.get(self.position + 1) == Some(&b'"') => {
+                    cell.push(b'"');
+                    self.position += 2;
+                }
+                Some(b'"') => {
+                    self.position += 1;
+                    return Ok(());
+                }
+                Some(&byte) => {
+                    if byte == b'\n' {
+                        self.line += 1;
+                    }
+                    cell.push(byte);
+                    self.position += 1;
+                }
+            }
+        }
+    }
+
+    fn skip_line_break(&mut self) {
+        if self.data[self.position..].starts_with(b"\r\n") {
+            self.position += 2;
+        } else {
+            self.position += 1;
+        }
+        self.line += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_csv_with_renames_and_quotes() {
+        let data = "Call,Date,Time,Comment,Internal\r\nK1MIX,20240101,1200,\"Tnx, 73 \"\"OM\"\"\",x\r\n\r\nDL1XX,20240102,1300,Jörg\r\n";
+        let renames = vec![
+            ("date".to_string(), "qso_date".to_string()),
+            ("time".to_string(), "time_on".to_string()),
+            ("internal".to_string(), String::new()),
+        ];
+
+        let adif = parse(data.as_bytes(), b',', &renames).unwrap();
+
+        assert_eq!(adif.records.len(), 2);
+        let first = &adif.records[0];
+        let names: Vec<&str> = first.fields.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["call", "qso_date", "time_on", "comment"]);
+        assert_eq!(first.get("comment"), Some("Tnx, 73 \"OM\""));
+        assert_eq!(adif.records[1].get("comment"), Some("Jörg"));
+        assert_eq!(adif.records[1].fields[3].original_bytes, "Jörg".as_bytes());
+    }
+
+    #[test]
+    fn test_parse_tsv_reports_unterminated_quote() {
+        let adif = parse(b"call\tband\nK1MIX\t20m\n", b'\t', &[]).unwrap();
+        assert_eq!(adif.records[0].get("band"), Some("20m"));
+
+        assert!(matches!(parse(b"call\n\"K1MIX\n", b',', &[]), Err(CsvError::UnterminatedQuote(2))));
+    }
+}
@@ -0,0 +1,298 @@
+use crate::adif::Record;
+use crate::callsign;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use thiserror::Error;
+
+/// Portable suffixes that say nothing about where a station is
+const LOCATION_NEUTRAL_SUFFIXES: [&str; 7] = ["P", "M", "QRP", "A", "B", "LH", "R"];
+
+/// Suffixes of stations at sea or in the air, which count for no entity
+const MOBILE_SUFFIXES: [&str; 2] = ["MM", "AM"];
+
+#[derive(Error, Debug)]
+pub enum CtyError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Line {0}: expected an entity line of cty.dat or cty.csv")]
+    InvalidEntity(usize),
+}
+
+/// A DXCC entity as listed in a country file, with the zones and continent
+/// of the prefix a callsign matched
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entity {
+    pub name: String,
+    /// ADIF entity code, which only cty.csv carries
+    pub dxcc: Option<u32>,
+    pub continent: String,
+    pub cq_zone: u32,
+    pub itu_zone: u32,
+}
+
+/// A prefix or exact callsign with the zones and continent it overrides
+#[derive(Debug, Clone, Default)]
+struct Prefix {
+    entity: usize,
+    continent: Option<String>,
+    cq_zone: Option<u32>,
+    itu_zone: Option<u32>,
+}
+
+/// Callsign prefixes of the DXCC entities, from the country files published
+/// at country-files.com: cty.dat as used by contest loggers, or cty.csv,
+/// which also carries the ADIF entity codes needed to fill in DXCC.
+/// WAE-only entities (marked `*`) are left out, so their prefixes resolve to
+/// the DXCC entity they belong to.
+#[derive(Debug, Clone, Default)]
+pub struct CtyDatabase {
+    entities: Vec<Entity>,
+    prefixes: HashMap<String, Prefix>,
+    callsigns: HashMap<String, Prefix>,
+}
+
+impl CtyDatabase {
+    pub fn load(path: &Path) -> Result<Self, CtyError> {
+        Self::parse(&String::from_utf8_lossy(&fs::read(path)?))
+    }
+
+    /// Parses cty.dat or cty.csv, whichever `text` looks like.
+    pub fn parse(text: &str) -> Result<Self, CtyError> {
+        let mut database = Self::default();
+        let csv = text.lines().find(|line| !line.trim().is_empty()).is_some_and(|line| !line.contains(':'));
+
+        let mut line = 1;
+        for entry in text.split_inclusive(';') {
+            let leading = &entry[..entry.len() - entry.trim_start().len()];
+            let entry_line = line + leading.matches('\n').count();
+            line += entry.matches('\n').count();
+            let entry = entry.trim().trim_end_matches(';');
+            if entry.is_empty() {
+                continue;
+            }
+
+            let parsed = if csv { parse_csv_entry(entry) } else { parse_dat_entry(entry) };
+            let (primary, entity, prefixes) = parsed.ok_or(CtyError::InvalidEntity(entry_line))?;
+            if primary.starts_with('*') {
+                continue;
+            }
+
+            let index = database.entities.len();
+            database.entities.push(entity);
+            for token in prefixes.split([',', ' ', '\t', '\r', '\n']).filter(|token| !token.is_empty()) {
+                let (exact, name, prefix) = parse_prefix(token, index).ok_or(CtyError::InvalidEntity(entry_line))?;
+                let table = if exact { &mut database.callsigns } else { &mut database.prefixes };
+                table.insert(name, prefix);
+            }
+        }
+
+        Ok(database)
+    }
+
+    /// The entity a callsign belongs to: an exact listing first, then the
+    /// longest listed prefix. For calls like `HB9/DL1ABC/P`, the shorter of
+    /// the parts around the first slash counts as the prefix. Stations at
+    /// sea or in the air (`/MM`, `/AM`) belong to none.
+    pub fn lookup(&self, call: &str) -> Option<Entity> {
+        let call = callsign::normalize(call);
+        if let Some(prefix) = self.callsigns.get(&call) {
+            return Some(self.resolve(prefix));
+        }
+
+        let mut parts: Vec<&str> = call.split('/').filter(|part| !part.is_empty()).collect();
+        if parts.iter().skip(1).any(|part| MOBILE_SUFFIXES.contains(part)) {
+            return None;
+        }
+        parts.retain(|part| !is_location_neutral(part));
+        if let Some(prefix) = self.callsigns.get(&parts.join("/")) {
+            return Some(self.resolve(prefix));
+        }
+
+        let location = match parts.as_slice() {
+            [] => return None,
+            [call] => *call,
+            [first, second, ..] if second.len() < first.len() => *second,
+            [first, ..] => *first,
+        };
+        location
+            .char_indices()
+            .map(|(start, c)| start + c.len_utf8())
+            .rev()
+            .find_map(|length| self.prefixes.get(&location[..length]))
+            .map(|prefix| self.resolve(prefix))
+    }
+
+    /// Sets missing or empty DXCC, COUNTRY, CONT, CQZ and ITUZ fields from the
+    /// record's CALL. DXCC is only filled in from cty.csv. Returns the number
+    /// of fields filled in.
+    pub fn enrich(&self, record: &mut Record) -> usize {
+        let Some(call_field) = record.fields.iter().find(|field| field.name.eq_ignore_ascii_case("call")) else {
+            return 0;
+        };
+        let lowercase = call_field.name == "call";
+        let Some(entity) = self.lookup(&call_field.data) else {
+            return 0;
+        };
+
+        let values = [
+            ("DXCC", entity.dxcc.map(|dxcc| dxcc.to_string())),
+            ("COUNTRY", Some(entity.name)),
+            ("CONT", Some(entity.continent)),
+            ("CQZ", Some(entity.cq_zone.to_string())),
+            ("ITUZ", Some(entity.itu_zone.to_string())),
+        ];
+
+        // New fields follow the case of the record's own field names
+        let mut filled = 0;
+        for (name, value) in values {
+            let Some(value) = value else { continue };
+            if record.get(name).is_none_or(|value| value.trim().is_empty()) {
+                record.set(&if lowercase { name.to_lowercase() } else { name.to_string() }, &value);
+                filled += 1;
+            }
+        }
+
+        filled
+    }
+
+    fn resolve(&self, prefix: &Prefix) -> Entity {
+        let entity = &self.entities[prefix.entity];
+        Entity {
+            continent: prefix.continent.clone().unwrap_or_else(|| entity.continent.clone()),
+            cq_zone: prefix.cq_zone.unwrap_or(entity.cq_zone),
+            itu_zone: prefix.itu_zone.unwrap_or(entity.itu_zone),
+            ..entity.clone()
+        }
+    }
+}
+
+/// Portable suffixes and call area digits, as in `W1AW/4`, which are taken
+/// to stay within the entity
+fn is_location_neutral(part: &str) -> bool {
+    LOCATION_NEUTRAL_SUFFIXES.contains(&part) || part.len() == 1 && part.as_bytes()[0].is_ascii_digit()
+}
+
+/// `Name: CQ: ITU: Cont: Lat: Lon: UTC offset: Primary prefix: prefixes`
+fn parse_dat_entry(entry: &str) -> Option<(&str, Entity, &str)> {
+    let columns: Vec<&str> = entry.splitn(9, ':').map(str::trim).collect();
+    let [name, cq_zone, itu_zone, continent, _, _, _, primary, prefixes] = columns.as_slice() else {
+        return None;
+    };
+
+    let entity = Entity {
+        name: name.to_string(),
+        dxcc: None,
+        continent: continent.to_string(),
+        cq_zone: cq_zone.parse().ok()?,
+        itu_zone: itu_zone.parse().ok()?,
+    };
+    Some((primary, entity, prefixes))
+}
+
+/// `Primary prefix,Name,DXCC,Cont,CQ,ITU,Lat,Lon,UTC offset,prefixes`
+fn parse_csv_entry(entry: &str) -> Option<(&str, Entity, &str)> {
+    let columns: Vec<&str> = entry.splitn(10, ',').map(str::trim).collect();
+    let [primary, name, dxcc, continent, cq_zone, itu_zone, _, _, _, prefixes] = columns.as_slice() else {
+        return None;
+    };
+
+    let entity = Entity {
+        name: name.to_string(),
+        dxcc: Some(dxcc.parse().ok()?),
+        continent: continent.to_string(),
+        cq_zone: cq_zone.parse().ok()?,
+        itu_zone: itu_zone.parse().ok()?,
+    };
+    Some((primary, entity, prefixes))
+}
+
+/// `=W1AW(5)[8]{NA}<42.5/-72.7>~-5.0~`: a leading `=` marks an exact
+/// callsign, `(CQ)`, `[ITU]` and `{continent}` override the entity's values.
+fn parse_prefix(token: &str, entity: usize) -> Option<(bool, String, Prefix)> {
+    let (exact, token) = token.strip_prefix('=').map_or((false, token), |token| (true, token));
+    let end = token.find(['(', '[', '{', '<', '~']).unwrap_or(token.len());
+    let (name, mut overrides) = token.split_at(end);
+    if name.is_empty() {
+        return None;
+    }
+
+    let mut prefix = Prefix { entity, ..Prefix::default() };
+    while let Some(open) = overrides.chars().next() {
+        let close = match open {
+            '(' => ')',
+            '[' => ']',
+            '{' => '}',
+            '<' => '>',
+            '~' => '~',
+            _ => return None,
+        };
+        let (value, rest) = overrides[1..].split_once(close)?;
+        match open {
+            '(' => prefix.cq_zone = Some(value.parse().ok()?),
+            '[' => prefix.itu_zone = Some(value.parse().ok()?),
+            '{' => prefix.continent = Some(value.to_string()),
+            _ => {}
+        }
+        overrides = rest;
+    }
+
+    Some((exact, name.to_uppercase(), prefix))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adif::AdifFile;
+
+    const CTY_DAT: &str = "\
+Fed. Rep. of Germany:     14:  28:  EU:   51.00:   -10.00:    -1.0:  DL:
+    DA,DB,DC,DD,DE,DF,DG,DH,DI,DJ,DK,DL,DM,DN,DO,DP,DQ,DR,Y2,Y3,Y4,Y5,Y6,Y7,Y8,Y9;
+Switzerland:              14:  28:  EU:   46.87:    -8.12:    -1.0:  HB:
+    HB,HE;
+Liechtenstein:            14:  28:  EU:   47.13:    -9.57:    -1.0:  HB0:
+    HB0,HE0;
+United States:            05:  08:  NA:   37.53:    91.67:     5.0:  K:
+    AA,K,N,W,=W1AW/7(3)[6],KH6(31)[61]{OC};
+Sicily:                   15:  28:  EU:   37.50:   -14.00:    -1.0:  *IT9:
+    IT9;
+Italy:                    15:  28:  EU:   42.82:   -12.58:    -1.0:  I:
+    I;
+";
+
+    #[test]
+    fn test_lookup() {
+        let cty = CtyDatabase::parse(CTY_DAT).unwrap();
+        let name = |call: &str| cty.lookup(call).map(|entity| entity.name);
+
+        assert_eq!(name("dl1abc").as_deref(), Some("Fed. Rep. of Germany"));
+        assert_eq!(name("HB9/DL1ABC/P").as_deref(), Some("Switzerland"));
+        assert_eq!(name("HB0/DL1ABC").as_deref(), Some("Liechtenstein"));
+        assert_eq!(name("IT9ABC").as_deref(), Some("Italy"));
+        assert_eq!(name("DL1ABC/MM"), None);
+        assert_eq!(name("ZZ9ZZ"), None);
+        assert_eq!(name("DLä1A").as_deref(), Some("Fed. Rep. of Germany"));
+        assert_eq!(name("Ä1ÖX/P"), None);
+
+        let hawaii = cty.lookup("KH6ABC").unwrap();
+        assert_eq!((hawaii.continent.as_str(), hawaii.cq_zone, hawaii.itu_zone), ("OC", 31, 61));
+        let exact = cty.lookup("W1AW/7").unwrap();
+        assert_eq!((exact.continent.as_str(), exact.cq_zone, exact.itu_zone, exact.dxcc), ("NA", 3, 6, None));
+
+        assert!(matches!(CtyDatabase::parse("Germany: 14: 28: EU: DL: DL;"), Err(CtyError::InvalidEntity(1))));
+    }
+
+    #[test]
+    fn test_enrich() {
+        let cty = CtyDatabase::parse("DL,Fed. Rep. of Germany,230,EU,14,28,51.00,-10.00,-1.0,DA DL DK;\n").unwrap();
+        let mut adif = AdifFile::parse(b"<eoh><call:6>DK1ABC<cqz:2>15<ituz:0><eor><CALL:4>W1AW<eor>").unwrap();
+
+        assert_eq!(cty.enrich(&mut adif.records[0]), 4);
+        assert_eq!(cty.enrich(&mut adif.records[1]), 0);
+        let fields: Vec<(&str, &str)> = adif.records[0].fields.iter().map(|field| (field.name.as_str(), field.data.as_str())).collect();
+        assert_eq!(
+            fields,
+            vec![("call", "DK1ABC"), ("cqz", "15"), ("ituz", "28"), ("dxcc", "230"), ("country", "Fed. Rep. of Germany"), ("cont", "EU")]
+        );
+    }
+}
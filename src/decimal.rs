@@ -0,0 +1,236 @@
+use crate::adif::Record;
+use crate::validation::{field_type, DataType, BANDS};
+use std::fmt;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum DecimalError {
+    #[error("QSO {}: {} at byte {}: {:?} could be {} or {}", .record + 1, .field.to_uppercase(), .offset, .value, .decimal, .thousands)]
+    Ambiguous {
+        record: usize,
+        field: String,
+        offset: usize,
+        value: String,
+        decimal: String,
+        thousands: String,
+    },
+}
+
+/// How a number written with commas reads with a decimal point
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reading {
+    /// The commas can only mean one thing
+    Certain(String),
+    /// A single comma before three digits, as a decimal comma or a thousands separator
+    Ambiguous { decimal: String, thousands: String },
+}
+
+/// A number field rewritten with a decimal point
+#[derive(Debug, Clone, PartialEq)]
+pub struct Repair {
+    /// Index of the record in the file, starting at 0
+    pub record: usize,
+    pub field: String,
+    /// Byte offset of the field in the input
+    pub offset: usize,
+    pub original: String,
+    pub repaired: String,
+    /// The other reading, when nothing in the record settled which was meant
+    pub alternative: Option<String>,
+}
+
+impl fmt::Display for Repair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "QSO {}: {} at byte {}: {:?} -> {:?}", self.record + 1, self.field.to_uppercase(), self.offset, self.original, self.repaired)?;
+        if let Some(alternative) = &self.alternative {
+            write!(f, " (ambiguous, could also be {:?})", alternative)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads a number written with a decimal comma (`14,074`), thousands
+/// separators (`1,296.1`) or both (`1.296,1`). Returns `None` for values
+/// without commas and for those that do not parse either way.
+pub fn read_number(value: &str) -> Option<Reading> {
+    let value = value.trim();
+    let (sign, digits) = match value.strip_prefix('-') {
+        Some(digits) => ("-", digits),
+        None => ("", value),
+    };
+    if !digits.contains(',') || !digits.chars().all(|c| c.is_ascii_digit() || c == ',' || c == '.') {
+        return None;
+    }
+
+    let commas = digits.matches(',').count();
+    let dots = digits.matches('.').count();
+    let number = |integer: &str, fraction: &str| {
+        if fraction.is_empty() {
+            format!("{}{}", sign, integer)
+        } else {
+            format!("{}{}.{}", sign, if integer.is_empty() { "0" } else { integer }, fraction)
+        }
+    };
+
+    match (commas, dots) {
+        (1, 0) => {
+            let (integer, fraction) = digits.split_once(',')?;
+            if integer.is_empty() && fraction.is_empty() {
+                return None;
+            }
+            let decimal = number(integer, fraction);
+            if fraction.len() == 3 && is_grouped(&[integer, fraction]) && integer.trim_start_matches('0') == integer {
+                Some(Reading::Ambiguous { decimal, thousands: format!("{}{}{}", sign, integer, fraction) })
+            } else {
+                Some(Reading::Certain(decimal))
+            }
+        }
+        // Thousands separators only
+        (_, 0) => {
+            let groups: Vec<&str> = digits.split(',').collect();
+            is_grouped(&groups).then(|| Reading::Certain(number(&groups.concat(), "")))
+        }
+        // Thousands commas before a decimal point
+        (_, 1) if digits.rfind(',') < digits.find('.') => {
+            let (integer, fraction) = digits.split_once('.')?;
+            let groups: Vec<&str> = integer.split(',').collect();
+            is_grouped(&groups).then(|| Reading::Certain(number(&groups.concat(), fraction)))
+        }
+        // Thousands dots before a decimal comma
+        (1, _) if digits.rfind('.') < digits.find(',') => {
+            let (integer, fraction) = digits.split_once(',')?;
+            let groups: Vec<&str> = integer.split('.').collect();
+            is_grouped(&groups).then(|| Reading::Certain(number(&groups.concat(), fraction)))
+        }
+        _ => None,
+    }
+}
+
+/// Whether `groups` are digit groups split at thousands: one to three digits,
+/// then three each.
+fn is_grouped(groups: &[&str]) -> bool {
+    groups.iter().all(|group| group.bytes().all(|b| b.is_ascii_digit()))
+        && groups.first().is_some_and(|first| (1..=3).contains(&first.len()))
+        && groups[1..].iter().all(|group| group.len() == 3)
+}
+
+/// Rewrites number fields of a record that use decimal commas or thousands
+/// separators. Ambiguous frequencies are settled by the band they were logged
+/// on; other ambiguous values are read as decimal commas, or refused when
+/// `strict`.
+pub fn repair_record(index: usize, record: &mut Record, strict: bool) -> Result<Vec<Repair>, DecimalError> {
+    let mut repairs = Vec::new();
+
+    for position in 0..record.fields.len() {
+        let field = &record.fields[position];
+        let name = field.name.to_lowercase();
+        if field_type(&name) != Some(DataType::Number) {
+            continue;
+        }
+        let Some(reading) = read_number(&field.data) else {
+            continue;
+        };
+
+        let (repaired, alternative) = match reading {
+            Reading::Certain(repaired) => (repaired, None),
+            Reading::Ambiguous { decimal, thousands } => {
+                let band = match name.as_str() {
+                    "freq" => record.get("band"),
+                    "freq_rx" => record.get("band_rx"),
+                    _ => None,
+                };
+                let in_band = |value: &str| in_band(band, value);
+
+                match (in_band(&decimal), in_band(&thousands)) {
+                    (Some(true), Some(false)) => (decimal, None),
+                    (Some(false), Some(true)) => (thousands, None),
+                    _ if strict => {
+                        return Err(DecimalError::Ambiguous {
+                            record: index,
+                            field: name,
+                            offset: field.offset,
+                            value: field.data.clone(),
+                            decimal,
+                            thousands,
+                        });
+                    }
+                    _ => (decimal, Some(thousands)),
+                }
+            }
+        };
+
+        let field = &mut record.fields[position];
+        repairs.push(Repair {
+            record: index,
+            field: name,
+            offset: field.offset,
+            original: field.data.clone(),
+            repaired: repaired.clone(),
+            alternative,
+        });
+        field.length = repaired.len();
+        field.original_bytes = repaired.as_bytes().into();
+        field.data = repaired;
+    }
+
+    Ok(repairs)
+}
+
+/// Whether a frequency in MHz lies within `band`, if the band is known.
+fn in_band(band: Option<&str>, value: &str) -> Option<bool> {
+    let band = band?.trim();
+    let (_, low, high) = BANDS.iter().find(|(name, _, _)| name.eq_ignore_ascii_case(band))?;
+    let freq: f64 = value.parse().ok()?;
+    Some(freq >= *low && freq <= *high)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adif::AdifFile;
+
+    fn certain(value: &str) -> Option<Reading> {
+        Some(Reading::Certain(value.to_string()))
+    }
+
+    #[test]
+    fn test_read_number() {
+        assert_eq!(read_number("14,07"), certain("14.07"));
+        assert_eq!(read_number("-0,5"), certain("-0.5"));
+        assert_eq!(read_number("1,296.1"), certain("1296.1"));
+        assert_eq!(read_number("1.296,1"), certain("1296.1"));
+        assert_eq!(read_number("10,368,100"), certain("10368100"));
+        assert_eq!(
+            read_number("14,074"),
+            Some(Reading::Ambiguous { decimal: "14.074".to_string(), thousands: "14074".to_string() })
+        );
+        assert_eq!(read_number("14.074"), None);
+        assert_eq!(read_number("1,2,3"), None);
+        assert_eq!(read_number("14,07.4"), None);
+    }
+
+    #[test]
+    fn test_repair_record() {
+        let mut adif = AdifFile::parse(
+            b"Log\r\n<eoh>\
+              <call:4>W1AW<band:3>20m<freq:6>14,074<tx_pwr:3>5,5<eor>\
+              <call:4>K1AB<band:4>23cm<freq:5>1,296<eor>\
+              <call:4>N0NB<freq:5>7,074<eor>",
+        )
+        .unwrap();
+
+        let repairs = repair_record(0, &mut adif.records[0], true).unwrap();
+        assert_eq!(repairs.len(), 2);
+        assert_eq!(adif.records[0].get("freq"), Some("14.074"));
+        assert_eq!(adif.records[0].get("tx_pwr"), Some("5.5"));
+
+        repair_record(1, &mut adif.records[1], true).unwrap();
+        assert_eq!(adif.records[1].get("freq"), Some("1296"));
+
+        let error = repair_record(2, &mut adif.records[2].clone(), true).unwrap_err();
+        assert_eq!(error.to_string(), "QSO 3: FREQ at byte 119: \"7,074\" could be 7.074 or 7074");
+
+        let repairs = repair_record(2, &mut adif.records[2], false).unwrap();
+        assert_eq!(repairs[0].to_string(), "QSO 3: FREQ at byte 119: \"7,074\" -> \"7.074\" (ambiguous, could also be \"7074\")");
+    }
+}
@@ -0,0 +1,148 @@
+use crate::adif::Record;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// What makes two QSOs duplicates of each other
+#[derive(Debug, Clone)]
+pub struct DedupeKey {
+    /// Fields whose values must match, ignoring case and surrounding spaces.
+    /// `qso_date` and `time_on` match when the start times are within `window_minutes`.
+    pub fields: Vec<String>,
+    pub window_minutes: u32,
+}
+
+impl Default for DedupeKey {
+    fn default() -> Self {
+        Self {
+            fields: ["call", "band", "mode", "qso_date", "time_on"].iter().map(|f| f.to_string()).collect(),
+            window_minutes: 2,
+        }
+    }
+}
+
+/// A QSO that repeats an earlier one
+#[derive(Debug, Clone, PartialEq)]
+pub struct Duplicate {
+    /// Index of the repeated record, starting at 0
+    pub record: usize,
+    /// Index of the earlier record it repeats
+    pub original: usize,
+}
+
+impl fmt::Display for Duplicate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "QSO {} duplicates QSO {}", self.record + 1, self.original + 1)
+    }
+}
+
+/// Finds the records that duplicate an earlier record under `key`. The first
+/// record of each set of duplicates counts as the original.
+pub fn find_duplicates(records: &[Record], key: &DedupeKey) -> Vec<Duplicate> {
+    let timed = key.fields.iter().any(|f| f.eq_ignore_ascii_case("qso_date"))
+        && key.fields.iter().any(|f| f.eq_ignore_ascii_case("time_on"));
+    let window = i64::from(key.window_minutes) * 60;
+
+    // Originals seen so far, by the values that must match exactly
+    let mut originals: HashMap<Vec<String>, Vec<(usize, Option<i64>)>> = HashMap::new();
+    let mut duplicates = Vec::new();
+
+    for (index, record) in records.iter().enumerate() {
+        let start = if timed { start_time(record) } else { None };
+        let exact: Vec<String> = key.fields
+            .iter()
+            .filter(|field| {
+                // Dates and times are compared through the window when they parse
+                start.is_none() || !(field.eq_ignore_ascii_case("qso_date") || field.eq_ignore_ascii_case("time_on"))
+            })
+            .map(|field| record.get(field).unwrap_or_default().trim().to_uppercase())
+            .collect();
+
+        let candidates = originals.entry(exact).or_default();
+        let original = candidates.iter().find(|(_, other)| match (start, other) {
+            (Some(start), Some(other)) => (start - other).abs() <= window,
+            (None, None) => true,
+            _ => false,
+        });
+
+        match original {
+            Some(&(original, _)) => duplicates.push(Duplicate { record: index, original }),
+            None => candidates.push((index, start)),
+        }
+    }
+
+    duplicates
+}
+
+/// Removes the duplicate records found by `find_duplicates`.
+pub fn remove_duplicates(records: &mut Vec<Record>, duplicates: &[Duplicate]) {
+    let removed: HashSet<usize> = duplicates.iter().map(|duplicate| duplicate.record).collect();
+    let mut index = 0;
+    records.retain(|_| {
+        let keep = !removed.contains(&index);
+        index += 1;
+        keep
+    });
+}
+
+/// Seconds since 1970-01-01 of the QSO_DATE (YYYYMMDD) and TIME_ON (HHMM or HHMMSS).
+pub(crate) fn start_time(record: &Record) -> Option<i64> {
+    let date = record.get("qso_date")?.trim();
+    let time = record.get("time_on")?.trim();
+    if date.len() != 8 || !matches!(time.len(), 4 | 6) || !date.bytes().chain(time.bytes()).all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let number = |text: &str, range: std::ops::Range<usize>| text[range].parse::<i64>().ok();
+    let days = days_from_civil(number(date, 0..4)?, number(date, 4..6)?, number(date, 6..8)?);
+    let seconds = number(time, 0..2)? * 3600 + number(time, 2..4)? * 60 + if time.len() == 6 { number(time, 4..6)? } else { 0 };
+
+    Some(days * 86400 + seconds)
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date (Howard Hinnant's algorithm).
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adif::AdifFile;
+
+    #[test]
+    fn test_find_duplicates_within_window() {
+        let adif = AdifFile::parse(
+            b"Log\r\n<eoh>\
+              <call:4>W1AW<band:3>20m<mode:3>FT8<qso_date:8>20240229<time_on:4>2359<eor>\
+              <call:4>w1aw<band:3>20M<mode:3>FT8<qso_date:8>20240301<time_on:6>000045<eor>\
+              <call:4>W1AW<band:3>20m<mode:3>FT8<qso_date:8>20240301<time_on:4>0010<eor>\
+              <call:4>W1AW<band:3>40m<mode:3>FT8<qso_date:8>20240301<time_on:4>0010<eor>\
+              <call:4>K1AB<band:3>40m<mode:2>CW<eor>\
+              <call:4>K1AB<band:3>40m<mode:2>CW<eor>",
+        )
+        .unwrap();
+
+        let duplicates = find_duplicates(&adif.records, &DedupeKey::default());
+        assert_eq!(duplicates, vec![Duplicate { record: 1, original: 0 }, Duplicate { record: 5, original: 4 }]);
+        assert_eq!(duplicates[0].to_string(), "QSO 2 duplicates QSO 1");
+
+        let mut records = adif.records.clone();
+        remove_duplicates(&mut records, &duplicates);
+        assert_eq!(records.len(), 4);
+
+        let wide = DedupeKey { window_minutes: 15, ..DedupeKey::default() };
+        assert_eq!(find_duplicates(&adif.records, &wide).len(), 3);
+    }
+
+    #[test]
+    fn test_days_from_civil() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11017);
+        assert_eq!(days_from_civil(2024, 3, 1) - days_from_civil(2024, 2, 28), 2);
+    }
+}
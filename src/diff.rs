@@ -0,0 +1,250 @@
+use crate::adif::Record;
+use crate::encoding::is_encoding_annotation;
+use crate::validation::{self, DataType};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Write};
+
+/// One line of a diff between two sequences of lines
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DiffLine<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Diffs two sequences of lines along their longest common subsequence.
+pub fn diff_lines<'a>(before: &'a [String], after: &'a [String]) -> Vec<DiffLine<'a>> {
+    // common[i][j] is the LCS length of before[i..] and after[j..]
+    let mut common = vec![vec![0usize; after.len() + 1]; before.len() + 1];
+    for i in (0..before.len()).rev() {
+        for j in (0..after.len()).rev() {
+            common[i][j] = if before[i] == after[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < before.len() || j < after.len() {
+        if i < before.len() && j < after.len() && before[i] == after[j] {
+            lines.push(DiffLine::Same(&before[i]));
+            i += 1;
+            j += 1;
+        } else if j == after.len() || (i < before.len() && common[i + 1][j] >= common[i][j + 1]) {
+            lines.push(DiffLine::Removed(&before[i]));
+            i += 1;
+        } else {
+            lines.push(DiffLine::Added(&after[j]));
+            j += 1;
+        }
+    }
+
+    lines
+}
+
+/// Formats one unified diff hunk spanning all of `before` and `after`, which
+/// start at the given 1-based line numbers, or `None` if nothing changed.
+/// `label` follows the `@@` range header, like a function name in `diff -p`.
+pub fn unified_hunk(before: &[String], after: &[String], before_start: usize, after_start: usize, label: &str) -> Option<String> {
+    if before == after {
+        return None;
+    }
+
+    // An empty range is numbered after the line it follows
+    let range = |start: usize, count: usize| if count == 0 { format!("{},0", start - 1) } else { format!("{},{}", start, count) };
+
+    let mut hunk = format!("@@ -{} +{} @@ {}\n", range(before_start, before.len()), range(after_start, after.len()), label);
+    for line in diff_lines(before, after) {
+        let _ = match line {
+            DiffLine::Same(text) => writeln!(hunk, " {}", text),
+            DiffLine::Removed(text) => writeln!(hunk, "-{}", text),
+            DiffLine::Added(text) => writeln!(hunk, "+{}", text),
+        };
+    }
+
+    Some(hunk)
+}
+
+/// A field whose value differs between two matching records; `None` where
+/// a record does not have the field
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldChange {
+    /// Field name in upper case
+    pub field: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+impl fmt::Display for FieldChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = |value: &Option<String>| value.as_ref().map_or("(none)".to_string(), |value| format!("{:?}", value));
+        write!(f, "{}: {} -> {}", self.field, value(&self.before), value(&self.after))
+    }
+}
+
+/// Records of two files that match by key but differ in other fields
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChangedRecord {
+    /// Indexes of the records in the first and second file, starting at 0
+    pub before: usize,
+    pub after: usize,
+    pub changes: Vec<FieldChange>,
+}
+
+/// How the records of two files differ
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RecordDiff {
+    /// Indexes of records of the first file the second does not have
+    pub removed: Vec<usize>,
+    /// Indexes of records of the second file the first does not have
+    pub added: Vec<usize>,
+    pub changed: Vec<ChangedRecord>,
+    pub unchanged: usize,
+}
+
+impl RecordDiff {
+    pub fn is_empty(&self) -> bool {
+        self.removed.is_empty() && self.added.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Compares the decoded records of two files. Records match when the values
+/// of the `key` fields do, ignoring case, surrounding spaces and whether
+/// times have seconds; each record matches at most one of the other file.
+/// Matching records are compared field by field regardless of field order,
+/// field name case, declared lengths or whether times have seconds, with
+/// empty fields counting as absent and encoding annotations left out.
+pub fn compare_records(before: &[Record], after: &[Record], key: &[String]) -> RecordDiff {
+    let mut unmatched: HashMap<Vec<String>, Vec<usize>> = HashMap::new();
+    for (index, record) in after.iter().enumerate().rev() {
+        unmatched.entry(record_key(record, key)).or_default().push(index);
+    }
+
+    let mut diff = RecordDiff::default();
+    for (index, record) in before.iter().enumerate() {
+        let Some(other) = unmatched.get_mut(&record_key(record, key)).and_then(Vec::pop) else {
+            diff.removed.push(index);
+            continue;
+        };
+
+        let (fields, other_fields) = (field_values(record), field_values(&after[other]));
+        let mut names: Vec<&String> = fields.keys().chain(other_fields.keys()).collect();
+        names.sort();
+        names.dedup();
+        let changes: Vec<FieldChange> = names
+            .into_iter()
+            .filter(|name| fields.get(*name) != other_fields.get(*name))
+            .map(|name| FieldChange { field: name.clone(), before: fields.get(name).cloned(), after: other_fields.get(name).cloned() })
+            .collect();
+        if changes.is_empty() {
+            diff.unchanged += 1;
+        } else {
+            diff.changed.push(ChangedRecord { before: index, after: other, changes });
+        }
+    }
+
+    diff.added = unmatched.into_values().flatten().collect();
+    diff.added.sort();
+    diff
+}
+
+/// Values of the `key` fields as compared: upper case and trimmed.
+fn record_key(record: &Record, key: &[String]) -> Vec<String> {
+    key.iter().map(|field| comparable(field, record.get(field).unwrap_or_default().trim()).to_uppercase()).collect()
+}
+
+/// `value` with times padded to six digits, so that `1200` equals `120000`,
+/// and enumerated values such as bands, modes and QSL flags in upper case,
+/// so that `20m` equals `20M`.
+fn comparable(name: &str, value: &str) -> String {
+    if name.eq_ignore_ascii_case("time_on") || name.eq_ignore_ascii_case("time_off") {
+        format!("{:0<6}", value)
+    } else if is_enumerated(name) {
+        value.to_uppercase()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Whether the field's values come from a list the spec compares ignoring
+/// case. SUBMODE is one too, though it is checked as text.
+fn is_enumerated(name: &str) -> bool {
+    name.eq_ignore_ascii_case("submode")
+        || matches!(
+            validation::field_type(name),
+            Some(DataType::Band | DataType::Mode | DataType::Boolean | DataType::Enumeration(_))
+        )
+}
+
+/// The record's non-empty fields by upper-case name, without encoding annotations.
+fn field_values(record: &Record) -> BTreeMap<String, String> {
+    record
+        .fields
+        .iter()
+        .filter(|field| !field.data.is_empty() && !is_encoding_annotation(&field.name))
+        .map(|field| (field.name.trim().to_uppercase(), comparable(field.name.trim(), &field.data)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adif::AdifFile;
+    use crate::encoding::{AdifEncoding, EncodingProcessor};
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_unified_hunk() {
+        let before = lines("<call:4>W1AW\n<name:6>JosÃ©\n<qth:6>Boston");
+        let after = lines("<call:4>W1AW\n<name:4>José\n<qth:6>Boston");
+
+        assert_eq!(
+            unified_hunk(&before, &after, 4, 5, "QSO 2").unwrap(),
+            "@@ -4,3 +5,3 @@ QSO 2\n <call:4>W1AW\n-<name:6>JosÃ©\n+<name:4>José\n <qth:6>Boston\n"
+        );
+        assert_eq!(unified_hunk(&before, &before, 1, 1, "QSO 1"), None);
+        assert_eq!(unified_hunk(&[], &after[..1], 1, 1, "QSO 1").unwrap(), "@@ -0,0 +1,1 @@ QSO 1\n+<call:4>W1AW\n");
+    }
+
+    #[test]
+    fn test_compare_records() {
+        let before = AdifFile::parse(
+            "<eoh><call:4>W1AW<qso_date:8>20240301<time_on:4>1200<name:4>José<eor>\
+             <call:4>K1AB<qso_date:8>20240301<time_on:4>1300<eor>\
+             <call:4>N0XX<qso_date:8>20240301<time_on:4>1400<comment:2>hi<band:3>20m<submode:3>ft4<qsl_rcvd:1>y<eor>"
+                .as_bytes(),
+        )
+        .unwrap();
+        let after = AdifFile::parse(
+            "<eoh><CALL:4>N0XX<QSO_DATE:8>20240301<TIME_ON:6>140000<COMMENT:2>hi<BAND:3>20M<SUBMODE:3>FT4<QSL_RCVD:1>Y<eor>\
+             <name:4>Jose<call:4>w1aw<qso_date:8>20240301<time_on:4>1200<qth:0><eor>\
+             <call:4>DL1X<qso_date:8>20240302<time_on:4>0800<eor>"
+                .as_bytes(),
+        )
+        .unwrap();
+        let (mut before, mut after) = (before, after);
+        let processor = EncodingProcessor::new(None, AdifEncoding::Utf8, false);
+        processor.decode_file(&mut before).unwrap();
+        processor.decode_file(&mut after).unwrap();
+        let key: Vec<String> = ["call", "qso_date", "time_on"].iter().map(|f| f.to_string()).collect();
+
+        let diff = compare_records(&before.records, &after.records, &key);
+        assert_eq!(diff.removed, vec![1]);
+        assert_eq!(diff.added, vec![2]);
+        // N0XX differs only in its time's digits and the case of its band,
+        // submode and QSL flag
+        assert_eq!(diff.unchanged, 1);
+        assert_eq!(diff.changed.len(), 1);
+        let changed = &diff.changed[0];
+        assert_eq!((changed.before, changed.after), (0, 1));
+        // The callsign's case is a change, but not a reason to mismatch
+        let changes: Vec<String> = changed.changes.iter().map(FieldChange::to_string).collect();
+        assert_eq!(changes, vec!["CALL: \"W1AW\" -> \"w1aw\"", "NAME: \"José\" -> \"Jose\""]);
+    }
+}
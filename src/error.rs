@@ -0,0 +1,88 @@
+use crate::adif::AdifError;
+use crate::adx::AdxError;
+use crate::config::ConfigError;
+use crate::csv::CsvError;
+use crate::cty::CtyError;
+use crate::decimal::DecimalError;
+use crate::encoding::EncodingError;
+use crate::output::{OutputError, SelectorError};
+use crate::rewrite::RewriteError;
+use crate::transcode::TranscodeError;
+use crate::translit::TranslitError;
+use thiserror::Error;
+
+/// Any error the library returns. Each module keeps its own error type, and
+/// `?` converts all of them into this one, so callers using several modules
+/// need only one. `test_runner::TestError` is left out: the test runner is
+/// the `test-runner` binary's, not part of the library's API.
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    Adif(#[from] AdifError),
+    #[error(transparent)]
+    Adx(#[from] AdxError),
+    #[error(transparent)]
+    Csv(#[from] CsvError),
+    #[error(transparent)]
+    Encoding(#[from] EncodingError),
+    #[error(transparent)]
+    Output(#[from] OutputError),
+    #[error(transparent)]
+    Selector(#[from] SelectorError),
+    #[error(transparent)]
+    Decimal(#[from] DecimalError),
+    #[error(transparent)]
+    Rewrite(#[from] RewriteError),
+    #[error(transparent)]
+    Translit(#[from] TranslitError),
+    #[error(transparent)]
+    Cty(#[from] CtyError),
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// `Result` with the crate's `Error`
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl From<TranscodeError> for Error {
+    fn from(error: TranscodeError) -> Self {
+        match error {
+            TranscodeError::Parse(error) => Self::Adif(error),
+            TranscodeError::Output(error) => Self::Output(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adif::AdifFile;
+    use crate::output::QsoSelector;
+    use crate::transcode::AdifTranscoder;
+
+    #[test]
+    fn test_module_errors_convert() {
+        fn record_count(data: &[u8]) -> Result<usize> {
+            let adif = AdifFile::parse(data)?;
+            transcoded_records(data)?;
+            Ok(adif.records.len())
+        }
+        fn transcoded_records(data: &[u8]) -> Result<usize, TranscodeError> {
+            AdifTranscoder::new(Default::default()).transcode(data, std::io::sink())
+        }
+
+        let data = b"<call:99999999999999999999>W1AW<eor>";
+        let error = record_count(data).unwrap_err();
+        assert!(matches!(error, Error::Adif(AdifError::InvalidField(_))));
+        assert_eq!(error.to_string(), "Invalid field format: QSO 1: CALL at byte 0: Invalid field length");
+
+        // Transcoder errors are flattened, not nested
+        let error = Error::from(transcoded_records(data).unwrap_err());
+        assert!(matches!(error, Error::Adif(AdifError::InvalidField(_))));
+
+        let error = Error::from("first".parse::<QsoSelector>().unwrap_err());
+        assert!(matches!(error, Error::Selector(SelectorError::Syntax(_))));
+    }
+}
@@ -0,0 +1,160 @@
+use crate::convert::{convert, ConversionOptions};
+use crate::encoding::AdifEncoding;
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+/// Do not correct invalid characters or field counts
+pub const TRANSADIF_STRICT: u32 = 1;
+/// Transliterate to characters without diacritics
+pub const TRANSADIF_ASCII: u32 = 2;
+/// Delete what the output encoding cannot represent instead of writing `?`
+pub const TRANSADIF_DELETE: u32 = 4;
+/// Skip fields that cannot be parsed instead of failing
+pub const TRANSADIF_SKIP_MALFORMED: u32 = 8;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+/// Runs the body of an exported function, turning a panic into status -2
+/// instead of unwinding into the caller, which is undefined behavior. This
+/// needs a library built with `panic = "unwind"`, as the `ffi` profile is.
+fn catch_panic(body: impl FnOnce() -> c_int) -> c_int {
+    panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("unknown cause");
+        set_last_error(format!("Internal error: {}", message));
+        -2
+    })
+}
+
+/// Options from the arguments of `transadif_convert`.
+unsafe fn options(input_encoding: *const c_char, output_encoding: *const c_char, flags: u32) -> Result<ConversionOptions, String> {
+    let name = |name: *const c_char| {
+        if name.is_null() {
+            return Ok(None);
+        }
+        CStr::from_ptr(name).to_str().map(Some).map_err(|_| "encoding name is not UTF-8".to_string())
+    };
+    let input_encoding = name(input_encoding)?.map(str::parse::<AdifEncoding>).transpose().map_err(|e| e.to_string())?;
+    let output_encoding = name(output_encoding)?.map(AdifEncoding::parse_output).transpose().map_err(|e| e.to_string())?;
+
+    Ok(ConversionOptions {
+        input_encoding,
+        output_encoding: output_encoding.unwrap_or(AdifEncoding::Utf8),
+        strict: flags & TRANSADIF_STRICT != 0,
+        replacement_char: (flags & TRANSADIF_DELETE == 0).then_some('?'),
+        ascii: flags & TRANSADIF_ASCII != 0,
+        skip_malformed: flags & TRANSADIF_SKIP_MALFORMED != 0,
+        ..ConversionOptions::default()
+    })
+}
+
+/// Converts the ADIF file in `input` like `transadif::convert`. The input
+/// encoding is taken from the header or detected when `input_encoding` is
+/// NULL, and the output is UTF-8 when `output_encoding` is NULL. `flags`
+/// combines the `TRANSADIF_*` constants.
+///
+/// Returns 0 and stores the converted file in `*output` and `*output_len`,
+/// to be released with `transadif_free`. Returns -1 on failure, or -2 if
+/// the conversion panicked, with the reason available from
+/// `transadif_last_error`.
+///
+/// # Safety
+///
+/// `input` must point to `input_len` readable bytes, the encoding names must
+/// be NULL or NUL-terminated strings, and `output` and `output_len` must be
+/// valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn transadif_convert(
+    input: *const u8,
+    input_len: usize,
+    input_encoding: *const c_char,
+    output_encoding: *const c_char,
+    flags: u32,
+    output: *mut *mut u8,
+    output_len: *mut usize,
+) -> c_int {
+    if (input.is_null() && input_len > 0) || output.is_null() || output_len.is_null() {
+        set_last_error("NULL argument".to_string());
+        return -1;
+    }
+    let input = if input_len == 0 { &[] } else { std::slice::from_raw_parts(input, input_len) };
+
+    catch_panic(|| {
+        let result = options(input_encoding, output_encoding, flags).and_then(|options| convert(input, &options).map_err(|e| e.to_string()));
+        match result {
+            Ok(result) => {
+                let converted = result.output.into_boxed_slice();
+                *output_len = converted.len();
+                *output = Box::into_raw(converted).cast();
+                0
+            }
+            Err(message) => {
+                set_last_error(message);
+                -1
+            }
+        }
+    })
+}
+
+/// Releases output returned by `transadif_convert`.
+///
+/// # Safety
+///
+/// `data` and `len` must come from one successful `transadif_convert` call,
+/// and must not be used after, or passed here twice.
+#[no_mangle]
+pub unsafe extern "C" fn transadif_free(data: *mut u8, len: usize) {
+    if !data.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(data, len)));
+    }
+}
+
+/// Why the last `transadif_convert` call on this thread failed, or NULL.
+/// The string stays valid until the next failing call on the thread.
+#[no_mangle]
+pub extern "C" fn transadif_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |message| message.as_ptr()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_through_c_abi() {
+        let input = b"Log<encoding:10>ISO-8859-1<eoh>\n<call:4>DL1X<name:4>J\xfcrg<eor>\n";
+        let mut output = ptr::null_mut();
+        let mut output_len = 0;
+
+        let status = unsafe {
+            transadif_convert(input.as_ptr(), input.len(), ptr::null(), ptr::null(), 0, &mut output, &mut output_len)
+        };
+        assert_eq!(status, 0);
+        let converted = unsafe { std::slice::from_raw_parts(output, output_len) };
+        assert!(converted.ends_with("<name:4>Jürg<eor>\n".as_bytes()));
+        unsafe { transadif_free(output, output_len) };
+
+        let status = unsafe {
+            transadif_convert(input.as_ptr(), input.len(), ptr::null(), c"EBCDIC".as_ptr(), 0, &mut output, &mut output_len)
+        };
+        assert_eq!(status, -1);
+        let error = unsafe { CStr::from_ptr(transadif_last_error()) };
+        assert!(error.to_str().unwrap().contains("EBCDIC"), "{:?}", error);
+
+        assert_eq!(catch_panic(|| panic!("index out of bounds")), -2);
+        let error = unsafe { CStr::from_ptr(transadif_last_error()) };
+        assert_eq!(error.to_str().unwrap(), "Internal error: index out of bounds");
+    }
+}
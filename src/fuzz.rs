@@ -0,0 +1,197 @@
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+/// Field names that steer the parser and the decoding, besides ordinary ones
+const NAMES: &[&str] = &[
+    "call",
+    "name",
+    "qth",
+    "comment",
+    "notes_intl",
+    "freq",
+    "qso_date",
+    "encoding",
+    "adif_ver",
+    "app_transadif_count",
+    "app_transadif_encoding_name",
+    "userdef1",
+];
+
+/// Values that exercise detection, mojibake repair, entity references and
+/// the field count heuristics
+const VALUES: &[&str] = &[
+    "W1AW",
+    "Jürgen",
+    "Łukasz",
+    "Юрий",
+    "東京",
+    "🎉 73",
+    "JÃ¼rgen",
+    "M&uuml;nchen",
+    "&0xE4;",
+    "dipole <10m up",
+    "a<eor>b",
+    "bytes",
+    "characters",
+    "ISO-8859-1",
+    "UTF-8",
+    "",
+];
+
+/// An ADIF document made of the pieces the parser looks for, with declared
+/// lengths that may count bytes or characters or be plain wrong, for fuzzing
+/// beyond what random bytes reach.
+#[derive(Debug, Clone)]
+pub struct AdifDocument {
+    pub preamble: Option<Vec<u8>>,
+    pub header: Vec<FieldSpec>,
+    pub records: Vec<RecordSpec>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RecordSpec {
+    pub fields: Vec<FieldSpec>,
+    /// `<eor>` in some case, or nothing for a record cut short
+    pub end_tag: Option<&'static str>,
+    pub excess: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FieldSpec {
+    pub name: String,
+    pub field_type: Option<char>,
+    pub value: Vec<u8>,
+    pub length: DeclaredLength,
+    /// What follows the value before the next tag
+    pub excess: Vec<u8>,
+}
+
+/// The length a field declares for its value
+#[derive(Debug, Clone, Copy)]
+pub enum DeclaredLength {
+    Bytes,
+    Characters,
+    /// The byte length plus this, down to 0
+    Off(i8),
+    /// Far past the end of the data
+    Huge,
+}
+
+impl AdifDocument {
+    /// The document as ADIF.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        if let Some(preamble) = &self.preamble {
+            data.extend_from_slice(preamble);
+            for field in &self.header {
+                field.write(&mut data);
+            }
+            data.extend_from_slice(b"<eoh>\r\n");
+        }
+        for record in &self.records {
+            for field in &record.fields {
+                field.write(&mut data);
+            }
+            if let Some(end_tag) = record.end_tag {
+                data.extend_from_slice(end_tag.as_bytes());
+            }
+            data.extend_from_slice(&record.excess);
+        }
+        data
+    }
+}
+
+impl FieldSpec {
+    fn write(&self, data: &mut Vec<u8>) {
+        let length = match self.length {
+            DeclaredLength::Bytes => self.value.len(),
+            DeclaredLength::Characters => String::from_utf8_lossy(&self.value).chars().count(),
+            DeclaredLength::Off(offset) => self.value.len().saturating_add_signed(isize::from(offset)),
+            DeclaredLength::Huge => usize::MAX,
+        };
+        data.extend_from_slice(format!("<{}:{}", self.name, length).as_bytes());
+        if let Some(field_type) = self.field_type {
+            data.extend_from_slice(format!(":{}", field_type).as_bytes());
+        }
+        data.push(b'>');
+        data.extend_from_slice(&self.value);
+        data.extend_from_slice(&self.excess);
+    }
+}
+
+impl<'a> Arbitrary<'a> for AdifDocument {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let preamble = if u.arbitrary()? { Some(excess(u, b"Exported log\r\n")?) } else { None };
+        let header = (0..u.int_in_range(0..=4)?).map(|_| FieldSpec::arbitrary(u)).collect::<Result<_>>()?;
+        let records = (0..u.int_in_range(0..=8)?).map(|_| RecordSpec::arbitrary(u)).collect::<Result<_>>()?;
+        Ok(Self { preamble, header, records })
+    }
+}
+
+impl<'a> Arbitrary<'a> for RecordSpec {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let fields = (0..u.int_in_range(0..=6)?).map(|_| FieldSpec::arbitrary(u)).collect::<Result<_>>()?;
+        let end_tag = *u.choose(&[Some("<eor>"), Some("<EOR>"), Some("<Eor>"), None])?;
+        Ok(Self { fields, end_tag, excess: excess(u, b"\r\n")? })
+    }
+}
+
+impl<'a> Arbitrary<'a> for FieldSpec {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let name = if u.ratio(1, 8)? { u.arbitrary::<String>()? } else { u.choose(NAMES)?.to_string() };
+        let field_type = if u.ratio(1, 4)? { Some(*u.choose(&['S', 'N', 'D', 'M', 'I', 'E'])?) } else { None };
+        let text = u.choose(VALUES)?;
+        let value = match u.int_in_range(0..=4)? {
+            0 => text.as_bytes().to_vec(),
+            1 => encoding_rs::WINDOWS_1252.encode(text).0.into_owned(),
+            2 => encoding_rs::SHIFT_JIS.encode(text).0.into_owned(),
+            // Read as Latin-1 and written as UTF-8 again: mojibake
+            3 => encoding_rs::WINDOWS_1252.decode(text.as_bytes()).0.as_bytes().to_vec(),
+            _ => u.arbitrary::<Vec<u8>>()?,
+        };
+        let length = match u.int_in_range(0..=5)? {
+            0 | 1 => DeclaredLength::Bytes,
+            2 => DeclaredLength::Characters,
+            3 | 4 => DeclaredLength::Off(u.arbitrary()?),
+            _ => DeclaredLength::Huge,
+        };
+        Ok(Self { name, field_type, value, length, excess: excess(u, b" ")? })
+    }
+}
+
+/// Usually `usual`, sometimes nothing or arbitrary bytes.
+fn excess(u: &mut Unstructured<'_>, usual: &[u8]) -> Result<Vec<u8>> {
+    Ok(match u.int_in_range(0..=3)? {
+        0 | 1 => usual.to_vec(),
+        2 => Vec::new(),
+        _ => u.arbitrary()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adif::{AdifFile, StreamingParser};
+    use crate::{convert, ConversionOptions};
+
+    #[test]
+    fn test_generated_documents_convert() {
+        // A fixed stream of pseudo-random bytes, so failures reproduce
+        let mut state = 0x9e3779b97f4a7c15u64;
+        let bytes: Vec<u8> = (0..1 << 16)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+
+        for start in (0..bytes.len() - 1024).step_by(512) {
+            let document = AdifDocument::arbitrary(&mut Unstructured::new(&bytes[start..start + 1024])).unwrap();
+            let data = document.to_bytes();
+            let _ = AdifFile::parse_recovering(data.clone());
+            let _ = StreamingParser::new(data.as_slice()).map(|parser| parser.take_while(Result::is_ok).count());
+            let _ = convert(&data, &ConversionOptions::default());
+        }
+    }
+}
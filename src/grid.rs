@@ -0,0 +1,93 @@
+/// Fields holding a single Maidenhead locator
+const GRID_FIELDS: [&str; 2] = ["gridsquare", "my_gridsquare"];
+
+/// Pairs appended when extending a locator, the subsquare or extended square
+/// just north-east of the enclosing square's center
+const CENTER_PAIRS: [&str; 4] = ["", "55", "MM", "55"];
+
+pub fn is_grid_field(name: &str) -> bool {
+    GRID_FIELDS.iter().any(|field| field.eq_ignore_ascii_case(name))
+}
+
+/// Why `value` is not a Maidenhead locator of 2, 4, 6 or 8 characters, or
+/// `None` if it is one. Case does not matter.
+pub fn check(value: &str) -> Option<String> {
+    if ![2, 4, 6, 8].contains(&value.chars().count()) {
+        return Some("is not 2, 4, 6 or 8 characters long".to_string());
+    }
+
+    for (index, c) in value.chars().enumerate() {
+        let (valid, expected) = match index {
+            0 | 1 => (matches!(c.to_ascii_uppercase(), 'A'..='R'), "a letter from A to R"),
+            4 | 5 => (matches!(c.to_ascii_uppercase(), 'A'..='X'), "a letter from A to X"),
+            _ => (c.is_ascii_digit(), "a digit"),
+        };
+        if !valid {
+            return Some(format!("has {:?} at position {} where {} belongs", c, index + 1, expected));
+        }
+    }
+    None
+}
+
+/// Writes a locator the usual way, as in `JN58td`: field letters uppercase,
+/// subsquare letters lowercase, without surrounding whitespace.
+pub fn normalize(value: &str) -> String {
+    value
+        .trim()
+        .chars()
+        .enumerate()
+        .map(|(index, c)| if index < 4 { c.to_ascii_uppercase() } else { c.to_ascii_lowercase() })
+        .collect()
+}
+
+/// Cuts a valid locator down to `precision` characters, or extends it with
+/// the pairs nearest the center of the area it covers.
+pub fn set_precision(grid: &str, precision: usize) -> String {
+    let mut grid: String = grid.chars().take(precision).collect();
+    while grid.len() < precision {
+        grid.push_str(CENTER_PAIRS[grid.len() / 2]);
+    }
+    normalize(&grid)
+}
+
+/// Field transform normalizing the case of valid locators and, with a
+/// `precision`, bringing them to that many characters. Invalid locators are
+/// left for the report.
+pub fn normalize_field(name: &str, value: &str, precision: Option<usize>) -> Option<String> {
+    if !is_grid_field(name) {
+        return None;
+    }
+
+    let normalized = normalize(value);
+    if check(&normalized).is_some() {
+        return None;
+    }
+    let normalized = precision.map_or_else(|| normalized.clone(), |precision| set_precision(&normalized, precision));
+    (normalized != value).then_some(normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        for grid in ["JN", "jn58", "JN58td", "fn31PR47"] {
+            assert_eq!(check(grid), None, "{}", grid);
+        }
+        assert_eq!(check("JN5").as_deref(), Some("is not 2, 4, 6 or 8 characters long"));
+        assert_eq!(check("ZZ99").as_deref(), Some("has 'Z' at position 1 where a letter from A to R belongs"));
+        assert_eq!(check("JN5X").as_deref(), Some("has 'X' at position 4 where a digit belongs"));
+        assert_eq!(check("JN58tz").as_deref(), Some("has 'z' at position 6 where a letter from A to X belongs"));
+    }
+
+    #[test]
+    fn test_normalize_field() {
+        assert_eq!(normalize_field("GRIDSQUARE", " jn58TD ", None).as_deref(), Some("JN58td"));
+        assert_eq!(normalize_field("gridsquare", "JN58td", None), None);
+        assert_eq!(normalize_field("my_gridsquare", "JN58td47", Some(6)).as_deref(), Some("JN58td"));
+        assert_eq!(normalize_field("my_gridsquare", "jn58", Some(8)).as_deref(), Some("JN58mm55"));
+        assert_eq!(normalize_field("gridsquare", "JN5X", Some(6)), None);
+        assert_eq!(normalize_field("vucc_grids", "jn58,jn59", None), None);
+    }
+}
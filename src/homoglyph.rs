@@ -0,0 +1,64 @@
+/// Fields holding callsigns or grid squares, which are always plain ASCII
+const ASCII_FIELDS: [&str; 12] = [
+    "call",
+    "contacted_op",
+    "eq_call",
+    "operator",
+    "owner_callsign",
+    "station_callsign",
+    "gridsquare",
+    "gridsquare_ext",
+    "my_gridsquare",
+    "my_gridsquare_ext",
+    "vucc_grids",
+    "my_vucc_grids",
+];
+
+/// Field transform replacing look-alike characters in callsign and grid
+/// square fields, which often arrive that way when copied from web chats.
+pub fn normalize_field(name: &str, value: &str) -> Option<String> {
+    if !ASCII_FIELDS.iter().any(|field| field.eq_ignore_ascii_case(name)) || value.is_ascii() {
+        return None;
+    }
+
+    Some(value.chars().map(|c| ascii_lookalike(c).unwrap_or(c)).collect())
+}
+
+/// ASCII character that `c` is visually confused with, if any.
+pub fn ascii_lookalike(c: char) -> Option<char> {
+    let ascii = match c {
+        // Fullwidth forms of ASCII
+        '\u{FF01}'..='\u{FF5E}' => return char::from_u32(c as u32 - 0xFEE0),
+        '\u{3000}' => ' ',
+        '\u{2215}' | '\u{2044}' => '/',
+        '\u{2010}' | '\u{2011}' | '\u{2012}' | '\u{2013}' | '\u{2212}' => '-',
+
+        // Cyrillic
+        'А' => 'A', 'В' => 'B', 'Е' => 'E', 'К' => 'K', 'М' => 'M', 'Н' => 'H', 'О' => 'O',
+        'Р' => 'P', 'С' => 'C', 'Т' => 'T', 'У' => 'Y', 'Х' => 'X', 'Ѕ' => 'S', 'І' => 'I', 'Ј' => 'J',
+        'а' => 'a', 'е' => 'e', 'о' => 'o', 'р' => 'p', 'с' => 'c', 'у' => 'y', 'х' => 'x',
+        'ѕ' => 's', 'і' => 'i', 'ј' => 'j',
+
+        // Greek
+        'Α' => 'A', 'Β' => 'B', 'Ε' => 'E', 'Ζ' => 'Z', 'Η' => 'H', 'Ι' => 'I', 'Κ' => 'K',
+        'Μ' => 'M', 'Ν' => 'N', 'Ο' => 'O', 'Ρ' => 'P', 'Τ' => 'T', 'Υ' => 'Y', 'Χ' => 'X',
+        'ο' => 'o', 'ν' => 'v',
+
+        _ => return None,
+    };
+    Some(ascii)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalizes_callsign_and_grid_fields_only() {
+        // Cyrillic А and О, Greek Κ, fullwidth digits and slash
+        assert_eq!(normalize_field("CALL", "UА3ΚОＷ／ＰＯＲＴ１").as_deref(), Some("UA3KOW/PORT1"));
+        assert_eq!(normalize_field("gridsquare", "КО85").as_deref(), Some("KO85"));
+        assert_eq!(normalize_field("call", "K1MIX"), None);
+        assert_eq!(normalize_field("name", "Алексей"), None);
+    }
+}
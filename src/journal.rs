@@ -0,0 +1,191 @@
+use crate::adif::Record;
+use crate::sink::AdifSink;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// How many records are written between journal checkpoints
+const CHECKPOINT_INTERVAL: usize = 100;
+
+/// Progress of a conversion, saved next to the output file so an interrupted
+/// run can continue where it stopped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Journal {
+    pub records_written: usize,
+    /// Input byte offset of the first record that has not been written yet
+    pub input_offset: usize,
+    /// Size of the input file, used to refuse resuming against a different input
+    pub input_length: usize,
+    /// Size of the output file at the checkpoint; anything after it is discarded on resume
+    pub output_length: u64,
+}
+
+impl Journal {
+    pub fn new(input_length: usize) -> Self {
+        Self {
+            records_written: 0,
+            input_offset: 0,
+            input_length,
+            output_length: 0,
+        }
+    }
+
+    /// Journal location for a given output file: `log.adi` → `log.adi.journal`
+    pub fn path_for(output_path: &Path) -> PathBuf {
+        let mut name = output_path.file_name().unwrap_or_default().to_os_string();
+        name.push(".journal");
+        output_path.with_file_name(name)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Option<Self>> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        let mut journal = Self::new(0);
+        for line in content.lines() {
+            let Some((key, value)) = line.split_once('=') else { continue };
+            let value: u64 = value.trim().parse()
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid journal line: {}", line)))?;
+            match key.trim() {
+                "records_written" => journal.records_written = value as usize,
+                "input_offset" => journal.input_offset = value as usize,
+                "input_length" => journal.input_length = value as usize,
+                "output_length" => journal.output_length = value,
+                _ => {}
+            }
+        }
+
+        Ok(Some(journal))
+    }
+
+    /// Writes the journal through a temporary file so it is never left half-written.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+        temp_name.push(".tmp");
+        let temp_path = path.with_file_name(temp_name);
+
+        fs::write(
+            &temp_path,
+            format!(
+                "records_written={}\ninput_offset={}\ninput_length={}\noutput_length={}\n",
+                self.records_written, self.input_offset, self.input_length, self.output_length
+            ),
+        )?;
+        fs::rename(&temp_path, path)
+    }
+}
+
+/// File sink that checkpoints its progress into a `Journal`.
+///
+/// A fresh run creates the output file and writes the header; a resumed run
+/// truncates the output back to the last checkpoint, then appends and skips
+/// the header. The journal is only saved after the output has been flushed,
+/// and it is removed once the conversion finishes.
+pub struct JournalSink {
+    writer: BufWriter<File>,
+    journal: Journal,
+    journal_path: PathBuf,
+    resuming: bool,
+    since_checkpoint: usize,
+    bytes_written: u64,
+}
+
+impl JournalSink {
+    pub fn create(output_path: &Path, input_length: usize) -> io::Result<Self> {
+        Ok(Self {
+            writer: BufWriter::new(File::create(output_path)?),
+            journal: Journal::new(input_length),
+            journal_path: Journal::path_for(output_path),
+            resuming: false,
+            since_checkpoint: 0,
+            bytes_written: 0,
+        })
+    }
+
+    pub fn resume(output_path: &Path, journal: Journal) -> io::Result<Self> {
+        let file = OpenOptions::new().write(true).open(output_path)?;
+        file.set_len(journal.output_length)?;
+        let file = OpenOptions::new().append(true).open(output_path)?;
+
+        Ok(Self {
+            writer: BufWriter::new(file),
+            bytes_written: journal.output_length,
+            journal,
+            journal_path: Journal::path_for(output_path),
+            resuming: true,
+            since_checkpoint: 0,
+        })
+    }
+
+    pub fn records_written(&self) -> usize {
+        self.journal.records_written
+    }
+
+    fn checkpoint(&mut self, next_offset: usize) -> io::Result<()> {
+        self.writer.flush()?;
+        self.journal.input_offset = next_offset;
+        self.journal.output_length = self.bytes_written;
+        self.journal.save(&self.journal_path)?;
+        self.since_checkpoint = 0;
+        Ok(())
+    }
+}
+
+impl AdifSink for JournalSink {
+    fn write_header(&mut self, header: &[u8]) -> io::Result<()> {
+        if !self.resuming {
+            self.writer.write_all(header)?;
+            self.bytes_written += header.len() as u64;
+            self.checkpoint(0)?;
+        }
+        Ok(())
+    }
+
+    fn write_record(&mut self, record: &Record, bytes: &[u8]) -> io::Result<()> {
+        if self.since_checkpoint >= CHECKPOINT_INTERVAL {
+            self.checkpoint(record.offset)?;
+        }
+
+        self.writer.write_all(bytes)?;
+        self.bytes_written += bytes.len() as u64;
+        self.journal.records_written += 1;
+        self.since_checkpoint += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        match fs::remove_file(&self.journal_path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_journal_round_trip() {
+        let dir = std::env::temp_dir().join(format!("transadif-journal-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = Journal::path_for(&dir.join("log.adi"));
+        assert!(path.ends_with("log.adi.journal"));
+        assert_eq!(Journal::load(&path).unwrap(), None);
+
+        let journal = Journal {
+            records_written: 1200,
+            input_offset: 345678,
+            input_length: 999999,
+            output_length: 400000,
+        };
+        journal.save(&path).unwrap();
+        assert_eq!(Journal::load(&path).unwrap(), Some(journal));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
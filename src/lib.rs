@@ -1,5 +1,60 @@
-pub mod adif;
-pub mod encoding;
-pub mod cli;
-pub mod output;
-pub mod test_runner;
\ No newline at end of file
+pub use adif::{AdifFile, Field, Record};
+pub use convert::{convert, convert_bytes, ConversionOptions, ConversionResult};
+pub use encoding::AdifEncoding;
+pub use error::{Error, Result};
+pub use output::OutputFormatter;
+pub use report::Report;
+pub use transcode::{AdifTranscoder, TranscodeOptions};
+
+pub mod adif;
+pub mod anonymize;
+pub mod adx;
+pub mod atomic;
+pub mod bdiff;
+pub mod builder;
+pub mod encoding;
+pub mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "arbitrary")]
+pub mod fuzz;
+pub mod grid;
+pub mod homoglyph;
+pub mod cache;
+pub mod callsign;
+pub mod color;
+#[doc(hidden)]
+pub mod cli;
+pub mod compress;
+#[doc(hidden)]
+pub mod config;
+pub mod convert;
+pub mod csv;
+pub mod cty;
+pub mod decimal;
+pub mod dedupe;
+pub mod diff;
+pub mod journal;
+pub mod mojibake;
+pub mod output;
+pub mod preset;
+pub mod progress;
+pub mod profile;
+pub mod qslmsg;
+pub mod reconcile;
+pub mod reference;
+pub mod report;
+pub mod rewrite;
+pub mod rst;
+pub mod sink;
+pub mod sort;
+pub mod stats;
+#[doc(hidden)]
+pub mod test_runner;
+pub mod transcode;
+pub mod translit;
+pub mod validation;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "tui")]
+pub mod tui;
\ No newline at end of file
@@ -1,42 +1,223 @@
-use transadif::{adif, encoding, cli, output};
+use transadif::{adif, encoding, cli, compress, decimal, dedupe, diff, output, qslmsg, rewrite, translit, validation};
+use dedupe::DedupeKey;
+use translit::TranslitTable;
 
-use clap::Parser;
-use cli::Cli;
-use encoding::AdifEncoding;
-use output::{OutputFormatter, DebugFormatter};
+use clap::{CommandFactory, FromArgMatches, Parser, ValueEnum};
+use cli::{Cli, Command, ConvertArgs, FailOn, HashOutput, InputFormat, OutputFormat};
+use encoding::{AdifEncoding, EncodingProcessor};
+use transadif::anonymize::Anonymization;
+use transadif::journal::{Journal, JournalSink};
+use transadif::atomic::AtomicFile;
+use transadif::sink::{AdifSink, FileSink, GzSink, SplitSink, WriterSink};
+use compress::Compression;
+use flate2::write::GzEncoder;
+use output::{AdxFormatter, JsonFormatter, OutputFormatter, OutputStreamer, DebugFormatter, PreamblePolicy, QsoSelector};
+use std::collections::BTreeMap;
 use std::fs;
-use std::io::{self, Read};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Mutex};
+use transadif::cache::{self, Decision, EncodingCache};
+use transadif::progress::{Progress, ProgressSink};
+use transadif::config::Config;
+use transadif::cty::{CtyDatabase, CtyError};
+use transadif::reconcile::QslService;
+use transadif::stats::Stats;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Cli::parse();
+    let args = parse_args()?;
+    let created_at = created_at(args.deterministic);
 
-    // Read input
-    let input_data = if let Some(input_path) = &args.input {
-        fs::read(input_path)?
-    } else {
-        let mut buffer = Vec::new();
-        io::stdin().read_to_end(&mut buffer)?;
-        buffer
+    match &args.command {
+        Some(Command::Convert(convert_args)) => convert(convert_args, created_at),
+        Some(Command::Validate(convert_args)) => {
+            let mut convert_args = conversion_for("validate", convert_args)?;
+            convert_args.validate_strict = true;
+            convert_args.validate_only = true;
+            convert(&convert_args, created_at)
+        }
+        Some(Command::Stats(convert_args)) => {
+            let mut convert_args = conversion_for("stats", convert_args)?;
+            convert_args.stats = true;
+            convert(&convert_args, created_at)
+        }
+        Some(Command::Merge(convert_args)) => {
+            if convert_args.input.len() < 2 {
+                return Err("merge takes two or more input files".into());
+            }
+            convert(&conversion_for("merge", convert_args)?, created_at)
+        }
+        Some(Command::Dedupe(convert_args)) => {
+            let mut convert_args = conversion_for("dedupe", convert_args)?;
+            convert_args.dedupe = true;
+            convert(&convert_args, created_at)
+        }
+        Some(Command::Debug { qsos, args: convert_args }) => {
+            let mut convert_args = conversion_for("debug", convert_args)?;
+            convert_args.debug = Some(qsos.clone());
+            convert(&convert_args, created_at)
+        }
+        Some(Command::View { file, input_encoding }) => view(file, input_encoding.as_deref()),
+        Some(Command::Cat { files, output, encoding, replace, delete, ascii }) => {
+            let output_encoding = AdifEncoding::parse_output(encoding)?;
+            let replacement_char = if *delete { None } else { Some(*replace) };
+
+            let mut sink = open_sink(output.as_deref(), None)?;
+            cat(files, sink.as_mut(), created_at, |input_encoding| {
+                OutputFormatter::new(input_encoding, output_encoding.clone(), false, replacement_char, *delete, *ascii)
+            })
+        }
+        Some(Command::Reconcile { log, confirmations, output, service, window, encoding }) => {
+            let output_encoding = AdifEncoding::parse_output(encoding)?;
+            reconcile(log, confirmations, output.as_deref(), *service, *window, created_at, |input_encoding| {
+                OutputFormatter::new(input_encoding, output_encoding.clone(), false, Some('?'), false, false)
+            })
+        }
+        Some(Command::Batch { dir, out_dir, encoding, replace, delete, ascii, jobs, no_cache }) => {
+            let output_encoding = AdifEncoding::parse_output(encoding)?;
+            let replacement_char = if *delete { None } else { Some(*replace) };
+            let jobs = jobs.unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()));
+
+            let cache = (!no_cache).then(|| Mutex::new(EncodingCache::load(out_dir)));
+
+            batch(dir, out_dir, jobs, &output_encoding, cache.as_ref(), |input_encoding, output_encoding| {
+                OutputFormatter::new(input_encoding, output_encoding, false, replacement_char, *delete, *ascii)
+            })
+        }
+        Some(Command::Bdiff { a, b, limit }) => bdiff(a, b, *limit),
+        Some(Command::Diff { a, b, key }) => diff_files(a, b, key),
+        Some(Command::Completions { shell }) => {
+            clap_complete::generate(*shell, &mut Cli::command(), "transadif", &mut io::stdout());
+            Ok(())
+        }
+        Some(Command::Manpage { out_dir }) => manpage(out_dir.as_deref()),
+        None => convert(&args.convert, created_at),
+    }
+}
+
+/// Commands that run a conversion and take its options
+const CONVERSION_COMMANDS: [&str; 6] = ["convert", "validate", "stats", "merge", "dedupe", "debug"];
+
+/// The conversion options of a command that runs a conversion for a narrower
+/// purpose, which needs the whole input in memory.
+fn conversion_for(command: &str, args: &ConvertArgs) -> Result<ConvertArgs, Box<dyn std::error::Error>> {
+    if args.stream || args.resume || args.watch {
+        return Err(format!("transadif {} does not support --stream, --resume or --watch", command).into());
+    }
+    Ok(args.clone())
+}
+
+/// Logs to stderr at the level -v, -vv and -vvv ask for, or as RUST_LOG says.
+fn init_logging(verbose: u8) {
+    let level = match verbose {
+        0 => log::LevelFilter::Warn,
+        1 => log::LevelFilter::Info,
+        2 => log::LevelFilter::Debug,
+        _ => log::LevelFilter::Trace,
     };
+    env_logger::Builder::new()
+        .filter_module("transadif", level)
+        .parse_default_env()
+        .format_timestamp(None)
+        .format_target(false)
+        .init();
+}
 
-    // Parse ADIF file
-    let adif = adif::AdifFile::parse(&input_data)?;
+/// Parses the command line, filling in conversion options it leaves out from
+/// a transadif.toml if there is one.
+fn parse_args() -> Result<Cli, Box<dyn std::error::Error>> {
+    let argv: Vec<std::ffi::OsString> = std::env::args_os().collect();
+    let command = Cli::command();
+    let matches = command.clone().get_matches_from(&argv);
+    init_logging(matches.get_count("verbose"));
+    if matches.get_flag("no_config") {
+        return Ok(Cli::from_arg_matches(&matches)?);
+    }
 
-    // Handle debug mode
-    let debug_qsos = args.parse_debug_qsos();
-    if !debug_qsos.is_empty() {
-        DebugFormatter::print_qso_debug(&adif, &debug_qsos);
-        return Ok(());
+    // The defaults go right before the options of the command they are for
+    let (command, command_matches, position) = match matches.subcommand() {
+        None => (command, &matches, 1),
+        Some((name, command_matches)) if CONVERSION_COMMANDS.contains(&name) => {
+            let position = argv.iter().position(|arg| arg == name).map_or(1, |position| position + 1);
+            (command.find_subcommand(name).unwrap().clone(), command_matches, position)
+        }
+        Some(_) => return Ok(Cli::from_arg_matches(&matches)?),
+    };
+    let Some(config) = Config::find()? else {
+        return Ok(Cli::from_arg_matches(&matches)?);
+    };
+
+    let defaults = config.args(&command, command_matches)?;
+    log::info!("Using defaults from {}", config.path.display());
+    let argv = argv[..position].iter().chain(&defaults).chain(&argv[position..]);
+    Ok(Cli::parse_from(argv))
+}
+
+fn convert(args: &ConvertArgs, created_at: u64) -> Result<(), Box<dyn std::error::Error>> {
+    if args.from != InputFormat::Adi && (args.stream || args.resume || args.watch) {
+        return Err("--stream, --resume and --watch only support ADI input".into());
+    }
+    if args.input.len() > 1 && (args.stream || args.resume) {
+        return Err("--stream and --resume take a single input file".into());
     }
+    if args.stream {
+        return convert_streaming(args, created_at);
+    }
+    if args.watch {
+        return convert_watching(args, created_at);
+    }
+
+    // Read input; merged inputs are read file by file
+    let input_data = match args.input.as_slice() {
+        [] => {
+            let mut buffer = Vec::new();
+            io::stdin().read_to_end(&mut buffer)?;
+            compress::decompress(buffer)?
+        }
+        [input_path] => compress::decompress(fs::read(input_path)?)?,
+        _ => Vec::new(),
+    };
+    if args.input.len() <= 1 {
+        log::info!("Read {} bytes from {}", input_data.len(), input_name(&args.input));
+    }
+    if !args.extract_records.is_empty() {
+        return extract_records(args, input_data);
+    }
+    let input_length = input_data.len();
+
+    // Look for an interrupted run to continue
+    let journal = match &args.output {
+        Some(output_path) if args.resume => Journal::load(&Journal::path_for(output_path))?,
+        _ => None,
+    };
+
+    // Parse ADIF file
+    let mut adif = if args.input.len() > 1 {
+        merge_inputs(args)?
+    } else if let Some(journal) = &journal {
+        if journal.input_length != input_length {
+            return Err("Input has changed since the interrupted run; remove its journal to start over".into());
+        }
+        eprintln!("Resuming after {} records", journal.records_written);
+        adif::AdifFile::parse_with(input_data.clone(), journal.input_offset, parse_options(args))?
+    } else {
+        parse_input(args, input_data)?
+    };
+    log::info!("Parsed {} QSOs and {} header fields", adif.records.len(), adif.header_fields.len());
 
     // Determine input and output encodings
-    let input_encoding = if let Some(encoding_str) = &args.input_encoding {
-        Some(AdifEncoding::from_str(encoding_str)?)
+    let input_encoding = if args.input.len() > 1 {
+        Some(AdifEncoding::Utf8) // Already decoded by merge_inputs
     } else {
-        adif.encoding.as_ref().and_then(|e| AdifEncoding::from_str(e).ok())
+        input_encoding_for(args.input_encoding.as_deref(), &adif)?
     };
+    match &input_encoding {
+        Some(encoding) => log::info!("Decoding as {}", encoding.to_encoding_rs().name()),
+        None => log::info!("Detecting the encoding of each field"),
+    }
 
-    let output_encoding = AdifEncoding::from_str(&args.encoding)?;
+    let output_encoding = AdifEncoding::parse_output(&args.encoding)?;
 
     // Create formatter
     let replacement_char = if args.delete {
@@ -45,24 +226,1215 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         Some(args.replace)
     };
 
-    let formatter = OutputFormatter::new(
+    let mut formatter = OutputFormatter::new(
         input_encoding,
-        output_encoding,
+        output_encoding.clone(),
         args.strict,
         replacement_char,
         args.delete,
         args.ascii,
     );
+    configure_formatter(args, created_at, &mut formatter)?;
+
+    // Decode once; everything from here on works on the decoded values.
+    // Records go first, one by one, to show progress on large files.
+    let mut progress = Progress::new("Decoding", "QSOs", Some(adif.records.len()));
+    for (index, record) in adif.records.iter_mut().enumerate() {
+        formatter.decode_record(record)?;
+        log::trace!("QSO {}: decoded {} fields", index + 1, record.fields.len());
+        progress.update(index + 1);
+    }
+    progress.clear();
+    formatter.decode(&mut adif)?;
+    if let Some(detected) = &adif.detected_encoding {
+        log::info!("Detected {}", detected);
+    }
+    if log::log_enabled!(log::Level::Debug) {
+        for change in formatter.report(&adif)?.changes {
+            log::debug!("{}", change);
+        }
+    }
+    if args.show_detected {
+        print_detected(&formatter, &adif);
+    }
+
+    for warning in &adif.warnings {
+        eprintln!("Warning: {}", warning);
+    }
+    for error in &adif.errors {
+        eprintln!("Skipped: {}", error);
+    }
+    let mut warning_count = adif.warnings.len() + adif.errors.len();
+    let mut repair_count = 0;
+
+    if let Some(profile) = args.profile {
+        for repair in profile.repair(&mut adif) {
+            eprintln!("Repaired: {}", repair);
+            repair_count += 1;
+        }
+    }
+
+    rewrite_header(args, created_at, &mut adif);
+
+    if args.fix_decimals {
+        let first_record = journal.as_ref().map_or(0, |journal| journal.records_written);
+        for (index, record) in adif.records.iter_mut().enumerate() {
+            for repair in decimal::repair_record(first_record + index, record, args.strict)? {
+                eprintln!("Repaired: {}", repair);
+                repair_count += 1;
+            }
+        }
+    }
+
+    if args.validate || args.validate_strict {
+        let first_record = journal.as_ref().map_or(0, |journal| journal.records_written);
+        let mut violations = validation::validate(&adif);
+        for violation in &mut violations {
+            violation.record += first_record;
+            eprintln!("Invalid: {}", violation);
+        }
+        warning_count += violations.len();
+        if args.validate_strict && !violations.is_empty() {
+            return Err(format!("{} ADIF specification violations", violations.len()).into());
+        }
+    }
+    if args.validate_only {
+        return check_fail_on(args, warning_count, repair_count);
+    }
+
+    // Handle debug mode
+    if let Some(selection) = &args.debug {
+        let debug_qsos = QsoSelector::select(&selection.0, &adif.records);
+        if debug_qsos.is_empty() {
+            println!("No QSO matches {}", selection);
+        }
+        DebugFormatter::print_qso_debug(&adif, &debug_qsos, formatter.processor(), args.debug_hex, &args.color.stdout());
+        return Ok(());
+    }
+
+    if args.dedupe || args.report_dupes {
+        let key = DedupeKey { fields: args.dedupe_key.clone(), window_minutes: args.dedupe_window };
+        let duplicates = dedupe::find_duplicates(&adif.records, &key);
+        if args.report_dupes {
+            for duplicate in &duplicates {
+                let record = &adif.records[duplicate.record];
+                let values: Vec<&str> = key.fields.iter().filter_map(|field| record.get(field)).collect();
+                eprintln!("Duplicate: {} ({})", duplicate, values.join(" "));
+            }
+        }
+        if args.dedupe {
+            dedupe::remove_duplicates(&mut adif.records, &duplicates);
+        }
+    }
+
+    if !args.sort.is_empty() {
+        transadif::sort::sort_records(&mut adif.records, &args.sort, args.sort_desc);
+    }
+
+    if args.fill_rst {
+        for record in &mut adif.records {
+            transadif::rst::fill_rst(record);
+        }
+    }
+
+    if let Some(cty) = load_cty(args)? {
+        for record in &mut adif.records {
+            cty.enrich(record);
+        }
+    }
+
+    match args.emit_hashes {
+        Some(HashOutput::App) => {
+            for record in &mut adif.records {
+                record.set("app_transadif_hash", &record.fingerprint());
+            }
+        }
+        Some(HashOutput::Sidecar) if !args.dry_run => {
+            let Some(output_path) = &args.output else {
+                return Err("--emit-hashes sidecar requires --output".into());
+            };
+            let first_record = journal.as_ref().map_or(0, |journal| journal.records_written);
+            write_hash_sidecar(&adif, first_record, output_path)?;
+        }
+        _ => {}
+    }
+
+    for record in &mut adif.records {
+        select_fields(args, record);
+        if args.anonymize == Some(Anonymization::Strip) {
+            transadif::anonymize::strip(&mut record.fields);
+        }
+        transadif::sort::order_fields(record, args.field_order, &args.field_priority);
+    }
+
+    let correction_count = if args.fail_on >= FailOn::Correction {
+        repair_count + formatter.corrections(&adif)?.iter().filter(|correction| correction.reason.is_repair()).count()
+    } else {
+        0
+    };
+
+    if !args.replace_field.is_empty() {
+        let mut counts = vec![0; args.replace_field.len()];
+        for record in &adif.records {
+            rewrite::count_changes(&args.replace_field, &formatter.corrected_values(record)?, &mut counts);
+        }
+        print_rewrite_counts(args, &counts);
+    }
+
+    if args.report {
+        eprintln!("Report for {}", input_name(&args.input));
+        let mut report = String::new();
+        formatter.report(&adif)?.render(&mut report, &args.color.stderr())?;
+        eprint!("{}", report);
+    }
+
+    if let Some(log_path) = &args.log_json {
+        let mut file = AtomicFile::create(log_path)?;
+        output::write_corrections_json(&formatter.corrections(&adif)?, &mut file)?;
+        file.commit()?;
+    }
+
+    if args.stats {
+        let mut stats = Stats::default();
+        for record in &adif.records {
+            let values: Vec<(&str, String)> = formatter.record_values(record)?.into_iter().map(|(field, value)| (field.name.as_str(), value)).collect();
+            stats.add(&values);
+        }
+        print!("{}", stats);
+        return check_fail_on(args, warning_count, correction_count);
+    }
+
+    if args.dry_run {
+        if args.show_diff {
+            print_diff(&formatter, &adif, &args.input)?;
+        } else if !args.report {
+            // --report already lists the changes
+            print_changes(&formatter, &adif)?;
+        }
+        return check_fail_on(args, warning_count, correction_count);
+    }
 
     // Write output
-    if let Some(output_path) = &args.output {
-        let mut file = fs::File::create(output_path)?;
-        formatter.format_adif(&adif, &mut file)?;
+    if args.format != OutputFormat::Adi && output_encoding != AdifEncoding::Utf8 {
+        return Err("ADX and JSON output are always UTF-8".into());
+    }
+    if args.format != OutputFormat::Adi && (args.split_by.is_some() || args.max_records_per_file.is_some()) {
+        return Err("--split-by and --max-records-per-file only support ADI output".into());
+    }
+
+    if args.format == OutputFormat::Adx {
+        let formatter = AdxFormatter::new(formatter);
+        write_output(args, |mut writer| formatter.format_adx(&adif, &mut writer))?;
+    } else if args.format == OutputFormat::Json {
+        let formatter = JsonFormatter::new(formatter, args.json_keys);
+        write_output(args, |mut writer| formatter.format_json(&adif, &mut writer))?;
+    } else if args.resume {
+        let output_path = args.output.as_ref().unwrap();
+        let mut sink = match journal {
+            Some(journal) => JournalSink::resume(output_path, journal)?,
+            None => JournalSink::create(output_path, input_length)?,
+        };
+        formatter.write_to_sink(&adif, &mut ProgressSink::new(&mut sink, adif.records.len()))?;
+    } else if args.split_by.is_some() || args.max_records_per_file.is_some() {
+        formatter.write_to_sink(&adif, &mut ProgressSink::new(output_sink(args)?.as_mut(), adif.records.len()))?;
     } else {
-        let stdout = io::stdout();
-        let mut handle = stdout.lock();
-        formatter.format_adif(&adif, &mut handle)?;
+        write_output(args, |writer| {
+            formatter.write_to_sink(&adif, &mut ProgressSink::new(&mut WriterSink::new(writer), adif.records.len()))
+        })?;
+    }
+    log::info!("Wrote {} QSOs to {}", adif.records.len(), args.output.as_ref().map_or("stdout".to_string(), |path| path.display().to_string()));
+
+    check_fail_on(args, warning_count, correction_count)
+}
+
+/// Hands `write` the output file, which appears once written completely, or
+/// stdout, compressing what it writes with `--compress`.
+fn write_output<F>(args: &ConvertArgs, write: F) -> Result<(), Box<dyn std::error::Error>>
+where
+    F: FnOnce(&mut dyn Write) -> Result<(), output::OutputError>,
+{
+    let mut file = args.output.as_ref().map(AtomicFile::create).transpose()?;
+    let mut stdout = io::stdout().lock();
+    let writer: &mut dyn Write = match &mut file {
+        Some(file) => file,
+        None => &mut stdout,
+    };
+
+    match args.compress {
+        Some(Compression::Gz) => {
+            let mut encoder = GzEncoder::new(writer, flate2::Compression::default());
+            write(&mut encoder)?;
+            encoder.finish()?;
+        }
+        None => write(writer)?,
+    }
+
+    if let Some(file) = &mut file {
+        file.commit()?;
+    }
+    Ok(())
+}
+
+/// Applies --fail-on once the conversion itself has succeeded.
+fn check_fail_on(args: &ConvertArgs, warnings: usize, corrections: usize) -> Result<(), Box<dyn std::error::Error>> {
+    let plural = |count: usize| if count == 1 { "" } else { "s" };
+    let level = args.fail_on.to_possible_value().map_or_else(String::new, |value| value.get_name().to_string());
+    if args.fail_on >= FailOn::Warning && warnings > 0 {
+        return Err(format!("{} warning{} in the input (--fail-on {})", warnings, plural(warnings), level).into());
+    }
+    if args.fail_on >= FailOn::Correction && corrections > 0 {
+        return Err(format!("{} correction{} needed (--fail-on {})", corrections, plural(corrections), level).into());
+    }
+    Ok(())
+}
+
+fn parse_input(args: &ConvertArgs, data: Vec<u8>) -> Result<adif::AdifFile, Box<dyn std::error::Error>> {
+    Ok(match args.from {
+        InputFormat::Adi => adif::AdifFile::parse_with(data, 0, parse_options(args))?,
+        InputFormat::Csv => transadif::csv::parse(&data, b',', &args.parse_field_map())?,
+        InputFormat::Tsv => transadif::csv::parse(&data, b'\t', &args.parse_field_map())?,
+    })
+}
+
+/// With --strict, or lengths said to count bytes or characters, declared
+/// lengths are taken as they are instead of being read up to the next tag.
+fn parse_options(args: &ConvertArgs) -> adif::ParseOptions {
+    adif::ParseOptions { recover: args.skip_malformed, strict: args.strict || args.count_mode != adif::FieldCountMode::Auto }
+}
+
+/// Writes the header and the records chosen with --extract-records as they
+/// are in the input.
+fn extract_records(args: &ConvertArgs, data: Vec<u8>) -> Result<(), Box<dyn std::error::Error>> {
+    if args.input.len() > 1 || args.from != InputFormat::Adi {
+        return Err("--extract-records takes a single ADI input".into());
+    }
+    if encoding::detect_utf16(&data).is_some() {
+        return Err("--extract-records does not support UTF-16 input; convert it to UTF-8 first".into());
+    }
+
+    let adif = adif::AdifFile::parse_recovering(data.clone())?;
+    // Ranges are clamped before they are expanded, as they may be huge
+    let count = adif.records.len();
+    let indexes: Vec<usize> = args.extract_records.iter().flat_map(|range| *range.start() - 1..(*range.end()).min(count)).collect();
+    if let Some(missing) = args.extract_records.iter().filter(|range| *range.end() > count).map(|range| (*range.start()).max(count + 1)).min() {
+        eprintln!("Warning: there is no QSO {}; the input has {}", missing, count);
+    }
+    let extract = adif.extract_records(&data, &indexes);
+
+    match &args.output {
+        Some(output_path) => transadif::atomic::write(output_path, extract)?,
+        None => io::stdout().write_all(&extract)?,
+    }
+    Ok(())
+}
+
+/// Reads several inputs into one file with the header of the first. Each
+/// input is decoded with its own declared (or detected) encoding, so the
+/// merged fields all hold UTF-8.
+fn merge_inputs(args: &ConvertArgs) -> Result<adif::AdifFile, Box<dyn std::error::Error>> {
+    let mut merged: Option<adif::AdifFile> = None;
+
+    for input_path in &args.input {
+        let mut adif = parse_input(args, compress::decompress(fs::read(input_path)?)?)?;
+        log::debug!("Read {} QSOs from {}", adif.records.len(), input_path.display());
+        let input_encoding = input_encoding_for(args.input_encoding.as_deref(), &adif)?;
+        EncodingProcessor::new(input_encoding, AdifEncoding::Utf8, args.strict).decode_file(&mut adif)?;
+
+        // The merged file is UTF-8, whatever the encodings of its inputs
+        let fields = adif.header_fields.iter_mut().chain(adif.records.iter_mut().flat_map(|record| record.fields.iter_mut()));
+        for field in fields {
+            field.original_bytes = field.data.as_bytes().into();
+        }
+        for record in &mut adif.records {
+            // They described bytes that are gone now
+            record.fields.retain(|field| !encoding::is_encoding_annotation(&field.name));
+        }
+        for warning in &mut adif.warnings {
+            *warning = format!("{}: {}", input_path.display(), warning);
+        }
+        // Record numbers in the merged file would be off, so name the input instead
+        for error in adif.errors.drain(..) {
+            adif.warnings.push(format!("{}: skipped {}", input_path.display(), error));
+        }
+
+        match &mut merged {
+            Some(merged) => {
+                merged.records.append(&mut adif.records);
+                merged.warnings.append(&mut adif.warnings);
+            }
+            None => merged = Some(adif),
+        }
+    }
+
+    let mut merged = merged.ok_or("No input files to merge")?;
+    merged.header_fields.retain(|field| !field.name.eq_ignore_ascii_case("encoding"));
+    merged.encoding = Some("UTF-8".to_string());
+    Ok(merged)
+}
+
+/// Prints what encoding detection made of the file, and of each value it
+/// reads as a different encoding.
+fn print_detected(formatter: &OutputFormatter, adif: &adif::AdifFile) {
+    let Some(file) = adif.detected_encoding else {
+        eprintln!("Detected: nothing, since the input encoding is known or all values are ASCII");
+        return;
+    };
+    eprintln!("Detected: {}", file);
+
+    for (index, record) in adif.records.iter().enumerate() {
+        for field in &record.fields {
+            if field.original_bytes.is_ascii() || encoding::encoding_override(record, &field.name).is_some() {
+                continue;
+            }
+            let detection = formatter.processor().detect(&field.original_bytes);
+            if detection.encoding != file.encoding {
+                eprintln!("Detected: QSO {}: {} at byte {}: {}", index + 1, field.name.to_uppercase(), field.offset, detection);
+            }
+        }
+    }
+}
+
+/// Prints one line per field whose written value would differ from the input.
+fn print_changes(formatter: &OutputFormatter, adif: &adif::AdifFile) -> Result<(), Box<dyn std::error::Error>> {
+    let changes = formatter.report(adif)?.changes;
+    let mut stdout = io::stdout().lock();
+    for change in &changes {
+        writeln!(stdout, "{}", change)?;
+    }
+    writeln!(stdout, "{} field{} would change", changes.len(), if changes.len() == 1 { "" } else { "s" })?;
+    Ok(())
+}
+
+/// The input files as named in diffs and reports.
+fn input_name(inputs: &[PathBuf]) -> String {
+    if inputs.is_empty() {
+        "stdin".to_string()
+    } else {
+        inputs.iter().map(|path| path.display().to_string()).collect::<Vec<_>>().join(" + ")
+    }
+}
+
+/// Prints a unified diff with one hunk per changed record, comparing the
+/// decoded input fields with the fields that would be written.
+fn print_diff(formatter: &OutputFormatter, adif: &adif::AdifFile, inputs: &[PathBuf]) -> Result<(), Box<dyn std::error::Error>> {
+    let name = input_name(inputs);
+    let mut stdout = io::stdout().lock();
+    writeln!(stdout, "--- {}\n+++ {} (converted)", name, name)?;
+
+    let (mut before_line, mut after_line) = (1, 1);
+    for (index, record) in adif.records.iter().enumerate() {
+        let before = formatter.input_lines(record)?;
+        let after = formatter.output_lines(record)?;
+        if let Some(hunk) = diff::unified_hunk(&before, &after, before_line, after_line, &format!("QSO {}", index + 1)) {
+            stdout.write_all(hunk.as_bytes())?;
+        }
+        before_line += before.len();
+        after_line += after.len();
     }
 
     Ok(())
 }
+
+/// Converts one record at a time from the input straight to the output.
+fn convert_streaming(args: &ConvertArgs, created_at: u64) -> Result<(), Box<dyn std::error::Error>> {
+    if args.format != OutputFormat::Adi {
+        return Err("--stream only supports ADI output".into());
+    }
+    if args.emit_hashes == Some(HashOutput::Sidecar) {
+        return Err("--stream does not support --emit-hashes sidecar".into());
+    }
+
+    let input = match args.input.first() {
+        Some(input_path) => compress::reader(fs::File::open(input_path)?)?,
+        None => compress::reader(io::stdin().lock())?,
+    };
+    let mut parser = adif::StreamingParser::new(input)?;
+    parser.set_recover(args.skip_malformed);
+    parser.set_strict(parse_options(args).strict);
+
+    for warning in &parser.header().warnings {
+        eprintln!("Warning: {}", warning);
+    }
+
+    let input_encoding = input_encoding_for(args.input_encoding.as_deref(), parser.header())?;
+    let replacement_char = if args.delete { None } else { Some(args.replace) };
+    let mut formatter = OutputFormatter::new(
+        input_encoding,
+        AdifEncoding::parse_output(&args.encoding)?,
+        args.strict,
+        replacement_char,
+        args.delete,
+        args.ascii,
+    );
+    configure_formatter(args, created_at, &mut formatter)?;
+
+    let mut sink = output_sink(args)?;
+    let mut header = parser.header().clone();
+    formatter.decode(&mut header)?;
+    rewrite_header(args, created_at, &mut header);
+    let mut streamer = OutputStreamer::new(&formatter, sink.as_mut(), &header)?;
+
+    let userdefs = validation::userdef_names(&header);
+
+    let cty = load_cty(args)?;
+    let mut warning_count = header.warnings.len();
+    let mut counts = RecordCounts::new(args);
+
+    let mut index = 0;
+    let mut progress = Progress::new("Converting", "QSOs", None);
+    while let Some(record) = parser.next() {
+        let mut record = record?;
+        for warning in parser.take_warnings() {
+            progress.clear();
+            eprintln!("Warning: {}", warning);
+            warning_count += 1;
+        }
+        for error in parser.take_errors() {
+            progress.clear();
+            eprintln!("Skipped: {}", error);
+            warning_count += 1;
+        }
+        prepare_record(args, &formatter, cty.as_ref(), &userdefs, index, &mut record, &mut counts)?;
+        streamer.write_record(&record)?;
+        index += 1;
+        progress.update(index);
+    }
+    progress.clear();
+
+    streamer.finish()?;
+    log::info!("Converted {} QSOs", index);
+    print_rewrite_counts(args, &counts.rewrites);
+
+    check_fail_on(args, warning_count + counts.violations, counts.corrections)
+}
+
+/// How often `--watch` looks for records appended to the input
+const WATCH_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Converts the records of the input and then every record appended to it,
+/// writing each batch through to the output as soon as it is complete.
+/// Runs until interrupted.
+fn convert_watching(args: &ConvertArgs, created_at: u64) -> Result<(), Box<dyn std::error::Error>> {
+    if args.format != OutputFormat::Adi {
+        return Err("--watch only supports ADI output".into());
+    }
+    if args.emit_hashes == Some(HashOutput::Sidecar) {
+        return Err("--watch does not support --emit-hashes sidecar".into());
+    }
+    let [input_path] = args.input.as_slice() else {
+        return Err("--watch takes a single input file".into());
+    };
+
+    // A new log may not exist yet, or not have its header or first QSO complete
+    let read_input = || match fs::read(input_path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        result => result,
+    };
+    let (mut data, mut adif) = loop {
+        let data = read_input()?;
+        let adif = adif::AdifFile::parse(&data)?;
+        if !adif.header_end_tag.is_empty() || adif.records.iter().any(|record| !record.end_tag.is_empty()) {
+            break (data, adif);
+        }
+        std::thread::sleep(WATCH_INTERVAL);
+    };
+    for warning in &adif.warnings {
+        eprintln!("Warning: {}", warning);
+    }
+
+    let input_encoding = input_encoding_for(args.input_encoding.as_deref(), &adif)?;
+    let replacement_char = if args.delete { None } else { Some(args.replace) };
+    let mut formatter = OutputFormatter::new(
+        input_encoding,
+        AdifEncoding::parse_output(&args.encoding)?,
+        args.strict,
+        replacement_char,
+        args.delete,
+        args.ascii,
+    );
+    configure_formatter(args, created_at, &mut formatter)?;
+
+    // Written in place rather than atomically, so readers see each QSO
+    let mut sink: Box<dyn AdifSink> = match &args.output {
+        Some(output_path) => Box::new(WriterSink::new(fs::File::create(output_path)?)),
+        None => Box::new(WriterSink::new(io::stdout().lock())),
+    };
+    let mut header = adif.clone();
+    header.records.clear();
+    formatter.decode(&mut header)?;
+    rewrite_header(args, created_at, &mut header);
+    let mut streamer = OutputStreamer::new(&formatter, sink.as_mut(), &header)?;
+
+    let userdefs = validation::userdef_names(&header);
+    let cty = load_cty(args)?;
+    let mut counts = RecordCounts::new(args);
+    let mut index = 0;
+    let mut progress = Progress::status(&format!("Watching {}", input_path.display()), "QSOs converted");
+
+    loop {
+        // A record without its <eor> is still being written
+        let complete = adif.records.iter().take_while(|record| !record.end_tag.is_empty()).count();
+        let resume_offset = adif.records.get(complete).map_or(data.len(), |record| record.offset);
+        if complete > 0 {
+            // Repairs and violations go on lines of their own
+            progress.clear();
+            log::info!("Converting {} new QSOs", complete);
+        }
+        for mut record in adif.records.drain(..complete) {
+            prepare_record(args, &formatter, cty.as_ref(), &userdefs, index, &mut record, &mut counts)?;
+            streamer.write_record(&record)?;
+            index += 1;
+        }
+        streamer.flush()?;
+        progress.update(index);
+
+        let grown = loop {
+            std::thread::sleep(WATCH_INTERVAL);
+            let grown = read_input()?;
+            if grown.len() < data.len() {
+                return Err(format!("{} got shorter; restart to convert it from the beginning", input_path.display()).into());
+            }
+            if grown.len() > data.len() {
+                break grown;
+            }
+        };
+        data = grown;
+        adif = adif::AdifFile::parse_with(data.clone(), resume_offset, parse_options(args))?;
+    }
+}
+
+/// What happened to the records of a conversion that handles them one at a time
+struct RecordCounts {
+    violations: usize,
+    corrections: usize,
+    /// Values changed by each --replace-field rewrite
+    rewrites: Vec<usize>,
+}
+
+impl RecordCounts {
+    fn new(args: &ConvertArgs) -> Self {
+        Self { violations: 0, corrections: 0, rewrites: vec![0; args.replace_field.len()] }
+    }
+}
+
+/// Applies the record-level options of `--stream` and `--watch` to the record
+/// at `index` before it is written, reporting repairs and violations on stderr.
+fn prepare_record(
+    args: &ConvertArgs,
+    formatter: &OutputFormatter,
+    cty: Option<&CtyDatabase>,
+    userdefs: &[String],
+    index: usize,
+    record: &mut adif::Record,
+    counts: &mut RecordCounts,
+) -> Result<(), Box<dyn std::error::Error>> {
+    formatter.decode_record(record)?;
+    if args.fix_decimals {
+        for repair in decimal::repair_record(index, record, args.strict)? {
+            eprintln!("Repaired: {}", repair);
+            counts.corrections += 1;
+        }
+    }
+    if args.validate || args.validate_strict {
+        for violation in validation::validate_record(index, record, userdefs) {
+            eprintln!("Invalid: {}", violation);
+            counts.violations += 1;
+        }
+    }
+    if args.fill_rst {
+        transadif::rst::fill_rst(record);
+    }
+    if let Some(cty) = cty {
+        cty.enrich(record);
+    }
+    if args.emit_hashes == Some(HashOutput::App) {
+        record.set("app_transadif_hash", &record.fingerprint());
+    }
+    select_fields(args, record);
+    if args.anonymize == Some(Anonymization::Strip) {
+        transadif::anonymize::strip(&mut record.fields);
+    }
+    transadif::sort::order_fields(record, args.field_order, &args.field_priority);
+    if !args.replace_field.is_empty() {
+        rewrite::count_changes(&args.replace_field, &formatter.corrected_values(record)?, &mut counts.rewrites);
+    }
+    if args.fail_on >= FailOn::Correction {
+        counts.corrections += formatter.record_corrections(index, record)?.iter().filter(|correction| correction.reason.is_repair()).count();
+    }
+    Ok(())
+}
+
+/// The country file for --enrich-dxcc, if enabled.
+fn load_cty(args: &ConvertArgs) -> Result<Option<CtyDatabase>, CtyError> {
+    match &args.cty_file {
+        Some(path) if args.enrich_dxcc => CtyDatabase::load(path).map(Some),
+        _ => Ok(None),
+    }
+}
+
+/// Summarizes what each --replace-field changed.
+fn print_rewrite_counts(args: &ConvertArgs, counts: &[usize]) {
+    for (rewrite, count) in args.replace_field.iter().zip(counts) {
+        eprintln!("Replaced: {} in {} record{}", rewrite, count, if *count == 1 { "" } else { "s" });
+    }
+}
+
+/// Applies --add-header, --preamble, --set-header and --created-timestamp.
+fn rewrite_header(args: &ConvertArgs, created_at: u64, adif: &mut adif::AdifFile) {
+    if args.add_header {
+        adif.add_header(&generated_preamble(args, created_at), created_at);
+    }
+    match args.preamble {
+        // The preamble often names the operator
+        PreamblePolicy::Keep if args.anonymize.is_some() => adif.preamble = generated_preamble(args, created_at),
+        PreamblePolicy::Keep => {}
+        // The header is only recognized behind a non-empty preamble
+        PreamblePolicy::Strip => adif.preamble = "\r\n".to_string(),
+        PreamblePolicy::Regenerate => adif.preamble = generated_preamble(args, created_at),
+    }
+    for (name, value) in &args.set_header {
+        adif.set_header(name, value);
+    }
+    if args.created_timestamp {
+        adif.set_header("created_timestamp", &adif::timestamp(created_at));
+    }
+    if args.anonymize == Some(Anonymization::Strip) {
+        transadif::anonymize::strip(&mut adif.header_fields);
+    }
+}
+
+/// A preamble naming TransADIF, the input and the time of conversion.
+fn generated_preamble(args: &ConvertArgs, created_at: u64) -> String {
+    let source = match args.input.as_slice() {
+        [] => "standard input".to_string(),
+        [path] => path.display().to_string(),
+        paths => format!("{} files", paths.len()),
+    };
+    format!(
+        "Converted by TransADIF {} from {} on {} UTC\r\n\r\n",
+        env!("CARGO_PKG_VERSION"),
+        source,
+        adif::timestamp(created_at)
+    )
+}
+
+/// Applies --keep-fields and --drop-fields. Encoding annotations always stay,
+/// since they steer decoding of the remaining fields and are never written.
+fn select_fields(args: &ConvertArgs, record: &mut adif::Record) {
+    record.fields.retain(|field| encoding::is_encoding_annotation(&field.name) || args.selects_field(&field.name));
+}
+
+/// Applies the optional value corrections selected on the command line.
+fn configure_formatter(args: &ConvertArgs, created_at: u64, formatter: &mut OutputFormatter) -> Result<(), translit::TranslitError> {
+    formatter.set_emoji_policy(args.emoji);
+    formatter.set_entity_references(args.entities);
+    formatter.set_count_mode(args.count_mode);
+    formatter.set_annotate_encoding(args.annotate_encoding);
+    formatter.set_byte_order_mark(args.bom);
+    formatter.set_line_endings(args.line_endings);
+    formatter.set_excess_policy(args.excess);
+    formatter.set_layout(args.layout);
+    if args.bom && args.encoding.parse::<AdifEncoding>().is_ok_and(|encoding| encoding != AdifEncoding::Utf8) {
+        eprintln!("Warning: --bom only applies to UTF-8 output");
+    }
+    formatter.set_preserve(args.preserve);
+    formatter.set_mojibake_threshold(args.mojibake_confidence());
+
+    if let Some(preset) = args.preset {
+        formatter.set_preset(preset);
+    }
+    formatter.set_locale(args.locale);
+
+    if let Some(path) = &args.translit_table {
+        let mut table = args.preset.map_or_else(TranslitTable::builtin, |preset| preset.translit_table());
+        table.extend(TranslitTable::load(path)?);
+        formatter.set_translit_table(table);
+    }
+
+    if args.fix_homoglyphs {
+        formatter.add_field_transform(transadif::homoglyph::normalize_field);
+    }
+
+    if args.fix_callsigns {
+        formatter.add_field_transform(transadif::callsign::normalize_field);
+    }
+
+    if args.fix_grids || args.grid_precision.is_some() {
+        let precision = args.grid_precision;
+        formatter.add_field_transform(move |name, value| transadif::grid::normalize_field(name, value, precision));
+    }
+
+    if args.fix_refs {
+        formatter.add_field_transform(transadif::reference::normalize_field);
+    }
+
+    for rewrite in &args.replace_field {
+        let rewrite = rewrite.clone();
+        formatter.add_field_transform(move |name, value| rewrite.apply(name, value));
+    }
+
+    if let Some(fix) = args.qslmsg_fix {
+        let width = args.qslmsg_width;
+        formatter.add_field_transform(move |name, value| qslmsg::fix_field(name, value, fix, width));
+    }
+
+    // Last, so nothing is derived from the hashes
+    if args.anonymize == Some(Anonymization::Hash) {
+        // Unsalted with --deterministic, which also dates every file to 0
+        let salt = if created_at == 0 { 0 } else { transadif::anonymize::random_salt() };
+        formatter.add_field_transform(move |name, value| transadif::anonymize::hash_field(name, value, salt));
+    }
+
+    Ok(())
+}
+
+/// Seconds since the Unix epoch to write as the creation time of generated
+/// headers: now, or 0 with `--deterministic`.
+fn created_at(deterministic: bool) -> u64 {
+    if deterministic {
+        return 0;
+    }
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// Writes the records of several files after one generated header. Each file
+/// is decoded with its own declared (or detected) encoding.
+fn cat<F>(files: &[PathBuf], sink: &mut dyn AdifSink, created_at: u64, formatter_for: F) -> Result<(), Box<dyn std::error::Error>>
+where
+    F: Fn(Option<AdifEncoding>) -> OutputFormatter,
+{
+    let mut header = adif::AdifFile::new();
+    header.preamble = format!("Concatenated by TransADIF from {} files\r\n\r\n", files.len());
+    let mut programid = adif::Field::new("programid", "TransADIF");
+    programid.excess_data = "\r\n".to_string();
+    header.header_fields.push(programid);
+    let mut created = adif::Field::new("created_timestamp", &adif::timestamp(created_at));
+    created.excess_data = "\r\n".to_string();
+    header.header_fields.push(created);
+    header.header_excess_data = "\r\n".to_string();
+
+    sink.write_header(&formatter_for(None).format_header(&header)?)?;
+
+    for file in files {
+        let mut adif = adif::AdifFile::parse_owned(compress::decompress(fs::read(file)?)?)?;
+        let formatter = formatter_for(input_encoding_for(None, &adif)?);
+        formatter.decode(&mut adif)?;
+
+        for record in &adif.records {
+            sink.write_record(record, &formatter.format_record(record)?)?;
+        }
+    }
+
+    sink.finish()?;
+    Ok(())
+}
+
+/// Updates the QSL fields of `log` from `confirmations` and writes it,
+/// reporting each change and each unmatched confirmation on stderr.
+fn reconcile<F>(
+    log: &Path,
+    confirmations: &Path,
+    output: Option<&Path>,
+    service: Option<QslService>,
+    window: u32,
+    created_at: u64,
+    formatter_for: F,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    F: Fn(Option<AdifEncoding>) -> OutputFormatter,
+{
+    let read = |path: &Path| -> Result<(adif::AdifFile, OutputFormatter), Box<dyn std::error::Error>> {
+        let mut adif = adif::AdifFile::parse_owned(compress::decompress(fs::read(path)?)?)?;
+        let formatter = formatter_for(input_encoding_for(None, &adif)?);
+        formatter.decode(&mut adif)?;
+        for warning in &adif.warnings {
+            eprintln!("Warning: {}: {}", path.display(), warning);
+        }
+        Ok((adif, formatter))
+    };
+    let (mut adif, formatter) = read(log)?;
+    let (confirmed, _) = read(confirmations)?;
+
+    let service = service
+        .or_else(|| QslService::detect(&confirmed))
+        .ok_or("Cannot tell which service the confirmations are from; name it with --service")?;
+    let today = adif::timestamp(created_at)[..8].to_string();
+    let reconciliation = transadif::reconcile::reconcile(&mut adif.records, &confirmed.records, service, window, &today);
+
+    for confirmed in &reconciliation.confirmed {
+        eprintln!("Confirmed: {}", confirmed);
+    }
+    for unmatched in &reconciliation.unmatched {
+        eprintln!("Unmatched: {}", unmatched);
+    }
+    let plural = |count: usize| if count == 1 { "" } else { "s" };
+    let (confirmed, unmatched) = (reconciliation.confirmed.len(), reconciliation.unmatched.len());
+    eprintln!(
+        "Reconciled: {} QSO{} newly confirmed, {} already confirmed, {} confirmation{} unmatched",
+        confirmed,
+        plural(confirmed),
+        reconciliation.already_confirmed,
+        unmatched,
+        plural(unmatched)
+    );
+
+    let mut sink = open_sink(output, None)?;
+    sink.write_header(&formatter.format_header(&adif)?)?;
+    for record in &adif.records {
+        sink.write_record(record, &formatter.format_record(record)?)?;
+    }
+    sink.finish()?;
+    Ok(())
+}
+
+/// Writes `record-number<TAB>hash` lines to `<output>.hashes`. Record numbers
+/// start at 1, matching `--debug`.
+fn write_hash_sidecar(adif: &adif::AdifFile, first_record: usize, output_path: &Path) -> io::Result<()> {
+    let mut name = output_path.file_name().unwrap_or_default().to_os_string();
+    name.push(".hashes");
+
+    let path = output_path.with_file_name(name);
+
+    // A resumed run keeps the hashes of the records written before. The
+    // interrupted run hashed all of its records up front, so the ones after
+    // those have to go.
+    let mut lines = String::new();
+    if first_record > 0 {
+        let previous = fs::read_to_string(&path).unwrap_or_default();
+        for line in previous.lines() {
+            let number = line.split('\t').next().and_then(|number| number.parse::<usize>().ok());
+            if number.is_some_and(|number| number <= first_record) {
+                lines.push_str(line);
+                lines.push('\n');
+            }
+        }
+    }
+    for (index, record) in adif.records.iter().enumerate() {
+        lines.push_str(&format!("{}\t{}\n", first_record + index + 1, record.fingerprint()));
+    }
+    transadif::atomic::write(path, lines)
+}
+
+/// Converts every .adi and .adx file under `dir` to the same relative path
+/// under `out_dir`, `jobs` files at a time, reporting on each file in order
+/// and then on the whole batch. ADX files are written as ADX, and so always as UTF-8.
+/// Files that do not declare an encoding are decoded field by field like
+/// `convert` does; `cache` remembers the outcome for the next run over the
+/// same files.
+fn batch<F>(
+    dir: &Path,
+    out_dir: &Path,
+    jobs: usize,
+    output_encoding: &AdifEncoding,
+    cache: Option<&Mutex<EncodingCache>>,
+    formatter_for: F,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    F: Fn(Option<AdifEncoding>, AdifEncoding) -> OutputFormatter + Sync,
+{
+    // Leave out the results of earlier runs when the output is inside the input tree
+    let out_dir_canonical = fs::canonicalize(out_dir).ok();
+    let mut files = Vec::new();
+    find_adif_files(dir, out_dir_canonical.as_deref(), &mut files)?;
+    files.sort();
+
+    let next = AtomicUsize::new(0);
+    let mut records = 0;
+    let mut failed = 0;
+    let mut cached = 0;
+
+    let (sender, receiver) = mpsc::channel();
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, files.len().max(1)) {
+            let sender = sender.clone();
+            let (files, next, formatter_for) = (&files, &next, &formatter_for);
+            scope.spawn(move || loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(file) = files.get(index) else {
+                    break;
+                };
+                let relative = file.strip_prefix(dir).unwrap_or(file);
+                let result = batch_file(file, &out_dir.join(relative), output_encoding, cache, formatter_for)
+                    .map_err(|e| e.to_string());
+                if sender.send((index, result)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+
+        // Report in file order, however the jobs finish
+        let mut finished = BTreeMap::new();
+        let mut next_report = 0;
+        for (index, result) in receiver {
+            finished.insert(index, result);
+            while let Some(result) = finished.remove(&next_report) {
+                let relative = files[next_report].strip_prefix(dir).unwrap_or(&files[next_report]);
+                next_report += 1;
+                match result {
+                    Ok(report) => {
+                        for warning in report.warnings {
+                            eprintln!("{}: Warning: {}", relative.display(), warning);
+                        }
+                        println!("{}: {} records", relative.display(), report.records);
+                        records += report.records;
+                        cached += usize::from(report.cached);
+                    }
+                    Err(e) => {
+                        eprintln!("{}: Error: {}", relative.display(), e);
+                        failed += 1;
+                    }
+                }
+            }
+        }
+    });
+
+    if let Some(cache) = cache {
+        fs::create_dir_all(out_dir)?;
+        cache.lock().unwrap().save()?;
+    }
+
+    println!(
+        "Converted {} of {} files ({} records, {} encodings from cache)",
+        files.len() - failed,
+        files.len(),
+        records,
+        cached
+    );
+
+    if failed > 0 {
+        return Err(format!("{} files could not be converted", failed).into());
+    }
+    Ok(())
+}
+
+/// Outcome of converting one file of a batch
+struct BatchReport {
+    records: usize,
+    warnings: Vec<String>,
+    /// Whether the input encoding came from the cache
+    cached: bool,
+}
+
+fn batch_file<F>(
+    input: &Path,
+    output: &Path,
+    output_encoding: &AdifEncoding,
+    cache: Option<&Mutex<EncodingCache>>,
+    formatter_for: &F,
+) -> Result<BatchReport, Box<dyn std::error::Error>>
+where
+    F: Fn(Option<AdifEncoding>, AdifEncoding) -> OutputFormatter,
+{
+    let data = compress::decompress(fs::read(input)?)?;
+    let is_adx = has_extension(input, "adx");
+    let mut adif = if is_adx {
+        transadif::adx::parse(&data)?
+    } else {
+        adif::AdifFile::parse(&data)?
+    };
+    let declared = input_encoding_for(None, &adif)?;
+    let hash = cache::content_hash(&data);
+    let known = cache.filter(|_| declared.is_none()).and_then(|cache| cache.lock().unwrap().get(&hash).cloned());
+    let input_encoding = match &known {
+        Some(Decision::Encoding(encoding)) => encoding.parse().ok(),
+        _ => declared.clone(),
+    };
+
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut writer = AtomicFile::create(output)?;
+    let formatter = formatter_for(input_encoding, if is_adx { AdifEncoding::Utf8 } else { output_encoding.clone() });
+    let decoded = match &known {
+        Some(Decision::PerField(encodings)) => {
+            let encodings: Option<Vec<_>> = encodings.iter().map(|name| encoding_rs::Encoding::for_label(name.as_bytes())).collect();
+            encodings.map_or(Ok(false), |encodings| formatter.processor().decode_file_with(&mut adif, &encodings))?
+        }
+        _ => false,
+    };
+    let cached = decoded || matches!(known, Some(Decision::Encoding(_)));
+    if let Some(cache) = cache.filter(|_| declared.is_none() && !cached) {
+        // Decoding with the one encoding detection picked for every value
+        // gives the same output without detecting again
+        let processor = formatter.processor();
+        let decision = match processor.common_encoding(&adif) {
+            Some(encoding) => Decision::Encoding(encoding.to_string().to_string()),
+            None => Decision::PerField(processor.field_encodings(&adif).iter().map(|encoding| encoding.name().to_string()).collect()),
+        };
+        cache.lock().unwrap().insert(hash, decision);
+    }
+    if !decoded {
+        formatter.decode(&mut adif)?;
+    }
+    if is_adx {
+        AdxFormatter::new(formatter).format_adx(&adif, &mut writer)?;
+    } else {
+        formatter.format_adif(&adif, &mut writer)?;
+    }
+    writer.commit()?;
+
+    Ok(BatchReport { records: adif.records.len(), warnings: adif.warnings, cached })
+}
+
+/// Collects the .adi and .adx files below `dir`, not descending into `skip`.
+fn find_adif_files(dir: &Path, skip: Option<&Path>, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            if skip.is_none() || fs::canonicalize(&path).ok().as_deref() != skip {
+                find_adif_files(&path, skip, files)?;
+            }
+        } else if has_extension(&path, "adi") || has_extension(&path, "adx") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension().is_some_and(|e| e.eq_ignore_ascii_case(extension))
+}
+
+/// The sink for the converted file, split by --split-by and
+/// --max-records-per-file if given.
+fn output_sink(args: &ConvertArgs) -> io::Result<Box<dyn AdifSink>> {
+    let Some(output_path) = args.output.as_ref().filter(|_| args.split_by.is_some() || args.max_records_per_file.is_some()) else {
+        return open_sink(args.output.as_deref(), args.compress);
+    };
+
+    let split_by = args.split_by.clone();
+    let mut sink = SplitSink::new(output_path, move |record: &adif::Record| {
+        split_by.as_ref().map_or_else(String::new, |split_by| split_by.key(record))
+    });
+    if let Some(max_records) = args.max_records_per_file {
+        sink = sink.max_records_per_file(max_records.get());
+    }
+    Ok(Box::new(sink))
+}
+
+/// Sink writing to the given file, or to stdout when there is none.
+fn open_sink(output: Option<&Path>, compression: Option<Compression>) -> io::Result<Box<dyn AdifSink>> {
+    Ok(match (output, compression) {
+        (Some(output_path), None) => Box::new(FileSink::create(output_path)?),
+        (None, None) => Box::new(WriterSink::new(io::stdout().lock())),
+        (Some(output_path), Some(Compression::Gz)) => Box::new(GzSink::create(output_path)?),
+        (None, Some(Compression::Gz)) => Box::new(GzSink::new(io::stdout().lock())),
+    })
+}
+
+/// Determines the input encoding from the command line, falling back to the file's header.
+/// UTF-16 input was already converted to UTF-8 by the parser, which the header then says.
+fn input_encoding_for(suggested: Option<&str>, adif: &adif::AdifFile) -> Result<Option<AdifEncoding>, encoding::EncodingError> {
+    match suggested.map(str::parse::<AdifEncoding>).transpose()? {
+        Some(encoding) if !encoding.is_utf16() => Ok(Some(encoding)),
+        _ => Ok(adif.declared_encoding()),
+    }
+}
+
+/// Prints the first `limit` differing bytes of two files with their
+/// surroundings, exiting with status 1 if the files differ like `cmp`.
+fn bdiff(a: &Path, b: &Path, limit: usize) -> Result<(), Box<dyn std::error::Error>> {
+    let (a_data, b_data) = (fs::read(a)?, fs::read(b)?);
+    let differences = transadif::bdiff::compare_bytes(&a_data, &b_data, 0);
+    if differences.is_empty() {
+        return Ok(());
+    }
+
+    let (a_name, b_name) = (a.display().to_string(), b.display().to_string());
+    let width = a_name.len().max(b_name.len());
+    println!("{} ({} bytes) and {} ({} bytes) differ in {} bytes", a_name, a_data.len(), b_name, b_data.len(), differences.len());
+
+    for (i, diff) in differences.iter().take(limit).enumerate() {
+        println!(
+            "[{}] Position {} (0x{:x}): 0x{:02X} ('{}') vs 0x{:02X} ('{}')",
+            i + 1,
+            diff.position,
+            diff.position,
+            diff.expected,
+            transadif::bdiff::printable(diff.expected),
+            diff.actual,
+            transadif::bdiff::printable(diff.actual)
+        );
+        for (name, data) in [(&a_name, &a_data), (&b_name, &b_data)] {
+            println!("    {:width$}  {}", name, transadif::bdiff::hex_context(data, diff.position));
+        }
+        for (name, data) in [(&a_name, &a_data), (&b_name, &b_data)] {
+            println!("    {:width$}  {:?}", name, transadif::bdiff::text_context(data, diff.position));
+        }
+    }
+    if differences.len() > limit {
+        println!("... and {} more differences", differences.len() - limit);
+    }
+
+    std::process::exit(1);
+}
+
+/// Prints the QSOs only one of two files has and the fields that differ
+/// between QSOs both have, exiting with status 1 if there are any.
+fn diff_files(a: &Path, b: &Path, key: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let read = |path: &Path| -> Result<adif::AdifFile, Box<dyn std::error::Error>> {
+        let mut adif = adif::AdifFile::parse_owned(compress::decompress(fs::read(path)?)?)?;
+        EncodingProcessor::new(input_encoding_for(None, &adif)?, AdifEncoding::Utf8, false).decode_file(&mut adif)?;
+        Ok(adif)
+    };
+    let (before, after) = (read(a)?, read(b)?);
+    let records = diff::compare_records(&before.records, &after.records, key);
+
+    let label = |record: &adif::Record| key.iter().filter_map(|field| record.get(field)).map(str::trim).collect::<Vec<_>>().join(" ");
+    println!("--- {}", a.display());
+    println!("+++ {}", b.display());
+    for &index in &records.removed {
+        println!("- QSO {}: {}", index + 1, label(&before.records[index]));
+    }
+    for &index in &records.added {
+        println!("+ QSO {}: {}", index + 1, label(&after.records[index]));
+    }
+    for changed in &records.changed {
+        println!("~ QSO {} / QSO {}: {}", changed.before + 1, changed.after + 1, label(&before.records[changed.before]));
+        for change in &changed.changes {
+            println!("    {}", change);
+        }
+    }
+    println!(
+        "{} removed, {} added, {} changed, {} unchanged",
+        records.removed.len(),
+        records.added.len(),
+        records.changed.len(),
+        records.unchanged
+    );
+
+    if !records.is_empty() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn manpage(out_dir: Option<&Path>) -> Result<(), Box<dyn std::error::Error>> {
+    match out_dir {
+        Some(out_dir) => {
+            fs::create_dir_all(out_dir)?;
+            clap_mangen::generate_to(Cli::command(), out_dir)?;
+        }
+        None => clap_mangen::Man::new(Cli::command()).render(&mut io::stdout())?,
+    }
+    Ok(())
+}
+
+#[cfg(feature = "tui")]
+fn view(file: &Path, input_encoding: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut adif = adif::AdifFile::parse_owned(compress::decompress(fs::read(file)?)?)?;
+    let input_encoding = input_encoding_for(input_encoding, &adif)?;
+    let processor = encoding::EncodingProcessor::new(input_encoding, AdifEncoding::Utf8, false);
+    processor.decode_file(&mut adif)?;
+
+    transadif::tui::Viewer::new(&adif, &processor).run()?;
+    Ok(())
+}
+
+#[cfg(not(feature = "tui"))]
+fn view(_file: &Path, _input_encoding: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    Err("transadif was built without the `tui` feature".into())
+}
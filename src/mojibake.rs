@@ -0,0 +1,440 @@
+use encoding_rs::{Encoding, WINDOWS_1252};
+use std::sync::OnceLock;
+
+/// Most layers of double encoding `unwind` models: text misread as a
+/// single-byte encoding and saved as UTF-8 again, up to this many times
+const MAX_DEPTH: usize = 4;
+
+/// How eagerly `--mojibake` corrects text that looks double-encoded
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum MojibakeMode {
+    /// Also correct symbols and rarer characters that could be intended
+    Aggressive,
+    /// Only correct letters and common punctuation
+    Conservative,
+    /// Leave double-encoded text as it is
+    Off,
+}
+
+impl MojibakeMode {
+    /// Lowest confidence a correction needs in this mode, or `None` when off.
+    pub fn threshold(self) -> Option<f64> {
+        match self {
+            Self::Aggressive => Some(0.5),
+            Self::Conservative => Some(0.9),
+            Self::Off => None,
+        }
+    }
+}
+
+/// Single-byte encodings UTF-8 is commonly misread as
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Codec {
+    Windows1252,
+    Latin1,
+    MacRoman,
+}
+
+impl Codec {
+    pub const ALL: [Codec; 3] = [Codec::Windows1252, Codec::Latin1, Codec::MacRoman];
+
+    /// The byte this codec decodes to `c`, if it is one of the upper 128.
+    pub fn high_byte(self, c: char) -> Option<u8> {
+        match self {
+            // Latin-1 maps bytes straight to the first 256 code points
+            Self::Latin1 => (0x80..=0xFF).contains(&(c as u32)).then_some(c as u8),
+            Self::Windows1252 => table_position(c, WINDOWS_1252, &WINDOWS_1252_TABLE),
+            // Before Mac OS 8.5, 0xDB was the currency sign rather than the euro
+            Self::MacRoman if c == '¤' => Some(0xDB),
+            Self::MacRoman => MAC_ROMAN.iter().position(|&entry| entry == c).map(|position| 0x80 + position as u8),
+        }
+    }
+}
+
+/// Characters of MacRoman bytes 0x80 to 0xFF, as exported by older Mac loggers
+const MAC_ROMAN: [char; 128] = [
+    'Ä', 'Å', 'Ç', 'É', 'Ñ', 'Ö', 'Ü', 'á', 'à', 'â', 'ä', 'ã', 'å', 'ç', 'é', 'è',
+    'ê', 'ë', 'í', 'ì', 'î', 'ï', 'ñ', 'ó', 'ò', 'ô', 'ö', 'õ', 'ú', 'ù', 'û', 'ü',
+    '†', '°', '¢', '£', '§', '•', '¶', 'ß', '®', '©', '™', '´', '¨', '≠', 'Æ', 'Ø',
+    '∞', '±', '≤', '≥', '¥', 'µ', '∂', '∑', '∏', 'π', '∫', 'ª', 'º', 'Ω', 'æ', 'ø',
+    '¿', '¡', '¬', '√', 'ƒ', '≈', '∆', '«', '»', '…', '\u{A0}', 'À', 'Ã', 'Õ', 'Œ', 'œ',
+    '–', '—', '“', '”', '‘', '’', '÷', '◊', 'ÿ', 'Ÿ', '⁄', '€', '‹', '›', 'ﬁ', 'ﬂ',
+    '‡', '·', '‚', '„', '‰', 'Â', 'Ê', 'Á', 'Ë', 'È', 'Í', 'Î', 'Ï', 'Ì', 'Ó', 'Ô',
+    '\u{F8FF}', 'Ò', 'Ú', 'Û', 'Ù', 'ı', 'ˆ', '˜', '¯', '˘', '˙', '˚', '¸', '˝', '˛', 'ˇ',
+];
+
+/// Punctuation that sits right before a word in ordinary text
+const WORD_OPENERS: &[char] = &['‘', '’', '“', '”', '„', '‚', '–', '—', '…', '«', '»', '‹', '›', '¿', '¡'];
+
+static WINDOWS_1252_TABLE: OnceLock<Vec<char>> = OnceLock::new();
+
+fn table_position(c: char, encoding: &'static Encoding, table: &OnceLock<Vec<char>>) -> Option<u8> {
+    let table = table.get_or_init(|| {
+        let high_bytes: Vec<u8> = (0x80..=0xFF).collect();
+        encoding.decode_without_bom_handling(&high_bytes).0.chars().collect()
+    });
+    table.iter().position(|&entry| entry == c).map(|position| 0x80 + position as u8)
+}
+
+/// One UTF-8 sequence found decoded as single-byte characters
+#[derive(Debug, Clone, PartialEq)]
+pub struct Correction {
+    /// Byte range of the misdecoded characters in the text
+    pub start: usize,
+    pub end: usize,
+    pub replacement: char,
+    pub codec: Codec,
+    /// How likely the characters are mojibake rather than intended, from 0 to 1
+    pub confidence: f64,
+}
+
+/// A `Correction` by character position, for unwinding several layers
+struct Sequence {
+    index: usize,
+    length: usize,
+    replacement: char,
+    codec: Codec,
+    confidence: f64,
+}
+
+/// A character of partly unwound text and the span of the input it came from
+#[derive(Clone)]
+struct Piece {
+    c: char,
+    start: usize,
+    end: usize,
+    /// Confidence of the correction that produced the character, if any
+    confidence: Option<f64>,
+}
+
+/// Finds every run of characters that, encoded back with one of the codecs,
+/// forms a UTF-8 sequence. Where several codecs match, the one giving the
+/// most plausible character wins.
+pub fn find_corrections(text: &str) -> Vec<Correction> {
+    let offsets: Vec<usize> = text.char_indices().map(|(offset, _)| offset).chain([text.len()]).collect();
+    let chars: Vec<char> = text.chars().collect();
+
+    sequences(&chars)
+        .into_iter()
+        .filter(|sequence| sequence.confidence > 0.0)
+        .map(|sequence| Correction {
+            start: offsets[sequence.index],
+            end: offsets[sequence.index + sequence.length],
+            replacement: sequence.replacement,
+            codec: sequence.codec,
+            confidence: sequence.confidence,
+        })
+        .collect()
+}
+
+/// Like `find_corrections`, but keeping sequences that spell control or
+/// private-use characters, which may be an intermediate layer.
+fn sequences(chars: &[char]) -> Vec<Sequence> {
+    let mut sequences = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let mut best: Option<Sequence> = None;
+        for codec in Codec::ALL {
+            let Some((length, replacement)) = sequence_at(&chars[i..], codec) else {
+                continue;
+            };
+            let confidence = confidence(&chars[i..i + length], replacement);
+            if best.as_ref().is_none_or(|best| confidence > best.confidence) {
+                best = Some(Sequence { index: i, length, replacement, codec, confidence });
+            }
+        }
+
+        match best {
+            Some(sequence) => {
+                i += sequence.length;
+                sequences.push(sequence);
+            }
+            None => i += 1,
+        }
+    }
+
+    sequences
+}
+
+/// Text with its layers of double encoding removed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unwound {
+    pub text: String,
+    /// How many times the text had been misread and encoded as UTF-8 again
+    pub depth: usize,
+}
+
+/// Corrects double-encoded text; see `unwind`.
+pub fn fix_mojibake(text: &str, threshold: f64) -> String {
+    unwind(text, threshold).text
+}
+
+/// Unwinds the layers of double encoding one at a time, for as long as each
+/// layer makes the text more plausible (see `quality`), then keeps the
+/// corrected characters with at least `threshold` confidence. A character
+/// unwound through several layers is judged by the one it finally became, so
+/// the intermediate mojibake characters never count.
+pub fn unwind(text: &str, threshold: f64) -> Unwound {
+    let mut pieces: Vec<Piece> = text
+        .char_indices()
+        .map(|(start, c)| Piece { c, start, end: start + c.len_utf8(), confidence: None })
+        .collect();
+    let mut depth = 0;
+
+    while depth < MAX_DEPTH {
+        let chars: Vec<char> = pieces.iter().map(|piece| piece.c).collect();
+        let sequences = sequences(&chars);
+        if sequences.is_empty() {
+            break;
+        }
+
+        let mut unwound = Vec::with_capacity(pieces.len());
+        let mut position = 0;
+        for sequence in sequences {
+            unwound.extend_from_slice(&pieces[position..sequence.index]);
+            unwound.push(Piece {
+                c: sequence.replacement,
+                start: pieces[sequence.index].start,
+                end: pieces[sequence.index + sequence.length - 1].end,
+                confidence: Some(sequence.confidence),
+            });
+            position = sequence.index + sequence.length;
+        }
+        unwound.extend_from_slice(&pieces[position..]);
+
+        let unwound_chars: Vec<char> = unwound.iter().map(|piece| piece.c).collect();
+        if quality(&unwound_chars) <= quality(&chars) {
+            break;
+        }
+        pieces = unwound;
+        depth += 1;
+    }
+
+    let mut result = String::with_capacity(text.len());
+    for piece in pieces {
+        match piece.confidence {
+            Some(confidence) if confidence == 0.0 || confidence < threshold => {
+                result.push_str(&text[piece.start..piece.end]);
+            }
+            _ => result.push(piece.c),
+        }
+    }
+    Unwound { text: result, depth }
+}
+
+/// How much `chars` reads like intended text: zero at best, lower for every
+/// unusual character and every sequence that still looks double-encoded.
+/// Sequences spelling a control character are left out, since misread text
+/// may pass through those on its way to a plausible character.
+fn quality(chars: &[char]) -> f64 {
+    let unusual: f64 = chars
+        .iter()
+        .map(|&c| {
+            if c.is_ascii() && !c.is_ascii_control() || c.is_alphanumeric() || c.is_whitespace() {
+                0.0
+            } else if is_common_symbol(c) {
+                0.2
+            } else if c.is_control() || is_private_or_noncharacter(c) {
+                1.0
+            } else {
+                0.5
+            }
+        })
+        .sum();
+
+    let plausible_sequences = sequences(chars).iter().filter(|sequence| sequence.confidence > 0.0).count();
+    -(unusual + plausible_sequences as f64)
+}
+
+/// The UTF-8 sequence spelled by the characters at the start of `chars` when
+/// encoded with `codec`, as its length in characters and the character it encodes.
+fn sequence_at(chars: &[char], codec: Codec) -> Option<(usize, char)> {
+    let lead = codec.high_byte(*chars.first()?)?;
+    let length = match lead {
+        0xC2..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        0xF0..=0xF4 => 4,
+        _ => return None,
+    };
+
+    let bytes: Vec<u8> = chars
+        .iter()
+        .take(length)
+        .map_while(|c| codec.high_byte(*c))
+        .collect();
+    if bytes.len() < length {
+        return None;
+    }
+
+    // Rejects bad continuation bytes, overlong forms and surrogates
+    let decoded = std::str::from_utf8(&bytes).ok()?.chars().next()?;
+    Some((length, decoded))
+}
+
+/// Scores how likely `original` was meant to spell `c` in UTF-8. Longer
+/// sequences rarely occur by chance, and letters are more plausible than
+/// symbols, which are more plausible than anything else.
+fn confidence(original: &[char], c: char) -> f64 {
+    if c.is_control() || is_private_or_noncharacter(c) {
+        return 0.0;
+    }
+
+    // A quote or dash before a letter is ordinary text, such as `l’école`,
+    // even though MacRoman reads it as a two-byte sequence
+    if let [opener, letter] = original {
+        if WORD_OPENERS.contains(opener) && letter.is_alphabetic() {
+            return 0.3;
+        }
+    }
+
+    let base: f64 = match original.len() {
+        2 => 0.7,
+        3 => 0.8,
+        _ => 0.9,
+    };
+
+    if c.is_alphanumeric() {
+        (base + 0.25).min(1.0)
+    } else if is_common_symbol(c) {
+        base + 0.15
+    } else {
+        base - 0.1
+    }
+}
+
+/// Latin-1 symbols, general punctuation, currency signs and emoji
+fn is_common_symbol(c: char) -> bool {
+    let code = c as u32;
+    (0xA0..=0xBF).contains(&code)
+        || (0x2010..=0x206F).contains(&code)
+        || (0x20A0..=0x20CF).contains(&code)
+        || (0x1F300..=0x1FAFF).contains(&code)
+}
+
+fn is_private_or_noncharacter(c: char) -> bool {
+    let code = c as u32;
+    (0xE000..=0xF8FF).contains(&code) || (0xFDD0..=0xFDEF).contains(&code) || code & 0xFFFE == 0xFFFE
+}
+
+/// Text written in one legacy encoding and read as another as a whole, such
+/// as Russian Windows-1251 logs shown as Windows-1252 (`Ïðèâåò` for `Привет`).
+/// Unlike double-encoded UTF-8 there is no byte pattern to recognize, so a
+/// misreading is only reversed when the result is entirely in `script`.
+#[derive(Debug, Clone, Copy)]
+pub struct Misreading {
+    pub written_in: &'static Encoding,
+    pub read_as: &'static Encoding,
+    /// Characters of the writing system the text was meant to be in
+    pub script: fn(char) -> bool,
+    /// Whether the script runs into Latin letters within a word, as Japanese
+    /// does with call signs; otherwise a word mixing the two is rejected
+    pub mixes_with_latin: bool,
+}
+
+impl Misreading {
+    /// `text` as originally written, or `None` if it does not read as this
+    /// misreading because the result is not `is_in_script`.
+    pub fn reverse(&self, text: &str) -> Option<String> {
+        if text.is_ascii() || text.chars().any(self.script) {
+            return None;
+        }
+
+        let (bytes, _encoding, unmappable) = self.read_as.encode(text);
+        if unmappable {
+            return None;
+        }
+        let (original, had_errors) = self.written_in.decode_without_bom_handling(&bytes);
+        if had_errors {
+            return None;
+        }
+
+        self.is_in_script(&original).then(|| original.into_owned())
+    }
+
+    /// Whether the non-ASCII text in `text` is all in the script, apart from
+    /// spaces and common symbols, with at least two letters of it.
+    pub fn is_in_script(&self, text: &str) -> bool {
+        let mut script_letters = 0;
+        for c in text.chars().filter(|c| !c.is_ascii()) {
+            if (self.script)(c) {
+                script_letters += 1;
+            } else if c.is_alphabetic() || c.is_control() || !(c.is_whitespace() || is_common_symbol(c)) {
+                return false;
+            }
+        }
+
+        let mixed_word = |word: &str| word.chars().any(|c| c.is_ascii_alphabetic()) && word.chars().any(self.script);
+        if !self.mixes_with_latin && text.split(|c: char| !c.is_alphanumeric()).any(mixed_word) {
+            return false;
+        }
+
+        script_letters >= 2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_corrections() {
+        let corrections = find_corrections("JosÃ© â€™ Â°");
+        let found: Vec<(char, Codec)> = corrections.iter().map(|c| (c.replacement, c.codec)).collect();
+        assert_eq!(found, vec![('é', Codec::Windows1252), ('’', Codec::Windows1252), ('°', Codec::Windows1252)]);
+        assert_eq!((corrections[0].start, corrections[0].end), (3, 7));
+        assert!(corrections[0].confidence >= 0.9);
+        assert!(corrections[2].confidence < 0.9);
+
+        // MacRoman reading of é
+        assert_eq!(find_corrections("Jos√©")[0].replacement, 'é');
+        // Latin-1 keeps the C1 control the CP1252 reading lacks
+        assert_eq!(find_corrections("Ã\u{80}")[0].codec, Codec::Latin1);
+
+        assert!(find_corrections("São Paulo, Zürich, Ñandú").is_empty());
+    }
+
+    #[test]
+    fn test_fix_mojibake() {
+        let threshold = MojibakeMode::Conservative.threshold().unwrap();
+        assert_eq!(fix_mojibake("JuÃ¡n MuÃ±oz", threshold), "Juán Muñoz");
+        assert_eq!(fix_mojibake("ä¸–ç•Œ", threshold), "世界");
+        assert_eq!(fix_mojibake("ÃƒÂ¡", threshold), "á");
+        assert_eq!(fix_mojibake("73 â€” 5Â°C", threshold), "73 — 5Â°C");
+
+        let threshold = MojibakeMode::Aggressive.threshold().unwrap();
+        assert_eq!(fix_mojibake("73 â€” 5Â°C", threshold), "73 — 5°C");
+    }
+
+    /// `text` misread as Windows-1252 and saved as UTF-8 again, `depth` times
+    fn misdecode(text: &str, depth: usize) -> String {
+        (0..depth).fold(text.to_string(), |text, _| WINDOWS_1252.decode_without_bom_handling(text.as_bytes()).0.into_owned())
+    }
+
+    #[test]
+    fn test_unwind_depth() {
+        let threshold = MojibakeMode::Conservative.threshold().unwrap();
+        assert_eq!(misdecode("é", 3), "ÃƒÆ’Ã‚Â©");
+
+        for depth in 1..=3 {
+            let unwound = unwind(&misdecode("Jürgen, 73 de Łódź", depth), threshold);
+            assert_eq!(unwound, Unwound { text: "Jürgen, 73 de Łódź".to_string(), depth });
+        }
+        assert_eq!(unwind("Jürgen", threshold).depth, 0);
+
+        // A layer that would only produce a control character is not unwound
+        assert_eq!(unwind("Â\u{81}", 0.0), Unwound { text: "Â\u{81}".to_string(), depth: 0 });
+    }
+
+    #[test]
+    fn test_mac_roman() {
+        let high_bytes: Vec<u8> = (0x80..=0xFF).collect();
+        let decoded: Vec<char> = encoding_rs::MACINTOSH.decode_without_bom_handling(&high_bytes).0.chars().collect();
+        assert_eq!(decoded, MAC_ROMAN);
+
+        let threshold = MojibakeMode::Conservative.threshold().unwrap();
+        assert_eq!(fix_mojibake("Jos√© M√ºller, Bj√∂rn‚Äôs rig", threshold), "José Müller, Björn’s rig");
+        assert_eq!(fix_mojibake("l’école, “äh”, «Über»", threshold), "l’école, “äh”, «Über»");
+        assert_eq!(find_corrections("√©")[0].codec, Codec::MacRoman);
+    }
+}
@@ -1,256 +1,1870 @@
-use crate::adif::{AdifFile, Field, Record};
-use crate::encoding::{AdifEncoding, EncodingProcessor};
-use std::io::Write;
-use thiserror::Error;
-
-#[derive(Error, Debug)]
-pub enum OutputError {
-    #[error("IO error: {0}")]
-    Io(#[from] std::io::Error),
-    #[error("Encoding error: {0}")]
-    Encoding(#[from] crate::encoding::EncodingError),
-}
-
-pub struct OutputFormatter {
-    processor: EncodingProcessor,
-    output_encoding: AdifEncoding,
-    replacement_char: Option<char>,
-    delete_incompatible: bool,
-    transliterate_ascii: bool,
-}
-
-impl OutputFormatter {
-    pub fn new(
-        input_encoding: Option<AdifEncoding>,
-        output_encoding: AdifEncoding,
-        strict_mode: bool,
-        replacement_char: Option<char>,
-        delete_incompatible: bool,
-        transliterate_ascii: bool,
-    ) -> Self {
-        let processor = EncodingProcessor::new(input_encoding, output_encoding.clone(), strict_mode);
-
-        Self {
-            processor,
-            output_encoding,
-            replacement_char,
-            delete_incompatible,
-            transliterate_ascii,
-        }
-    }
-
-    pub fn format_adif<W: Write>(&self, adif: &AdifFile, writer: &mut W) -> Result<(), OutputError> {
-        // Write preamble
-        if !adif.preamble.is_empty() {
-            writer.write_all(adif.preamble.as_bytes())?;
-        }
-
-        // Write header fields first, then add encoding
-        for field in &adif.header_fields {
-            if field.name.to_lowercase() != "encoding" {
-                self.write_field(writer, field)?;
-            }
-        }
-
-        // Write encoding field after other header fields
-        self.write_encoding_field(writer)?;
-
-        // Write <eoh>
-        writer.write_all(b"<eoh>")?;
-
-        // Write header excess data
-        if !adif.header_excess_data.is_empty() {
-            writer.write_all(adif.header_excess_data.as_bytes())?;
-        }
-
-        // Write records
-        for record in &adif.records {
-            self.write_record(writer, record)?;
-        }
-
-        Ok(())
-    }
-
-    fn write_encoding_field<W: Write>(&self, writer: &mut W) -> Result<(), OutputError> {
-        let encoding_name = self.output_encoding.to_string();
-        let length = self.processor.count_length(&encoding_name, &self.output_encoding);
-
-        write!(writer, "<encoding:{}>{}\r\n", length, encoding_name)?;
-        Ok(())
-    }
-
-    fn write_field<W: Write>(&self, writer: &mut W, field: &Field) -> Result<(), OutputError> {
-        // Process the field data
-        let processed_data = self.processor.process_field_data(&field.original_bytes)?;
-        let final_data = self.apply_output_transformations(&processed_data);
-
-        // Calculate new length based on output encoding
-        let length = self.processor.count_length(&final_data, &self.output_encoding);
-
-        // Write field
-        if let Some(ref field_type) = field.field_type {
-            write!(writer, "<{}:{}:{}>{}", field.name, length, field_type, final_data)?;
-        } else {
-            write!(writer, "<{}:{}>{}", field.name, length, final_data)?;
-        }
-
-        // Write excess data (preserve as-is)
-        if !field.excess_data.is_empty() {
-            writer.write_all(field.excess_data.as_bytes())?;
-        }
-
-        Ok(())
-    }
-
-    fn write_record<W: Write>(&self, writer: &mut W, record: &Record) -> Result<(), OutputError> {
-        for field in &record.fields {
-            self.write_field(writer, field)?;
-        }
-
-        writer.write_all(b"<eor>")?;
-
-        if !record.excess_data.is_empty() {
-            writer.write_all(record.excess_data.as_bytes())?;
-        }
-
-        Ok(())
-    }
-
-    fn apply_output_transformations(&self, text: &str) -> String {
-        let mut result = text.to_string();
-
-        // Apply ASCII transliteration if requested
-        if self.transliterate_ascii {
-            result = self.transliterate_to_ascii(&result);
-        }
-
-        // Handle incompatible characters based on output encoding
-        if self.output_encoding != AdifEncoding::Utf8 {
-            result = self.handle_incompatible_characters(&result);
-        }
-
-        result
-    }
-
-    fn transliterate_to_ascii(&self, text: &str) -> String {
-        use unicode_normalization::UnicodeNormalization;
-
-        // Normalize to NFD (decomposed form) and remove combining characters
-        text.nfd()
-            .filter(|c| !unicode_normalization::char::is_combining_mark(*c))
-            .collect::<String>()
-            .chars()
-            .map(|c| {
-                if c.is_ascii() {
-                    c
-                } else {
-                    // Simple transliterations for common cases
-                    match c {
-                        'æ' | 'ǽ' => 'a',
-                        'ð' => 'd',
-                        'ø' => 'o',
-                        'þ' => 'p',
-                        'ß' => 's',
-                        _ => self.replacement_char.unwrap_or('?'),
-                    }
-                }
-            })
-            .collect()
-    }
-
-    fn handle_incompatible_characters(&self, text: &str) -> String {
-        let encoding = self.output_encoding.to_encoding_rs();
-
-        text.chars()
-            .filter_map(|c| {
-                let char_str = c.to_string();
-                let (_, _, had_errors) = encoding.encode(&char_str);
-
-                if had_errors {
-                    if self.delete_incompatible {
-                        None // Remove the character
-                    } else if let Some(replacement) = self.replacement_char {
-                        Some(replacement)
-                    } else {
-                        // For now, just use '?' - entity references need special handling
-                        Some('?')
-                    }
-                } else {
-                    Some(c)
-                }
-            })
-            .collect()
-    }
-
-    pub fn format_as_entity_reference(c: char) -> String {
-        format!("&0x{:X};", c as u32)
-    }
-}
-
-pub struct DebugFormatter;
-
-impl DebugFormatter {
-    pub fn print_qso_debug(adif: &AdifFile, qso_indices: &[usize]) {
-        use crate::encoding::EncodingProcessor;
-        for &index in qso_indices {
-            if let Some(record) = adif.records.get(index) {
-                println!("=== QSO {} ===", index + 1);
-
-                for field in &record.fields {
-                    println!("Field: {}", field.name);
-                    println!("  Length: {} (original)", field.length);
-                    println!("  Type: {:?}", field.field_type);
-                    println!("  Data (original): {:?}", field.data);
-                    println!("  Data (bytes): {:?}", field.original_bytes);
-                    println!("  Excess: {:?}", field.excess_data);
-
-                    // Try to show what the corrected data would be
-                    let processor = EncodingProcessor::new(None, AdifEncoding::Utf8, false);
-                    if let Ok(processed) = processor.process_field_data(&field.original_bytes) {
-                        println!("  Processed: {:?}", processed);
-                        if processed != field.data {
-                            println!("  ** Data was corrected **");
-                        }
-                    }
-                    println!();
-                }
-
-                if !record.excess_data.is_empty() {
-                    println!("Record excess data: {:?}", record.excess_data);
-                }
-                println!();
-            } else {
-                println!("QSO {} not found (file has {} QSOs)", index + 1, adif.records.len());
-            }
-        }
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::adif::Field;
-
-    #[test]
-    fn test_ascii_transliteration() {
-        let formatter = OutputFormatter::new(
-            None,
-            AdifEncoding::Ascii,
-            false,
-            Some('?'),
-            false,
-            true,
-        );
-
-        let text = "José Müller";
-        let result = formatter.transliterate_to_ascii(text);
-        // Should convert accented characters to base forms
-        assert!(result.chars().all(|c| c.is_ascii()));
-    }
-
-    #[test]
-    fn test_entity_reference_formatting() {
-        let entity = OutputFormatter::format_as_entity_reference('€');
-        assert_eq!(entity, "&0x20AC;");
-    }
+use crate::adif::{AdifFile, Field, FieldCountMode, Record, COUNT_MODE_FIELD};
+use crate::{callsign, grid};
+use crate::color::{align, Palette};
+use crate::encoding::{encoding_override, is_encoding_annotation, AdifEncoding, ByteOrderMark, entity_reference, EncodingProcessor, Locale, Replacement, SOURCE_ENCODING_ANNOTATION};
+use crate::report::{Change, Correction, CorrectionReason, InvalidValue, Report};
+use crate::preset::Preset;
+use crate::sink::{AdifSink, WriterSink};
+use crate::translit::TranslitTable;
+use regex::Regex;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::io::Write;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum OutputError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Encoding error: {0}")]
+    Encoding(#[from] crate::encoding::EncodingError),
+    #[error("Field name {0:?} cannot be written as an XML element")]
+    InvalidElementName(String),
+}
+
+/// Rewrites a decoded field value before output transformations apply.
+/// Receives the field name and value; returns `None` to leave the value as is.
+pub type FieldTransform = Box<dyn Fn(&str, &str) -> Option<String>>;
+
+pub struct OutputFormatter {
+    processor: EncodingProcessor,
+    output_encoding: AdifEncoding,
+    replacement_char: Option<char>,
+    delete_incompatible: bool,
+    entity_references: bool,
+    count_mode: FieldCountMode,
+    transliterate_ascii: bool,
+    emoji_policy: EmojiPolicy,
+    field_transforms: Vec<FieldTransform>,
+    annotate_encoding: bool,
+    translit_table: TranslitTable,
+    preserve: bool,
+    byte_order_mark: bool,
+    line_endings: LineEndings,
+    excess_policy: ExcessPolicy,
+    layout: Option<Layout>,
+}
+
+/// What to do with emoji and pictographs the output encoding cannot represent
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum EmojiPolicy {
+    /// Treat them like any other incompatible character
+    Keep,
+    /// Remove them
+    Strip,
+    /// Replace common ones with `:shortcode:` text
+    Shortcode,
+    /// Write them as `&0x1F600;` entity references
+    Entity,
+}
+
+/// What to write in front of the header
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum PreamblePolicy {
+    /// The input's preamble, in the output encoding
+    Keep,
+    /// Nothing but a line break
+    Strip,
+    /// A line naming TransADIF, the input and the time of conversion
+    Regenerate,
+}
+
+/// What to do with the text between `<eoh>` and the first record and between records
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum ExcessPolicy {
+    /// Write it as it was
+    Keep,
+    /// Write a single line break in its place
+    Trim,
+    /// Leave it out
+    Strip,
+}
+
+impl ExcessPolicy {
+    /// What to write for the excess `text`. A trimmed line break follows the
+    /// style of the text, or is `\r\n` if it had none.
+    pub fn apply(self, text: &str) -> &str {
+        match self {
+            _ if text.is_empty() => text,
+            Self::Keep => text,
+            Self::Trim => line_break_style(text).unwrap_or("\r\n"),
+            Self::Strip => "",
+        }
+    }
+}
+
+/// Whitespace written between fields and records in place of the input's
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Layout {
+    /// Each record on a line of its own, with spaces between fields
+    OneLine,
+    /// Each field on a line of its own, with a blank line after each record
+    OneFieldPerLine,
+    /// No whitespace at all
+    Compact,
+}
+
+impl Layout {
+    /// Separators after a record field, after a record's last field and after a record.
+    fn separators(self) -> (&'static str, &'static str, &'static str) {
+        match self {
+            Self::OneLine => (" ", "", "\r\n"),
+            Self::OneFieldPerLine => ("\r\n", "\r\n", "\r\n\r\n"),
+            Self::Compact => ("", "", ""),
+        }
+    }
+
+    /// `record` with its separators replaced. Text other than whitespace in
+    /// them is kept, followed by the new separator.
+    pub fn record(self, record: &Record) -> Record {
+        let (between, last, after) = self.separators();
+        let mut record = record.clone();
+        let count = record.fields.len();
+        for (index, field) in record.fields.iter_mut().enumerate() {
+            field.excess_data = separated(&field.excess_data, if index + 1 < count { between } else { last });
+        }
+        record.excess_data = separated(&record.excess_data, after);
+        record
+    }
+
+    /// The header parts of `adif`, with one header field per line unless
+    /// compact.
+    pub fn header(self, adif: &AdifFile) -> AdifFile {
+        let (_, _, after) = self.separators();
+        let between = if self == Self::Compact { "" } else { "\r\n" };
+        let mut header = AdifFile {
+            preamble: adif.preamble.clone(),
+            preamble_bytes: adif.preamble_bytes.clone(),
+            header_fields: adif.header_fields.clone(),
+            header_excess_data: separated(&adif.header_excess_data, after),
+            header_end_tag: adif.header_end_tag.clone(),
+            encoding: adif.encoding.clone(),
+            count_mode: adif.count_mode,
+            ..AdifFile::new()
+        };
+        for field in &mut header.header_fields {
+            field.excess_data = separated(&field.excess_data, between);
+        }
+        header
+    }
+}
+
+/// `separator`, after the text in `excess` other than surrounding whitespace.
+fn separated(excess: &str, separator: &str) -> String {
+    let text = excess.trim();
+    if text.is_empty() {
+        separator.to_string()
+    } else {
+        format!("{}{}", text, separator)
+    }
+}
+
+/// Line breaks written in the preamble and between fields and records
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum LineEndings {
+    /// `\r\n`, as LoTW and most Windows programs expect
+    Crlf,
+    /// `\n`, as Unix tools expect
+    Lf,
+    /// Whatever the input used
+    Preserve,
+}
+
+impl LineEndings {
+    /// `text` with every `\r\n`, `\r` and `\n` made this line ending.
+    pub fn apply(self, text: &str) -> Cow<'_, str> {
+        let line_ending = match self {
+            Self::Crlf => "\r\n",
+            Self::Lf => "\n",
+            Self::Preserve => return Cow::Borrowed(text),
+        };
+        if !text.contains(['\r', '\n']) {
+            return Cow::Borrowed(text);
+        }
+
+        let mut result = String::with_capacity(text.len() + 8);
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\r' => {
+                    chars.next_if_eq(&'\n');
+                    result.push_str(line_ending);
+                }
+                '\n' => result.push_str(line_ending),
+                c => result.push(c),
+            }
+        }
+        Cow::Owned(result)
+    }
+}
+
+impl OutputFormatter {
+    pub fn new(
+        input_encoding: Option<AdifEncoding>,
+        output_encoding: AdifEncoding,
+        strict_mode: bool,
+        replacement_char: Option<char>,
+        delete_incompatible: bool,
+        transliterate_ascii: bool,
+    ) -> Self {
+        let processor = EncodingProcessor::new(input_encoding, output_encoding.clone(), strict_mode);
+
+        Self {
+            processor,
+            output_encoding,
+            replacement_char,
+            delete_incompatible,
+            entity_references: false,
+            count_mode: FieldCountMode::Auto,
+            transliterate_ascii,
+            emoji_policy: EmojiPolicy::Keep,
+            field_transforms: Vec::new(),
+            annotate_encoding: false,
+            translit_table: TranslitTable::builtin(),
+            preserve: false,
+            byte_order_mark: false,
+            line_endings: LineEndings::Preserve,
+            excess_policy: ExcessPolicy::Keep,
+            layout: None,
+        }
+    }
+
+    pub fn set_emoji_policy(&mut self, policy: EmojiPolicy) {
+        self.emoji_policy = policy;
+    }
+
+    /// Sets what written field lengths count. Other than `Auto`, the mode is
+    /// declared in the header, where transadif reads it back.
+    pub fn set_count_mode(&mut self, count_mode: FieldCountMode) {
+        self.count_mode = count_mode;
+    }
+
+    /// Writes characters the output encoding cannot represent as `&0xNN;`
+    /// references instead of the replacement character.
+    pub fn set_entity_references(&mut self, entity_references: bool) {
+        self.entity_references = entity_references;
+    }
+
+    /// Adds an `APP_TRANSADIF_SOURCE_ENCODING_<FIELD>` field to records for
+    /// each value whose input encoding could only be guessed with low confidence.
+    pub fn set_annotate_encoding(&mut self, annotate: bool) {
+        self.annotate_encoding = annotate;
+    }
+
+    /// Writes fields whose bytes do not change with the length they were
+    /// declared with, keeps the header as it was apart from its ENCODING value
+    /// and keeps the input's spelling of `<eoh>` and `<eor>`, so the output
+    /// differs from the input only where values were re-encoded or corrected.
+    pub fn set_preserve(&mut self, preserve: bool) {
+        self.preserve = preserve;
+    }
+
+    /// Starts UTF-8 output with a byte order mark. Other output encodings
+    /// have none, so they are not affected.
+    pub fn set_byte_order_mark(&mut self, byte_order_mark: bool) {
+        self.byte_order_mark = byte_order_mark;
+    }
+
+    /// Sets the line breaks written outside of field values.
+    pub fn set_line_endings(&mut self, line_endings: LineEndings) {
+        self.line_endings = line_endings;
+    }
+
+    /// Sets the whitespace between fields and records, or keeps the input's with `None`.
+    pub fn set_layout(&mut self, layout: Option<Layout>) {
+        self.layout = layout;
+    }
+
+    /// Sets what is written of the text after `<eoh>` and after each `<eor>`.
+    pub fn set_excess_policy(&mut self, policy: ExcessPolicy) {
+        self.excess_policy = policy;
+    }
+
+    /// Sets the confidence a double-encoded sequence needs to be corrected,
+    /// or turns correction off with `None`.
+    pub fn set_mojibake_threshold(&mut self, threshold: Option<f64>) {
+        self.processor.set_mojibake_threshold(threshold);
+    }
+
+    /// Applies a regional preset's detection candidates, misreadings and
+    /// transliterations.
+    pub fn set_preset(&mut self, preset: Preset) {
+        self.processor.set_detection_candidates(preset.encodings(), Some(preset.tld()));
+        self.processor.set_misreadings(preset.misreadings());
+        self.translit_table = preset.translit_table();
+    }
+
+    /// Hints encoding detection with the language of the log's free text.
+    pub fn set_locale(&mut self, locale: Option<Locale>) {
+        self.processor.set_locale(locale);
+    }
+
+    /// Replaces the table used to transliterate to ASCII.
+    pub fn set_translit_table(&mut self, table: TranslitTable) {
+        self.translit_table = table;
+    }
+
+    /// Adds a transform applied to every decoded field value, in the order added.
+    pub fn add_field_transform<F>(&mut self, transform: F)
+    where
+        F: Fn(&str, &str) -> Option<String> + 'static,
+    {
+        self.field_transforms.push(Box::new(transform));
+    }
+
+    /// The processor decoding and correcting field values.
+    pub fn processor(&self) -> &EncodingProcessor {
+        &self.processor
+    }
+
+    /// Decodes the values of `adif` with the input encoding, once, so every
+    /// later step and the output itself work on the same text.
+    pub fn decode(&self, adif: &mut AdifFile) -> Result<(), OutputError> {
+        Ok(self.processor.decode_file(adif)?)
+    }
+
+    /// Decodes the values of a record read on its own, like `decode`.
+    pub fn decode_record(&self, record: &mut Record) -> Result<(), OutputError> {
+        Ok(self.processor.decode_record(record)?)
+    }
+
+    pub fn format_adif<W: Write>(&self, adif: &AdifFile, writer: &mut W) -> Result<(), OutputError> {
+        self.write_to_sink(adif, &mut WriterSink::new(writer))
+    }
+
+    /// Serializes the whole file into a sink: the header once, then each record.
+    pub fn write_to_sink(&self, adif: &AdifFile, sink: &mut dyn AdifSink) -> Result<(), OutputError> {
+        let mut streamer = OutputStreamer::new(self, sink, adif)?;
+
+        for record in &adif.records {
+            streamer.write_record(record)?;
+        }
+
+        streamer.finish()
+    }
+
+    /// Serializes everything up to and including `<eoh>` and the header excess data.
+    pub fn format_header(&self, adif: &AdifFile) -> Result<Vec<u8>, OutputError> {
+        let mut buffer = Vec::new();
+        self.write_header(&mut buffer, adif)?;
+        Ok(buffer)
+    }
+
+    /// Serializes a single record, including its `<eor>` and trailing excess data.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(offset = record.offset, call = record.get("call").unwrap_or_default())))]
+    pub fn format_record(&self, record: &Record) -> Result<Vec<u8>, OutputError> {
+        let mut buffer = Vec::new();
+        self.write_record(&mut buffer, record)?;
+        Ok(buffer)
+    }
+
+    fn write_header<W: Write>(&self, writer: &mut W, adif: &AdifFile) -> Result<(), OutputError> {
+        let laid_out;
+        let adif = match self.layout {
+            Some(layout) => {
+                laid_out = layout.header(adif);
+                &laid_out
+            }
+            None => adif,
+        };
+        let separator = if self.layout == Some(Layout::Compact) { "" } else { header_separator(adif) };
+
+        if self.byte_order_mark && self.output_encoding == AdifEncoding::Utf8 {
+            writer.write_all(ByteOrderMark::Utf8.bytes())?;
+        }
+
+        // Write preamble, decoding it here if the file was not decoded beforehand
+        if !adif.preamble_bytes.is_empty() {
+            self.write_text(writer, &self.processor.decode_field_data(&adif.preamble_bytes, None)?)?;
+        } else if !adif.preamble.is_empty() {
+            self.write_text(writer, &adif.preamble)?;
+        }
+
+        if self.preserve {
+            self.write_header_fields_in_place(writer, adif, separator)?;
+        } else {
+            // Write header fields first, then add encoding
+            for field in adif.output_header_fields() {
+                self.write_field(writer, field)?;
+            }
+
+            // Write encoding field after other header fields
+            self.write_encoding_field(writer, separator)?;
+        }
+        self.write_count_mode_field(writer, separator)?;
+
+        // Write <eoh>
+        writer.write_all(self.end_tag(&adif.header_end_tag, "<eoh>").as_bytes())?;
+
+        // Write header excess data
+        let excess_data = self.excess_policy.apply(&adif.header_excess_data);
+        if !excess_data.is_empty() {
+            self.write_text(writer, excess_data)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes every header field where it was, with the first ENCODING field
+    /// naming the output encoding. Only files without one get it appended.
+    fn write_header_fields_in_place<W: Write>(&self, writer: &mut W, adif: &AdifFile, separator: &str) -> Result<(), OutputError> {
+        let mut wrote_encoding = false;
+
+        for field in &adif.header_fields {
+            if field.name.trim().eq_ignore_ascii_case(COUNT_MODE_FIELD) {
+                continue;
+            } else if !field.name.trim().eq_ignore_ascii_case("encoding") {
+                self.write_field(writer, field)?;
+            } else if !wrote_encoding {
+                self.write_field_value(writer, field, self.output_encoding.to_string())?;
+                wrote_encoding = true;
+            }
+        }
+
+        if !wrote_encoding {
+            self.write_encoding_field(writer, separator)?;
+        }
+        Ok(())
+    }
+
+    /// `<eoh>` or `<eor>` as spelled in the input when preserving, else `default`.
+    fn end_tag<'t>(&self, written: &'t str, default: &'t str) -> &'t str {
+        if self.preserve && !written.is_empty() {
+            written
+        } else {
+            default
+        }
+    }
+
+    fn write_encoding_field<W: Write>(&self, writer: &mut W, separator: &str) -> Result<(), OutputError> {
+        let encoding_name = self.output_encoding.to_string();
+        let length = self.processor.count_length(encoding_name, &self.output_encoding);
+
+        write!(writer, "<encoding:{}>{}{}", length, encoding_name, self.line_endings.apply(separator))?;
+        Ok(())
+    }
+
+    fn write_count_mode_field<W: Write>(&self, writer: &mut W, separator: &str) -> Result<(), OutputError> {
+        if self.count_mode != FieldCountMode::Auto {
+            let name = self.count_mode.name();
+            write!(writer, "<{}:{}>{}{}", COUNT_MODE_FIELD, name.len(), name, self.line_endings.apply(separator))?;
+        }
+        Ok(())
+    }
+
+    /// The length to write for `text`, counted as `set_count_mode` says.
+    fn output_length(&self, text: &str) -> usize {
+        match self.count_mode {
+            FieldCountMode::Bytes if self.output_encoding == AdifEncoding::Utf8 => text.len(),
+            FieldCountMode::Bytes | FieldCountMode::Auto => self.processor.count_length(text, &self.output_encoding),
+            FieldCountMode::Characters => text.chars().count(),
+        }
+    }
+
+    /// Decoded, corrected and output-transformed value of a field.
+    pub fn field_value(&self, field: &Field) -> Result<String, OutputError> {
+        self.decoded_value(field, None)
+    }
+
+    /// Values of a record's fields, each decoded with the encoding its
+    /// `APP_TRANSADIF_ENCODING` annotations name, if any. The annotations
+    /// themselves describe the input bytes and are left out.
+    pub fn record_values<'r>(&self, record: &'r Record) -> Result<Vec<(&'r Field, String)>, OutputError> {
+        record.fields
+            .iter()
+            .filter(|field| !is_encoding_annotation(&field.name))
+            .map(|field| Ok((field, self.decoded_value(field, encoding_override(record, &field.name).as_ref())?)))
+            .collect()
+    }
+
+    /// Names and values of a record's fields, decoded and corrected but
+    /// before field transforms and output policies apply.
+    pub fn corrected_values<'r>(&self, record: &'r Record) -> Result<Vec<(&'r str, String)>, OutputError> {
+        record.fields
+            .iter()
+            .filter(|field| !is_encoding_annotation(&field.name))
+            .map(|field| {
+                let value = self.processor.correct(&self.text(field, encoding_override(record, &field.name).as_ref())?);
+                Ok((field.name.as_str(), value))
+            })
+            .collect()
+    }
+
+    /// The record's fields one per line as `<name:length>value`, decoded
+    /// without any corrections.
+    pub fn input_lines(&self, record: &Record) -> Result<Vec<String>, OutputError> {
+        record.fields
+            .iter()
+            .map(|field| {
+                let value = self.text(field, encoding_override(record, &field.name).as_ref())?;
+                Ok(field_line(field, field.length, &value))
+            })
+            .collect()
+    }
+
+    /// The record's fields one per line as they would be written, for
+    /// comparison with `input_lines`.
+    pub fn output_lines(&self, record: &Record) -> Result<Vec<String>, OutputError> {
+        Ok(self.record_values(record)?
+            .into_iter()
+            .map(|(field, value)| field_line(field, self.output_length(&value), &value))
+            .collect())
+    }
+
+    /// A field's value as decoded by `decode`. Fields of files that were not
+    /// decoded beforehand are decoded here.
+    fn text<'f>(&self, field: &'f Field, encoding: Option<&AdifEncoding>) -> Result<Cow<'f, str>, OutputError> {
+        if field.decoded {
+            Ok(Cow::Borrowed(&field.data))
+        } else {
+            Ok(Cow::Owned(self.processor.decode_field_data(&field.original_bytes, encoding)?))
+        }
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(field = %field.name)))]
+    fn decoded_value(&self, field: &Field, encoding: Option<&AdifEncoding>) -> Result<String, OutputError> {
+        let prepared = self.prepared_value(field, encoding)?;
+        let written = self.handle_incompatible_characters(&prepared);
+        #[cfg(feature = "tracing")]
+        if written != prepared {
+            tracing::debug!(before = %prepared, after = %written, "unrepresentable characters handled");
+        }
+        Ok(written)
+    }
+
+    /// Value with corrections, field transforms and the emoji and ASCII
+    /// policies applied, before incompatible characters are handled.
+    fn prepared_value(&self, field: &Field, encoding: Option<&AdifEncoding>) -> Result<String, OutputError> {
+        let mut processed_data = self.processor.correct(&self.text(field, encoding)?);
+        for transform in &self.field_transforms {
+            if let Some(transformed) = transform(&field.name, &processed_data) {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(before = %processed_data, after = %transformed, "field transform applied");
+                processed_data = transformed;
+            }
+        }
+        let prepared = self.prepare_output(&processed_data);
+        #[cfg(feature = "tracing")]
+        if prepared != processed_data {
+            tracing::debug!(before = %processed_data, after = %prepared, "emoji or ASCII policy applied");
+        }
+        Ok(prepared)
+    }
+
+    /// What writing `adif` would do: the encodings its fields are decoded
+    /// with, the corrections made, the characters lost to the output encoding
+    /// and every field whose value changes.
+    pub fn report(&self, adif: &AdifFile) -> Result<Report, OutputError> {
+        let mut report = Report {
+            deleted: self.delete_incompatible,
+            length_corrections: adif.length_corrections.clone(),
+            ..Report::default()
+        };
+
+        for (index, record) in adif.records.iter().enumerate() {
+            for field in record.fields.iter().filter(|field| !is_encoding_annotation(&field.name)) {
+                let encoding = encoding_override(record, &field.name);
+                let before = self.text(field, encoding.as_ref())?.into_owned();
+
+                if !field.original_bytes.is_ascii() {
+                    let (detected, had_errors) = self.processor.field_encoding(&field.original_bytes, encoding.as_ref());
+                    let name = detected.name().parse::<AdifEncoding>().map_or(detected.name(), |encoding| encoding.to_string());
+                    *report.encodings.entry(name.to_string()).or_default() += 1;
+                    report.invalid_fields += usize::from(had_errors);
+                }
+                report.mojibake_corrections += usize::from(self.processor.mojibake_corrected(&before).is_some());
+
+                let prepared = self.prepared_value(field, encoding.as_ref())?;
+                let unrepresentable = prepared.chars().filter(|c| !self.can_encode(*c)).count();
+                if unrepresentable > 0 {
+                    *report.unrepresentable.entry(field.name.to_lowercase()).or_default() += unrepresentable;
+                }
+
+                let after = self.handle_incompatible_characters(&prepared);
+                let invalid = |problem| InvalidValue {
+                    record: index,
+                    field: field.name.to_lowercase(),
+                    offset: field.offset,
+                    value: after.clone(),
+                    problem,
+                };
+                if callsign::is_callsign_field(&field.name) {
+                    report.impossible_callsigns.extend(callsign::check(&after).map(invalid));
+                } else if grid::is_grid_field(&field.name) {
+                    report.invalid_grids.extend(grid::check(&after).map(invalid));
+                }
+                if after != before {
+                    report.changes.push(Change { record: index, field: field.name.to_lowercase(), offset: field.offset, before, after });
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Every step that changes a field of `adif` on its way to the output,
+    /// in order, so automation can audit or revert the conversion.
+    pub fn corrections(&self, adif: &AdifFile) -> Result<Vec<Correction>, OutputError> {
+        let mut corrections = Vec::new();
+        for (index, record) in adif.records.iter().enumerate() {
+            corrections.extend(self.record_corrections(index, record)?);
+        }
+        Ok(corrections)
+    }
+
+    /// The corrections made to one record, the `index`th of its file.
+    pub fn record_corrections(&self, index: usize, record: &Record) -> Result<Vec<Correction>, OutputError> {
+        let mut corrections = Vec::new();
+
+        for field in record.fields.iter().filter(|field| !is_encoding_annotation(&field.name)) {
+            let encoding = encoding_override(record, &field.name);
+            let mut value = self.text(field, encoding.as_ref())?.into_owned();
+            let mut step = |reason, after: String, value: &mut String| {
+                if after != *value {
+                    corrections.push(Correction {
+                        record: index,
+                        field: field.name.to_lowercase(),
+                        offset: field.offset,
+                        original_bytes: field.original_bytes.to_vec(),
+                        before: std::mem::replace(value, after.clone()),
+                        after,
+                        reason,
+                    });
+                }
+            };
+
+            // Read past the declared length, which counted characters
+            if field.original_bytes.len() > field.length && value.chars().count() == field.length {
+                let mut before = String::from_utf8_lossy(&field.original_bytes[..field.length]).into_owned();
+                step(CorrectionReason::LengthReinterpretation, value.clone(), &mut before);
+            }
+            if let Some(corrected) = self.processor.mojibake_corrected(&value) {
+                step(CorrectionReason::Mojibake, corrected, &mut value);
+            }
+            if let Some(resolved) = self.processor.entities_resolved(&value) {
+                step(CorrectionReason::Entity, resolved, &mut value);
+            }
+            for transform in &self.field_transforms {
+                if let Some(transformed) = transform(&field.name, &value) {
+                    step(CorrectionReason::Transform, transformed, &mut value);
+                }
+            }
+            let prepared = self.prepare_output(&value);
+            step(CorrectionReason::Transliteration, prepared, &mut value);
+            let written = self.handle_incompatible_characters(&value);
+            step(CorrectionReason::Unrepresentable, written, &mut value);
+        }
+
+        Ok(corrections)
+    }
+
+    fn write_field<W: Write>(&self, writer: &mut W, field: &Field) -> Result<(), OutputError> {
+        self.write_field_value(writer, field, &self.field_value(field)?)
+    }
+
+    fn write_field_value<W: Write>(&self, writer: &mut W, field: &Field, final_data: &str) -> Result<(), OutputError> {
+        let encoded = self.processor.encode_output(final_data, self.replacement())?;
+
+        // Calculate new length based on output encoding, unless preserving a
+        // field that comes out as it went in and no count mode is declared
+        let length = if self.preserve && self.count_mode == FieldCountMode::Auto && encoded == *field.original_bytes {
+            field.length
+        } else {
+            self.output_length(final_data)
+        };
+
+        // Write field
+        if let Some(ref field_type) = field.field_type {
+            write!(writer, "<{}:{}:{}>", field.name, length, field_type)?;
+        } else {
+            write!(writer, "<{}:{}>", field.name, length)?;
+        }
+        writer.write_all(&encoded)?;
+
+        // Write excess data
+        if !field.excess_data.is_empty() {
+            self.write_text(writer, &field.excess_data)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_record<W: Write>(&self, writer: &mut W, record: &Record) -> Result<(), OutputError> {
+        let laid_out;
+        let record = match self.layout {
+            Some(layout) => {
+                laid_out = layout.record(record);
+                &laid_out
+            }
+            None => record,
+        };
+        let values = self.record_values(record)?;
+        for (field, value) in &values {
+            self.write_field_value(writer, field, value)?;
+        }
+
+        if self.annotate_encoding {
+            // Same separator as the fields before them, like Record::set
+            let separator = values.last().map_or(" ", |(field, _)| field.excess_data.as_str());
+            for (field, _) in &values {
+                if encoding_override(record, &field.name).is_some() {
+                    continue;
+                }
+                if let Some(encoding) = self.processor.uncertain_encoding(&field.original_bytes) {
+                    write!(writer, "<{}_{}:{}>{}", SOURCE_ENCODING_ANNOTATION, field.name.to_uppercase(), encoding.name().len(), encoding.name())?;
+                    self.write_text(writer, separator)?;
+                }
+            }
+        }
+
+        writer.write_all(self.end_tag(&record.end_tag, "<eor>").as_bytes())?;
+
+        let excess_data = self.excess_policy.apply(&record.excess_data);
+        if !excess_data.is_empty() {
+            self.write_text(writer, excess_data)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes free text (preamble, excess data) in the output encoding, using
+    /// the same replacement policy as field data and the chosen line endings.
+    fn write_text<W: Write>(&self, writer: &mut W, text: &str) -> Result<(), OutputError> {
+        let text = self.apply_output_transformations(&self.line_endings.apply(text));
+        writer.write_all(&self.processor.encode_output(&text, self.replacement())?)?;
+        Ok(())
+    }
+
+    fn apply_output_transformations(&self, text: &str) -> String {
+        self.handle_incompatible_characters(&self.prepare_output(text))
+    }
+
+    /// Emoji policy and ASCII transliteration
+    fn prepare_output(&self, text: &str) -> String {
+        let mut result = text.to_string();
+
+        // Emoji first, before transliteration replaces them
+        if self.emoji_policy != EmojiPolicy::Keep && (self.output_encoding != AdifEncoding::Utf8 || self.transliterate_ascii) {
+            result = self.apply_emoji_policy(&result);
+        }
+
+        // Apply ASCII transliteration if requested
+        if self.transliterate_ascii {
+            result = self.transliterate_to_ascii(&result);
+        }
+
+        result
+    }
+
+    fn apply_emoji_policy(&self, text: &str) -> String {
+        let mut result = String::with_capacity(text.len());
+
+        for c in text.chars() {
+            let encodable = if self.transliterate_ascii { c.is_ascii() } else { self.can_encode(c) };
+
+            if encodable || !is_emoji(c) {
+                result.push(c);
+                continue;
+            }
+
+            match self.emoji_policy {
+                EmojiPolicy::Keep => result.push(c),
+                EmojiPolicy::Strip => {}
+                EmojiPolicy::Entity => result.push_str(&Self::format_as_entity_reference(c)),
+                EmojiPolicy::Shortcode => {
+                    if let Some(shortcode) = emoji_shortcode(c) {
+                        result.push(':');
+                        result.push_str(shortcode);
+                        result.push(':');
+                    } else if !is_emoji_modifier(c) {
+                        // Left for the incompatible character handling
+                        result.push(c);
+                    }
+                }
+            }
+        }
+
+        result
+    }
+
+    fn transliterate_to_ascii(&self, text: &str) -> String {
+        self.translit_table.transliterate(text, self.replacement_char.unwrap_or('?'))
+    }
+
+    fn can_encode(&self, c: char) -> bool {
+        self.output_encoding.can_encode(c)
+    }
+
+    /// Replaces or deletes the characters the output encoding cannot represent.
+    fn handle_incompatible_characters(&self, text: &str) -> String {
+        // Strict ASCII output fails on them when encoding instead
+        if self.output_encoding == AdifEncoding::Utf8
+            || (self.output_encoding == AdifEncoding::Ascii && self.processor.strict_mode())
+        {
+            return text.to_string();
+        }
+
+        let mut result = String::with_capacity(text.len());
+        for c in text.chars() {
+            if self.can_encode(c) {
+                result.push(c);
+                continue;
+            }
+            match self.replacement() {
+                Replacement::Char(replacement) if self.can_encode(replacement) => result.push(replacement),
+                Replacement::Char(_) => result.push('?'),
+                Replacement::Delete => {}
+                Replacement::Entity => result.push_str(&entity_reference(c)),
+            }
+        }
+        result
+    }
+
+    /// What `--replace`, `--delete` and `--entities` chose for characters
+    /// the output encoding cannot represent
+    fn replacement(&self) -> Replacement {
+        if self.delete_incompatible {
+            Replacement::Delete
+        } else if self.entity_references {
+            Replacement::Entity
+        } else {
+            Replacement::Char(self.replacement_char.unwrap_or('?'))
+        }
+    }
+
+    pub fn format_as_entity_reference(c: char) -> String {
+        entity_reference(c)
+    }
+}
+
+/// Emoji, pictographs and the joiners, selectors and modifiers that combine them
+fn is_emoji(c: char) -> bool {
+    matches!(c as u32, 0x1F000..=0x1FAFF | 0x2600..=0x27BF | 0x2B00..=0x2BFF | 0x2300..=0x23FF) || is_emoji_modifier(c)
+}
+
+/// Characters that only modify the emoji before or after them
+fn is_emoji_modifier(c: char) -> bool {
+    matches!(c as u32, 0x200D | 0xFE0E | 0xFE0F | 0x20E3 | 0x1F3FB..=0x1F3FF)
+}
+
+fn emoji_shortcode(c: char) -> Option<&'static str> {
+    let shortcode = match c {
+        '😀' => "grinning",
+        '😃' => "smiley",
+        '😄' => "smile",
+        '😁' => "grin",
+        '😂' => "joy",
+        '😊' => "blush",
+        '😉' => "wink",
+        '😎' => "sunglasses",
+        '🙂' => "slightly_smiling_face",
+        '😢' => "cry",
+        '👍' => "+1",
+        '👎' => "-1",
+        '👋' => "wave",
+        '👌' => "ok_hand",
+        '🙏' => "pray",
+        '🤝' => "handshake",
+        '❤' => "heart",
+        '🎉' => "tada",
+        '📻' => "radio",
+        '📡' => "satellite",
+        '📶' => "signal_strength",
+        '🌍' => "earth_africa",
+        '🌎' => "earth_americas",
+        '🌏' => "earth_asia",
+        '⭐' => "star",
+        '☀' => "sunny",
+        '⚡' => "zap",
+        '🔥' => "fire",
+        '✅' => "white_check_mark",
+        '❌' => "x",
+        '🍺' => "beer",
+        '☕' => "coffee",
+        '🎄' => "christmas_tree",
+        _ => return None,
+    };
+    Some(shortcode)
+}
+
+/// Separator to write after the added encoding field, following the layout
+/// of the header: the same as after the last header field, or the header's
+/// line break style when it has no fields.
+fn header_separator(adif: &AdifFile) -> &'static str {
+    if let Some(field) = adif.output_header_fields().last() {
+        if let Some(line_break) = line_break_style(&field.excess_data) {
+            return line_break;
+        }
+        if !field.excess_data.is_empty() && field.excess_data.trim().is_empty() {
+            return " ";
+        }
+    }
+
+    line_break_style(&adif.preamble)
+        .or_else(|| line_break_style(&adif.header_excess_data))
+        .unwrap_or("\r\n")
+}
+
+fn line_break_style(text: &str) -> Option<&'static str> {
+    if text.contains("\r\n") {
+        Some("\r\n")
+    } else if text.contains('\n') {
+        Some("\n")
+    } else {
+        None
+    }
+}
+
+/// Bytes per line of a `hex_dump`
+const HEX_DUMP_WIDTH: usize = 16;
+
+/// `data` as lines of hex bytes, each followed by a line with every
+/// character `encoding` decodes under its first byte, and `!!` under bytes
+/// that are not valid in it, which `palette` shows in red.
+fn hex_dump(data: &[u8], encoding: &'static encoding_rs::Encoding, palette: &Palette) -> Vec<String> {
+    // The character each byte starts, if any, or None for invalid bytes
+    let mut columns: Vec<Option<String>> = Vec::with_capacity(data.len());
+    let mut start = 0;
+    while start < data.len() {
+        let decoded = (1..=4.min(data.len() - start)).find_map(|length| {
+            let text = encoding.decode_without_bom_handling_and_without_replacement(&data[start..start + length])?;
+            (!text.is_empty()).then(|| (length, text.into_owned()))
+        });
+        match decoded {
+            Some((length, text)) => {
+                columns.push(Some(text.chars().map(|c| c.escape_debug().to_string()).collect()));
+                columns.extend((1..length).map(|_| Some(String::new())));
+                start += length;
+            }
+            None => {
+                columns.push(None);
+                start += 1;
+            }
+        }
+    }
+
+    let mut lines = Vec::new();
+    for (row, (bytes, columns)) in data.chunks(HEX_DUMP_WIDTH).zip(columns.chunks(HEX_DUMP_WIDTH)).enumerate() {
+        let hex: Vec<String> = bytes
+            .iter()
+            .zip(columns)
+            .map(|(byte, column)| match column {
+                Some(_) => format!("{:02x}", byte),
+                None => palette.red(&format!("{:02x}", byte)),
+            })
+            .collect();
+        let text: Vec<String> = columns
+            .iter()
+            .map(|column| match column {
+                Some(text) => format!("{:<3}", text),
+                None => format!("{} ", palette.red("!!")),
+            })
+            .collect();
+        lines.push(format!("{:04x}  {}", row * HEX_DUMP_WIDTH, hex.join(" ")));
+        lines.push(format!("      {}", text.concat().trim_end()));
+    }
+    lines
+}
+
+/// A field on a single line, with line breaks in its value made visible.
+fn field_line(field: &Field, length: usize, value: &str) -> String {
+    let value = value.replace('\r', "\\r").replace('\n', "\\n");
+    match field.field_type {
+        Some(ref field_type) => format!("<{}:{}:{}>{}", field.name, length, field_type, value),
+        None => format!("<{}:{}>{}", field.name, length, value),
+    }
+}
+
+/// Writes records to a sink as they are produced instead of from a complete
+/// `AdifFile`, so a `StreamingParser` can feed output with bounded memory.
+pub struct OutputStreamer<'a> {
+    formatter: &'a OutputFormatter,
+    sink: &'a mut dyn AdifSink,
+}
+
+impl<'a> OutputStreamer<'a> {
+    /// Writes the header of `header` right away; its records are ignored.
+    pub fn new(formatter: &'a OutputFormatter, sink: &'a mut dyn AdifSink, header: &AdifFile) -> Result<Self, OutputError> {
+        sink.write_header(&formatter.format_header(header)?)?;
+        Ok(Self { formatter, sink })
+    }
+
+    pub fn write_record(&mut self, record: &Record) -> Result<(), OutputError> {
+        self.sink.write_record(record, &self.formatter.format_record(record)?)?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), OutputError> {
+        self.sink.flush()?;
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<(), OutputError> {
+        self.sink.finish()?;
+        Ok(())
+    }
+}
+
+/// Serializes an `AdifFile` as ADX, the XML flavour of ADIF. ADX is always
+/// written as UTF-8; field values go through the wrapped `OutputFormatter`.
+pub struct AdxFormatter {
+    formatter: OutputFormatter,
+}
+
+impl AdxFormatter {
+    pub fn new(formatter: OutputFormatter) -> Self {
+        Self { formatter }
+    }
+
+    pub fn format_adx<W: Write>(&self, adif: &AdifFile, writer: &mut W) -> Result<(), OutputError> {
+        writer.write_all(b"<?xml version=\"1.0\" encoding=\"UTF-8\"?>\r\n<ADX>\r\n")?;
+
+        // USERDEF header fields declare names that records then use directly
+        let mut userdef_names = Vec::new();
+
+        writer.write_all(b"  <HEADER>\r\n")?;
+        // ENCODING is implied by the XML declaration
+        for field in adif.output_header_fields() {
+            let name = field.name.to_uppercase();
+            let value = self.formatter.field_value(field)?;
+            if let Some(id) = name.strip_prefix("USERDEF") {
+                let (userdef_name, restriction) = match value.split_once(',') {
+                    Some((userdef_name, restriction)) => (userdef_name.trim(), Some(restriction.trim())),
+                    None => (value.trim(), None),
+                };
+                userdef_names.push(userdef_name.to_uppercase());
+
+                write!(writer, "    <USERDEF FIELDID=\"{}\"", xml_escape(id))?;
+                if let Some(ref field_type) = field.field_type {
+                    write!(writer, " TYPE=\"{}\"", xml_escape(field_type))?;
+                }
+                if let Some(restriction) = restriction {
+                    let attribute = if restriction.contains(':') { "RANGE" } else { "ENUM" };
+                    write!(writer, " {}=\"{}\"", attribute, xml_escape(restriction))?;
+                }
+                write!(writer, ">{}</USERDEF>\r\n", xml_escape(userdef_name))?;
+            } else {
+                write!(writer, "    <{}>{}</{}>\r\n", element_name(&name)?, xml_escape(&value), name)?;
+            }
+        }
+        writer.write_all(b"  </HEADER>\r\n  <RECORDS>\r\n")?;
+
+        for record in &adif.records {
+            writer.write_all(b"    <RECORD>\r\n")?;
+            for (field, value) in self.formatter.record_values(record)? {
+                let value = xml_escape(&value);
+                let name = field.name.to_uppercase();
+
+                if let Some((program_id, field_name)) = name.strip_prefix("APP_").and_then(|rest| rest.split_once('_')) {
+                    write!(writer, "      <APP PROGRAMID=\"{}\" FIELDNAME=\"{}\"", xml_escape(program_id), xml_escape(field_name))?;
+                    if let Some(ref field_type) = field.field_type {
+                        write!(writer, " TYPE=\"{}\"", xml_escape(field_type))?;
+                    }
+                    write!(writer, ">{}</APP>\r\n", value)?;
+                } else if userdef_names.contains(&name) {
+                    write!(writer, "      <USERDEF FIELDNAME=\"{}\">{}</USERDEF>\r\n", xml_escape(&name), value)?;
+                } else {
+                    write!(writer, "      <{}>{}</{}>\r\n", element_name(&name)?, value, name)?;
+                }
+            }
+            writer.write_all(b"    </RECORD>\r\n")?;
+        }
+
+        writer.write_all(b"  </RECORDS>\r\n</ADX>\r\n")?;
+        Ok(())
+    }
+}
+
+/// Case of the field names used as JSON keys
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum KeyCase {
+    Lower,
+    Upper,
+}
+
+/// Writes the header and records as JSON, one object per record mapping
+/// field names to values, for jq pipelines and web apps.
+pub struct JsonFormatter {
+    formatter: OutputFormatter,
+    key_case: KeyCase,
+}
+
+impl JsonFormatter {
+    pub fn new(formatter: OutputFormatter, key_case: KeyCase) -> Self {
+        Self { formatter, key_case }
+    }
+
+    pub fn format_json<W: Write>(&self, adif: &AdifFile, writer: &mut W) -> Result<(), OutputError> {
+        // ENCODING is implied by JSON always being UTF-8
+        let header_fields = adif.output_header_fields()
+            .into_iter()
+            .map(|field| Ok((field, self.formatter.field_value(field)?)))
+            .collect::<Result<Vec<_>, OutputError>>()?;
+
+        writer.write_all(b"{\n  \"header\": ")?;
+        self.write_object(writer, &header_fields)?;
+        writer.write_all(b",\n  \"records\": [")?;
+
+        for (index, record) in adif.records.iter().enumerate() {
+            writer.write_all(if index == 0 { b"\n    " } else { b",\n    " })?;
+            self.write_object(writer, &self.formatter.record_values(record)?)?;
+        }
+
+        writer.write_all(if adif.records.is_empty() { b"]\n}\n" } else { b"\n  ]\n}\n" })?;
+        Ok(())
+    }
+
+    fn write_object<W: Write>(&self, writer: &mut W, fields: &[(&Field, String)]) -> Result<(), OutputError> {
+        writer.write_all(b"{")?;
+        for (index, (field, value)) in fields.iter().enumerate() {
+            let name = match self.key_case {
+                KeyCase::Lower => field.name.to_lowercase(),
+                KeyCase::Upper => field.name.to_uppercase(),
+            };
+            let separator = if index == 0 { "" } else { ", " };
+            write!(writer, "{}\"{}\": \"{}\"", separator, json_escape(&name), json_escape(value))?;
+        }
+        writer.write_all(b"}")?;
+        Ok(())
+    }
+}
+
+/// Writes corrections as a JSON array, one object per line, with the
+/// original bytes in hex.
+pub fn write_corrections_json<W: Write>(corrections: &[Correction], writer: &mut W) -> Result<(), OutputError> {
+    writer.write_all(b"[")?;
+    for (index, correction) in corrections.iter().enumerate() {
+        let bytes: String = correction.original_bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        write!(
+            writer,
+            "{}\n  {{\"record\": {}, \"field\": \"{}\", \"offset\": {}, \"original_bytes\": \"{}\", \"before\": \"{}\", \"after\": \"{}\", \"reason\": \"{}\"}}",
+            if index == 0 { "" } else { "," },
+            correction.record,
+            json_escape(&correction.field),
+            correction.offset,
+            bytes,
+            json_escape(&correction.before),
+            json_escape(&correction.after),
+            correction.reason.as_str()
+        )?;
+    }
+    writer.write_all(if corrections.is_empty() { b"]\n" } else { b"\n]\n" })?;
+    Ok(())
+}
+
+fn json_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// `name` if it is a valid XML element name, which ADIF field names need
+/// not be.
+fn element_name(name: &str) -> Result<&str, OutputError> {
+    let mut chars = name.chars();
+    let valid = chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'));
+    if valid { Ok(name) } else { Err(OutputError::InvalidElementName(name.to_string())) }
+}
+
+/// Escapes text for XML element content and attribute values, dropping
+/// control characters XML 1.0 cannot represent.
+pub(crate) fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if (c as u32) < 0x20 => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[derive(Error, Debug)]
+pub enum SelectorError {
+    #[error("{0:?} is not a QSO number, FIELD=VALUE or FIELD~PATTERN")]
+    Syntax(String),
+    #[error("Invalid pattern: {0}")]
+    Pattern(#[from] regex::Error),
+}
+
+/// A QSO for `--debug` to print: its number, starting at 0, or every record
+/// whose field equals a value (`call=EA4ABC`) or matches a regex
+/// (`comment~mojibake`), both ignoring case.
+#[derive(Debug, Clone)]
+pub enum QsoSelector {
+    Index(usize),
+    Equals(String, String),
+    Matches(String, Regex),
+}
+
+impl FromStr for QsoSelector {
+    type Err = SelectorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Ok(index) = s.parse() {
+            return Ok(Self::Index(index));
+        }
+
+        let (field, operator, value) = match s.find(['=', '~']) {
+            Some(at) => (s[..at].trim(), &s[at..at + 1], &s[at + 1..]),
+            None => return Err(SelectorError::Syntax(s.to_string())),
+        };
+        if field.is_empty() || !field.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(SelectorError::Syntax(s.to_string()));
+        }
+        Ok(match operator {
+            "=" => Self::Equals(field.to_string(), value.trim().to_string()),
+            _ => Self::Matches(field.to_string(), Regex::new(&format!("(?i){}", value))?),
+        })
+    }
+}
+
+impl std::fmt::Display for QsoSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Index(index) => write!(f, "{}", index),
+            Self::Equals(field, value) => write!(f, "{}={}", field, value),
+            Self::Matches(field, pattern) => write!(f, "{}~{}", field, pattern.as_str().trim_start_matches("(?i)")),
+        }
+    }
+}
+
+/// Comma-separated QSO selectors, as `--debug` takes them, e.g.
+/// `5,call=EA4ABC`. A comma only starts a new selector when a QSO number or
+/// `FIELD=`/`FIELD~` follows it, so values and patterns may contain commas.
+#[derive(Debug, Clone)]
+pub struct QsoSelection(pub Vec<QsoSelector>);
+
+impl QsoSelection {
+    /// Whether `rest`, the text after a comma, begins a selector of its own.
+    fn starts_selector(rest: &str) -> bool {
+        let rest = rest.trim_start();
+        let first = rest.split(',').next().unwrap_or_default().trim_end();
+        if !first.is_empty() && first.bytes().all(|b| b.is_ascii_digit()) {
+            return true;
+        }
+        let name_length = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+        name_length > 0 && rest[name_length..].trim_start().starts_with(['=', '~'])
+    }
+}
+
+impl FromStr for QsoSelection {
+    type Err = SelectorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut selectors = Vec::new();
+        let mut start = 0;
+        for (at, _) in s.match_indices(',') {
+            if Self::starts_selector(&s[at + 1..]) {
+                selectors.push(s[start..at].parse()?);
+                start = at + 1;
+            }
+        }
+        selectors.push(s[start..].parse()?);
+        Ok(Self(selectors))
+    }
+}
+
+impl std::fmt::Display for QsoSelection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let selectors: Vec<String> = self.0.iter().map(ToString::to_string).collect();
+        write!(f, "{}", selectors.join(","))
+    }
+}
+
+impl QsoSelector {
+    /// Indexes of the records `selectors` pick, in the order given, each once.
+    pub fn select(selectors: &[QsoSelector], records: &[Record]) -> Vec<usize> {
+        let mut indexes: Vec<usize> = Vec::new();
+        let mut seen = HashSet::new();
+        for selector in selectors {
+            let picked: Vec<usize> = match selector {
+                Self::Index(index) => vec![*index],
+                _ => (0..records.len()).filter(|&index| selector.matches(&records[index])).collect(),
+            };
+            indexes.extend(picked.into_iter().filter(|&index| seen.insert(index)));
+        }
+        indexes
+    }
+
+    fn matches(&self, record: &Record) -> bool {
+        match self {
+            Self::Index(_) => false,
+            Self::Equals(field, value) => record.get(field).is_some_and(|data| data.trim().eq_ignore_ascii_case(value)),
+            Self::Matches(field, pattern) => record.get(field).is_some_and(|data| pattern.is_match(data)),
+        }
+    }
+}
+
+pub struct DebugFormatter;
+
+impl DebugFormatter {
+    /// Prints the given records of a decoded file with the corrections
+    /// `processor` would make to them, and with `hex`, hex dumps of the
+    /// fields' bytes.
+    pub fn print_qso_debug(adif: &AdifFile, qso_indices: &[usize], processor: &EncodingProcessor, hex: bool, palette: &Palette) {
+        for &index in qso_indices {
+            if let Some(record) = adif.records.get(index) {
+                println!("{}", palette.bold(&format!("=== QSO {} ===", index + 1)));
+
+                for line in Self::record_lines(record, processor, hex, palette) {
+                    println!("{}", line);
+                }
+                for correction in adif.length_corrections.iter().filter(|correction| correction.record == Some(index)) {
+                    println!("Length correction: {}", correction);
+                }
+                println!();
+            } else {
+                println!("QSO {} not found (file has {} QSOs)", index + 1, adif.records.len());
+            }
+        }
+    }
+
+    /// Debug description of every field in a record, one entry per line.
+    pub fn record_lines(record: &Record, processor: &EncodingProcessor, hex: bool, palette: &Palette) -> Vec<String> {
+        let mut lines = Vec::new();
+
+        for field in &record.fields {
+            lines.extend(Self::field_lines(field, encoding_override(record, &field.name).as_ref(), processor, hex, palette));
+            lines.push(String::new());
+        }
+
+        if !record.excess_data.is_empty() {
+            lines.push(format!("Record excess data: {:?}", record.excess_data));
+        }
+
+        lines
+    }
+
+    /// Debug description of a field, with its values lined up and what
+    /// correcting it changes in the colors of `palette`.
+    pub fn field_lines(field: &Field, encoding: Option<&AdifEncoding>, processor: &EncodingProcessor, hex: bool, palette: &Palette) -> Vec<String> {
+        let processed = processor.correct(&field.data);
+        let (decoded, corrected) = palette.change(&field.data, &processed);
+        let mut values = vec![
+            ("Length", format!("{} (original)", field.length)),
+            ("Type", format!("{:?}", field.field_type)),
+            ("Data (decoded)", decoded),
+        ];
+        if !hex {
+            values.push(("Data (bytes)", format!("{:?}", field.original_bytes)));
+        }
+        values.push(("Excess", format!("{:?}", field.excess_data)));
+        values.push(("Processed", corrected));
+
+        let mut lines = vec![palette.bold(&format!("Field: {}", field.name))];
+        let mut values = align(&values).into_iter().map(|line| format!("  {}", line));
+        lines.extend(values.by_ref().take(3));
+        if hex {
+            let (encoding, _) = processor.field_encoding(&field.original_bytes, encoding);
+            lines.push(format!("  Data (bytes as {}):", encoding.name()));
+            lines.extend(hex_dump(&field.original_bytes, encoding, palette).into_iter().map(|line| format!("    {}", line)));
+        }
+        lines.extend(values);
+        if processed != field.data {
+            lines.push(format!("  {}", palette.yellow("** Data was corrected **")));
+        }
+
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adif::StreamingParser;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_ascii_transliteration() {
+        let formatter = OutputFormatter::new(
+            None,
+            AdifEncoding::Ascii,
+            false,
+            Some('?'),
+            false,
+            true,
+        );
+
+        let text = "José Müller";
+        let result = formatter.transliterate_to_ascii(text);
+        // Should convert accented characters to base forms
+        assert!(result.is_ascii());
+    }
+
+    #[test]
+    fn test_qso_selector() {
+        let adif = AdifFile::parse(
+            "<eoh><call:6>EA4ABC<eor><call:4>W1AW<comment:14>Ã¼ber Mojibake<eor><call:6>ea4abc<eor>".as_bytes(),
+        )
+        .unwrap();
+        let selectors: Vec<QsoSelector> = ["call=EA4ABC ", "1", "COMMENT~mojibake"].iter().map(|s| s.parse().unwrap()).collect();
+        assert_eq!(QsoSelector::select(&selectors, &adif.records), vec![0, 2, 1]);
+        assert_eq!(selectors[2].to_string(), "COMMENT~mojibake");
+
+        assert!(matches!("call".parse::<QsoSelector>(), Err(SelectorError::Syntax(_))));
+        assert!(matches!("comment~(".parse::<QsoSelector>(), Err(SelectorError::Pattern(_))));
+
+        // Commas split selectors only where a new one starts
+        let selection: QsoSelection = "0, 12,call=EA4ABC,comment~a{1,3}, b,name=Smith, J,qth = Kent".parse().unwrap();
+        assert_eq!(selection.to_string(), "0,12,call=EA4ABC,comment~a{1,3}, b,name=Smith, J,qth=Kent");
+        assert_eq!(selection.0.len(), 6);
+        assert!(matches!("0,,1".parse::<QsoSelection>(), Err(SelectorError::Syntax(_))));
+    }
+
+    #[test]
+    fn test_hex_dump() {
+        let lines = hex_dump(b"J\xc3\xbcr\xe9\r\n", encoding_rs::UTF_8, &Palette::default());
+        assert_eq!(lines, vec!["0000  4a c3 bc 72 e9 0d 0a", "      J  ü     r  !! \\r \\n"]);
+
+        let lines = hex_dump(&[b'a'; 17], encoding_rs::WINDOWS_1252, &Palette::default());
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[2], "0010  61");
+    }
+
+    #[test]
+    fn test_excess_policy() {
+        let data = "Log\r\n<eoh>\r\n<call:4>DL1X<eor>\r\nLogger v2.1 export\0\0\r\n<call:4>DL1Y<eor> ".as_bytes();
+        let adif = AdifFile::parse(data).unwrap();
+        let mut formatter = OutputFormatter::new(Some(AdifEncoding::Utf8), AdifEncoding::Utf8, false, Some('?'), false, false);
+
+        formatter.set_excess_policy(ExcessPolicy::Trim);
+        let mut output = Vec::new();
+        formatter.format_adif(&adif, &mut output).unwrap();
+        assert!(output.ends_with(b"<eoh>\r\n<call:4>DL1X<eor>\r\n<call:4>DL1Y<eor>\r\n"), "{:?}", String::from_utf8_lossy(&output));
+
+        formatter.set_excess_policy(ExcessPolicy::Strip);
+        let mut output = Vec::new();
+        formatter.format_adif(&adif, &mut output).unwrap();
+        assert!(output.ends_with(b"<eoh><call:4>DL1X<eor><call:4>DL1Y<eor>"), "{:?}", String::from_utf8_lossy(&output));
+    }
+
+    #[test]
+    fn test_layout() {
+        let data = "Log\n<programid:4>Test <eoh>\n<call:4>DL1X\n<mode:3>FT8 <eor>banner\n<call:4>DL1Y<eor>\n".as_bytes();
+        let adif = AdifFile::parse(data).unwrap();
+        let mut formatter = OutputFormatter::new(Some(AdifEncoding::Utf8), AdifEncoding::Utf8, false, Some('?'), false, false);
+        let formatted = |formatter: &OutputFormatter| {
+            let mut output = Vec::new();
+            formatter.format_adif(&adif, &mut output).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        formatter.set_layout(Some(Layout::OneLine));
+        assert_eq!(
+            formatted(&formatter),
+            "Log\n<programid:4>Test\r\n<encoding:5>UTF-8\r\n<eoh>\r\n<call:4>DL1X <mode:3>FT8<eor>banner\r\n<call:4>DL1Y<eor>\r\n"
+        );
+
+        formatter.set_layout(Some(Layout::OneFieldPerLine));
+        assert!(formatted(&formatter).ends_with("<eoh>\r\n\r\n<call:4>DL1X\r\n<mode:3>FT8\r\n<eor>banner\r\n\r\n<call:4>DL1Y\r\n<eor>\r\n\r\n"));
+
+        formatter.set_layout(Some(Layout::Compact));
+        assert_eq!(formatted(&formatter), "Log\n<programid:4>Test<encoding:5>UTF-8<eoh><call:4>DL1X<mode:3>FT8<eor>banner<call:4>DL1Y<eor>");
+    }
+
+    #[test]
+    fn test_preamble_and_excess_use_output_encoding() {
+        let adif = AdifFile::parse("Café log\r\n<call:5>K1MIX<eoh>\r\n<name:4>José<eor> señor\r\n".as_bytes()).unwrap();
+        let formatter = OutputFormatter::new(
+            Some(AdifEncoding::Utf8),
+            AdifEncoding::Iso88591,
+            false,
+            Some('?'),
+            false,
+            false,
+        );
+
+        let mut output = Vec::new();
+        formatter.format_adif(&adif, &mut output).unwrap();
+
+        assert!(output.starts_with(b"Caf\xE9 log"));
+        assert!(output.ends_with(b"<eor> se\xF1or\r\n"));
+        assert!(std::str::from_utf8(&output).is_err());
+
+        // Streamed headers are written without being decoded first
+        let adif = AdifFile::parse(b"Caf\xE9 log\r\n<encoding:10>ISO-8859-1<eoh>\r\n").unwrap();
+        let latin1 = OutputFormatter::new(Some(AdifEncoding::Iso88591), AdifEncoding::Iso88591, false, Some('?'), false, false);
+        let header = latin1.format_header(&adif).unwrap();
+        assert!(header.starts_with(b"Caf\xE9 log\r\n"), "{:?}", String::from_utf8_lossy(&header));
+    }
+
+    #[test]
+    fn test_count_mode_is_declared() {
+        let adif = AdifFile::parse("Log<app_transadif_count_mode:5>chars\n<eoh>\n<name:6>Jürgen<eor>\n".as_bytes()).unwrap();
+        let mut formatter = OutputFormatter::new(None, AdifEncoding::Utf8, false, Some('?'), false, false);
+
+        let mut output = Vec::new();
+        formatter.format_adif(&adif, &mut output).unwrap();
+        assert_eq!(output, "Log<encoding:5>UTF-8\n<eoh>\n<name:6>Jürgen<eor>\n".as_bytes());
+
+        formatter.set_count_mode(FieldCountMode::Bytes);
+        let mut output = Vec::new();
+        formatter.format_adif(&adif, &mut output).unwrap();
+        assert_eq!(output, "Log<encoding:5>UTF-8\n<APP_TRANSADIF_COUNT_MODE:5>bytes\n<eoh>\n<name:7>Jürgen<eor>\n".as_bytes());
+        assert_eq!(AdifFile::parse(&output).unwrap().count_mode, Some(FieldCountMode::Bytes));
+    }
+
+    #[test]
+    fn test_ascii_output_is_seven_bit() {
+        let adif = AdifFile::parse("Café log\n<eoh>\n<name:6>Jürgen<qth:7>München<eor>\n".as_bytes()).unwrap();
+        let formatter = OutputFormatter::new(Some(AdifEncoding::Utf8), AdifEncoding::Ascii, false, Some('é'), false, false);
+
+        let mut output = Vec::new();
+        formatter.format_adif(&adif, &mut output).unwrap();
+        assert!(output.is_ascii());
+        assert!(output.ends_with(b"<name:6>J?rgen<qth:7>M?nchen<eor>\n"));
+
+        let strict = OutputFormatter::new(Some(AdifEncoding::Utf8), AdifEncoding::Ascii, true, Some('?'), false, false);
+        let error = strict.format_adif(&adif, &mut Vec::new()).unwrap_err();
+        assert_eq!(error.to_string(), "Encoding error: Conversion error: Cannot encode 'é' as US-ASCII");
+    }
+
+    #[test]
+    fn test_byte_order_mark() {
+        let adif = AdifFile::parse(b"\xEF\xBB\xBFLog\r\n<eoh>\r\n<name:4>J\xc3\xbcrg<eor>\r\n").unwrap();
+        let mut formatter = OutputFormatter::new(None, AdifEncoding::Utf8, false, Some('?'), false, false);
+
+        let mut output = Vec::new();
+        formatter.format_adif(&adif, &mut output).unwrap();
+        assert!(output.starts_with(b"Log\r\n"));
+
+        formatter.set_byte_order_mark(true);
+        let mut output = Vec::new();
+        formatter.format_adif(&adif, &mut output).unwrap();
+        assert!(output.starts_with(b"\xEF\xBB\xBFLog\r\n"));
+        assert!(output.ends_with("<name:4>Jürg<eor>\r\n".as_bytes()));
+    }
+
+    #[test]
+    fn test_line_endings() {
+        let adif = AdifFile::parse(b"Log\n<adif_ver:5>3.1.4\n<eoh>\n<call:4>W1AW <name:4>A\r\nB\r\n<eor>\r<call:5>K1MIX<eor>\n").unwrap();
+        let mut formatter = OutputFormatter::new(None, AdifEncoding::Utf8, false, Some('?'), false, false);
+
+        formatter.set_line_endings(LineEndings::Crlf);
+        let mut output = Vec::new();
+        formatter.format_adif(&adif, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Log\r\n<adif_ver:5>3.1.4\r\n<encoding:5>UTF-8\r\n<eoh>\r\n<call:4>W1AW <name:4>A\r\nB\r\n<eor>\r\n<call:5>K1MIX<eor>\r\n"
+        );
+
+        assert_eq!(LineEndings::Lf.apply("a\r\nb\rc\n"), "a\nb\nc\n");
+        assert!(matches!(LineEndings::Preserve.apply("a\r\n"), Cow::Borrowed("a\r\n")));
+    }
+
+    #[test]
+    fn test_output_streamer_matches_format_adif() {
+        let data = b"Log\r\n<eoh>\r\n<call:4>W1AW<eor>\r\n<call:5>K1MIX<eor>\r\n";
+        let formatter = OutputFormatter::new(None, AdifEncoding::Utf8, false, Some('?'), false, false);
+
+        let mut expected = Vec::new();
+        formatter.format_adif(&AdifFile::parse(data).unwrap(), &mut expected).unwrap();
+
+        let parser = StreamingParser::new(data.as_slice()).unwrap();
+        let mut sink = WriterSink::new(Vec::new());
+        let mut streamer = OutputStreamer::new(&formatter, &mut sink, parser.header()).unwrap();
+        for record in parser {
+            streamer.write_record(&record.unwrap()).unwrap();
+        }
+        streamer.finish().unwrap();
+
+        assert_eq!(sink.into_inner(), expected);
+    }
+
+    #[test]
+    fn test_emoji_policies() {
+        let field = Field::new("comment", "Tnx 👍🏽 73 ❤️ 🦜");
+        let value = |policy, encoding| {
+            let mut formatter = OutputFormatter::new(None, encoding, false, Some('?'), false, false);
+            formatter.set_emoji_policy(policy);
+            formatter.field_value(&field).unwrap()
+        };
+
+        assert_eq!(value(EmojiPolicy::Keep, AdifEncoding::Windows1252), "Tnx ?? 73 ?? ?");
+        assert_eq!(value(EmojiPolicy::Strip, AdifEncoding::Windows1252), "Tnx  73  ");
+        assert_eq!(value(EmojiPolicy::Shortcode, AdifEncoding::Windows1252), "Tnx :+1: 73 :heart: ?");
+        assert_eq!(value(EmojiPolicy::Entity, AdifEncoding::Iso88591), "Tnx &0x1F44D;&0x1F3FD; 73 &0x2764;&0xFE0F; &0x1F99C;");
+        assert_eq!(value(EmojiPolicy::Strip, AdifEncoding::Utf8), "Tnx 👍🏽 73 ❤️ 🦜");
+    }
+
+    #[test]
+    fn test_encoding_field_follows_header_layout() {
+        let formatter = OutputFormatter::new(None, AdifEncoding::Utf8, false, Some('?'), false, false);
+        let header = |data: &[u8]| String::from_utf8(formatter.format_header(&AdifFile::parse(data).unwrap()).unwrap()).unwrap();
+
+        assert_eq!(header(b"Log\n<adif_ver:5>3.1.1\n<eoh>\n"), "Log\n<adif_ver:5>3.1.1\n<encoding:5>UTF-8\n<eoh>\n");
+        assert_eq!(header(b"Log\r\n<adif_ver:5>3.1.1 <eoh>\r\n"), "Log\r\n<adif_ver:5>3.1.1 <encoding:5>UTF-8 <eoh>\r\n");
+        assert_eq!(header(b"Log<eoh>\n"), "Log<encoding:5>UTF-8\n<eoh>\n");
+        assert_eq!(
+            header(b"Log\n<ENCODING:10>ISO-8859-1\n<adif_ver:5>3.1.1\n<Encoding:5>UTF-8\n<ADIF_VER:5>3.1.4\n<programid:3>XYZ\n<eoh>\n"),
+            "Log\n<adif_ver:5>3.1.1\n<programid:3>XYZ\n<encoding:5>UTF-8\n<eoh>\n"
+        );
+    }
+
+    #[test]
+    fn test_encoding_annotations() {
+        let adif = AdifFile::parse(b"Log\r\n<eoh>\r\n<call:4>UA9X <name:4>Ren\xe9 <qth:3>\xcf\xe5\xf0 <app_transadif_encoding_name:10>ISO-8859-5 <eor>\r\n").unwrap();
+        let record = |annotate| {
+            let mut formatter = OutputFormatter::new(None, AdifEncoding::Utf8, false, Some('?'), false, false);
+            formatter.set_annotate_encoding(annotate);
+            String::from_utf8(formatter.format_record(&adif.records[0]).unwrap()).unwrap()
+        };
+
+        assert_eq!(record(false), "<call:4>UA9X <name:4>Renщ <qth:3>Ïåð <eor>\r\n");
+        assert_eq!(record(true), "<call:4>UA9X <name:4>Renщ <qth:3>Ïåð <APP_TRANSADIF_SOURCE_ENCODING_QTH:12>windows-1252 <eor>\r\n");
+    }
+
+    #[test]
+    fn test_preserve_keeps_unchanged_bytes() {
+        let data = "Log\r\n<ADIF_VER:5>3.1.4 <ENCODING:10>ISO-8859-1 <PROGRAMID:3>XYZ <PROGRAMID:3>XYZ <EOH>\r\n\
+                    <CALL:4>OH2X <NAME:6>Jörg <QTH:5>Espoo <EOR>\r\n";
+        let adif = AdifFile::parse(data.as_bytes()).unwrap();
+        let mut formatter = OutputFormatter::new(Some(AdifEncoding::Utf8), AdifEncoding::Utf8, false, Some('?'), false, false);
+        formatter.set_preserve(true);
+
+        let mut output = Vec::new();
+        formatter.format_adif(&adif, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Log\r\n<ADIF_VER:5>3.1.4 <ENCODING:5>UTF-8 <PROGRAMID:3>XYZ <PROGRAMID:3>XYZ <EOH>\r\n\
+             <CALL:4>OH2X <NAME:6>Jörg <QTH:5>Espoo <EOR>\r\n"
+        );
+    }
+
+    #[test]
+    fn test_report() {
+        let adif = AdifFile::parse("Log\r\n<eoh>\r\n<call:4>EA1X <name:7>JuÃ¡n <qth:5>Leon <eor>\r\n<call:4>SP9X <name:6>Łukasz <eor>\r\n".as_bytes()).unwrap();
+        let formatter = OutputFormatter::new(None, AdifEncoding::Iso88591, false, Some('?'), false, false);
+        let report = formatter.report(&adif).unwrap();
+
+        assert_eq!(report.encodings.get("UTF-8"), Some(&2));
+        assert_eq!(report.invalid_fields, 0);
+        assert_eq!(report.mojibake_corrections, 1);
+        assert_eq!(report.unrepresentable.get("name"), Some(&1));
+        assert_eq!(
+            report.changes.iter().map(|change| change.to_string()).collect::<Vec<_>>(),
+            vec!["QSO 1: NAME at byte 25: \"JuÃ¡n\" -> \"Juán\"", "QSO 2: NAME at byte 73: \"Łukasz\" -> \"?ukasz\""]
+        );
+        assert!(report.impossible_callsigns.is_empty());
+
+        let adif = AdifFile::parse("<eoh><call:6>DL1ÄBC<operator:4>W1AW<eor><call:5>SP9 X<gridsquare:4>JN5X<eor>".as_bytes()).unwrap();
+        let report = formatter.report(&adif).unwrap();
+        assert_eq!(report.invalid_grids[0].to_string(), "QSO 2: GRIDSQUARE at byte 54: \"JN5X\" has 'X' at position 4 where a digit belongs");
+        assert_eq!(
+            report.impossible_callsigns.iter().map(|callsign| callsign.to_string()).collect::<Vec<_>>(),
+            vec!["QSO 1: CALL at byte 5: \"DL1ÄBC\" contains 'Ä', which no callsign has", "QSO 2: CALL at byte 41: \"SP9 X\" contains ' ', which no callsign has"]
+        );
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn test_tracing_events() {
+        use std::sync::Mutex;
+        use tracing::field::{Field as EventField, Visit};
+        use tracing::span::{Attributes, Id, Record as SpanValues};
+        use tracing::{Event, Metadata, Subscriber};
+
+        /// Collects the message of every event
+        struct Messages(Mutex<Vec<String>>);
+
+        struct MessageVisitor<'a>(&'a mut Vec<String>);
+
+        impl Visit for MessageVisitor<'_> {
+            fn record_debug(&mut self, field: &EventField, value: &dyn std::fmt::Debug) {
+                if field.name() == "message" {
+                    self.0.push(format!("{:?}", value));
+                }
+            }
+        }
+
+        impl Subscriber for &'static Messages {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+            fn new_span(&self, _: &Attributes<'_>) -> Id {
+                Id::from_u64(1)
+            }
+            fn record(&self, _: &Id, _: &SpanValues<'_>) {}
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+            fn event(&self, event: &Event<'_>) {
+                event.record(&mut MessageVisitor(&mut self.0.lock().unwrap()));
+            }
+            fn enter(&self, _: &Id) {}
+            fn exit(&self, _: &Id) {}
+        }
+
+        let messages: &'static Messages = Box::leak(Box::new(Messages(Mutex::new(Vec::new()))));
+        tracing::subscriber::with_default(messages, || {
+            let adif = AdifFile::parse("<eoh><name:7>JuÃ¡n<qth:12>M&uuml;nchen<op:6>Łukasz<eor>".as_bytes()).unwrap();
+            let formatter = OutputFormatter::new(None, AdifEncoding::Iso88591, false, Some('?'), false, false);
+            formatter.format_adif(&adif, &mut Vec::new()).unwrap();
+        });
+
+        let messages = messages.0.lock().unwrap();
+        for message in ["parsed record", "mojibake corrected", "entity references resolved", "unrepresentable characters handled"] {
+            assert!(messages.iter().any(|m| m == message), "no {:?} in {:?}", message, messages);
+        }
+    }
+
+    #[test]
+    fn test_corrections() {
+        let adif = AdifFile::parse("<eoh><name:7>JuÃ¡n<qth:12>M&uuml;nchen<city:4>Köln<op:7>Łukasz<eor>".as_bytes()).unwrap();
+        let formatter = OutputFormatter::new(None, AdifEncoding::Iso88591, false, Some('?'), false, false);
+
+        let mut output = Vec::new();
+        write_corrections_json(&formatter.corrections(&adif).unwrap(), &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "[\n  {\"record\": 0, \"field\": \"name\", \"offset\": 5, \"original_bytes\": \"4a75c383c2a16e\", \"before\": \"JuÃ¡n\", \"after\": \"Juán\", \"reason\": \"mojibake\"},\
+             \n  {\"record\": 0, \"field\": \"qth\", \"offset\": 20, \"original_bytes\": \"4d2675756d6c3b6e6368656e\", \"before\": \"M&uuml;nchen\", \"after\": \"München\", \"reason\": \"entity\"},\
+             \n  {\"record\": 0, \"field\": \"city\", \"offset\": 40, \"original_bytes\": \"4bc3b66c6e\", \"before\": \"Köl\", \"after\": \"Köln\", \"reason\": \"length-reinterpretation\"},\
+             \n  {\"record\": 0, \"field\": \"op\", \"offset\": 53, \"original_bytes\": \"c581756b61737a\", \"before\": \"Łukasz\", \"after\": \"?ukasz\", \"reason\": \"unrepresentable\"}\n]\n"
+        );
+    }
+
+    #[test]
+    fn test_json_output() {
+        let adif = AdifFile::parse(b"Log\r\n<adif_ver:5>3.1.4<encoding:5>UTF-8<eoh>\r\n<CALL:4>W1AW<comment:7>\"Hi\"\\\r\n<eor>\r\n").unwrap();
+        let formatter = OutputFormatter::new(None, AdifEncoding::Utf8, false, Some('?'), false, false);
+
+        let mut output = Vec::new();
+        JsonFormatter::new(formatter, KeyCase::Lower).format_json(&adif, &mut output).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "{\n  \"header\": {\"adif_ver\": \"3.1.4\"},\n  \"records\": [\n    {\"call\": \"W1AW\", \"comment\": \"\\\"Hi\\\"\\\\\\r\\n\"}\n  ]\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_adx_output() {
+        let data = b"Log<userdef1:15:N>ShoeSize,{5:20}<encoding:5>UTF-8<eoh>\
+            <call:5>K1MIX<name:7>Jo & Al<shoesize:2>11<app_monolog_compression:3:S>off<eor>";
+        let adif = AdifFile::parse(data).unwrap();
+        let formatter = AdxFormatter::new(OutputFormatter::new(
+            None,
+            AdifEncoding::Utf8,
+            false,
+            Some('?'),
+            false,
+            false,
+        ));
+
+        let mut output = Vec::new();
+        formatter.format_adx(&adif, &mut output).unwrap();
+        let xml = String::from_utf8(output).unwrap();
+
+        assert!(xml.contains("<USERDEF FIELDID=\"1\" TYPE=\"N\" RANGE=\"{5:20}\">ShoeSize</USERDEF>"));
+        assert!(!xml.contains("ENCODING"));
+        assert!(xml.contains("<CALL>K1MIX</CALL>"));
+        assert!(xml.contains("<NAME>Jo &amp; Al</NAME>"));
+        assert!(xml.contains("<USERDEF FIELDNAME=\"SHOESIZE\">11</USERDEF>"));
+        assert!(xml.contains("<APP PROGRAMID=\"MONOLOG\" FIELDNAME=\"COMPRESSION\" TYPE=\"S\">off</APP>"));
+    }
+
+    #[test]
+    fn test_entity_reference_formatting() {
+        let entity = OutputFormatter::format_as_entity_reference('€');
+        assert_eq!(entity, "&0x20AC;");
+    }
+    /// Values made of characters that look like tags and, in UTF-8, of
+    /// characters whose length in bytes differs from their count. None ends
+    /// in a space: counted in characters, `<a:2>ä ` reads just as well as
+    /// two bytes followed by a space.
+    fn value(encoding: &AdifEncoding) -> impl Strategy<Value = String> {
+        let mut alphabet: Vec<char> = "Az09 ./-<>:äöüßéñ".chars().collect();
+        if *encoding == AdifEncoding::Utf8 {
+            alphabet.extend("ŁщΩ東京🎉".chars());
+        }
+        prop::collection::vec(prop::sample::select(alphabet), 0..12).prop_map(|chars| String::from_iter(chars).trim_end().to_string())
+    }
+
+    /// A field whose declared length counts bytes or, only in UTF-8 where
+    /// they differ, characters
+    fn field(encoding: &AdifEncoding) -> impl Strategy<Value = Field> {
+        let utf8 = *encoding == AdifEncoding::Utf8;
+        let name = "[a-z]{1,8}(_[a-z]{1,4})?".prop_filter("names with a meaning of their own", |name| name != "encoding" && !name.starts_with("app_"));
+        let field_type = prop::option::of(prop::sample::select(vec!["S", "N", "D"]));
+        let excess = prop::sample::select(vec!["", " ", "\n", "\r\n"]);
+        (name, value(encoding), any::<bool>(), field_type, excess).prop_map(move |(name, value, bytes, field_type, excess)| {
+            let mut field = Field::new(&name, &value);
+            if utf8 && bytes {
+                field.length = value.len();
+            }
+            field.field_type = field_type.map(str::to_string);
+            field.excess_data = excess.to_string();
+            field
+        })
+    }
+
+    fn record(encoding: &AdifEncoding) -> impl Strategy<Value = Record> {
+        let end_tag = prop::sample::select(vec!["<eor>", "<EOR>"]);
+        let excess = prop::sample::select(vec!["", " ", "\n", "\r\n"]);
+        (prop::collection::vec(field(encoding), 1..6), end_tag, excess).prop_map(|(fields, end_tag, excess)| Record {
+            fields,
+            excess_data: excess.to_string(),
+            offset: 0,
+            end_tag: end_tag.to_string(),
+        })
+    }
+
+    /// A file in one of the encodings, with a header naming it
+    fn adif_file() -> impl Strategy<Value = (AdifEncoding, AdifFile)> {
+        prop::sample::select(vec![AdifEncoding::Utf8, AdifEncoding::Iso88591, AdifEncoding::Windows1252]).prop_flat_map(|encoding| {
+            let header = prop::collection::vec(field(&encoding), 0..3);
+            let end_tag = prop::sample::select(vec!["<eoh>", "<EOH>"]);
+            let records = prop::collection::vec(record(&encoding), 0..6);
+            ("[A-Za-z][A-Za-z ]{0,10}\r\n", header, end_tag, records).prop_map(move |(preamble, mut header, end_tag, records)| {
+                let mut named = Field::new("encoding", encoding.to_string());
+                named.excess_data = " ".to_string();
+                header.push(named);
+                let mut adif = AdifFile::new();
+                adif.preamble = preamble;
+                adif.header_fields = header;
+                adif.header_end_tag = end_tag.to_string();
+                adif.header_excess_data = "\r\n".to_string();
+                adif.records = records;
+                (encoding.clone(), adif)
+            })
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn test_preserve_round_trip((encoding, adif) in adif_file()) {
+            let mut formatter = OutputFormatter::new(Some(encoding.clone()), encoding, false, Some('?'), false, false);
+            formatter.set_preserve(true);
+            let mut written = Vec::new();
+            formatter.format_adif(&adif, &mut written).unwrap();
+
+            let mut read = AdifFile::parse(&written).unwrap();
+            formatter.decode(&mut read).unwrap();
+            prop_assert_eq!(&read, &adif, "written as {:?}", String::from_utf8_lossy(&written));
+
+            // Written again, the file comes out the same, byte for byte
+            let mut rewritten = Vec::new();
+            formatter.format_adif(&read, &mut rewritten).unwrap();
+            prop_assert_eq!(rewritten, written);
+        }
+    }
 }
\ No newline at end of file
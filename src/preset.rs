@@ -0,0 +1,114 @@
+use crate::encoding::AdifEncoding;
+use crate::mojibake::Misreading;
+use crate::translit::TranslitTable;
+use encoding_rs::{EUC_JP, SHIFT_JIS, WINDOWS_1251, WINDOWS_1252};
+
+/// Russian letters after ICAO Doc 9303, as in passports and most QSL
+/// databases, where they differ from the built-in BGN/PCGN table
+const RUSSIAN_TRANSLIT: &str = "\
+ё=e\nЁ=E\nй=i\nЙ=I\nъ=ie\nЪ=Ie\nю=iu\nЮ=Iu\nя=ia\nЯ=Ia\n";
+
+/// Japanese punctuation and the ideographic space
+const JAPANESE_TRANSLIT: &str = "\
+。=.\n、=,\n「=\"\n」=\"\n『=\"\n』=\"\n・= \n\u{3000}= \nー=-\n〜=~\n";
+
+/// Settings for the logs of a region, bundled under one `--preset` name so
+/// fixing a file does not take knowing its code pages
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Preset {
+    /// Windows-1251, KOI8-R and ISO-8859-5 logs, passport-style transliteration
+    RussianLogs,
+    /// Shift_JIS and EUC-JP logs, ASCII stand-ins for Japanese punctuation
+    JapaneseLogs,
+}
+
+impl Preset {
+    /// Encodings auto-detection picks from, most common first
+    pub fn encodings(self) -> Vec<AdifEncoding> {
+        match self {
+            Self::RussianLogs => vec![AdifEncoding::Windows1251, AdifEncoding::Koi8R, AdifEncoding::Iso88595],
+            Self::JapaneseLogs => vec![AdifEncoding::ShiftJis, AdifEncoding::EucJp],
+        }
+    }
+
+    /// Top-level domain that hints the encoding detector towards the region
+    pub fn tld(self) -> &'static [u8] {
+        match self {
+            Self::RussianLogs => b"ru",
+            Self::JapaneseLogs => b"jp",
+        }
+    }
+
+    /// How logs of the region typically get garbled by Western software
+    pub fn misreadings(self) -> Vec<Misreading> {
+        match self {
+            Self::RussianLogs => vec![Misreading {
+                written_in: WINDOWS_1251,
+                read_as: WINDOWS_1252,
+                script: is_cyrillic,
+                mixes_with_latin: false,
+            }],
+            Self::JapaneseLogs => [SHIFT_JIS, EUC_JP]
+                .into_iter()
+                .map(|written_in| Misreading {
+                    written_in,
+                    read_as: WINDOWS_1252,
+                    script: is_japanese,
+                    mixes_with_latin: true,
+                })
+                .collect(),
+        }
+    }
+
+    /// The built-in transliterations with the region's conventions on top
+    pub fn translit_table(self) -> TranslitTable {
+        let mut table = TranslitTable::builtin();
+        let overrides = match self {
+            Self::RussianLogs => RUSSIAN_TRANSLIT.to_string(),
+            Self::JapaneseLogs => {
+                // Fullwidth ASCII, which Japanese input methods often produce
+                let fullwidth: String = ('\u{FF01}'..='\u{FF5E}')
+                    .filter_map(|c| char::from_u32(c as u32 - 0xFEE0).map(|ascii| format!("{}={}\n", c, ascii)))
+                    .collect();
+                format!("{}{}", JAPANESE_TRANSLIT, fullwidth)
+            }
+        };
+        table.extend(TranslitTable::parse(&overrides).expect("preset transliterations are valid"));
+        table
+    }
+}
+
+fn is_cyrillic(c: char) -> bool {
+    ('\u{0400}'..='\u{04FF}').contains(&c)
+}
+
+/// Kana, CJK punctuation, Han characters and fullwidth forms
+fn is_japanese(c: char) -> bool {
+    matches!(c as u32, 0x3000..=0x30FF | 0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xFF00..=0xFFEF)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reverse(preset: Preset, text: &str) -> Option<String> {
+        preset.misreadings().iter().find_map(|misreading| misreading.reverse(text))
+    }
+
+    #[test]
+    fn test_misreadings() {
+        assert_eq!(reverse(Preset::RussianLogs, "Ïðèâåò, Ñåðãåé!").as_deref(), Some("Привет, Сергей!"));
+        assert_eq!(reverse(Preset::JapaneseLogs, "JA1ABC ‚±‚ñ‚É‚¿‚Í").as_deref(), Some("JA1ABC こんにちは"));
+
+        // Western names would come out as mixed-script words
+        assert_eq!(reverse(Preset::RussianLogs, "Müller"), None);
+        assert_eq!(reverse(Preset::RussianLogs, "Привет"), None);
+        assert_eq!(reverse(Preset::JapaneseLogs, "José Müller"), None);
+    }
+
+    #[test]
+    fn test_translit_table() {
+        assert_eq!(Preset::RussianLogs.translit_table().transliterate("Юрий Алёшин", '?'), "Iurii Aleshin");
+        assert_eq!(Preset::JapaneseLogs.translit_table().transliterate("ＪＡ１ＡＢＣ、ＱＳＬ。", '?'), "JA1ABC,QSL.");
+    }
+}
@@ -0,0 +1,92 @@
+use crate::adif::AdifFile;
+use crate::dedupe::{self, DedupeKey, Duplicate};
+use std::fmt;
+
+/// Logs of a particular program, whose known quirks `--profile` repairs
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Profile {
+    /// wsjtx_log.adi: no header before WSJT-X 2.0, a partial last record
+    /// after a crash, and QSOs logged twice after rig restarts
+    Wsjtx,
+}
+
+/// A change made to a file to repair a quirk of its program
+#[derive(Debug, Clone, PartialEq)]
+pub enum Repair {
+    /// Added the header the program writes nowadays
+    Header,
+    /// Dropped a record cut off before its `<eor>`
+    IncompleteRecord { record: usize, offset: usize },
+    /// Dropped a repeated QSO
+    Duplicate(Duplicate),
+}
+
+impl fmt::Display for Repair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Header => write!(f, "added a WSJT-X header"),
+            Self::IncompleteRecord { record, offset } => {
+                write!(f, "QSO {} at byte {}: dropped incomplete record", record + 1, offset)
+            }
+            Self::Duplicate(duplicate) => write!(f, "{}: dropped", duplicate),
+        }
+    }
+}
+
+impl Profile {
+    /// Repairs the quirks of the program's logs in `adif`, describing each change.
+    pub fn repair(self, adif: &mut AdifFile) -> Vec<Repair> {
+        match self {
+            Self::Wsjtx => repair_wsjtx(adif),
+        }
+    }
+}
+
+fn repair_wsjtx(adif: &mut AdifFile) -> Vec<Repair> {
+    let mut repairs = Vec::new();
+
+    if !adif.has_header() {
+        adif.preamble = "WSJT-X ADIF Export\r\n".to_string();
+        adif.set_header("adif_ver", "3.1.0");
+        adif.set_header("programid", "WSJT-X");
+        adif.header_end_tag = "<eoh>".to_string();
+        adif.header_excess_data = "\r\n".to_string();
+        repairs.push(Repair::Header);
+    }
+
+    // WSJT-X appends each QSO with its <eor>, so only the last record can
+    // be missing one
+    if adif.records.last().is_some_and(|record| record.end_tag.is_empty()) {
+        let record = adif.records.pop().unwrap();
+        repairs.push(Repair::IncompleteRecord { record: adif.records.len(), offset: record.offset });
+    }
+
+    let duplicates = dedupe::find_duplicates(&adif.records, &DedupeKey::default());
+    dedupe::remove_duplicates(&mut adif.records, &duplicates);
+    repairs.extend(duplicates.into_iter().map(Repair::Duplicate));
+
+    repairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wsjtx_repairs() {
+        let qso = "<call:4>W1AW <gridsquare:4>FN31 <mode:3>FT8 <qso_date:8>20240101 <time_on:6>120000 <band:3>20m <eor>\n";
+        let data = format!("{}{}<call:4>K1AB <gridsquare:4>FN42 <mode:3>FT8 <qso_d", qso, qso);
+        let mut adif = AdifFile::parse(data.as_bytes()).unwrap();
+
+        let repairs: Vec<String> = Profile::Wsjtx.repair(&mut adif).iter().map(|repair| repair.to_string()).collect();
+        assert_eq!(
+            repairs,
+            vec!["added a WSJT-X header", "QSO 3 at byte 202: dropped incomplete record", "QSO 2 duplicates QSO 1: dropped"]
+        );
+        assert_eq!(adif.records.len(), 1);
+        assert_eq!(adif.header_fields.iter().map(|field| field.name.as_str()).collect::<Vec<_>>(), vec!["adif_ver", "programid"]);
+
+        let mut complete = AdifFile::parse(format!("WSJT-X ADIF Export<eoh>\n{}", qso).as_bytes()).unwrap();
+        assert!(Profile::Wsjtx.repair(&mut complete).is_empty());
+    }
+}
@@ -0,0 +1,130 @@
+use crate::adif::Record;
+use crate::sink::AdifSink;
+use std::io::{self, IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+/// How long an operation runs before its progress is shown, so quick
+/// conversions print nothing
+const SHOW_AFTER: Duration = Duration::from_millis(500);
+
+/// How often the progress line is redrawn
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A progress line on stderr, such as `Decoding: 12000/40000 QSOs (30%)`,
+/// drawn over itself and only when stderr is a terminal
+pub struct Progress {
+    label: String,
+    unit: &'static str,
+    total: Option<usize>,
+    started: Instant,
+    drawn_at: Option<Instant>,
+    enabled: bool,
+    immediate: bool,
+}
+
+impl Progress {
+    /// Progress of `label` counting `unit`s, towards `total` if known.
+    pub fn new(label: &str, unit: &'static str, total: Option<usize>) -> Self {
+        Self {
+            label: label.to_string(),
+            unit,
+            total,
+            started: Instant::now(),
+            drawn_at: None,
+            enabled: std::io::stderr().is_terminal(),
+            immediate: false,
+        }
+    }
+
+    /// Progress that is shown from the first update, for operations that
+    /// run until interrupted.
+    pub fn status(label: &str, unit: &'static str) -> Self {
+        let mut progress = Self::new(label, unit, None);
+        progress.immediate = true;
+        progress
+    }
+
+    /// Shows that `done` units are done, unless the line was drawn very
+    /// recently or the operation only just started.
+    pub fn update(&mut self, done: usize) {
+        if !self.enabled {
+            return;
+        }
+        let now = Instant::now();
+        let due = self.immediate
+            || match self.drawn_at {
+                Some(drawn_at) => now.duration_since(drawn_at) >= REDRAW_INTERVAL,
+                None => now.duration_since(self.started) >= SHOW_AFTER,
+            };
+        if due {
+            self.drawn_at = Some(now);
+            let _ = write!(std::io::stderr(), "\r\x1b[K{}", self.line(done));
+        }
+    }
+
+    /// Removes the line, so that other messages can be printed.
+    pub fn clear(&mut self) {
+        if self.drawn_at.take().is_some() {
+            let _ = write!(std::io::stderr(), "\r\x1b[K");
+        }
+    }
+
+    fn line(&self, done: usize) -> String {
+        match self.total {
+            Some(total) if total > 0 => format!("{}: {}/{} {} ({}%)", self.label, done, total, self.unit, done * 100 / total),
+            _ => format!("{}: {} {}", self.label, done, self.unit),
+        }
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+/// Sink showing how many of `total` records went through to the sink it wraps
+pub struct ProgressSink<'a> {
+    sink: &'a mut dyn AdifSink,
+    progress: Progress,
+    written: usize,
+}
+
+impl<'a> ProgressSink<'a> {
+    pub fn new(sink: &'a mut dyn AdifSink, total: usize) -> Self {
+        Self { sink, progress: Progress::new("Writing", "QSOs", Some(total)), written: 0 }
+    }
+}
+
+impl AdifSink for ProgressSink<'_> {
+    fn write_header(&mut self, header: &[u8]) -> io::Result<()> {
+        self.sink.write_header(header)
+    }
+
+    fn write_record(&mut self, record: &Record, bytes: &[u8]) -> io::Result<()> {
+        self.sink.write_record(record, bytes)?;
+        self.written += 1;
+        self.progress.update(self.written);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.sink.flush()
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.progress.clear();
+        self.sink.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line() {
+        assert_eq!(Progress::new("Decoding", "QSOs", Some(40000)).line(12000), "Decoding: 12000/40000 QSOs (30%)");
+        assert_eq!(Progress::status("Watching log.adi", "QSOs converted").line(7), "Watching log.adi: 7 QSOs converted");
+    }
+}
@@ -0,0 +1,155 @@
+/// Longest QSL message eQSL accepts; longer messages are cut off on the card
+pub const EQSL_LIMIT: usize = 240;
+
+/// How `--qslmsg-fix` rewrites QSLMSG and QSLMSG_INTL values
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum QslMessageFix {
+    /// Replace line breaks and tabs with spaces and remove other control characters
+    Clean,
+    /// Clean, then cut the message at a word boundary to fit the eQSL limit
+    Truncate,
+    /// Clean, then break the message into lines for card printing software
+    Wrap,
+}
+
+pub fn is_qsl_message(name: &str) -> bool {
+    name.eq_ignore_ascii_case("qslmsg") || name.eq_ignore_ascii_case("qslmsg_intl")
+}
+
+/// Problems that keep a QSL message from rendering on a card as written.
+pub fn check_message(value: &str) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    let length = value.chars().count();
+    if length > EQSL_LIMIT {
+        problems.push(format!("{} characters is longer than the {} eQSL allows", length, EQSL_LIMIT));
+    }
+    if value.chars().any(is_line_break) {
+        problems.push("line breaks do not render on QSL cards".to_string());
+    }
+    if let Some(c) = value.chars().find(|c| c.is_control() && !is_line_break(*c)) {
+        problems.push(format!("control character U+{:04X} does not render on QSL cards", c as u32));
+    }
+
+    problems
+}
+
+/// Field transform applying `fix` to QSL messages. `width` is the line length for `Wrap`.
+pub fn fix_field(name: &str, value: &str, fix: QslMessageFix, width: usize) -> Option<String> {
+    if !is_qsl_message(name) {
+        return None;
+    }
+
+    let fixed = match fix {
+        QslMessageFix::Clean => clean(value),
+        QslMessageFix::Truncate => truncate(&clean(value), EQSL_LIMIT),
+        QslMessageFix::Wrap => wrap(&clean(value), width),
+    };
+    (fixed != value).then_some(fixed)
+}
+
+/// Replaces line breaks and tabs with a space and drops other control characters.
+pub fn clean(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if is_line_break(c) || c == '\t' {
+            if c == '\r' && chars.peek() == Some(&'\n') {
+                chars.next();
+            }
+            if !result.ends_with(' ') {
+                result.push(' ');
+            }
+        } else if !c.is_control() {
+            result.push(c);
+        }
+    }
+
+    result.trim_end().to_string()
+}
+
+/// Cuts `value` to at most `limit` characters, at the last space if there is one
+/// in the second half of the message.
+pub fn truncate(value: &str, limit: usize) -> String {
+    if value.chars().count() <= limit {
+        return value.to_string();
+    }
+
+    let cut: String = value.chars().take(limit).collect();
+    if value.chars().nth(limit) == Some(' ') {
+        return cut.trim_end().to_string();
+    }
+
+    match cut.rfind(' ') {
+        Some(space) if cut[..space].chars().count() >= limit / 2 => cut[..space].trim_end().to_string(),
+        _ => cut,
+    }
+}
+
+/// Breaks `value` into CRLF-separated lines of at most `width` characters,
+/// between words where possible.
+pub fn wrap(value: &str, width: usize) -> String {
+    let width = width.max(1);
+    let mut lines: Vec<String> = Vec::new();
+    let mut line = String::new();
+
+    for word in value.split(' ').filter(|word| !word.is_empty()) {
+        let mut word: Vec<char> = word.chars().collect();
+        let line_length = line.chars().count();
+
+        if line_length > 0 && line_length + 1 + word.len() > width {
+            lines.push(std::mem::take(&mut line));
+        } else if line_length > 0 {
+            line.push(' ');
+        }
+
+        // Words longer than a line are split
+        while word.len() > width {
+            let rest = word.split_off(width);
+            lines.push(word.into_iter().collect());
+            word = rest;
+        }
+        line.extend(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    lines.join("\r\n")
+}
+
+fn is_line_break(c: char) -> bool {
+    matches!(c, '\n' | '\r' | '\u{0B}' | '\u{0C}' | '\u{85}' | '\u{2028}' | '\u{2029}')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_message() {
+        assert!(check_message("Tnx for the QSO, 73!").is_empty());
+        assert_eq!(check_message("Tnx\r\nfor\tthe QSO"), vec![
+            "line breaks do not render on QSL cards".to_string(),
+            "control character U+0009 does not render on QSL cards".to_string(),
+        ]);
+        assert_eq!(check_message(&"x".repeat(241)), vec!["241 characters is longer than the 240 eQSL allows".to_string()]);
+    }
+
+    #[test]
+    fn test_fixes() {
+        assert_eq!(clean("Tnx\r\nfor the\n\nQSO\u{7}\t73 "), "Tnx for the QSO 73");
+
+        let long = format!("{} tnx es 73", "a".repeat(232));
+        assert_eq!(truncate(&long, 240), format!("{} tnx es", "a".repeat(232)));
+        assert_eq!(truncate(&"a".repeat(250), 240), "a".repeat(240));
+
+        assert_eq!(wrap("Thanks for the nice QSO on 20m, hope to meet again", 16), "Thanks for the\r\nnice QSO on 20m,\r\nhope to meet\r\nagain");
+        assert_eq!(wrap("abcdefghij xy", 4), "abcd\r\nefgh\r\nij\r\nxy");
+
+        assert_eq!(fix_field("QSLMSG_INTL", "Tnx\nQSO", QslMessageFix::Clean, 40).as_deref(), Some("Tnx QSO"));
+        assert_eq!(fix_field("QSLMSG", "Tnx QSO", QslMessageFix::Clean, 40), None);
+        assert_eq!(fix_field("COMMENT", "Tnx\nQSO", QslMessageFix::Clean, 40), None);
+    }
+}
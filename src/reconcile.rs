@@ -0,0 +1,210 @@
+use crate::adif::{AdifFile, Record};
+use crate::dedupe::start_time;
+use crate::validation::BANDS;
+use std::fmt;
+
+/// Where confirmations come from, which decides the QSL fields they set
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum QslService {
+    /// A Logbook of the World report: LOTW_QSL_RCVD and LOTW_QSLRDATE
+    Lotw,
+    /// An eQSL inbox download: EQSL_QSL_RCVD and EQSL_QSLRDATE
+    Eqsl,
+    /// Paper cards: QSL_RCVD and QSLRDATE
+    Card,
+}
+
+impl QslService {
+    /// The received flag and received date fields of the service.
+    pub fn fields(self) -> (&'static str, &'static str) {
+        match self {
+            Self::Lotw => ("lotw_qsl_rcvd", "lotw_qslrdate"),
+            Self::Eqsl => ("eqsl_qsl_rcvd", "eqsl_qslrdate"),
+            Self::Card => ("qsl_rcvd", "qslrdate"),
+        }
+    }
+
+    /// The service that produced a confirmations file, going by its PROGRAMID.
+    pub fn detect(adif: &AdifFile) -> Option<Self> {
+        let program = adif.header_fields.iter().find(|field| field.name.eq_ignore_ascii_case("programid"))?.data.to_lowercase();
+        if program.contains("lotw") {
+            Some(Self::Lotw)
+        } else if program.contains("eqsl") {
+            Some(Self::Eqsl)
+        } else {
+            None
+        }
+    }
+}
+
+/// A QSO of the log newly marked as confirmed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Confirmed {
+    /// Index of the record in the log, starting at 0
+    pub record: usize,
+    pub call: String,
+    /// Date the confirmation was received, YYYYMMDD
+    pub date: String,
+}
+
+impl fmt::Display for Confirmed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "QSO {}: {} confirmed on {}", self.record + 1, self.call, self.date)
+    }
+}
+
+/// A confirmation for a QSO the log does not have
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unmatched {
+    /// Index of the record in the confirmations file, starting at 0
+    pub confirmation: usize,
+    pub call: String,
+    pub qso_date: String,
+    pub time_on: String,
+    pub band: String,
+    pub mode: String,
+}
+
+impl fmt::Display for Unmatched {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "confirmation {}: {} on {} at {}, {} {} matches no QSO",
+            self.confirmation + 1,
+            self.call,
+            self.qso_date,
+            self.time_on,
+            self.band,
+            self.mode
+        )
+    }
+}
+
+/// What `reconcile` did
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Reconciliation {
+    pub confirmed: Vec<Confirmed>,
+    /// Confirmations of QSOs the log already had marked as received
+    pub already_confirmed: usize,
+    pub unmatched: Vec<Unmatched>,
+}
+
+/// Marks the QSOs of `log` that `confirmations` confirm as received through
+/// `service`. A confirmation matches a QSO with the same callsign, band and
+/// mode (or submode) that started within `window_minutes` of it. The received
+/// date is the confirmation's own if it has one, else `today` (YYYYMMDD).
+/// Records of the confirmations file saying QSL_RCVD other than Y are
+/// unconfirmed QSOs, as LoTW reports list them, and are left out.
+pub fn reconcile(log: &mut [Record], confirmations: &[Record], service: QslService, window_minutes: u32, today: &str) -> Reconciliation {
+    let (received, received_date) = service.fields();
+    let window = i64::from(window_minutes) * 60;
+    let mut reconciliation = Reconciliation::default();
+
+    for (index, confirmation) in confirmations.iter().enumerate() {
+        if confirmation.get("qsl_rcvd").is_some_and(|value| !value.trim().eq_ignore_ascii_case("y")) {
+            continue;
+        }
+
+        let start = start_time(confirmation);
+        let matching = log.iter().position(|qso| {
+            same_value(qso, confirmation, "call")
+                && band(qso).is_some_and(|qso_band| Some(qso_band) == band(confirmation))
+                && shares_mode(qso, confirmation)
+                && matches!((start_time(qso), start), (Some(a), Some(b)) if (a - b).abs() <= window)
+        });
+        let Some(record) = matching else {
+            let value = |name| confirmation.get(name).unwrap_or_default().trim().to_string();
+            reconciliation.unmatched.push(Unmatched {
+                confirmation: index,
+                call: value("call"),
+                qso_date: value("qso_date"),
+                time_on: value("time_on"),
+                band: band(confirmation).unwrap_or_default(),
+                mode: value("mode"),
+            });
+            continue;
+        };
+
+        let qso = &mut log[record];
+        if qso.get(received).is_some_and(|value| value.trim().eq_ignore_ascii_case("y")) {
+            reconciliation.already_confirmed += 1;
+            continue;
+        }
+        let date = [received_date, "qslrdate"]
+            .iter()
+            .find_map(|name| confirmation.get(name).map(str::trim).filter(|date| !date.is_empty()))
+            .unwrap_or(today)
+            .to_string();
+        qso.set(received, "Y");
+        qso.set(received_date, &date);
+        reconciliation.confirmed.push(Confirmed { record, call: qso.get("call").unwrap_or_default().trim().to_string(), date });
+    }
+
+    reconciliation
+}
+
+fn same_value(a: &Record, b: &Record, name: &str) -> bool {
+    match (a.get(name), b.get(name)) {
+        (Some(a), Some(b)) => a.trim().eq_ignore_ascii_case(b.trim()),
+        _ => false,
+    }
+}
+
+/// The record's band, from its BAND field or else its FREQ, in lower case.
+fn band(record: &Record) -> Option<String> {
+    if let Some(band) = record.get("band").map(str::trim).filter(|band| !band.is_empty()) {
+        return Some(band.to_lowercase());
+    }
+    let freq: f64 = record.get("freq")?.trim().parse().ok()?;
+    BANDS.iter().find(|(_, low, high)| (*low..=*high).contains(&freq)).map(|(band, _, _)| band.to_lowercase())
+}
+
+/// Whether the MODE or SUBMODE of one record is the MODE or SUBMODE of the
+/// other, so that FT4 as a mode matches MFSK with submode FT4.
+fn shares_mode(a: &Record, b: &Record) -> bool {
+    let modes = |record: &Record| -> Vec<String> {
+        ["mode", "submode"]
+            .iter()
+            .filter_map(|name| record.get(name))
+            .map(|mode| mode.trim().to_uppercase())
+            .filter(|mode| !mode.is_empty())
+            .collect()
+    };
+    let b_modes = modes(b);
+    modes(a).iter().any(|mode| b_modes.contains(mode))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reconcile() {
+        let mut log = AdifFile::parse(
+            b"Log\r\n<eoh>\
+              <call:4>W1AW<band:3>20m<mode:4>MFSK<submode:3>FT4<qso_date:8>20240301<time_on:4>1200<eor>\
+              <call:4>K1AB<band:3>40m<mode:2>CW<qso_date:8>20240301<time_on:4>1300<eor>\
+              <call:6>N0CALL<band:3>40m<mode:2>CW<qso_date:8>20240301<time_on:4>1400<lotw_qsl_rcvd:1>Y<eor>",
+        )
+        .unwrap();
+        let report = AdifFile::parse(
+            b"ARRL Logbook of the World Status Report\r\n<programid:4>LoTW<eoh>\
+              <call:4>w1aw<freq:6>14.080<mode:3>FT4<qso_date:8>20240301<time_on:6>121500<qsl_rcvd:1>Y<qslrdate:8>20240305<eor>\
+              <call:4>K1AB<band:3>40m<mode:2>CW<qso_date:8>20240301<time_on:4>1300<qsl_rcvd:1>N<eor>\
+              <call:6>N0CALL<band:3>40m<mode:2>CW<qso_date:8>20240301<time_on:4>1405<qsl_rcvd:1>Y<eor>\
+              <call:4>DL1X<band:3>20m<mode:3>SSB<qso_date:8>20240302<time_on:4>0800<qsl_rcvd:1>Y<eor>",
+        )
+        .unwrap();
+        assert_eq!(QslService::detect(&report), Some(QslService::Lotw));
+
+        let reconciliation = reconcile(&mut log.records, &report.records, QslService::Lotw, 30, "20240310");
+        assert_eq!(reconciliation.confirmed, vec![Confirmed { record: 0, call: "W1AW".to_string(), date: "20240305".to_string() }]);
+        assert_eq!(reconciliation.already_confirmed, 1);
+        assert_eq!(reconciliation.unmatched.len(), 1);
+        assert_eq!(reconciliation.unmatched[0].to_string(), "confirmation 4: DL1X on 20240302 at 0800, 20m SSB matches no QSO");
+
+        assert_eq!(log.records[0].get("lotw_qsl_rcvd"), Some("Y"));
+        assert_eq!(log.records[0].get("lotw_qslrdate"), Some("20240305"));
+        assert_eq!(log.records[1].get("lotw_qsl_rcvd"), None);
+    }
+}
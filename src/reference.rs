@@ -0,0 +1,145 @@
+/// Award programs for portable operation, each with its own reference format
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Program {
+    /// Summits on the Air: association, region and summit, as in `EA4/MD-001`
+    Sota,
+    /// Parks on the Air: prefix and park, as in `US-0001`, optionally with a
+    /// location (`K-0817@US-MA`) and as a comma-separated list
+    Pota,
+    /// World Wide Flora & Fauna: program and area, as in `DLFF-0001`
+    Wwff,
+}
+
+impl Program {
+    /// The program a field holds references of: the ADIF `*_REF` fields, and
+    /// APP fields ending in `_WWFF` or `_WWFF_REF` from loggers that predate
+    /// WWFF_REF.
+    pub fn for_field(name: &str) -> Option<Self> {
+        let name = name.to_ascii_lowercase();
+        let name = name.strip_prefix("my_").unwrap_or(&name);
+        match name {
+            "sota_ref" => Some(Self::Sota),
+            "pota_ref" => Some(Self::Pota),
+            "wwff_ref" => Some(Self::Wwff),
+            _ if name.starts_with("app_") && (name.ends_with("_wwff") || name.ends_with("_wwff_ref")) => Some(Self::Wwff),
+            _ => None,
+        }
+    }
+
+    /// Digits in the number part of a reference
+    fn digits(self) -> usize {
+        match self {
+            Self::Sota => 3,
+            Self::Pota | Self::Wwff => 4,
+        }
+    }
+}
+
+/// Why `value` is not a reference of the program, or `None` if it is one.
+/// Case does not matter.
+pub fn check(program: Program, value: &str) -> Option<String> {
+    if program == Program::Pota {
+        return value.split(',').find_map(|reference| check_one(program, reference));
+    }
+    check_one(program, value)
+}
+
+fn check_one(program: Program, reference: &str) -> Option<String> {
+    let reference = reference.trim();
+    let uppercase = reference.to_ascii_uppercase();
+    let valid = match program {
+        Program::Sota => uppercase.split_once('/').is_some_and(|(association, summit)| {
+            is_prefix(association) && summit.split_once('-').is_some_and(|(region, number)| is_region(region) && is_number(number, 3..=3))
+        }),
+        Program::Pota => {
+            let (park, location) = uppercase.split_once('@').map_or((uppercase.as_str(), None), |(park, location)| (park, Some(location)));
+            park.split_once('-').is_some_and(|(prefix, number)| is_prefix(prefix) && is_number(number, 4..=5))
+                && location.is_none_or(|location| location.split_once('-').is_some_and(|(country, region)| is_region(country) && is_region(region)))
+        }
+        Program::Wwff => uppercase.split_once('-').is_some_and(|(program, number)| {
+            program.strip_suffix("FF").is_some_and(is_prefix) && is_number(number, 4..=4)
+        }),
+    };
+
+    (!valid).then(|| match program {
+        Program::Sota => format!("{:?} is not a SOTA reference like EA4/MD-001", reference),
+        Program::Pota => format!("{:?} is not a POTA reference like US-0001", reference),
+        Program::Wwff => format!("{:?} is not a WWFF reference like DLFF-0001", reference),
+    })
+}
+
+/// Uppercases a reference, removes whitespace and pads its number with
+/// zeros (`ea4/md-1` -> `EA4/MD-001`). Values that still are no reference of
+/// the program are left alone.
+pub fn normalize(program: Program, value: &str) -> Option<String> {
+    let compact: String = value.chars().filter(|c| !c.is_whitespace()).map(|c| c.to_ascii_uppercase()).collect();
+    let normalized = if program == Program::Pota {
+        compact.split(',').map(|reference| pad_number(program, reference)).collect::<Vec<_>>().join(",")
+    } else {
+        pad_number(program, &compact)
+    };
+
+    (check(program, &normalized).is_none() && normalized != value).then_some(normalized)
+}
+
+/// Field transform normalizing SOTA, POTA and WWFF references.
+pub fn normalize_field(name: &str, value: &str) -> Option<String> {
+    normalize(Program::for_field(name)?, value)
+}
+
+/// Pads the digits after the last `-`, up to any POTA location.
+fn pad_number(program: Program, reference: &str) -> String {
+    let (reference, location) = reference.split_once('@').map_or((reference, None), |(park, location)| (park, Some(location)));
+    let padded = match reference.rsplit_once('-') {
+        Some((prefix, number)) if !number.is_empty() && number.bytes().all(|b| b.is_ascii_digit()) => {
+            format!("{}-{:0>width$}", prefix, number, width = program.digits())
+        }
+        _ => reference.to_string(),
+    };
+    match location {
+        Some(location) => format!("{}@{}", padded, location),
+        None => padded,
+    }
+}
+
+/// Callsign prefix or association, like `EA4`, `W7W` or `K`
+fn is_prefix(text: &str) -> bool {
+    (1..=4).contains(&text.len()) && text.bytes().all(|b| b.is_ascii_uppercase() || b.is_ascii_digit())
+}
+
+/// SOTA region or POTA location part, like `MD` or `MA`
+fn is_region(text: &str) -> bool {
+    (2..=3).contains(&text.len()) && text.bytes().all(|b| b.is_ascii_uppercase() || b.is_ascii_digit())
+}
+
+fn is_number(text: &str, digits: std::ops::RangeInclusive<usize>) -> bool {
+    digits.contains(&text.len()) && text.bytes().all(|b| b.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        assert_eq!(check(Program::Sota, "EA4/MD-001"), None);
+        assert_eq!(check(Program::Sota, "W7W/LC-001"), None);
+        assert_eq!(check(Program::Pota, "US-0001,K-0817@US-MA,DE-10234"), None);
+        assert_eq!(check(Program::Wwff, "DLFF-0001"), None);
+
+        assert_eq!(check(Program::Sota, "EA4-MD-001").as_deref(), Some("\"EA4-MD-001\" is not a SOTA reference like EA4/MD-001"));
+        assert_eq!(check(Program::Pota, "US-0001,K-81").as_deref(), Some("\"K-81\" is not a POTA reference like US-0001"));
+        assert!(check(Program::Wwff, "DL-0001").is_some());
+        assert_eq!(check(Program::Sota, "ea4/md-001"), None);
+    }
+
+    #[test]
+    fn test_normalize_field() {
+        assert_eq!(normalize_field("SOTA_REF", " ea4/md-1").as_deref(), Some("EA4/MD-001"));
+        assert_eq!(normalize_field("my_pota_ref", "k-817@us-ma, us-12").as_deref(), Some("K-0817@US-MA,US-0012"));
+        assert_eq!(normalize_field("APP_LOGGER_WWFF", "dlff-1").as_deref(), Some("DLFF-0001"));
+        assert_eq!(normalize_field("wwff_ref", "DLFF-0001"), None);
+        assert_eq!(normalize_field("sota_ref", "Mount Everest"), None);
+        assert_eq!(normalize_field("comment", "ea4/md-1"), None);
+    }
+}
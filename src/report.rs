@@ -0,0 +1,190 @@
+use crate::adif::LengthCorrection;
+use crate::color::{align, Palette};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// What converting a file did to it, for auditing the output before trusting it
+#[derive(Debug, Default, PartialEq)]
+pub struct Report {
+    /// Number of fields with non-ASCII data decoded with each encoding
+    pub encodings: BTreeMap<String, usize>,
+    /// Fields holding byte sequences that are invalid in the encoding they were decoded with
+    pub invalid_fields: usize,
+    /// Fields whose double-encoded UTF-8 was corrected
+    pub mojibake_corrections: usize,
+    /// Characters the output encoding cannot represent, by field name
+    pub unrepresentable: BTreeMap<String, usize>,
+    /// Whether unrepresentable characters are deleted rather than replaced
+    pub deleted: bool,
+    pub changes: Vec<Change>,
+    /// Callsign fields whose written value cannot be a callsign
+    pub impossible_callsigns: Vec<InvalidValue>,
+    /// Grid square fields whose written value is not a Maidenhead locator
+    pub invalid_grids: Vec<InvalidValue>,
+    /// Fields the parser read with another length than the declared one
+    pub length_corrections: Vec<LengthCorrection>,
+}
+
+/// A field whose written value differs from its input as decoded
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    /// Index of the record in the file, starting at 0
+    pub record: usize,
+    pub field: String,
+    /// Byte offset of the field in the input
+    pub offset: usize,
+    pub before: String,
+    pub after: String,
+}
+
+impl Change {
+    /// Where the field is, e.g. `QSO 3: NAME at byte 112`
+    pub fn location(&self) -> String {
+        format!("QSO {}: {} at byte {}", self.record + 1, self.field.to_uppercase(), self.offset)
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {:?} -> {:?}", self.location(), self.before, self.after)
+    }
+}
+
+/// A callsign or grid square field that award services would reject,
+/// typically because undecodable bytes or other text ended up in it
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidValue {
+    /// Index of the record in the file, starting at 0
+    pub record: usize,
+    pub field: String,
+    /// Byte offset of the field in the input
+    pub offset: usize,
+    pub value: String,
+    /// Why the value is not valid for the field
+    pub problem: String,
+}
+
+impl InvalidValue {
+    /// Where the field is, e.g. `QSO 3: CALL at byte 87`
+    pub fn location(&self) -> String {
+        format!("QSO {}: {} at byte {}", self.record + 1, self.field.to_uppercase(), self.offset)
+    }
+}
+
+impl fmt::Display for InvalidValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {:?} {}", self.location(), self.value, self.problem)
+    }
+}
+
+/// Why a step of the conversion changed a field
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CorrectionReason {
+    /// The declared length counted characters rather than bytes
+    LengthReinterpretation,
+    /// Double-encoded UTF-8 was reversed
+    Mojibake,
+    /// HTML or `&0xNN;` entity references were resolved
+    Entity,
+    /// A field transform such as --fix-callsigns or --replace-field applied
+    Transform,
+    /// The emoji policy or --ascii transliteration applied
+    Transliteration,
+    /// Characters the output encoding cannot represent were replaced or deleted
+    Unrepresentable,
+}
+
+impl CorrectionReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::LengthReinterpretation => "length-reinterpretation",
+            Self::Mojibake => "mojibake",
+            Self::Entity => "entity",
+            Self::Transform => "transform",
+            Self::Transliteration => "transliteration",
+            Self::Unrepresentable => "unrepresentable",
+        }
+    }
+
+    /// Whether the step repaired damaged input, rather than applying a
+    /// change the options asked for
+    pub fn is_repair(self) -> bool {
+        matches!(self, Self::LengthReinterpretation | Self::Mojibake | Self::Entity)
+    }
+}
+
+/// One step that changed a field on its way from the input to the output
+#[derive(Debug, Clone, PartialEq)]
+pub struct Correction {
+    /// Index of the record in the file, starting at 0
+    pub record: usize,
+    pub field: String,
+    /// Byte offset of the field in the input
+    pub offset: usize,
+    pub original_bytes: Vec<u8>,
+    /// Value before the step
+    pub before: String,
+    /// Value after the step
+    pub after: String,
+    pub reason: CorrectionReason,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.render(f, &Palette::default())
+    }
+}
+
+impl Report {
+    /// Writes the report with the values of changed and invalid fields lined
+    /// up, and what changed in them in the colors of `palette`.
+    pub fn render(&self, f: &mut dyn fmt::Write, palette: &Palette) -> fmt::Result {
+        let encodings: Vec<String> = self.encodings
+            .iter()
+            .map(|(encoding, fields)| format!("{} ({} field{})", encoding, fields, if *fields == 1 { "" } else { "s" }))
+            .collect();
+        writeln!(f, "Encodings: {}", if encodings.is_empty() { "ASCII only".to_string() } else { encodings.join(", ") })?;
+        writeln!(f, "Fields with invalid sequences: {}", self.invalid_fields)?;
+        writeln!(f, "Mojibake corrections: {}", self.mojibake_corrections)?;
+
+        let unrepresentable: Vec<String> = self.unrepresentable
+            .iter()
+            .map(|(field, count)| format!("{} {}", field.to_uppercase(), count))
+            .collect();
+        writeln!(
+            f,
+            "Characters {}: {}",
+            if self.deleted { "deleted" } else { "replaced" },
+            if unrepresentable.is_empty() { "none".to_string() } else { unrepresentable.join(", ") }
+        )?;
+
+        writeln!(f, "Length corrections: {}", self.length_corrections.len())?;
+        for correction in &self.length_corrections {
+            writeln!(f, "  {}", correction)?;
+        }
+
+        writeln!(f, "Changed fields: {}", self.changes.len())?;
+        let changes: Vec<(String, String)> = self.changes
+            .iter()
+            .map(|change| {
+                let (before, after) = palette.change(&change.before, &change.after);
+                (change.location(), format!("{} -> {}", before, after))
+            })
+            .collect();
+        for line in align(&changes) {
+            writeln!(f, "  {}", line)?;
+        }
+
+        for (title, values) in [("Impossible callsigns", &self.impossible_callsigns), ("Invalid grid squares", &self.invalid_grids)] {
+            writeln!(f, "{}: {}", title, values.len())?;
+            let values: Vec<(String, String)> = values
+                .iter()
+                .map(|value| (value.location(), format!("{} {}", palette.red(&format!("{:?}", value.value)), value.problem)))
+                .collect();
+            for line in align(&values) {
+                writeln!(f, "  {}", line)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+use regex::Regex;
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum RewriteError {
+    #[error("{0:?} is not FIELD:/PATTERN/REPLACEMENT/")]
+    Syntax(String),
+    #[error("Invalid pattern: {0}")]
+    Pattern(#[from] regex::Error),
+}
+
+/// A regex substitution applied to every value of one field, written
+/// `call:/\/QRP$//` like sed. `\/` stands for a slash in the pattern or the
+/// replacement, which may refer to groups as `$1` or `${name}`. A trailing
+/// `i` makes the pattern case-insensitive.
+#[derive(Debug, Clone)]
+pub struct FieldRewrite {
+    pub field: String,
+    pattern: Regex,
+    replacement: String,
+}
+
+impl FromStr for FieldRewrite {
+    type Err = RewriteError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let syntax = || RewriteError::Syntax(s.to_string());
+        let (field, expression) = s.split_once(':').ok_or_else(syntax)?;
+        let field = field.trim();
+        let expression = expression.strip_prefix('/').ok_or_else(syntax)?;
+        if field.is_empty() {
+            return Err(syntax());
+        }
+
+        let parts = split_unescaped(expression);
+        let [pattern, replacement, flags] = parts.as_slice() else {
+            return Err(syntax());
+        };
+        let pattern = match flags.as_str() {
+            "" => pattern.clone(),
+            "i" => format!("(?i){}", pattern),
+            _ => return Err(syntax()),
+        };
+
+        Ok(Self {
+            field: field.to_string(),
+            pattern: Regex::new(&pattern)?,
+            replacement: replacement.clone(),
+        })
+    }
+}
+
+impl fmt::Display for FieldRewrite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let escape = |text: &str| text.replace('/', "\\/");
+        write!(f, "{} /{}/{}/", self.field.to_uppercase(), escape(self.pattern.as_str()), escape(&self.replacement))
+    }
+}
+
+impl FieldRewrite {
+    /// Field transform replacing every match in values of the field, or
+    /// `None` when there is nothing to replace.
+    pub fn apply(&self, name: &str, value: &str) -> Option<String> {
+        if !name.eq_ignore_ascii_case(&self.field) {
+            return None;
+        }
+
+        let replaced = self.pattern.replace_all(value, self.replacement.as_str());
+        (replaced != value).then(|| replaced.into_owned())
+    }
+}
+
+/// Adds one to the count of each rewrite that changes any of a record's
+/// `fields` (name and value pairs), applying the rewrites in order like
+/// the field transforms do.
+pub fn count_changes(rewrites: &[FieldRewrite], fields: &[(&str, String)], counts: &mut [usize]) {
+    let mut changed = vec![false; rewrites.len()];
+
+    for (name, value) in fields {
+        let mut value = value.clone();
+        for (index, rewrite) in rewrites.iter().enumerate() {
+            if let Some(replaced) = rewrite.apply(name, &value) {
+                changed[index] = true;
+                value = replaced;
+            }
+        }
+    }
+
+    for (count, changed) in counts.iter_mut().zip(changed) {
+        *count += usize::from(changed);
+    }
+}
+
+/// Splits at slashes, except those escaped as `\/`, which become plain slashes.
+fn split_unescaped(text: &str) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'/') => {
+                chars.next();
+                parts.last_mut().unwrap().push('/');
+            }
+            '/' => parts.push(String::new()),
+            c => parts.last_mut().unwrap().push(c),
+        }
+    }
+
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_field_rewrite() {
+        let rewrite: FieldRewrite = r"call:/\/QRP$//".parse().unwrap();
+        assert_eq!(rewrite.apply("CALL", "EA4ABC/QRP").as_deref(), Some("EA4ABC"));
+        assert_eq!(rewrite.apply("CALL", "EA4ABC"), None);
+        assert_eq!(rewrite.apply("COMMENT", "EA4ABC/QRP"), None);
+        assert_eq!(rewrite.to_string(), "CALL /\\/QRP$//");
+
+        let rewrite: FieldRewrite = "gridsquare:/^([a-r]{2})([0-9]{2})$/${1}${2}aa/i".parse().unwrap();
+        assert_eq!(rewrite.apply("gridsquare", "JN58").as_deref(), Some("JN58aa"));
+
+        for invalid in ["call/QRP//", "call:/QRP/", "call:/QRP//x", ":/a/b/", "call:/(/x/"] {
+            assert!(invalid.parse::<FieldRewrite>().is_err(), "{}", invalid);
+        }
+
+        let rewrites: Vec<FieldRewrite> = [r"call:/\/QRP$//", "call:/^/DL\\//", "name:/x/y/"].iter().map(|r| r.parse().unwrap()).collect();
+        let mut counts = vec![0; 3];
+        count_changes(&rewrites, &[("CALL", "K1ABC/QRP".to_string()), ("NAME", "Joe".to_string())], &mut counts);
+        count_changes(&rewrites, &[("CALL", "K1ABC".to_string())], &mut counts);
+        assert_eq!(counts, vec![1, 2, 0]);
+    }
+}
@@ -0,0 +1,67 @@
+use crate::adif::Record;
+
+/// Modes reported as readability and strength only
+const PHONE_MODES: &[&str] = &["SSB", "AM", "FM", "DIGITALVOICE"];
+
+/// Default signal report for a mode: 59 for phone, 599 for CW and digital modes.
+pub fn default_report(mode: &str) -> Option<&'static str> {
+    let mode = mode.trim();
+    if mode.is_empty() {
+        None
+    } else if PHONE_MODES.iter().any(|phone| phone.eq_ignore_ascii_case(mode)) {
+        Some("59")
+    } else {
+        Some("599")
+    }
+}
+
+/// Sets missing or empty RST_SENT and RST_RCVD fields to the default report
+/// for the record's MODE. Records without a mode are left alone. Returns the
+/// number of fields filled in.
+pub fn fill_rst(record: &mut Record) -> usize {
+    let Some(mode_field) = record.fields.iter().find(|field| field.name.eq_ignore_ascii_case("mode")) else {
+        return 0;
+    };
+    let Some(report) = default_report(&mode_field.data) else {
+        return 0;
+    };
+
+    // New fields follow the case of the record's own field names
+    let lowercase = mode_field.name == "mode";
+    let mut filled = 0;
+    for name in ["RST_SENT", "RST_RCVD"] {
+        if record.get(name).is_none_or(|value| value.trim().is_empty()) {
+            record.set(&if lowercase { name.to_lowercase() } else { name.to_string() }, report);
+            filled += 1;
+        }
+    }
+
+    filled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adif::AdifFile;
+
+    #[test]
+    fn test_fill_rst() {
+        let mut adif = AdifFile::parse(
+            b"Log\r\n<eoh>\
+              <CALL:4>W1AW<MODE:3>FT8<EOR>\
+              <call:4>K1AB<mode:3>SSB<rst_sent:0><rst_rcvd:2>57<eor>\
+              <call:4>N0CW<mode:2>CW<rst_sent:3>579<rst_rcvd:3>449<eor>\
+              <call:4>N0NO<eor>",
+        )
+        .unwrap();
+
+        let filled: Vec<usize> = adif.records.iter_mut().map(fill_rst).collect();
+        assert_eq!(filled, vec![2, 1, 0, 0]);
+
+        let reports = |record: &Record| (record.get("rst_sent").map(str::to_string), record.get("rst_rcvd").map(str::to_string));
+        assert_eq!(reports(&adif.records[0]), (Some("599".to_string()), Some("599".to_string())));
+        assert_eq!(adif.records[0].fields[2].name, "RST_SENT");
+        assert_eq!(reports(&adif.records[1]), (Some("59".to_string()), Some("57".to_string())));
+        assert_eq!(reports(&adif.records[3]), (None, None));
+    }
+}
@@ -0,0 +1,422 @@
+use crate::adif::Record;
+use crate::atomic::AtomicFile;
+use flate2::write::GzEncoder;
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{self, Write};
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Destination for serialized ADIF output.
+///
+/// `OutputFormatter` serializes the header once and then every record, and
+/// hands the bytes to a sink. Sinks decide where the bytes end up, so file
+/// output, splitting and network forwarding all share one serialization path.
+pub trait AdifSink {
+    /// Receives the serialized header, from the preamble through `<eoh>`.
+    fn write_header(&mut self, header: &[u8]) -> io::Result<()>;
+
+    /// Receives one serialized record, including `<eor>` and its excess data.
+    fn write_record(&mut self, record: &Record, bytes: &[u8]) -> io::Result<()>;
+
+    /// Pushes records written so far to their destination, for output that
+    /// is read while it is still being written.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called once after the last record.
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Sink over any `Write`: files, stdout, or a compressing writer.
+pub struct WriterSink<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> WriterSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> AdifSink for WriterSink<W> {
+    fn write_header(&mut self, header: &[u8]) -> io::Result<()> {
+        self.writer.write_all(header)
+    }
+
+    fn write_record(&mut self, _record: &Record, bytes: &[u8]) -> io::Result<()> {
+        self.writer.write_all(bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Sink writing a file that only appears once `finish` succeeds. If the run
+/// fails before that, the file is left as it was.
+pub struct FileSink {
+    file: AtomicFile,
+}
+
+impl FileSink {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self { file: AtomicFile::create(path)? })
+    }
+}
+
+impl AdifSink for FileSink {
+    fn write_header(&mut self, header: &[u8]) -> io::Result<()> {
+        self.file.write_all(header)
+    }
+
+    fn write_record(&mut self, _record: &Record, bytes: &[u8]) -> io::Result<()> {
+        self.file.write_all(bytes)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.file.commit()
+    }
+}
+
+/// Sink gzip-compressing the output into any `Write`, or into a file that,
+/// like `FileSink`'s, only appears once `finish` succeeds.
+pub struct GzSink<W: Write> {
+    encoder: GzEncoder<W>,
+    /// Called on the writer once the compressed stream is complete
+    complete: fn(&mut W) -> io::Result<()>,
+}
+
+impl GzSink<AtomicFile> {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self { encoder: GzEncoder::new(AtomicFile::create(path)?, flate2::Compression::default()), complete: AtomicFile::commit })
+    }
+}
+
+impl<W: Write> GzSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self { encoder: GzEncoder::new(writer, flate2::Compression::default()), complete: W::flush }
+    }
+}
+
+impl<W: Write> AdifSink for GzSink<W> {
+    fn write_header(&mut self, header: &[u8]) -> io::Result<()> {
+        self.encoder.write_all(header)
+    }
+
+    fn write_record(&mut self, _record: &Record, bytes: &[u8]) -> io::Result<()> {
+        self.encoder.write_all(bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.encoder.flush()
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.encoder.try_finish()?;
+        (self.complete)(self.encoder.get_mut())
+    }
+}
+
+/// Sink that distributes records over several files according to a key.
+///
+/// Each file gets its own copy of the header. Files are named after the base
+/// path with the key inserted before the extension: `log.adi` → `log-2024.adi`.
+/// With a maximum number of records per file, each key's records are spread
+/// over numbered files: `log-2024-001.adi`, `log-2024-002.adi`, or `log-001.adi`
+/// for an empty key. Numbered files appear as soon as they are full, all other
+/// files not until `finish`.
+///
+/// At most `MAX_OPEN_FILES` files are kept open; the least recently written
+/// one is closed to make room and reopened if its key comes up again, so
+/// splitting by call does not run out of file handles.
+pub struct SplitSink<F: Fn(&Record) -> String> {
+    base_path: PathBuf,
+    key_fn: F,
+    max_records: Option<usize>,
+    header: Vec<u8>,
+    writers: BTreeMap<String, AtomicFile>,
+    /// Keys of the files currently open, least recently written first
+    open: VecDeque<String>,
+    record_counts: BTreeMap<String, usize>,
+    paths: Vec<PathBuf>,
+}
+
+/// How many files `SplitSink` keeps open at once
+const MAX_OPEN_FILES: usize = 64;
+
+impl<F: Fn(&Record) -> String> SplitSink<F> {
+    pub fn new<P: AsRef<Path>>(base_path: P, key_fn: F) -> Self {
+        Self {
+            base_path: base_path.as_ref().to_path_buf(),
+            key_fn,
+            max_records: None,
+            header: Vec::new(),
+            writers: BTreeMap::new(),
+            open: VecDeque::new(),
+            record_counts: BTreeMap::new(),
+            paths: Vec::new(),
+        }
+    }
+
+    /// Starts a new numbered file after every `max_records` records of a key.
+    pub fn max_records_per_file(mut self, max_records: usize) -> Self {
+        self.max_records = Some(max_records.max(1));
+        self
+    }
+
+    /// Paths of the files created so far, in the order they were opened.
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    pub fn path_for_key(&self, key: &str) -> PathBuf {
+        let stem = self.base_path.file_stem().and_then(|s| s.to_str()).unwrap_or("output");
+        let key: String = key
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' { c } else { '_' })
+            .collect();
+        let file_name = match self.base_path.extension().and_then(|e| e.to_str()) {
+            Some(extension) => format!("{}-{}.{}", stem, key, extension),
+            None => format!("{}-{}", stem, key),
+        };
+        self.base_path.with_file_name(file_name)
+    }
+}
+
+impl<F: Fn(&Record) -> String> AdifSink for SplitSink<F> {
+    fn write_header(&mut self, header: &[u8]) -> io::Result<()> {
+        self.header = header.to_vec();
+        Ok(())
+    }
+
+    fn write_record(&mut self, record: &Record, bytes: &[u8]) -> io::Result<()> {
+        let mut key = (self.key_fn)(record);
+        let mut full = false;
+        if let Some(max_records) = self.max_records {
+            let count = self.record_counts.entry(key.clone()).or_default();
+            let part = *count / max_records + 1;
+            *count += 1;
+            full = count.is_multiple_of(max_records);
+            key = if key.is_empty() { format!("{:03}", part) } else { format!("{}-{:03}", key, part) };
+        }
+
+        if !self.writers.contains_key(&key) {
+            let path = self.path_for_key(&key);
+            let mut writer = AtomicFile::create(&path)?;
+            writer.write_all(&self.header)?;
+            self.writers.insert(key.clone(), writer);
+            self.paths.push(path);
+        }
+
+        if self.open.back() != Some(&key) {
+            if let Some(position) = self.open.iter().position(|open| *open == key) {
+                self.open.remove(position);
+            }
+            self.open.push_back(key.clone());
+            if self.open.len() > MAX_OPEN_FILES {
+                let oldest = self.open.pop_front().unwrap();
+                self.writers.get_mut(&oldest).unwrap().close()?;
+            }
+        }
+
+        self.writers.get_mut(&key).unwrap().write_all(bytes)?;
+
+        // A full numbered file won't get any more records
+        if full {
+            self.open.pop_back();
+            self.writers.remove(&key).unwrap().commit()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        for writer in self.writers.values_mut() {
+            writer.commit()?;
+        }
+        Ok(())
+    }
+}
+
+/// What `--split-by` groups records into files by
+#[derive(Debug, Clone, PartialEq)]
+pub enum SplitKey {
+    /// Year of QSO_DATE
+    Year,
+    Band,
+    Call,
+    /// Value of any field, written `field:NAME`
+    Field(String),
+}
+
+impl FromStr for SplitKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "year" => Ok(Self::Year),
+            "band" => Ok(Self::Band),
+            "call" => Ok(Self::Call),
+            _ => match s.split_once(':') {
+                Some((kind, name)) if kind.eq_ignore_ascii_case("field") && !name.trim().is_empty() => {
+                    Ok(Self::Field(name.trim().to_string()))
+                }
+                _ => Err(format!("{} is not year, band, call or field:NAME", s)),
+            },
+        }
+    }
+}
+
+impl SplitKey {
+    /// Part of the file name for a record, `unknown` if the record has no
+    /// value to group it by
+    pub fn key(&self, record: &Record) -> String {
+        let value = match self {
+            Self::Year => record.get("qso_date").map(|date| date.trim().chars().take(4).collect()),
+            Self::Band => record.get("band").map(|band| band.trim().to_lowercase()),
+            Self::Call => record.get("call").map(crate::callsign::normalize),
+            Self::Field(name) => record.get(name).map(|value| value.trim().to_string()),
+        };
+        value.filter(|value| !value.is_empty()).unwrap_or_else(|| "unknown".to_string())
+    }
+}
+
+/// Sink that forwards every record as a single UDP datagram, the way loggers
+/// exchange live QSOs. The header is not sent.
+pub struct UdpSink {
+    socket: UdpSocket,
+}
+
+impl UdpSink {
+    pub fn connect<A: ToSocketAddrs>(target: A) -> io::Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.connect(target)?;
+        Ok(Self { socket })
+    }
+}
+
+impl AdifSink for UdpSink {
+    fn write_header(&mut self, _header: &[u8]) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn write_record(&mut self, _record: &Record, bytes: &[u8]) -> io::Result<()> {
+        self.socket.send(bytes)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adif::AdifFile;
+
+    #[test]
+    fn test_split_sink_writes_header_to_each_file() {
+        let dir = std::env::temp_dir().join(format!("transadif-split-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let adif = AdifFile::parse(b"<call:4>K1AA<band:3>20m<eor><call:4>K1BB<band:3>40m<eor>").unwrap();
+        let mut sink = SplitSink::new(dir.join("log.adi"), |record: &Record| record.fields[1].data.clone());
+
+        sink.write_header(b"HDR<eoh>").unwrap();
+        for (record, bytes) in adif.records.iter().zip([b"one".as_slice(), b"two".as_slice()]) {
+            sink.write_record(record, bytes).unwrap();
+        }
+        sink.finish().unwrap();
+
+        assert_eq!(sink.paths().len(), 2);
+        assert_eq!(std::fs::read(dir.join("log-20m.adi")).unwrap(), b"HDR<eoh>one");
+        assert_eq!(std::fs::read(dir.join("log-40m.adi")).unwrap(), b"HDR<eoh>two");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_split_sink_max_records_per_file() {
+        let dir = std::env::temp_dir().join(format!("transadif-chunks-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let adif = AdifFile::parse(b"<call:4>K1AA<eor><call:4>K1BB<eor><call:4>K1CC<eor>").unwrap();
+        let mut sink = SplitSink::new(dir.join("log.adi"), |_: &Record| String::new()).max_records_per_file(2);
+
+        sink.write_header(b"HDR<eoh>").unwrap();
+        for record in &adif.records {
+            sink.write_record(record, record.get("call").unwrap().as_bytes()).unwrap();
+        }
+        assert_eq!(std::fs::read(dir.join("log-001.adi")).unwrap(), b"HDR<eoh>K1AAK1BB");
+        assert!(sink.writers.len() == 1 && sink.open.len() == 1);
+        sink.finish().unwrap();
+
+        assert_eq!(std::fs::read(dir.join("log-001.adi")).unwrap(), b"HDR<eoh>K1AAK1BB");
+        assert_eq!(std::fs::read(dir.join("log-002.adi")).unwrap(), b"HDR<eoh>K1CC");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_split_sink_limits_open_files() {
+        let dir = std::env::temp_dir().join(format!("transadif-split-open-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let adif = AdifFile::parse(b"<call:4>K1AA<eor>").unwrap();
+        let key = std::cell::Cell::new(0);
+        let mut sink = SplitSink::new(dir.join("log.adi"), |_: &Record| key.get().to_string());
+
+        sink.write_header(b"HDR<eoh>").unwrap();
+        for round in ["a", "b"] {
+            for index in 0..=MAX_OPEN_FILES {
+                key.set(index);
+                sink.write_record(&adif.records[0], round.as_bytes()).unwrap();
+                assert!(sink.open.len() <= MAX_OPEN_FILES);
+            }
+        }
+        sink.finish().unwrap();
+
+        assert_eq!(sink.paths().len(), MAX_OPEN_FILES + 1);
+        assert_eq!(std::fs::read(dir.join("log-0.adi")).unwrap(), b"HDR<eoh>ab");
+        assert_eq!(std::fs::read(dir.join(format!("log-{}.adi", MAX_OPEN_FILES))).unwrap(), b"HDR<eoh>ab");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_split_keys() {
+        let adif = AdifFile::parse(b"<call:8>dl1abc/p<qso_date:8>20231231<band:3>20M<eor><call:4>K1AB<eor>").unwrap();
+        let keys = |split_by: &str| {
+            let split_by: SplitKey = split_by.parse().unwrap();
+            adif.records.iter().map(|record| split_by.key(record)).collect::<Vec<_>>()
+        };
+
+        assert_eq!(keys("year"), vec!["2023", "unknown"]);
+        assert_eq!(keys("band"), vec!["20m", "unknown"]);
+        assert_eq!(keys("call"), vec!["DL1ABC/P", "K1AB"]);
+        assert_eq!(keys("field:CALL"), vec!["dl1abc/p", "K1AB"]);
+        assert!("mode".parse::<SplitKey>().is_err());
+    }
+
+    #[test]
+    fn test_udp_sink_sends_records() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let adif = AdifFile::parse(b"<call:4>K1AA<eor>").unwrap();
+
+        let mut sink = UdpSink::connect(receiver.local_addr().unwrap()).unwrap();
+        sink.write_header(b"ignored").unwrap();
+        sink.write_record(&adif.records[0], b"<call:4>K1AA<eor>").unwrap();
+
+        let mut buffer = [0u8; 64];
+        let received = receiver.recv(&mut buffer).unwrap();
+        assert_eq!(&buffer[..received], b"<call:4>K1AA<eor>");
+    }
+}
@@ -0,0 +1,144 @@
+use crate::adif::Record;
+use crate::validation::{field_type, DataType, BANDS};
+use std::cmp::Ordering;
+
+/// Sorts records by the values of `fields`, in order of priority. Values are
+/// compared by their ADIF data type: numbers numerically, times with or
+/// without seconds, bands by frequency, and anything else as text ignoring
+/// case. Records without a value sort after those with one, even when
+/// `descending`. The sort is stable.
+pub fn sort_records(records: &mut [Record], fields: &[String], descending: bool) {
+    let types: Vec<Option<DataType>> = fields.iter().map(|field| field_type(field)).collect();
+
+    records.sort_by(|a, b| {
+        for (field, data_type) in fields.iter().zip(&types) {
+            let ordering = match (a.get(field).map(str::trim), b.get(field).map(str::trim)) {
+                (Some(a), Some(b)) => {
+                    let ordering = compare_values(*data_type, a, b);
+                    if descending { ordering.reverse() } else { ordering }
+                }
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            };
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        Ordering::Equal
+    });
+}
+
+/// How to order the fields of each record
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum FieldOrder {
+    /// The fields that identify a QSO first, in `CANONICAL_FIELDS` order,
+    /// then the rest as they were
+    Canonical,
+    /// As they were in the input
+    Preserve,
+    /// By name, ignoring case
+    Alpha,
+}
+
+/// Fields `FieldOrder::Canonical` writes first, in the order loggers list them
+pub const CANONICAL_FIELDS: [&str; 11] =
+    ["call", "qso_date", "time_on", "qso_date_off", "time_off", "band", "freq", "mode", "submode", "rst_sent", "rst_rcvd"];
+
+/// Reorders the fields of `record`: those named in `priority` first, in that
+/// order, then the rest as `order` says. The separators after fields stay
+/// where they were, so the record keeps its layout.
+pub fn order_fields(record: &mut Record, order: FieldOrder, priority: &[String]) {
+    if order == FieldOrder::Preserve && priority.is_empty() {
+        return;
+    }
+
+    let rank = |name: &str| {
+        let priority = priority.iter().position(|field| field.eq_ignore_ascii_case(name)).unwrap_or(usize::MAX);
+        let canonical = match order {
+            FieldOrder::Canonical => CANONICAL_FIELDS.iter().position(|field| field.eq_ignore_ascii_case(name)),
+            _ => None,
+        };
+        (priority, canonical.unwrap_or(usize::MAX))
+    };
+    let separators: Vec<String> = record.fields.iter_mut().map(|field| std::mem::take(&mut field.excess_data)).collect();
+    record.fields.sort_by(|a, b| {
+        rank(&a.name).cmp(&rank(&b.name)).then_with(|| match order {
+            FieldOrder::Alpha => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+            _ => Ordering::Equal,
+        })
+    });
+    for (field, separator) in record.fields.iter_mut().zip(separators) {
+        field.excess_data = separator;
+    }
+}
+
+/// Compares two values of a field. Values that do not parse as their type
+/// sort after those that do.
+pub fn compare_values(data_type: Option<DataType>, a: &str, b: &str) -> Ordering {
+    let key: fn(&str) -> Option<f64> = match data_type {
+        Some(DataType::Number | DataType::Integer | DataType::PositiveInteger | DataType::Dxcc) => |value| value.parse().ok(),
+        Some(DataType::Time) => |value| format!("{:0<6}", value).parse().ok(),
+        Some(DataType::Band) => |value| {
+            BANDS.iter().find(|(band, _, _)| band.eq_ignore_ascii_case(value)).map(|(_, low, _)| *low)
+        },
+        _ => return a.to_lowercase().cmp(&b.to_lowercase()),
+    };
+
+    match (key(a), key(b)) {
+        (Some(a), Some(b)) => a.total_cmp(&b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => a.cmp(b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adif::AdifFile;
+
+    fn calls(records: &[Record]) -> Vec<&str> {
+        records.iter().map(|record| record.get("call").unwrap()).collect()
+    }
+
+    #[test]
+    fn test_sort_records() {
+        let adif = AdifFile::parse(
+            b"Log\r\n<eoh>\
+              <call:1>A<qso_date:8>20240302<time_on:4>0930<freq:6>14.074<band:3>20m<eor>\
+              <call:1>B<qso_date:8>20240301<time_on:6>235959<freq:5>7.074<band:3>40m<eor>\
+              <call:1>C<qso_date:8>20240302<time_on:6>093015<freq:6>144.174<band:2>2m<eor>\
+              <call:1>D<freq:6>10.136<band:3>30m<eor>",
+        )
+        .unwrap();
+
+        let sorted = |fields: &[&str], descending| {
+            let mut records = adif.records.clone();
+            let fields: Vec<String> = fields.iter().map(|f| f.to_string()).collect();
+            sort_records(&mut records, &fields, descending);
+            calls(&records).join("")
+        };
+
+        assert_eq!(sorted(&["qso_date", "time_on"], false), "BACD");
+        assert_eq!(sorted(&["qso_date", "time_on"], true), "CABD");
+        assert_eq!(sorted(&["freq"], false), "BDAC");
+        assert_eq!(sorted(&["band"], true), "CADB");
+    }
+
+    #[test]
+    fn test_order_fields() {
+        let adif = AdifFile::parse(b"<eoh><mode:3>FT8 <comment:2>hi <call:4>DL1X <band:3>20m <qso_date:8>20240301<eor>").unwrap();
+        let ordered = |order, priority: &[&str]| {
+            let mut record = adif.records[0].clone();
+            let priority: Vec<String> = priority.iter().map(|f| f.to_string()).collect();
+            order_fields(&mut record, order, &priority);
+            record.fields.iter().map(|field| format!("{}{}", field.name, field.excess_data)).collect::<String>()
+        };
+
+        assert_eq!(ordered(FieldOrder::Preserve, &[]), "mode comment call band qso_date");
+        assert_eq!(ordered(FieldOrder::Canonical, &[]), "call qso_date band mode comment");
+        assert_eq!(ordered(FieldOrder::Alpha, &[]), "band call comment mode qso_date");
+        assert_eq!(ordered(FieldOrder::Preserve, &["CALL", "COMMENT"]), "call comment mode band qso_date");
+    }
+}
@@ -0,0 +1,110 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// Totals over a log's QSOs, for a quick look at a file before and after
+/// converting it
+#[derive(Debug, Default, PartialEq)]
+pub struct Stats {
+    pub qsos: usize,
+    /// Earliest and latest valid QSO_DATE, as YYYYMMDD
+    pub dates: Option<(String, String)>,
+    /// QSOs per BAND, in lowercase
+    pub bands: BTreeMap<String, usize>,
+    /// QSOs per MODE, in uppercase
+    pub modes: BTreeMap<String, usize>,
+    pub calls: BTreeSet<String>,
+    /// Distinct DXCC entity codes
+    pub dxcc: BTreeSet<u32>,
+    /// Records holding each field, by lowercase name
+    pub fields: BTreeMap<String, usize>,
+}
+
+impl Stats {
+    /// Counts one record, given as field names and values.
+    pub fn add(&mut self, fields: &[(&str, String)]) {
+        self.qsos += 1;
+
+        let mut names = BTreeSet::new();
+        for (name, value) in fields {
+            let name = name.to_lowercase();
+            let value = value.trim();
+            if value.is_empty() {
+                continue;
+            }
+
+            match name.as_str() {
+                "qso_date" if value.len() == 8 && value.bytes().all(|b| b.is_ascii_digit()) => {
+                    self.dates = Some(match self.dates.take() {
+                        Some((first, last)) => (first.min(value.to_string()), last.max(value.to_string())),
+                        None => (value.to_string(), value.to_string()),
+                    });
+                }
+                "band" => *self.bands.entry(value.to_lowercase()).or_default() += 1,
+                "mode" => *self.modes.entry(value.to_uppercase()).or_default() += 1,
+                "call" => {
+                    self.calls.insert(value.to_uppercase());
+                }
+                "dxcc" => self.dxcc.extend(value.parse::<u32>().ok()),
+                _ => {}
+            }
+            names.insert(name);
+        }
+
+        for name in names {
+            *self.fields.entry(name).or_default() += 1;
+        }
+    }
+}
+
+/// `20m 120, 40m 35`, most QSOs first
+fn breakdown(counts: &BTreeMap<String, usize>) -> String {
+    if counts.is_empty() {
+        return "none".to_string();
+    }
+
+    let mut counts: Vec<(&String, &usize)> = counts.iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+    counts.iter().map(|(name, count)| format!("{} {}", name, count)).collect::<Vec<_>>().join(", ")
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "QSOs: {}", self.qsos)?;
+        match &self.dates {
+            Some((first, last)) => writeln!(f, "Dates: {} to {}", first, last)?,
+            None => writeln!(f, "Dates: none")?,
+        }
+        writeln!(f, "Bands: {}", breakdown(&self.bands))?;
+        writeln!(f, "Modes: {}", breakdown(&self.modes))?;
+        writeln!(f, "Unique callsigns: {}", self.calls.len())?;
+        writeln!(f, "DXCC entities: {}", self.dxcc.len())?;
+
+        let fields: Vec<String> = self.fields.iter().map(|(name, count)| format!("{} {}", name.to_uppercase(), count)).collect();
+        writeln!(f, "Fields: {}", if fields.is_empty() { "none".to_string() } else { fields.join(", ") })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats() {
+        let mut stats = Stats::default();
+        let record = |fields: &[(&'static str, &str)]| fields.iter().map(|(name, value)| (*name, value.to_string())).collect::<Vec<_>>();
+        stats.add(&record(&[("CALL", "w1aw"), ("BAND", "20M"), ("MODE", "ft8"), ("QSO_DATE", "20240105"), ("DXCC", "291")]));
+        stats.add(&record(&[("call", "W1AW"), ("band", "40m"), ("mode", "FT8"), ("qso_date", "20231231")]));
+        stats.add(&record(&[("call", "DL1ABC"), ("band", "20m"), ("mode", "CW"), ("qso_date", "2024"), ("dxcc", "0230"), ("name", " ")]));
+
+        assert_eq!(
+            stats.to_string(),
+            "QSOs: 3\n\
+             Dates: 20231231 to 20240105\n\
+             Bands: 20m 2, 40m 1\n\
+             Modes: FT8 2, CW 1\n\
+             Unique callsigns: 2\n\
+             DXCC entities: 2\n\
+             Fields: BAND 3, CALL 3, DXCC 2, MODE 3, QSO_DATE 3\n"
+        );
+    }
+}
@@ -0,0 +1,157 @@
+use crate::adif::{AdifError, AdifFile, StreamingParser};
+use crate::encoding::AdifEncoding;
+use crate::output::{OutputError, OutputFormatter, OutputStreamer};
+use crate::sink::WriterSink;
+use std::io::{self, Read, Write};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum TranscodeError {
+    #[error("{0}")]
+    Parse(#[from] AdifError),
+    #[error("{0}")]
+    Output(#[from] OutputError),
+}
+
+impl From<io::Error> for TranscodeError {
+    fn from(error: io::Error) -> Self {
+        Self::Output(OutputError::Io(error))
+    }
+}
+
+/// Conversion settings for `AdifTranscoder`, the library counterpart of the
+/// main command line options.
+#[derive(Debug, Clone)]
+pub struct TranscodeOptions {
+    /// Encoding of the input; `None` uses the header's ENCODING, else detects per field
+    pub input_encoding: Option<AdifEncoding>,
+    pub output_encoding: AdifEncoding,
+    /// Do not correct invalid characters or field counts
+    pub strict: bool,
+    /// Character for what the output encoding cannot represent; `None` deletes it
+    pub replacement_char: Option<char>,
+    /// Transliterate to characters without diacritics
+    pub ascii: bool,
+}
+
+impl Default for TranscodeOptions {
+    fn default() -> Self {
+        Self {
+            input_encoding: None,
+            output_encoding: AdifEncoding::Utf8,
+            strict: false,
+            replacement_char: Some('?'),
+            ascii: false,
+        }
+    }
+}
+
+/// Converts ADIF from any `Read` to any `Write`, record by record, for use in
+/// byte pipelines such as HTTP handlers or archive extractors.
+pub struct AdifTranscoder {
+    options: TranscodeOptions,
+}
+
+impl AdifTranscoder {
+    pub fn new(options: TranscodeOptions) -> Self {
+        Self { options }
+    }
+
+    /// Writes the converted input to `output` and returns the number of records.
+    pub fn transcode<R: Read, W: Write>(&self, input: R, output: W) -> Result<usize, TranscodeError> {
+        let mut parser = StreamingParser::new(input)?;
+        parser.set_strict(self.options.strict);
+        let formatter = self.formatter_for(parser.header());
+
+        let mut sink = WriterSink::new(output);
+        let mut streamer = OutputStreamer::new(&formatter, &mut sink, parser.header())?;
+        let mut records = 0;
+        for record in parser {
+            streamer.write_record(&record?)?;
+            records += 1;
+        }
+        streamer.finish()?;
+
+        Ok(records)
+    }
+
+    /// Wraps `input` in a reader producing the converted bytes as they are
+    /// read. The header is parsed right away; errors after that surface as
+    /// `io::ErrorKind::InvalidData`.
+    pub fn reader<R: Read>(&self, input: R) -> Result<TranscodingReader<R>, TranscodeError> {
+        let mut parser = StreamingParser::new(input)?;
+        parser.set_strict(self.options.strict);
+        let formatter = self.formatter_for(parser.header());
+        let buffer = formatter.format_header(parser.header())?;
+
+        Ok(TranscodingReader { parser, formatter, buffer, position: 0 })
+    }
+
+    fn formatter_for(&self, header: &AdifFile) -> OutputFormatter {
+        let options = &self.options;
+        // The parser converts UTF-16 input to UTF-8, which the header then names
+        let input_encoding = options
+            .input_encoding
+            .clone()
+            .filter(|encoding| !encoding.is_utf16())
+            .or_else(|| header.declared_encoding());
+
+        OutputFormatter::new(
+            input_encoding,
+            options.output_encoding.clone(),
+            options.strict,
+            options.replacement_char,
+            options.replacement_char.is_none(),
+            options.ascii,
+        )
+    }
+}
+
+/// `Read` adapter returned by `AdifTranscoder::reader`
+pub struct TranscodingReader<R: Read> {
+    parser: StreamingParser<R>,
+    formatter: OutputFormatter,
+    /// Converted bytes not read yet, from `position` on
+    buffer: Vec<u8>,
+    position: usize,
+}
+
+impl<R: Read> Read for TranscodingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.buffer.len() {
+            let Some(record) = self.parser.next() else {
+                return Ok(0);
+            };
+            let invalid = |error: String| io::Error::new(io::ErrorKind::InvalidData, error);
+            let record = record.map_err(|e| invalid(e.to_string()))?;
+
+            self.buffer = self.formatter.format_record(&record).map_err(|e| invalid(e.to_string()))?;
+            self.position = 0;
+        }
+
+        let length = buf.len().min(self.buffer.len() - self.position);
+        buf[..length].copy_from_slice(&self.buffer[self.position..self.position + length]);
+        self.position += length;
+        Ok(length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transcoder() {
+        let input: &[u8] = b"Log\r\n<encoding:10>ISO-8859-1<eoh>\r\n<call:4>DL1A<name:4>J\xfcrg<eor>\r\n";
+        let options = TranscodeOptions::default();
+
+        let mut output = Vec::new();
+        let records = AdifTranscoder::new(options.clone()).transcode(input, &mut output).unwrap();
+        assert_eq!(records, 1);
+        assert_eq!(String::from_utf8(output.clone()).unwrap(), "Log\r\n<encoding:5>UTF-8\r\n<eoh>\r\n<call:4>DL1A<name:4>Jürg<eor>\r\n");
+
+        let mut read = Vec::new();
+        AdifTranscoder::new(options).reader(input).unwrap().read_to_end(&mut read).unwrap();
+        assert_eq!(read, output);
+    }
+}
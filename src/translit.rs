@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use thiserror::Error;
+use unicode_normalization::UnicodeNormalization;
+
+#[derive(Error, Debug)]
+pub enum TranslitError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Line {0}: expected `character=replacement`")]
+    InvalidLine(usize),
+}
+
+/// Letters without a decomposition to a base letter plus diacritics
+const LATIN: &[(char, &str)] = &[
+    ('æ', "ae"), ('ð', "d"), ('ø', "o"), ('þ', "th"), ('ß', "ss"), ('ł', "l"), ('đ', "d"),
+    ('œ', "oe"), ('ı', "i"), ('ħ', "h"), ('ŋ', "ng"), ('ĸ', "q"), ('ŀ', "l"), ('ſ', "s"), ('ŧ', "t"),
+];
+
+/// Russian, Ukrainian, Belarusian, Serbian and Macedonian letters, after BGN/PCGN
+const CYRILLIC: &[(char, &str)] = &[
+    ('а', "a"), ('б', "b"), ('в', "v"), ('г', "g"), ('д', "d"), ('е', "e"), ('ё', "yo"), ('ж', "zh"),
+    ('з', "z"), ('и', "i"), ('й', "y"), ('к', "k"), ('л', "l"), ('м', "m"), ('н', "n"), ('о', "o"),
+    ('п', "p"), ('р', "r"), ('с', "s"), ('т', "t"), ('у', "u"), ('ф', "f"), ('х', "kh"), ('ц', "ts"),
+    ('ч', "ch"), ('ш', "sh"), ('щ', "shch"), ('ъ', ""), ('ы', "y"), ('ь', ""), ('э', "e"), ('ю', "yu"),
+    ('я', "ya"), ('є', "ye"), ('і', "i"), ('ї', "yi"), ('ґ', "g"), ('ў', "u"), ('ђ', "dj"), ('ј', "j"),
+    ('љ', "lj"), ('њ', "nj"), ('ћ', "c"), ('џ', "dz"), ('ѓ', "gj"), ('ќ', "kj"), ('ѕ', "dz"),
+];
+
+/// Greek letters, after ELOT 743
+const GREEK: &[(char, &str)] = &[
+    ('α', "a"), ('β', "v"), ('γ', "g"), ('δ', "d"), ('ε', "e"), ('ζ', "z"), ('η', "i"), ('θ', "th"),
+    ('ι', "i"), ('κ', "k"), ('λ', "l"), ('μ', "m"), ('ν', "n"), ('ξ', "x"), ('ο', "o"), ('π', "p"),
+    ('ρ', "r"), ('σ', "s"), ('ς', "s"), ('τ', "t"), ('υ', "y"), ('φ', "f"), ('χ', "ch"), ('ψ', "ps"),
+    ('ω', "o"),
+];
+
+/// Typographic punctuation with a plain ASCII stand-in
+const PUNCTUATION: &[(char, &str)] = &[
+    ('\u{00A0}', " "), ('‘', "'"), ('’', "'"), ('‚', "'"), ('“', "\""), ('”', "\""), ('„', "\""),
+    ('«', "\""), ('»', "\""), ('–', "-"), ('—', "-"), ('…', "..."), ('•', "*"), ('·', "."), ('×', "x"),
+];
+
+/// Character replacements used by `--ascii`. Characters that are not in the
+/// table lose their diacritics; whatever is still not ASCII after that gets
+/// the fallback character.
+#[derive(Debug, Clone)]
+pub struct TranslitTable {
+    replacements: HashMap<char, String>,
+}
+
+impl TranslitTable {
+    /// Latin letters without decompositions, Cyrillic, Greek and typographic
+    /// punctuation. Built with the `cjk` feature, Han characters, kana and
+    /// Hangul are romanized too.
+    pub fn builtin() -> Self {
+        let mut replacements = HashMap::new();
+
+        for (c, replacement) in LATIN.iter().chain(CYRILLIC).chain(GREEK) {
+            replacements.insert(*c, replacement.to_string());
+            for upper in c.to_uppercase().filter(|upper| upper != c) {
+                replacements.insert(upper, capitalize(replacement));
+            }
+        }
+        for (c, replacement) in PUNCTUATION {
+            replacements.insert(*c, replacement.to_string());
+        }
+
+        Self { replacements }
+    }
+
+    /// Reads a table file: one `character=replacement` per line, with blank
+    /// lines and lines starting with `#` ignored. The replacement may be empty.
+    pub fn load(path: &Path) -> Result<Self, TranslitError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, TranslitError> {
+        let mut replacements = HashMap::new();
+
+        for (index, line) in text.lines().enumerate() {
+            if line.trim().is_empty() || line.trim_start().starts_with('#') {
+                continue;
+            }
+
+            let mut chars = line.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), Some('=')) => {
+                    replacements.insert(c, chars.as_str().to_string());
+                }
+                _ => return Err(TranslitError::InvalidLine(index + 1)),
+            }
+        }
+
+        Ok(Self { replacements })
+    }
+
+    /// Adds the entries of `other`, replacing existing ones.
+    pub fn extend(&mut self, other: TranslitTable) {
+        self.replacements.extend(other.replacements);
+    }
+
+    pub fn transliterate(&self, text: &str, fallback: char) -> String {
+        let mut result = String::with_capacity(text.len());
+
+        for c in text.chars() {
+            if c.is_ascii() {
+                result.push(c);
+            } else if let Some(replacement) = self.replacements.get(&c) {
+                result.push_str(replacement);
+            } else if let Some(romanized) = romanize_cjk(c) {
+                // Han syllables come with a trailing space to separate them
+                if result.ends_with(|last: char| last.is_ascii_alphanumeric()) && romanized.ends_with(' ') {
+                    result.push(' ');
+                }
+                result.push_str(romanized.trim_end());
+            } else {
+                // Strip diacritics, then look up what is left
+                for base in c.nfd().filter(|c| !unicode_normalization::char::is_combining_mark(*c)) {
+                    match self.replacements.get(&base) {
+                        Some(replacement) => result.push_str(replacement),
+                        None if base.is_ascii() => result.push(base),
+                        None => result.push(fallback),
+                    }
+                }
+            }
+        }
+
+        result
+    }
+}
+
+impl Default for TranslitTable {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(feature = "cjk")]
+fn romanize_cjk(c: char) -> Option<&'static str> {
+    let cjk = matches!(c as u32,
+        0x3040..=0x30FF // Hiragana, Katakana
+        | 0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xF900..=0xFAFF // Han
+        | 0x1100..=0x11FF | 0x3130..=0x318F | 0xAC00..=0xD7AF // Hangul
+    );
+    if cjk {
+        deunicode::deunicode_char(c)
+    } else {
+        None
+    }
+}
+
+#[cfg(not(feature = "cjk"))]
+fn romanize_cjk(_c: char) -> Option<&'static str> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_table() {
+        let table = TranslitTable::builtin();
+
+        assert_eq!(table.transliterate("José Müller", '?'), "Jose Muller");
+        assert_eq!(table.transliterate("Søren Æbelø, Łódź", '?'), "Soren Aebelo, Lodz");
+        assert_eq!(table.transliterate("Юрий Щербаков, Львів", '?'), "Yuriy Shcherbakov, Lviv");
+        assert_eq!(table.transliterate("Νίκος Παπαδάκης", '?'), "Nikos Papadakis");
+        assert_eq!(table.transliterate("“73” – tnx…", '?'), "\"73\" - tnx...");
+        assert_eq!(table.transliterate("ᚠ", '?'), "?");
+    }
+
+    #[test]
+    fn test_table_file_overrides() {
+        let mut table = TranslitTable::builtin();
+        table.extend(TranslitTable::parse("# German conventions\nü=ue\nÜ=Ue\n\nß=ss\n").unwrap());
+
+        assert_eq!(table.transliterate("Müller, Überlingen", '?'), "Mueller, Ueberlingen");
+        assert!(matches!(TranslitTable::parse("ü->ue"), Err(TranslitError::InvalidLine(1))));
+    }
+
+    #[cfg(feature = "cjk")]
+    #[test]
+    fn test_cjk_romanization() {
+        let table = TranslitTable::builtin();
+
+        assert_eq!(table.transliterate("ひらがな", '?'), "hiragana");
+        assert_eq!(table.transliterate("東京 QTH", '?'), "Dong Jing QTH");
+    }
+}
@@ -0,0 +1,264 @@
+use crate::adif::AdifFile;
+use crate::color::Palette;
+use crate::encoding::{encoding_override, EncodingProcessor};
+use crate::output::DebugFormatter;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use std::io;
+
+const PAGE_SIZE: usize = 10;
+
+/// Read-only browser for the records of a parsed file.
+///
+/// The left pane lists records by callsign and date, the right pane shows the
+/// same per-field decoding details as `--debug` for the selected record.
+pub struct Viewer<'a> {
+    adif: &'a AdifFile,
+    processor: &'a EncodingProcessor,
+    list_state: ListState,
+    detail_scroll: u16,
+    search: Option<String>,
+    last_search: String,
+    status: String,
+}
+
+impl<'a> Viewer<'a> {
+    pub fn new(adif: &'a AdifFile, processor: &'a EncodingProcessor) -> Self {
+        let mut list_state = ListState::default();
+        if !adif.records.is_empty() {
+            list_state.select(Some(0));
+        }
+
+        Self {
+            adif,
+            processor,
+            list_state,
+            detail_scroll: 0,
+            search: None,
+            last_search: String::new(),
+            status: "j/k move  PgUp/PgDn page  J/K scroll details  / search call  n next  q quit".to_string(),
+        }
+    }
+
+    pub fn run(mut self) -> io::Result<()> {
+        let mut terminal = ratatui::init();
+        let result = self.event_loop(&mut terminal);
+        ratatui::restore();
+        result
+    }
+
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+
+            let Event::Key(key) = event::read()? else { continue };
+            if key.kind == KeyEventKind::Press && !self.handle_key(key.code) {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Acts on a key press; `false` when it quits the viewer.
+    fn handle_key(&mut self, code: KeyCode) -> bool {
+        if let Some(ref mut query) = self.search {
+            match code {
+                KeyCode::Enter => {
+                    self.last_search = std::mem::take(query);
+                    self.search = None;
+                    self.find_next(true);
+                }
+                KeyCode::Esc => self.search = None,
+                KeyCode::Backspace => {
+                    query.pop();
+                }
+                KeyCode::Char(c) => query.push(c),
+                _ => {}
+            }
+            return true;
+        }
+
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Down | KeyCode::Char('j') => self.move_by(1),
+            KeyCode::Up | KeyCode::Char('k') => self.move_by(-1),
+            KeyCode::PageDown => self.move_by(PAGE_SIZE as isize),
+            KeyCode::PageUp => self.move_by(-(PAGE_SIZE as isize)),
+            KeyCode::Home => self.select(0),
+            KeyCode::End => self.select(self.adif.records.len().saturating_sub(1)),
+            KeyCode::Char('J') => self.detail_scroll = self.detail_scroll.saturating_add(1),
+            KeyCode::Char('K') => self.detail_scroll = self.detail_scroll.saturating_sub(1),
+            KeyCode::Char('/') => self.search = Some(String::new()),
+            KeyCode::Char('n') => self.find_next(false),
+            _ => {}
+        }
+        true
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, status] = Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+        let [list_area, detail_area] =
+            Layout::horizontal([Constraint::Percentage(30), Constraint::Percentage(70)]).areas(main);
+
+        let items: Vec<ListItem> = (0..self.adif.records.len()).map(|index| ListItem::new(self.list_label(index))).collect();
+        let list = List::new(items)
+            .block(Block::default().borders(Borders::ALL).title(format!(" {} QSOs ", self.adif.records.len())))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, list_area, &mut self.list_state);
+
+        let (title, lines) = match self.list_state.selected().and_then(|i| self.adif.records.get(i).map(|r| (i, r))) {
+            Some((index, record)) => (
+                format!(" QSO {} ", index + 1),
+                DebugFormatter::record_lines(record, self.processor, false, &Palette::default()).into_iter().map(Line::from).collect(),
+            ),
+            None => (" No records ".to_string(), Vec::new()),
+        };
+        let details = Paragraph::new(lines)
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .wrap(Wrap { trim: false })
+            .scroll((self.detail_scroll, 0));
+        frame.render_widget(details, detail_area);
+
+        let status_text = match self.search {
+            Some(ref query) => format!("Search call: {}", query),
+            None => self.status.clone(),
+        };
+        frame.render_widget(Paragraph::new(status_text), status);
+    }
+
+    /// The list pane's line for the record at `index`.
+    fn list_label(&self, index: usize) -> String {
+        format!(
+            "{:>5} {:<12} {}",
+            index + 1,
+            self.value(index, "call").as_deref().unwrap_or("-"),
+            self.value(index, "qso_date").unwrap_or_default(),
+        )
+    }
+
+    /// A value of the record at `index` as a conversion would write it:
+    /// decoded, where the caller has not, and corrected.
+    fn value(&self, index: usize, name: &str) -> Option<String> {
+        let record = &self.adif.records[index];
+        let field = record.fields.iter().find(|field| field.name.eq_ignore_ascii_case(name))?;
+        if field.decoded {
+            return Some(self.processor.correct(&field.data));
+        }
+
+        let decoded = self.processor
+            .decode_field_data(&field.original_bytes, encoding_override(record, &field.name).as_ref())
+            .unwrap_or_else(|_| String::from_utf8_lossy(&field.original_bytes).into_owned());
+        Some(self.processor.correct(&decoded))
+    }
+
+    fn move_by(&mut self, delta: isize) {
+        let current = self.list_state.selected().unwrap_or(0) as isize;
+        let last = self.adif.records.len().saturating_sub(1) as isize;
+        self.select((current + delta).clamp(0, last.max(0)) as usize);
+    }
+
+    fn select(&mut self, index: usize) {
+        if !self.adif.records.is_empty() {
+            self.list_state.select(Some(index));
+            self.detail_scroll = 0;
+        }
+    }
+
+    /// Selects the next record whose callsign contains the last search text.
+    fn find_next(&mut self, include_current: bool) {
+        if self.last_search.is_empty() || self.adif.records.is_empty() {
+            return;
+        }
+
+        let query = self.last_search.to_uppercase();
+        let count = self.adif.records.len();
+        let start = self.list_state.selected().unwrap_or(0) + usize::from(!include_current);
+
+        let found = (0..count)
+            .map(|offset| (start + offset) % count)
+            .find(|&i| self.value(i, "call").is_some_and(|call| call.to_uppercase().contains(&query)));
+
+        match found {
+            Some(index) => {
+                self.select(index);
+                self.status = format!("Found {} at QSO {}", self.last_search, index + 1);
+            }
+            None => self.status = format!("No callsign matches {}", self.last_search),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::AdifEncoding;
+
+    fn press(viewer: &mut Viewer, keys: &str) {
+        for c in keys.chars() {
+            viewer.handle_key(KeyCode::Char(c));
+        }
+    }
+
+    #[test]
+    fn test_navigation_and_search() {
+        let adif = AdifFile::parse(b"<call:4>K1AA<eor><call:4>W1AW<eor><call:5>DL\xe41A<qso_date:8>20240301<eor><call:4>K1BB<eor>").unwrap();
+        let processor = EncodingProcessor::new(Some(AdifEncoding::Iso88591), AdifEncoding::Utf8, false);
+        let mut viewer = Viewer::new(&adif, &processor);
+        let selected = |viewer: &Viewer| viewer.list_state.selected();
+
+        // Values are shown decoded, even when the caller has not decoded the file
+        assert_eq!(viewer.list_label(2), "    3 DLä1A        20240301");
+        assert_eq!(viewer.list_label(0), "    1 K1AA         ");
+
+        press(&mut viewer, "jjJJ");
+        assert_eq!((selected(&viewer), viewer.detail_scroll), (Some(2), 2));
+        viewer.handle_key(KeyCode::PageDown);
+        assert_eq!((selected(&viewer), viewer.detail_scroll), (Some(3), 0));
+        viewer.handle_key(KeyCode::PageUp);
+        assert_eq!(selected(&viewer), Some(0));
+        press(&mut viewer, "k");
+        assert_eq!(selected(&viewer), Some(0));
+        viewer.handle_key(KeyCode::End);
+        assert_eq!(selected(&viewer), Some(3));
+
+        // Searching wraps around and matches the decoded callsign
+        press(&mut viewer, "/dlä");
+        assert_eq!(viewer.search.as_deref(), Some("dlä"));
+        assert_eq!(selected(&viewer), Some(3));
+        viewer.handle_key(KeyCode::Enter);
+        assert_eq!((selected(&viewer), viewer.status.as_str()), (Some(2), "Found dlä at QSO 3"));
+
+        press(&mut viewer, "/k1");
+        viewer.handle_key(KeyCode::Enter);
+        press(&mut viewer, "n");
+        assert_eq!(selected(&viewer), Some(0));
+        press(&mut viewer, "n");
+        assert_eq!(selected(&viewer), Some(3));
+
+        press(&mut viewer, "/zz");
+        viewer.handle_key(KeyCode::Backspace);
+        viewer.handle_key(KeyCode::Enter);
+        assert_eq!((selected(&viewer), viewer.status.as_str()), (Some(3), "No callsign matches z"));
+
+        // Esc leaves the search first, and then the viewer
+        press(&mut viewer, "/");
+        assert!(viewer.handle_key(KeyCode::Esc));
+        assert!(viewer.search.is_none());
+        assert!(!viewer.handle_key(KeyCode::Char('q')));
+    }
+
+    #[test]
+    fn test_empty_file() {
+        let adif = AdifFile::parse(b"Log<eoh>").unwrap();
+        let processor = EncodingProcessor::new(None, AdifEncoding::Utf8, false);
+        let mut viewer = Viewer::new(&adif, &processor);
+
+        press(&mut viewer, "jn/x");
+        viewer.handle_key(KeyCode::Enter);
+        viewer.handle_key(KeyCode::End);
+        assert_eq!(viewer.list_state.selected(), None);
+    }
+}
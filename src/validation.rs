@@ -0,0 +1,544 @@
+use crate::adif::{AdifFile, Field, Record};
+use crate::grid;
+use crate::qslmsg;
+use crate::reference::{self, Program};
+use std::fmt;
+
+/// ADIF data types, as far as they can be checked without external lists
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DataType {
+    /// Free text (String, MultilineString and their _INTL variants)
+    Text,
+    Boolean,
+    Number,
+    Integer,
+    PositiveInteger,
+    /// YYYYMMDD
+    Date,
+    /// HHMM or HHMMSS
+    Time,
+    /// 2, 4, 6 or 8 character Maidenhead locator
+    GridSquare,
+    /// Comma-separated grid squares
+    GridSquareList,
+    /// XDDD MM.MMM
+    Location,
+    Band,
+    Mode,
+    /// DXCC entity code
+    Dxcc,
+    /// Reference of a portable operation award program
+    Reference(Program),
+    Enumeration(&'static [&'static str]),
+}
+
+const QSL_RCVD: &[&str] = &["Y", "N", "R", "I", "V"];
+const QSL_SENT: &[&str] = &["Y", "N", "R", "Q", "I"];
+const QSL_VIA: &[&str] = &["B", "D", "E", "M"];
+const UPLOAD_STATUS: &[&str] = &["Y", "N", "M"];
+const CONTINENTS: &[&str] = &["NA", "SA", "EU", "AF", "OC", "AS", "AN"];
+const ANT_PATHS: &[&str] = &["G", "O", "S", "L"];
+const QSO_COMPLETE: &[&str] = &["Y", "N", "NIL", "?"];
+const PROPAGATION_MODES: &[&str] = &[
+    "AS", "AUE", "AUR", "BS", "ECH", "EME", "ES", "F2", "FAI", "GWAVE", "INTERNET", "ION", "IRL",
+    "LOS", "MS", "RPT", "RS", "SAT", "TEP", "TR",
+];
+const MORSE_KEY_TYPES: &[&str] = &["SK", "SS", "BUG", "FAB", "SP", "DP", "CPU"];
+
+/// Bands with their frequency limits in MHz
+pub const BANDS: &[(&str, f64, f64)] = &[
+    ("2190m", 0.1357, 0.1378),
+    ("630m", 0.472, 0.479),
+    ("560m", 0.501, 0.504),
+    ("160m", 1.8, 2.0),
+    ("80m", 3.5, 4.0),
+    ("60m", 5.06, 5.45),
+    ("40m", 7.0, 7.3),
+    ("30m", 10.1, 10.15),
+    ("20m", 14.0, 14.35),
+    ("17m", 18.068, 18.168),
+    ("15m", 21.0, 21.45),
+    ("12m", 24.890, 24.99),
+    ("10m", 28.0, 29.7),
+    ("8m", 40.0, 45.0),
+    ("6m", 50.0, 54.0),
+    ("5m", 54.000001, 69.9),
+    ("4m", 70.0, 71.0),
+    ("2m", 144.0, 148.0),
+    ("1.25m", 222.0, 225.0),
+    ("70cm", 420.0, 450.0),
+    ("33cm", 902.0, 928.0),
+    ("23cm", 1240.0, 1300.0),
+    ("13cm", 2300.0, 2450.0),
+    ("9cm", 3300.0, 3500.0),
+    ("6cm", 5650.0, 5925.0),
+    ("3cm", 10000.0, 10500.0),
+    ("1.25cm", 24000.0, 24250.0),
+    ("6mm", 47000.0, 47200.0),
+    ("4mm", 75500.0, 81000.0),
+    ("2.5mm", 119980.0, 123000.0),
+    ("2mm", 134000.0, 149000.0),
+    ("1mm", 241000.0, 250000.0),
+    ("submm", 300000.0, 7500000.0),
+];
+
+const MODES: &[&str] = &[
+    "AM", "ARDOP", "ATV", "C4FM", "CHIP", "CLO", "CONTESTI", "CW", "DIGITALVOICE", "DOMINO", "DYNAMIC",
+    "FAX", "FM", "FSK441", "FT8", "HELL", "ISCAT", "JT4", "JT6M", "JT9", "JT44", "JT65", "MFSK",
+    "MSK144", "MT63", "OLIVIA", "OPERA", "PAC", "PAX", "PKT", "PSK", "PSK2K", "Q15", "QRA64", "ROS",
+    "RTTY", "RTTYM", "SSB", "SSTV", "T10", "THOR", "THRB", "TOR", "V4", "VOI", "WINMOR", "WSPR",
+];
+
+/// Submodes commonly found in MODE, with the mode they belong to
+const SUBMODES: &[(&str, &str)] = &[
+    ("USB", "SSB"),
+    ("LSB", "SSB"),
+    ("FT4", "MFSK"),
+    ("FST4", "MFSK"),
+    ("JS8", "MFSK"),
+    ("Q65", "MFSK"),
+    ("PSK31", "PSK"),
+    ("PSK63", "PSK"),
+    ("PSK125", "PSK"),
+    ("BPSK31", "PSK"),
+    ("OLIVIA 8/500", "OLIVIA"),
+    ("DSTAR", "DIGITALVOICE"),
+    ("FREEDV", "DIGITALVOICE"),
+];
+
+/// Highest DXCC entity code assigned as of ADIF 3.1.4
+const MAX_DXCC: u32 = 522;
+
+/// QSO fields of ADIF 3.1.4 and their data types
+const FIELDS: &[(&str, DataType)] = &[
+    ("address", DataType::Text),
+    ("address_intl", DataType::Text),
+    ("age", DataType::Number),
+    ("altitude", DataType::Number),
+    ("ant_az", DataType::Number),
+    ("ant_el", DataType::Number),
+    ("ant_path", DataType::Enumeration(ANT_PATHS)),
+    ("arrl_sect", DataType::Text),
+    ("award_submitted", DataType::Text),
+    ("award_granted", DataType::Text),
+    ("a_index", DataType::Number),
+    ("band", DataType::Band),
+    ("band_rx", DataType::Band),
+    ("call", DataType::Text),
+    ("check", DataType::Text),
+    ("class", DataType::Text),
+    ("clublog_qso_upload_date", DataType::Date),
+    ("clublog_qso_upload_status", DataType::Enumeration(UPLOAD_STATUS)),
+    ("cnty", DataType::Text),
+    ("comment", DataType::Text),
+    ("comment_intl", DataType::Text),
+    ("cont", DataType::Enumeration(CONTINENTS)),
+    ("contacted_op", DataType::Text),
+    ("contest_id", DataType::Text),
+    ("country", DataType::Text),
+    ("country_intl", DataType::Text),
+    ("cqz", DataType::PositiveInteger),
+    ("credit_submitted", DataType::Text),
+    ("credit_granted", DataType::Text),
+    ("darc_dok", DataType::Text),
+    ("dcl_qslrdate", DataType::Date),
+    ("dcl_qslsdate", DataType::Date),
+    ("dcl_qsl_rcvd", DataType::Enumeration(QSL_RCVD)),
+    ("dcl_qsl_sent", DataType::Enumeration(QSL_SENT)),
+    ("distance", DataType::Number),
+    ("dxcc", DataType::Dxcc),
+    ("email", DataType::Text),
+    ("eq_call", DataType::Text),
+    ("eqsl_qslrdate", DataType::Date),
+    ("eqsl_qslsdate", DataType::Date),
+    ("eqsl_qsl_rcvd", DataType::Enumeration(QSL_RCVD)),
+    ("eqsl_qsl_sent", DataType::Enumeration(QSL_SENT)),
+    ("fists", DataType::PositiveInteger),
+    ("fists_cc", DataType::PositiveInteger),
+    ("force_init", DataType::Boolean),
+    ("freq", DataType::Number),
+    ("freq_rx", DataType::Number),
+    ("gridsquare", DataType::GridSquare),
+    ("gridsquare_ext", DataType::Text),
+    ("guest_op", DataType::Text),
+    ("hamlogeu_qso_upload_date", DataType::Date),
+    ("hamlogeu_qso_upload_status", DataType::Enumeration(UPLOAD_STATUS)),
+    ("hamqth_qso_upload_date", DataType::Date),
+    ("hamqth_qso_upload_status", DataType::Enumeration(UPLOAD_STATUS)),
+    ("hrdlog_qso_upload_date", DataType::Date),
+    ("hrdlog_qso_upload_status", DataType::Enumeration(UPLOAD_STATUS)),
+    ("iota", DataType::Text),
+    ("iota_island_id", DataType::PositiveInteger),
+    ("ituz", DataType::PositiveInteger),
+    ("k_index", DataType::Integer),
+    ("lat", DataType::Location),
+    ("lon", DataType::Location),
+    ("lotw_qslrdate", DataType::Date),
+    ("lotw_qslsdate", DataType::Date),
+    ("lotw_qsl_rcvd", DataType::Enumeration(QSL_RCVD)),
+    ("lotw_qsl_sent", DataType::Enumeration(QSL_SENT)),
+    ("max_bursts", DataType::Number),
+    ("mode", DataType::Mode),
+    ("morse_key_info", DataType::Text),
+    ("morse_key_type", DataType::Enumeration(MORSE_KEY_TYPES)),
+    ("ms_shower", DataType::Text),
+    ("my_altitude", DataType::Number),
+    ("my_antenna", DataType::Text),
+    ("my_antenna_intl", DataType::Text),
+    ("my_arrl_sect", DataType::Text),
+    ("my_city", DataType::Text),
+    ("my_city_intl", DataType::Text),
+    ("my_cnty", DataType::Text),
+    ("my_country", DataType::Text),
+    ("my_country_intl", DataType::Text),
+    ("my_cq_zone", DataType::PositiveInteger),
+    ("my_dxcc", DataType::Dxcc),
+    ("my_fists", DataType::PositiveInteger),
+    ("my_gridsquare", DataType::GridSquare),
+    ("my_gridsquare_ext", DataType::Text),
+    ("my_iota", DataType::Text),
+    ("my_iota_island_id", DataType::PositiveInteger),
+    ("my_itu_zone", DataType::PositiveInteger),
+    ("my_lat", DataType::Location),
+    ("my_lon", DataType::Location),
+    ("my_morse_key_info", DataType::Text),
+    ("my_morse_key_type", DataType::Enumeration(MORSE_KEY_TYPES)),
+    ("my_name", DataType::Text),
+    ("my_name_intl", DataType::Text),
+    ("my_postal_code", DataType::Text),
+    ("my_postal_code_intl", DataType::Text),
+    ("my_pota_ref", DataType::Reference(Program::Pota)),
+    ("my_rig", DataType::Text),
+    ("my_rig_intl", DataType::Text),
+    ("my_sig", DataType::Text),
+    ("my_sig_intl", DataType::Text),
+    ("my_sig_info", DataType::Text),
+    ("my_sig_info_intl", DataType::Text),
+    ("my_sota_ref", DataType::Reference(Program::Sota)),
+    ("my_state", DataType::Text),
+    ("my_street", DataType::Text),
+    ("my_street_intl", DataType::Text),
+    ("my_usaca_counties", DataType::Text),
+    ("my_vucc_grids", DataType::GridSquareList),
+    ("my_wwff_ref", DataType::Reference(Program::Wwff)),
+    ("name", DataType::Text),
+    ("name_intl", DataType::Text),
+    ("notes", DataType::Text),
+    ("notes_intl", DataType::Text),
+    ("nr_bursts", DataType::Integer),
+    ("nr_pings", DataType::Integer),
+    ("operator", DataType::Text),
+    ("owner_callsign", DataType::Text),
+    ("pfx", DataType::Text),
+    ("pota_ref", DataType::Reference(Program::Pota)),
+    ("precedence", DataType::Text),
+    ("prop_mode", DataType::Enumeration(PROPAGATION_MODES)),
+    ("public_key", DataType::Text),
+    ("qrzcom_qso_download_date", DataType::Date),
+    ("qrzcom_qso_download_status", DataType::Enumeration(QSL_RCVD)),
+    ("qrzcom_qso_upload_date", DataType::Date),
+    ("qrzcom_qso_upload_status", DataType::Enumeration(UPLOAD_STATUS)),
+    ("qslmsg", DataType::Text),
+    ("qslmsg_intl", DataType::Text),
+    ("qslmsg_rcvd", DataType::Text),
+    ("qslrdate", DataType::Date),
+    ("qslsdate", DataType::Date),
+    ("qsl_rcvd", DataType::Enumeration(QSL_RCVD)),
+    ("qsl_rcvd_via", DataType::Enumeration(QSL_VIA)),
+    ("qsl_sent", DataType::Enumeration(QSL_SENT)),
+    ("qsl_sent_via", DataType::Enumeration(QSL_VIA)),
+    ("qsl_via", DataType::Text),
+    ("qso_complete", DataType::Enumeration(QSO_COMPLETE)),
+    ("qso_date", DataType::Date),
+    ("qso_date_off", DataType::Date),
+    ("qso_random", DataType::Boolean),
+    ("qth", DataType::Text),
+    ("qth_intl", DataType::Text),
+    ("region", DataType::Text),
+    ("rig", DataType::Text),
+    ("rig_intl", DataType::Text),
+    ("rst_rcvd", DataType::Text),
+    ("rst_sent", DataType::Text),
+    ("rx_pwr", DataType::Number),
+    ("sat_mode", DataType::Text),
+    ("sat_name", DataType::Text),
+    ("sfi", DataType::Integer),
+    ("sig", DataType::Text),
+    ("sig_intl", DataType::Text),
+    ("sig_info", DataType::Text),
+    ("sig_info_intl", DataType::Text),
+    ("silent_key", DataType::Boolean),
+    ("skcc", DataType::Text),
+    ("sota_ref", DataType::Reference(Program::Sota)),
+    ("srx", DataType::Integer),
+    ("srx_string", DataType::Text),
+    ("state", DataType::Text),
+    ("station_callsign", DataType::Text),
+    ("stx", DataType::Integer),
+    ("stx_string", DataType::Text),
+    ("submode", DataType::Text),
+    ("swl", DataType::Boolean),
+    ("ten_ten", DataType::PositiveInteger),
+    ("time_off", DataType::Time),
+    ("time_on", DataType::Time),
+    ("tx_pwr", DataType::Number),
+    ("uksmg", DataType::PositiveInteger),
+    ("usaca_counties", DataType::Text),
+    ("ve_prov", DataType::Text),
+    ("vucc_grids", DataType::GridSquareList),
+    ("web", DataType::Text),
+    ("wwff_ref", DataType::Reference(Program::Wwff)),
+];
+
+/// A problem with one field of one record
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    /// Index of the record in the file, starting at 0
+    pub record: usize,
+    pub field: String,
+    /// Byte offset of the offending field in the input
+    pub offset: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "QSO {}: {} at byte {}: {}", self.record + 1, self.field.to_uppercase(), self.offset, self.message)
+    }
+}
+
+/// Data type of a QSO field, or `None` if the field is not part of the spec.
+pub fn field_type(name: &str) -> Option<DataType> {
+    FIELDS
+        .iter()
+        .find(|(field, _)| field.eq_ignore_ascii_case(name))
+        .map(|(_, data_type)| *data_type)
+}
+
+/// Checks every record of the file. APP_ fields and fields declared by
+/// USERDEF header fields are accepted without checks.
+pub fn validate(adif: &AdifFile) -> Vec<Violation> {
+    let userdefs = userdef_names(adif);
+
+    adif.records
+        .iter()
+        .enumerate()
+        .flat_map(|(index, record)| validate_record(index, record, &userdefs))
+        .collect()
+}
+
+/// Lowercased names of the fields declared by USERDEF header fields.
+pub fn userdef_names(adif: &AdifFile) -> Vec<String> {
+    adif.header_fields
+        .iter()
+        .filter(|field| field.name.to_lowercase().starts_with("userdef"))
+        .filter_map(|field| field.data.split(',').next())
+        .map(|name| name.trim().to_lowercase())
+        .collect()
+}
+
+pub fn validate_record(index: usize, record: &Record, userdefs: &[String]) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    let mut violation = |field: &Field, message: String| {
+        violations.push(Violation { record: index, field: field.name.to_lowercase(), offset: field.offset, message });
+    };
+
+    for field in &record.fields {
+        let name = field.name.to_lowercase();
+        if name.starts_with("app_") || userdefs.contains(&name) {
+            continue;
+        }
+
+        let Some(data_type) = field_type(&name) else {
+            violation(field, "unknown field".to_string());
+            continue;
+        };
+
+        if let Err(message) = check_value(data_type, &field.data) {
+            violation(field, message);
+        }
+
+        if qslmsg::is_qsl_message(&name) {
+            for message in qslmsg::check_message(&field.data) {
+                violation(field, message);
+            }
+        }
+    }
+
+    // A frequency outside the band it is logged on
+    let freq_field = record.fields.iter().find(|field| field.name.eq_ignore_ascii_case("freq"));
+    if let (Some(freq_field), Some(band)) = (freq_field, record.get("band")) {
+        let limits = BANDS.iter().find(|(name, _, _)| name.eq_ignore_ascii_case(band.trim()));
+        if let (Ok(freq), Some((name, low, high))) = (freq_field.data.trim().parse::<f64>(), limits) {
+            if freq < *low || freq > *high {
+                violation(freq_field, format!("{} MHz is outside the {} band", freq, name));
+            }
+        }
+    }
+
+    violations
+}
+
+/// Checks a value against a data type, describing the problem if it does not fit.
+pub fn check_value(data_type: DataType, value: &str) -> Result<(), String> {
+    let value = value.trim();
+    let valid = match data_type {
+        DataType::Text => true,
+        DataType::Boolean => matches!(value.to_ascii_uppercase().as_str(), "Y" | "N"),
+        DataType::Number => is_number(value),
+        DataType::Integer => is_integer(value.strip_prefix('-').unwrap_or(value)),
+        DataType::PositiveInteger => is_integer(value) && !value.trim_start_matches('0').is_empty(),
+        DataType::Date => is_date(value),
+        DataType::Time => is_time(value),
+        DataType::GridSquare => is_grid_square(value),
+        DataType::GridSquareList => value.split(',').all(|grid| is_grid_square(grid.trim())),
+        DataType::Location => is_location(value),
+        DataType::Band => BANDS.iter().any(|(band, _, _)| band.eq_ignore_ascii_case(value)),
+        DataType::Dxcc => value.parse::<u32>().is_ok_and(|code| code <= MAX_DXCC),
+        DataType::Reference(program) => return reference::check(program, value).map_or(Ok(()), Err),
+        DataType::Enumeration(values) => values.iter().any(|v| v.eq_ignore_ascii_case(value)),
+        DataType::Mode => {
+            if MODES.iter().any(|mode| mode.eq_ignore_ascii_case(value)) {
+                return Ok(());
+            }
+            if let Some((submode, mode)) = SUBMODES.iter().find(|(submode, _)| submode.eq_ignore_ascii_case(value)) {
+                return Err(format!("{} is a submode; use MODE {} with SUBMODE {}", submode, mode, submode));
+            }
+            false
+        }
+    };
+
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("invalid {} value {:?}", type_name(data_type), value))
+    }
+}
+
+fn type_name(data_type: DataType) -> &'static str {
+    match data_type {
+        DataType::Text => "text",
+        DataType::Boolean => "boolean",
+        DataType::Number => "number",
+        DataType::Integer => "integer",
+        DataType::PositiveInteger => "positive integer",
+        DataType::Date => "date",
+        DataType::Time => "time",
+        DataType::GridSquare => "grid square",
+        DataType::GridSquareList => "grid square list",
+        DataType::Location => "location",
+        DataType::Band => "band",
+        DataType::Mode => "mode",
+        DataType::Dxcc => "DXCC entity",
+        DataType::Reference(_) => "reference",
+        DataType::Enumeration(_) => "enumeration",
+    }
+}
+
+fn is_integer(value: &str) -> bool {
+    !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit())
+}
+
+fn is_number(value: &str) -> bool {
+    let value = value.strip_prefix('-').unwrap_or(value);
+    let (whole, fraction) = value.split_once('.').unwrap_or((value, ""));
+    (is_integer(whole) || is_integer(fraction)) && whole.bytes().chain(fraction.bytes()).all(|b| b.is_ascii_digit())
+}
+
+fn is_date(value: &str) -> bool {
+    if value.len() != 8 || !is_integer(value) {
+        return false;
+    }
+
+    let year: u32 = value[0..4].parse().unwrap();
+    let month: u32 = value[4..6].parse().unwrap();
+    let day: u32 = value[6..8].parse().unwrap();
+    let leap = year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400));
+    let days = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap => 29,
+        2 => 28,
+        _ => return false,
+    };
+
+    year >= 1930 && (1..=days).contains(&day)
+}
+
+fn is_time(value: &str) -> bool {
+    if !(value.len() == 4 || value.len() == 6) || !is_integer(value) {
+        return false;
+    }
+
+    let hours: u32 = value[0..2].parse().unwrap();
+    let minutes: u32 = value[2..4].parse().unwrap();
+    let seconds: u32 = value.get(4..6).map_or(0, |s| s.parse().unwrap());
+    hours < 24 && minutes < 60 && seconds < 60
+}
+
+fn is_grid_square(value: &str) -> bool {
+    grid::check(value).is_none()
+}
+
+fn is_location(value: &str) -> bool {
+    let bytes = value.as_bytes();
+    if bytes.len() != 11 || !value.is_ascii() || !matches!(bytes[0].to_ascii_uppercase(), b'N' | b'S' | b'E' | b'W') {
+        return false;
+    }
+
+    let degrees = &value[1..4];
+    let minutes = &value[5..11];
+    bytes[4] == b' '
+        && is_integer(degrees)
+        && degrees.parse::<u32>().unwrap() <= 180
+        && minutes.as_bytes()[2] == b'.'
+        && is_integer(&minutes[0..2])
+        && is_integer(&minutes[3..6])
+        && minutes[0..2].parse::<u32>().unwrap() < 60
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_value_checks() {
+        assert!(check_value(DataType::Date, "20240229").is_ok());
+        assert!(check_value(DataType::Date, "20230229").is_err());
+        assert!(check_value(DataType::Time, "235959").is_ok());
+        assert!(check_value(DataType::Time, "2460").is_err());
+        assert!(check_value(DataType::Number, "-14.074").is_ok());
+        assert!(check_value(DataType::Number, "14,074").is_err());
+        assert!(check_value(DataType::GridSquare, "FN31pr").is_ok());
+        assert!(check_value(DataType::GridSquare, "ZZ99").is_err());
+        assert!(check_value(DataType::Location, "N040 12.345").is_ok());
+        assert!(check_value(DataType::Band, "2M").is_ok());
+        assert!(check_value(DataType::Reference(Program::Sota), "EA4/MD-001").is_ok());
+        assert_eq!(
+            check_value(DataType::Mode, "FT4"),
+            Err("FT4 is a submode; use MODE MFSK with SUBMODE FT4".to_string())
+        );
+    }
+
+    #[test]
+    fn test_validate_reports_per_record_violations() {
+        let adif = AdifFile::parse(
+            b"Log\r\n<userdef1:5:N>SCORE<eoh>\
+              <call:4>W1AW<qso_date:8>20241301<band:3>20m<freq:5>7.074<score:2>10<app_x_y:1>z<eor>\
+              <call:5>K1MIX<band:3>21m<mystery:1>x<eor>",
+        )
+        .unwrap();
+
+        let violations: Vec<String> = validate(&adif).iter().map(|v| v.to_string()).collect();
+
+        assert_eq!(
+            violations,
+            vec![
+                "QSO 1: QSO_DATE at byte 41: invalid date value \"20241301\"",
+                "QSO 1: FREQ at byte 72: 7.074 MHz is outside the 20m band",
+                "QSO 2: BAND at byte 126: invalid band value \"21m\"",
+                "QSO 2: MYSTERY at byte 137: unknown field",
+            ]
+        );
+    }
+}
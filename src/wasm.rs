@@ -0,0 +1,62 @@
+use crate::convert::{self, ConversionOptions};
+use crate::encoding::AdifEncoding;
+use wasm_bindgen::prelude::*;
+
+/// Settings for `convert`, as a JavaScript object with camelCase properties
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Encoding of the input; unset uses the header's ENCODING, else detects per field
+    #[wasm_bindgen(js_name = inputEncoding)]
+    pub input_encoding: Option<String>,
+    /// Encoding of the output, UTF-8 when unset
+    #[wasm_bindgen(js_name = outputEncoding)]
+    pub output_encoding: Option<String>,
+    /// Do not correct invalid characters or field counts
+    pub strict: bool,
+    /// Transliterate to characters without diacritics
+    pub ascii: bool,
+    /// Delete what the output encoding cannot represent instead of writing `?`
+    pub delete: bool,
+    /// Skip fields that cannot be parsed instead of failing
+    #[wasm_bindgen(js_name = skipMalformed)]
+    pub skip_malformed: bool,
+}
+
+#[wasm_bindgen]
+impl Options {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// The converted file, as a `Uint8Array`, and what the conversion ran into
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Debug, Clone)]
+pub struct Conversion {
+    pub output: Vec<u8>,
+    /// Number of records written
+    pub records: usize,
+    /// Problems the parser recovered from and fields it skipped
+    pub warnings: Vec<String>,
+}
+
+/// Converts an ADIF file read in the browser, e.g. from a `File`'s
+/// `arrayBuffer()`. Throws an `Error` with the reason if it cannot.
+#[wasm_bindgen]
+pub fn convert(bytes: &[u8], options: &Options) -> Result<Conversion, JsError> {
+    let options = ConversionOptions {
+        input_encoding: options.input_encoding.as_deref().map(str::parse::<AdifEncoding>).transpose()?,
+        output_encoding: options.output_encoding.as_deref().map(AdifEncoding::parse_output).transpose()?.unwrap_or(AdifEncoding::Utf8),
+        strict: options.strict,
+        replacement_char: (!options.delete).then_some('?'),
+        ascii: options.ascii,
+        skip_malformed: options.skip_malformed,
+        ..ConversionOptions::default()
+    };
+
+    let result = convert::convert(bytes, &options)?;
+    let skipped = result.errors.iter().map(|error| format!("Skipped {}", error));
+    Ok(Conversion { output: result.output, records: result.records, warnings: result.warnings.into_iter().chain(skipped).collect() })
+}
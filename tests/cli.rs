@@ -0,0 +1,160 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+/// Runs the transadif binary Cargo built for this test.
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_transadif"))
+        .args(args)
+        .args(["--no-config", "--deterministic"])
+        .output()
+        .expect("transadif runs")
+}
+
+/// Runs transadif, checking that it succeeds, and returns its stdout.
+fn transadif(args: &[&str]) -> Vec<u8> {
+    let output = run(args);
+    assert!(output.status.success(), "transadif failed: {}", String::from_utf8_lossy(&output.stderr));
+    output.stdout
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("transadif-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_cat_decodes_each_input_with_its_own_encoding() {
+    let dir = temp_dir("cat");
+    let inputs: [(&str, &[u8]); 3] = [
+        ("utf8.adi", b"Log\r\n<encoding:5>UTF-8<eoh>\r\n<call:4>DL1X<name:6>J\xc3\xbcrgen<eor>\r\n"),
+        ("cp1252.adi", b"Log\r\n<encoding:12>Windows-1252<eoh>\r\n<call:4>F5AB<name:9>Fran\xe7oise<comment:4>\x80 5\x94<eor>\r\n<call:4>F6CD<eor>\r\n"),
+        ("no-header.adi", b"<call:4>W1AW<eor>\r\n"),
+    ];
+    let mut args = vec!["cat".to_string()];
+    for (name, data) in inputs {
+        fs::write(dir.join(name), data).unwrap();
+        args.push(dir.join(name).display().to_string());
+    }
+
+    let output = transadif(&args.iter().map(String::as_str).collect::<Vec<_>>());
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "Concatenated by TransADIF from 3 files\r\n\r\n\
+         <programid:9>TransADIF\r\n<created_timestamp:15>19700101 000000\r\n<encoding:5>UTF-8\r\n<eoh>\r\n\
+         <call:4>DL1X<name:6>Jürgen<eor>\r\n\
+         <call:4>F5AB<name:9>Françoise<comment:4>€ 5”<eor>\r\n\
+         <call:4>F6CD<eor>\r\n\
+         <call:4>W1AW<eor>\r\n"
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_extract_records_clamps_huge_ranges() {
+    let dir = temp_dir("extract");
+    let input = dir.join("log.adi");
+    fs::write(&input, b"Log\r\n<eoh>\r\n<call:4>K1AA<eor>\r\n<call:4>K1BB<eor>\r\n<call:4>K1CC<eor>\r\n").unwrap();
+
+    let output = transadif(&[input.to_str().unwrap(), "--extract-records", "2-4000000000"]);
+    assert_eq!(output, b"Log\r\n<eoh>\r\n<call:4>K1BB<eor>\r\n<call:4>K1CC<eor>\r\n");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_resume_does_not_duplicate_sidecar_hashes() {
+    let dir = temp_dir("resume-hashes");
+    let input = dir.join("log.adi");
+    let output = dir.join("out.adi");
+    let data = b"Log\r\n<eoh>\r\n<call:4>K1AA<eor>\r\n<call:4>K1BB<eor>\r\n<call:4>K1CC<eor>\r\n";
+    fs::write(&input, data).unwrap();
+    let args = [input.to_str().unwrap(), "-o", output.to_str().unwrap(), "--emit-hashes=sidecar"];
+    transadif(&args);
+    let complete = fs::read(&output).unwrap();
+    let hashes = fs::read_to_string(dir.join("out.adi.hashes")).unwrap();
+
+    // Interrupted after the first record, with every hash already written
+    let output_length = complete.windows(4).position(|w| w == b"K1AA").unwrap() + 11;
+    fs::write(&output, &complete[..output_length]).unwrap();
+    let input_offset = data.windows(4).position(|w| w == b"K1BB").unwrap() - 8;
+    fs::write(
+        dir.join("out.adi.journal"),
+        format!("records_written=1\ninput_offset={}\ninput_length={}\noutput_length={}\n", input_offset, data.len(), output_length),
+    )
+    .unwrap();
+
+    transadif(&[&args[..], &["--resume"]].concat());
+    assert_eq!(fs::read(&output).unwrap(), complete);
+    assert_eq!(fs::read_to_string(dir.join("out.adi.hashes")).unwrap(), hashes);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_anonymize_covers_header_preamble_and_dry_run() {
+    let dir = temp_dir("anonymize");
+    let input = dir.join("log.adi");
+    fs::write(&input, b"Log of Bob Smith\r\n<my_name:3>Bob<eoh>\r\n<call:4>W1AW<name:3>Bob<eor>\r\n").unwrap();
+
+    let output = String::from_utf8(transadif(&[input.to_str().unwrap(), "--anonymize"])).unwrap();
+    assert!(!output.contains("Bob"), "{}", output);
+    assert_eq!(output.matches("<my_name:21>anon-").count() + output.matches("<name:21>anon-").count(), 2);
+
+    let output = String::from_utf8(transadif(&[input.to_str().unwrap(), "--anonymize", "--dry-run"])).unwrap();
+    assert!(output.starts_with("QSO 1: NAME at byte 51: \"Bob\" -> \"anon-"), "{}", output);
+    assert!(output.ends_with("1 field would change\n"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_batch_caches_per_field_encodings() {
+    let dir = temp_dir("batch-cache");
+    fs::create_dir_all(dir.join("logs")).unwrap();
+    fs::write(dir.join("logs/mixed.adi"), b"Log\r\n<eoh>\r\n<name:6>J\xc3\xbcrgen<qth:7>M\xfcnchen<eor>\r\n").unwrap();
+    let (logs, out) = (dir.join("logs"), dir.join("out"));
+    let args = ["batch", logs.to_str().unwrap(), "--out-dir", out.to_str().unwrap()];
+
+    assert!(String::from_utf8(transadif(&args)).unwrap().ends_with("(1 records, 0 encodings from cache)\n"));
+    let output = fs::read(dir.join("out/mixed.adi")).unwrap();
+    let cache = fs::read_to_string(dir.join("out/.transadif-cache.json")).unwrap();
+    assert!(cache.contains("\"per_field\": [\n      \"UTF-8\",\n      \"windows-1252\"\n    ]"), "{}", cache);
+
+    assert!(String::from_utf8(transadif(&args)).unwrap().ends_with("(1 records, 1 encodings from cache)\n"));
+    assert_eq!(fs::read(dir.join("out/mixed.adi")).unwrap(), output);
+    assert!(String::from_utf8(output).unwrap().contains("<name:6>Jürgen<qth:7>München<eor>"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_fail_on_levels_set_the_exit_status() {
+    let dir = temp_dir("fail-on");
+    let inputs: [(&str, &[u8]); 3] = [
+        ("clean.adi", b"Log\r\n<eoh>\r\n<call:4>W1AW<name:7>J\xc3\xbcrgen<eor>\r\n"),
+        ("entity.adi", b"Log\r\n<eoh>\r\n<call:4>W1AW<name:11>Tom &amp; J<eor>\r\n"),
+        ("warning.adi", b"Log\r\n<eoh>\r\n<call:3>W1AW<eor>\r\n"),
+    ];
+    // Whether each input fails at --fail-on error, warning and correction
+    let expected = [("clean.adi", [false; 3]), ("entity.adi", [false, false, true]), ("warning.adi", [false, true, true])];
+    for (name, data) in inputs {
+        fs::write(dir.join(name), data).unwrap();
+    }
+
+    for (name, fails) in expected {
+        let input = dir.join(name);
+        for (level, fails) in ["error", "warning", "correction"].into_iter().zip(fails) {
+            // --ascii changes the clean input, but only as asked
+            for extra in [&["--ascii"][..], &["--ascii", "--stream"]] {
+                let output = run(&[&[input.to_str().unwrap(), "--fail-on", level][..], extra].concat());
+                assert_eq!(!output.status.success(), fails, "{} --fail-on {} {:?}", name, level, extra);
+                assert!(output.stdout.ends_with(b"<eor>\r\n"), "{} --fail-on {} still writes the output", name, level);
+            }
+        }
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,32 @@
+use libtest_mimic::{Arguments, Failed, Trial};
+use std::path::{Path, PathBuf};
+use transadif::test_runner::TestRunner;
+
+/// Runs every case of test-cases/ through the transadif binary Cargo built for
+/// this test, as one libtest test each, named like `01-plain-examples::1-plain-ascii`.
+fn main() {
+    let args = Arguments::from_args();
+    let runner = TestRunner::new(executable());
+    let test_cases = runner.find_test_cases(Path::new(env!("CARGO_MANIFEST_DIR")).join("test-cases"), None).expect("test cases are readable");
+
+    let trials = test_cases
+        .into_iter()
+        .map(|test_case| {
+            Trial::test(test_case.name.clone(), move || {
+                let runner = TestRunner::new(executable());
+                let result = runner.run_test(&test_case);
+                if result.passed {
+                    Ok(())
+                } else {
+                    Err(Failed::from(runner.failure_details(&result)))
+                }
+            })
+        })
+        .collect();
+
+    libtest_mimic::run(&args, trials).exit();
+}
+
+fn executable() -> PathBuf {
+    PathBuf::from(env!("CARGO_BIN_EXE_transadif"))
+}